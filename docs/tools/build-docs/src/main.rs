@@ -1,12 +1,17 @@
+use fippli_lang::{
+    docgen,
+    interpreter::{Interpreter, Stability, Value},
+    json,
+};
 use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag};
 use std::{
     borrow::Cow,
     cmp::Ordering,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     env,
     error::Error,
     fs,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 use walkdir::WalkDir;
 
@@ -14,14 +19,51 @@ use walkdir::WalkDir;
 struct DocPage {
     title: String,
     source_path: PathBuf,
+    file_name: String, // output file in multi-page mode
     content_html: String,
     section_id: String,
     h1_slug: String,
     h2_headings: Vec<(String, String)>, // (slug, title)
+    search_sections: Vec<SearchSection>,
+}
+
+/// A heading and the plain text below it, up to the next heading.
+#[derive(Debug)]
+struct SearchSection {
+    slug: String,
+    title: String,
+    text: String,
+}
+
+struct RenderedMarkdown {
+    html: String,
+    title: Option<String>,
+    h1_slug: Option<String>,
+    h2_headings: Vec<(String, String)>,
+    search_sections: Vec<SearchSection>,
 }
 
+#[derive(Default)]
+struct BuildOptions {
+    /// Emit one HTML file per markdown source instead of a single index.html.
+    multi_page: bool,
+    /// Prefix for sitemap URLs, e.g. `https://example.org/fip/`.
+    base_url: String,
+    /// A FIP library to document from its doc comments instead of the
+    /// language docs.
+    api: Option<PathBuf>,
+    /// Where `--api` writes its pages; `<library>/doc` by default.
+    out: Option<PathBuf>,
+}
+
+const SITE_TITLE: &str = "Fip Language Documentation";
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let options = parse_args(env::args().skip(1))?;
     let project_root = project_root()?;
+    if let Some(library) = &options.api {
+        return build_api_site(library, &options, &project_root);
+    }
     let syntax_dir = project_root.join("syntax");
     let docs_dir = project_root.join("docs");
 
@@ -39,51 +81,176 @@ fn main() -> Result<(), Box<dyn Error>> {
     let spec_order = load_spec_order(&syntax_dir)?;
 
     let mut pages = Vec::new();
-    for path in markdown_files {
+    for path in &markdown_files {
         // Skip index.md - it's only used for ordering, not content
         if path.file_name().and_then(|n| n.to_str()) == Some("index.md") {
             continue;
         }
 
-        let content = fs::read_to_string(&path)?;
+        let content = fs::read_to_string(path)?;
         let file_stem = path
             .file_stem()
             .and_then(|s| s.to_str())
             .ok_or_else(|| format!("invalid file name {}", path.display()))?;
-        let slug_prefix = file_stem.replace('_', "-");
-        let section_id = format!("section-{}", slug_prefix);
-        let (html, doc_title, h1_slug, h2_headings) = render_markdown(&content, &slug_prefix);
-        let title = doc_title
-            .clone()
-            .unwrap_or_else(|| humanize_stem(file_stem));
+        let slug_prefix = slug_prefix(path);
+        let link_target = |dest: &str| {
+            resolve_doc_link(dest, path, &markdown_files, &syntax_dir, options.multi_page)
+        };
+        let rendered = render_markdown(&content, &slug_prefix, &link_target);
+        let title = rendered.title.unwrap_or_else(|| humanize_stem(file_stem));
         let fallback_slug = format!("{}-{}", slug_prefix, slugify(&title));
         pages.push(DocPage {
             title,
-            source_path: path,
-            content_html: html,
-            section_id,
-            h1_slug: h1_slug.unwrap_or(fallback_slug),
-            h2_headings,
+            source_path: path.clone(),
+            file_name: page_file_name(path, &syntax_dir),
+            content_html: rendered.html,
+            section_id: format!("section-{}", slug_prefix),
+            h1_slug: rendered.h1_slug.unwrap_or(fallback_slug),
+            h2_headings: rendered.h2_headings,
+            search_sections: rendered.search_sections,
         });
     }
 
+    let builtins_path = syntax_dir.join("builtins.md");
+    let rendered = render_markdown(&builtin_reference_markdown(), "builtins", &|_| None);
+    pages.push(DocPage {
+        title: "Builtin Reference".to_string(),
+        file_name: page_file_name(&builtins_path, &syntax_dir),
+        source_path: builtins_path,
+        content_html: rendered.html,
+        section_id: "section-builtins".to_string(),
+        h1_slug: rendered
+            .h1_slug
+            .unwrap_or_else(|| "builtins-builtin-reference".to_string()),
+        h2_headings: rendered.h2_headings,
+        search_sections: rendered.search_sections,
+    });
+
     let syntax_dir_for_sort = syntax_dir.clone();
     pages.sort_by(|a, b| page_order(a, b, &syntax_dir_for_sort, &spec_order));
 
     cleanup_existing_html(&docs_dir)?;
 
-    let index_html = build_full_site_html(&pages)?;
-    fs::write(docs_dir.join("index.html"), index_html)?;
+    if options.multi_page {
+        write_multi_page_site(&pages, &docs_dir, &options.base_url, SITE_TITLE)?;
+    } else {
+        let index_html = build_full_site_html(&pages, SITE_TITLE)?;
+        fs::write(docs_dir.join("index.html"), index_html)?;
+    }
+
+    Ok(())
+}
+
+/// Renders the API reference of the FIP library in `library`, one page per
+/// documented module, next to a copy of the site stylesheet.
+fn build_api_site(
+    library: &Path,
+    options: &BuildOptions,
+    project_root: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let out_dir = options.out.clone().unwrap_or_else(|| library.join("doc"));
+    let modules = docgen::library_docs(library)?;
+    if modules.is_empty() {
+        return Err(format!(
+            "no documented modules found in {}; add //! comments or exports",
+            library.display()
+        )
+        .into());
+    }
 
+    let pages: Vec<DocPage> = modules
+        .iter()
+        .map(|module| {
+            let slug_prefix = module.name.replace('/', "-");
+            let rendered = render_markdown(&module.markdown(), &slug_prefix, &|_| None);
+            DocPage {
+                title: module.name.clone(),
+                source_path: library.join(format!("{}.fip", module.name)),
+                file_name: format!("{}.html", slug_prefix),
+                content_html: rendered.html,
+                section_id: format!("section-{}", slug_prefix),
+                h1_slug: rendered
+                    .h1_slug
+                    .unwrap_or_else(|| format!("{}-{}", slug_prefix, slugify(&module.name))),
+                h2_headings: rendered.h2_headings,
+                search_sections: rendered.search_sections,
+            }
+        })
+        .collect();
+
+    fs::create_dir_all(&out_dir)?;
+    cleanup_existing_html(&out_dir)?;
+    fs::copy(
+        project_root.join("docs").join("style.css"),
+        out_dir.join("style.css"),
+    )?;
+    let title = "API Reference";
+    if options.multi_page {
+        write_multi_page_site(&pages, &out_dir, &options.base_url, title)?;
+    } else {
+        fs::write(
+            out_dir.join("index.html"),
+            build_full_site_html(&pages, title)?,
+        )?;
+    }
     Ok(())
 }
 
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<BuildOptions, Box<dyn Error>> {
+    let mut options = BuildOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--multi-page" => options.multi_page = true,
+            "--base-url" => {
+                options.base_url = args.next().ok_or("--base-url requires a value")?;
+            }
+            "--api" => {
+                options.api = Some(args.next().ok_or("--api requires a directory")?.into());
+            }
+            "--out" => {
+                options.out = Some(args.next().ok_or("--out requires a directory")?.into());
+            }
+            other => return Err(format!("unknown argument '{}'", other).into()),
+        }
+    }
+    Ok(options)
+}
+
+/// Renders the interpreter's builtin registry as markdown so the reference
+/// never drifts from what the interpreter actually installs.
+fn builtin_reference_markdown() -> String {
+    let mut markdown = String::from(
+        "# Builtin Reference\n\nEvery function installed in the global scope, generated from the interpreter's builtin registry.\n",
+    );
+    for builtin in Interpreter::new().builtins() {
+        markdown.push_str(&format!(
+            "\n## {name}\n\n**Signature** `{name}: ({params})`\n\n**Purity** {purity}\n\n**Stability** {stability}\n\n{doc}\n",
+            name = builtin.name,
+            params = builtin.params.join(", "),
+            purity = if builtin.impure { "impure" } else { "pure" },
+            stability = match builtin.stability {
+                Stability::Stable => "stable".to_string(),
+                Stability::Experimental => "experimental".to_string(),
+                Stability::Deprecated { replacement } => {
+                    format!("deprecated, use `{}` instead", replacement)
+                }
+            },
+            doc = builtin.doc,
+        ));
+    }
+    markdown
+}
+
 fn cleanup_existing_html(docs_dir: &Path) -> Result<(), Box<dyn Error>> {
     if docs_dir.exists() {
         for entry in fs::read_dir(docs_dir)? {
             let entry = entry?;
             let path = entry.path();
-            if path.extension().and_then(|ext| ext.to_str()) == Some("html") {
+            let generated = matches!(
+                path.file_name().and_then(|name| name.to_str()),
+                Some("search-index.json" | "sitemap.xml")
+            );
+            if generated || path.extension().and_then(|ext| ext.to_str()) == Some("html") {
                 fs::remove_file(path)?;
             }
         }
@@ -154,12 +321,8 @@ fn load_spec_order(syntax_dir: &Path) -> Result<HashMap<PathBuf, usize>, Box<dyn
 fn render_markdown(
     markdown: &str,
     slug_prefix: &str,
-) -> (
-    String,
-    Option<String>,
-    Option<String>,
-    Vec<(String, String)>,
-) {
+    link_target: &dyn Fn(&str) -> Option<String>,
+) -> RenderedMarkdown {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_TABLES);
     options.insert(Options::ENABLE_FOOTNOTES);
@@ -167,7 +330,15 @@ fn render_markdown(
     options.insert(Options::ENABLE_TASKLISTS);
 
     let parser = Parser::new_ext(markdown, options);
-    let mut events: Vec<Event<'_>> = parser.collect();
+    let mut events: Vec<Event<'_>> = parser
+        .map(|event| match event {
+            Event::Start(Tag::Link(link_type, dest, title)) => match link_target(&dest) {
+                Some(target) => Event::Start(Tag::Link(link_type, target.into(), title)),
+                None => Event::Start(Tag::Link(link_type, dest, title)),
+            },
+            other => other,
+        })
+        .collect();
     let mut headings = Vec::new();
     let mut slug_counts: HashMap<String, usize> = HashMap::new();
 
@@ -198,12 +369,34 @@ fn render_markdown(
                 events[end_index] = Event::Html(format!("</h{lvl}>", lvl = level_num).into());
             }
 
-            headings.push((level, slug, title));
+            headings.push((level, slug, title, i, end_index));
             i = end_index;
         }
         i += 1;
     }
 
+    // Each heading's search text runs until the next heading starts
+    let mut search_sections = Vec::new();
+    for (index, (_, slug, title, _, end_index)) in headings.iter().enumerate() {
+        let next_start = headings
+            .get(index + 1)
+            .map(|heading| heading.3)
+            .unwrap_or(events.len());
+        let text = events[end_index + 1..next_start]
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(content) | Event::Code(content) => Some(content.as_ref()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        search_sections.push(SearchSection {
+            slug: slug.clone(),
+            title: title.clone(),
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+        });
+    }
+
     let mut html_output = String::new();
     html::push_html(&mut html_output, events.into_iter());
 
@@ -213,7 +406,7 @@ fn render_markdown(
     let mut doc_title = None;
     let mut h1_slug = None;
     let mut h2_headings = Vec::new();
-    for (level, slug, title) in headings {
+    for (level, slug, title, _, _) in headings {
         let level_num = heading_level_to_u8(&level);
         if level_num == 1 && doc_title.is_none() {
             doc_title = Some(title.clone());
@@ -223,7 +416,78 @@ fn render_markdown(
         }
     }
 
-    (html_output, doc_title, h1_slug, h2_headings)
+    RenderedMarkdown {
+        html: html_output,
+        title: doc_title,
+        h1_slug,
+        h2_headings,
+        search_sections,
+    }
+}
+
+/// Rewrites a relative link to another markdown page so it points at the
+/// generated output: the page's file in multi-page mode, or its section of
+/// index.html otherwise. Fragments are prefixed the same way headings are.
+fn resolve_doc_link(
+    dest: &str,
+    source_path: &Path,
+    markdown_files: &[PathBuf],
+    syntax_dir: &Path,
+    multi_page: bool,
+) -> Option<String> {
+    if dest.contains("://") {
+        return None;
+    }
+    let (path, fragment) = match dest.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (dest, None),
+    };
+    if !path.ends_with(".md") {
+        return None;
+    }
+
+    let mut target = source_path.parent()?.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::ParentDir => {
+                target.pop();
+            }
+            Component::Normal(part) => target.push(part),
+            _ => {}
+        }
+    }
+    if !markdown_files.contains(&target) {
+        return None;
+    }
+
+    let prefix = slug_prefix(&target);
+    let anchor = match fragment {
+        Some(fragment) => format!("#{}-{}", prefix, fragment),
+        None if multi_page => String::new(),
+        None => format!("#section-{}", prefix),
+    };
+    if multi_page {
+        Some(format!("{}{}", page_file_name(&target, syntax_dir), anchor))
+    } else {
+        Some(anchor)
+    }
+}
+
+fn slug_prefix(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .replace('_', "-")
+}
+
+/// Flattens `core/array.md` into `core-array.html`.
+fn page_file_name(path: &Path, syntax_dir: &Path) -> String {
+    relative_to_syntax(path, syntax_dir)
+        .with_extension("html")
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("-")
 }
 
 fn collect_heading_text(events: &[Event<'_>], mut index: usize) -> (String, usize) {
@@ -295,25 +559,123 @@ fn humanize_stem(stem: &str) -> String {
         .join(" ")
 }
 
-fn build_full_site_html(pages: &[DocPage]) -> Result<String, Box<dyn Error>> {
+fn build_full_site_html(pages: &[DocPage], title: &str) -> Result<String, Box<dyn Error>> {
     let mut sections_html = String::new();
     for page in pages {
-        let source_path_display = page.source_path.to_string_lossy();
-        let source = html_escape(&source_path_display);
-        sections_html.push_str(&format!(
-            "<section id=\"{id}\" data-doc-section=\"{id}\" data-source=\"{source}\">\n{content}\n</section>\n",
-            id = page.section_id,
-            source = source,
-            content = page.content_html
+        sections_html.push_str(&page_section_html(page));
+    }
+
+    let sidebar_html = build_sidebar_html(pages, "", |_| String::new());
+    Ok(page_layout(title, &sidebar_html, &sections_html))
+}
+
+/// Writes one HTML file per page, an index.html listing every page, a
+/// `search-index.json` with one entry per heading, and a `sitemap.xml`.
+fn write_multi_page_site(
+    pages: &[DocPage],
+    docs_dir: &Path,
+    base_url: &str,
+    site_title: &str,
+) -> Result<(), Box<dyn Error>> {
+    let sidebar_html = build_sidebar_html(pages, SEARCH_HTML, |page| page.file_name.clone());
+
+    for page in pages {
+        let title = format!("{} - {}", page.title, site_title);
+        let html = page_layout(&title, &sidebar_html, &page_section_html(page));
+        fs::write(docs_dir.join(&page.file_name), html)?;
+    }
+
+    let mut contents = format!(
+        "<h1 id=\"contents\">{}</h1>\n<ul>\n",
+        html_escape(site_title)
+    );
+    for page in pages {
+        contents.push_str(&format!(
+            "  <li><a href=\"{file}\">{title}</a></li>\n",
+            file = page.file_name,
+            title = html_escape(&page.title)
         ));
     }
+    contents.push_str("</ul>\n");
+    let index_html = page_layout(site_title, &sidebar_html, &contents);
+    fs::write(docs_dir.join("index.html"), index_html)?;
 
-    // Build sidebar navigation from H1 headings with nested H2 headings
+    fs::write(
+        docs_dir.join("search-index.json"),
+        search_index_json(pages)?,
+    )?;
+    fs::write(docs_dir.join("sitemap.xml"), sitemap_xml(pages, base_url))?;
+    Ok(())
+}
+
+fn search_index_json(pages: &[DocPage]) -> Result<String, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for page in pages {
+        for section in &page.search_sections {
+            let mut fields = BTreeMap::new();
+            fields.insert("page".to_string(), Value::String(page.title.clone()));
+            fields.insert("title".to_string(), Value::String(section.title.clone()));
+            fields.insert(
+                "url".to_string(),
+                Value::String(format!("{}#{}", page.file_name, section.slug)),
+            );
+            fields.insert("text".to_string(), Value::String(section.text.clone()));
+            entries.push(Value::Object(fields));
+        }
+    }
+    Ok(json::encode(&Value::List(entries))?)
+}
+
+/// Sitemap locations are `base_url` joined with each file name, so they are
+/// only absolute when a base URL is given.
+fn sitemap_xml(pages: &[DocPage], base_url: &str) -> String {
+    let base = if base_url.is_empty() || base_url.ends_with('/') {
+        base_url.to_string()
+    } else {
+        format!("{}/", base_url)
+    };
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    let files =
+        std::iter::once("index.html").chain(pages.iter().map(|page| page.file_name.as_str()));
+    for file in files {
+        xml.push_str(&format!(
+            "  <url><loc>{}</loc></url>\n",
+            html_escape(&format!("{}{}", base, file))
+        ));
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+fn page_section_html(page: &DocPage) -> String {
+    let source_path_display = page.source_path.to_string_lossy();
+    let source = html_escape(&source_path_display);
+    format!(
+        "<section id=\"{id}\" data-doc-section=\"{id}\" data-source=\"{source}\">\n{content}\n</section>\n",
+        id = page.section_id,
+        source = source,
+        content = page.content_html
+    )
+}
+
+/// Builds the sidebar navigation from H1 headings with nested H2 headings.
+/// `search` is extra markup placed above the heading filter. `page_href`
+/// returns the file a page lives in, or an empty string when every page
+/// shares one document.
+fn build_sidebar_html(
+    pages: &[DocPage],
+    search: &str,
+    page_href: impl Fn(&DocPage) -> String,
+) -> String {
     let mut sidebar_items = String::new();
     for page in pages {
+        let href = page_href(page);
         let title_escaped = html_escape(&page.title);
         sidebar_items.push_str(&format!(
-            "      <li data-nav-item>\n        <a href=\"#{slug}\">{title}</a>\n",
+            "      <li data-nav-item>\n        <a href=\"{href}#{slug}\">{title}</a>\n",
+            href = href,
             slug = page.h1_slug,
             title = title_escaped
         ));
@@ -324,7 +686,8 @@ fn build_full_site_html(pages: &[DocPage]) -> Result<String, Box<dyn Error>> {
             for (h2_slug, h2_title) in &page.h2_headings {
                 let h2_title_escaped = html_escape(h2_title);
                 sidebar_items.push_str(&format!(
-                    "          <li data-nav-item><a href=\"#{slug}\">{title}</a></li>\n",
+                    "          <li data-nav-item><a href=\"{href}#{slug}\">{title}</a></li>\n",
+                    href = href,
                     slug = h2_slug,
                     title = h2_title_escaped
                 ));
@@ -334,9 +697,9 @@ fn build_full_site_html(pages: &[DocPage]) -> Result<String, Box<dyn Error>> {
         sidebar_items.push_str("      </li>\n");
     }
 
-    let sidebar_html = format!(
+    format!(
         r##"    <nav>
-      <input type="text" id="nav-filter" placeholder="Filter headings..." />
+{search}      <input type="text" id="nav-filter" placeholder="Filter headings..." />
       <ul id="nav-list">
 {items}      </ul>
     </nav>
@@ -388,33 +751,83 @@ fn build_full_site_html(pages: &[DocPage]) -> Result<String, Box<dyn Error>> {
         }});
       }})();
     </script>"##,
+        search = search,
         items = sidebar_items
-    );
+    )
+}
 
-    let html = format!(
+fn page_layout(title: &str, sidebar: &str, main: &str) -> String {
+    format!(
         r##"<!DOCTYPE html>
 <html lang="en">
   <head>
     <meta charset="UTF-8" />
     <meta name="viewport" content="width=device-width, initial-scale=1.0" />
-    <title>Fip Language Documentation</title>
+    <title>{title}</title>
     <link rel="stylesheet" href="style.css" />
   </head>
   <body id="top">
 {sidebar}
     <main>
-      {sections}
+      {main}
     </main>
   </body>
 </html>
 "##,
-        sidebar = sidebar_html,
-        sections = sections_html,
-    );
-
-    Ok(html)
+        title = html_escape(title),
+        sidebar = sidebar,
+        main = main,
+    )
 }
 
+/// Full-text search over `search-index.json`, only emitted in multi-page
+/// mode. Every whitespace-separated term must appear in a section's heading
+/// or text for it to match.
+const SEARCH_HTML: &str = r##"      <div id="site-search">
+        <input type="search" id="search-input" placeholder="Search docs..." />
+        <ul id="search-results"></ul>
+      </div>
+      <script>
+        (function() {
+          const input = document.getElementById('search-input');
+          const results = document.getElementById('search-results');
+          let index = null;
+
+          input.addEventListener('input', function() {
+            const terms = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+            if (terms.length === 0) {
+              results.innerHTML = '';
+              return;
+            }
+            const render = function() {
+              results.innerHTML = '';
+              index
+                .filter(function(entry) {
+                  const haystack = (entry.title + ' ' + entry.text).toLowerCase();
+                  return terms.every(function(term) { return haystack.includes(term); });
+                })
+                .slice(0, 20)
+                .forEach(function(entry) {
+                  const item = document.createElement('li');
+                  const link = document.createElement('a');
+                  link.href = entry.url;
+                  link.textContent = entry.page + ' › ' + entry.title;
+                  item.appendChild(link);
+                  results.appendChild(item);
+                });
+            };
+            if (index) {
+              render();
+            } else {
+              fetch('search-index.json')
+                .then(function(response) { return response.json(); })
+                .then(function(data) { index = data; render(); });
+            }
+          });
+        })();
+      </script>
+"##;
+
 fn page_order(
     a: &DocPage,
     b: &DocPage,
@@ -3,5 +3,5 @@ set -euo pipefail
 
 ROOT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")/.." && pwd)"
 
-cargo run --manifest-path "$ROOT_DIR/docs/tools/build-docs/Cargo.toml" --quiet "$@"
+cargo run --manifest-path "$ROOT_DIR/docs/tools/build-docs/Cargo.toml" --quiet -- "$@"
 
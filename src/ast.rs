@@ -1,41 +1,92 @@
-#[derive(Debug, Clone)]
+use std::{fmt, rc::Rc};
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Program {
     pub statements: Vec<Statement>,
+    /// The `//!` lines at the top of the file, describing the module.
+    pub doc: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Pattern {
     Identifier(String),
     List(Vec<Pattern>),
     Object(Vec<ObjectPatternField>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ObjectPatternField {
     Shorthand(String),                        // { name } - shorthand for { name: name }
     Field { name: String, pattern: Pattern }, // { name: pattern } - nested destructuring
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
-    Assignment { pattern: Pattern, expr: Expression },
+    Assignment {
+        pattern: Pattern,
+        expr: Expression,
+        /// The `///` lines directly above the binding.
+        doc: Option<String>,
+    },
     Function(Function),
     Expression(Expression),
     Use(UseStatement),
+    /// `use ... from when(condition, "path", "fallback")` picks the module at
+    /// runtime. Without a fallback nothing is imported when the condition is
+    /// false.
+    ConditionalUse {
+        condition: Expression,
+        then: UseStatement,
+        otherwise: Option<UseStatement>,
+    },
     Export(ExportStatement),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Function {
     pub name: String,
     pub params: Vec<String>,
-    pub body: Expression,
+    pub signature: Signature,
+    /// Shared with every function value made from this definition.
+    pub body: Rc<Expression>,
     pub impure: bool,
+    /// The `///` lines directly above the function.
+    pub doc: Option<String>,
+}
+
+/// Optional type annotations on a function: `(x: Number): String { ... }`.
+/// Only `fip check` reads them; the interpreter ignores them.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Signature {
+    /// One entry per parameter, `None` where the parameter is unannotated.
+    pub params: Vec<Option<TypeExpr>>,
+    pub result: Option<TypeExpr>,
+}
+
+/// A type as written in an annotation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeExpr {
+    /// `Number`, `String`, `Boolean`, `Null`, or `Any`.
+    Named(String),
+    /// A lowercase name such as `a`, standing for the same type at every use
+    /// within one signature.
+    Variable(String),
+    /// `[Number]`
+    List(Box<TypeExpr>),
+    /// `{ name: String, age: Number }`
+    Object(Vec<(String, TypeExpr)>),
+    /// `(Number, a) -> a`
+    Function(Vec<TypeExpr>, Box<TypeExpr>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
-    Number(i64),
+    /// An integer literal. `spelling` keeps the source text of hexadecimal,
+    /// binary, and `_`-separated literals so the formatter can reproduce it.
+    Number {
+        value: i64,
+        spelling: Option<String>,
+    },
     String(StringTemplate),
     Boolean(bool),
     Null,
@@ -43,7 +94,9 @@ pub enum Expression {
     Block(Vec<Expression>),
     Lambda {
         params: Vec<String>,
-        body: Box<Expression>,
+        signature: Signature,
+        /// Shared with every closure made from this lambda.
+        body: Rc<Expression>,
         impure: bool,
     },
     Object(Vec<ObjectField>),
@@ -55,22 +108,52 @@ pub enum Expression {
     PropertyAccess {
         object: Box<Expression>,
         property: String,
+        /// `object?.property`: a null object skips the rest of the chain.
+        optional: bool,
     },
     Binary {
         left: Box<Expression>,
         op: BinaryOperator,
         right: Box<Expression>,
     },
+    /// `!expr`, boolean negation.
+    Not(Box<Expression>),
     Spread(Box<Expression>),
+    /// A parameter or captured binding located ahead of time by
+    /// `crate::resolve`. The parser never produces it.
+    Local(Slot),
+    /// A `Lambda` after resolution, with where to copy each binding its body
+    /// reads from enclosing functions. The parser never produces it.
+    Closure {
+        lambda: Box<Expression>,
+        captures: Vec<Slot>,
+    },
 }
 
-#[derive(Debug, Clone)]
+/// Where a resolved variable lives, counted from the scope of the function
+/// whose body mentions it: `depth` 0 is the function's parameters and 1 the
+/// bindings its closure captured. `index` is the position in that scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slot {
+    pub name: String,
+    pub depth: usize,
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ObjectField {
-    Field { name: String, value: Expression },
+    Field {
+        name: String,
+        value: Expression,
+    },
     Spread(Expression),
+    /// The record before `with` in `record with { ... }`. It is copied like
+    /// a leading spread and only kept apart so the formatter can print the
+    /// `with` form again.
+    Base(Expression),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOperator {
     Add,
     Sub,
@@ -84,20 +167,23 @@ pub enum BinaryOperator {
     GreaterThanEq,
     And,
     Or,
+    Coalesce,
+    /// `value |> fn`, calls the right side with the left.
+    PipeForward,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct StringTemplate {
     pub segments: Vec<StringSegment>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum StringSegment {
     Literal(String),
     Expr(Expression),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum UseStatement {
     Single {
         name: String,
@@ -111,9 +197,96 @@ pub enum UseStatement {
         names: Vec<String>,
         module_path: String,
     },
+    /// `use * from "..."` brings every export of the module into scope.
+    Glob {
+        module_path: String,
+    },
+}
+
+impl Pattern {
+    /// The names the pattern binds, in source order.
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Pattern::Identifier(name) => vec![name],
+            Pattern::List(patterns) => patterns.iter().flat_map(Pattern::names).collect(),
+            Pattern::Object(fields) => fields
+                .iter()
+                .flat_map(|field| match field {
+                    ObjectPatternField::Shorthand(name) => vec![name.as_str()],
+                    ObjectPatternField::Field { pattern, .. } => pattern.names(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl BinaryOperator {
+    /// Binding strength used by the parser; higher binds tighter. All binary
+    /// operators are left-associative.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::PipeForward => 0,
+            BinaryOperator::Coalesce => 1,
+            BinaryOperator::Or => 2,
+            BinaryOperator::And => 3,
+            BinaryOperator::Eq
+            | BinaryOperator::NotEq
+            | BinaryOperator::LessThan
+            | BinaryOperator::LessThanEq
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanEq => 4,
+            BinaryOperator::Add | BinaryOperator::Sub => 5,
+            BinaryOperator::Mul | BinaryOperator::Div => 6,
+        }
+    }
+}
+
+impl TypeExpr {
+    /// Names accepted by `TypeExpr::Named`.
+    pub const NAMES: &'static [&'static str] = &[
+        "Number", "String", "Bytes", "Set", "Map", "Boolean", "Null", "Any",
+    ];
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeExpr::Named(name) | TypeExpr::Variable(name) => write!(f, "{}", name),
+            TypeExpr::List(element) => write!(f, "[{}]", element),
+            TypeExpr::Object(fields) if fields.is_empty() => write!(f, "{{}}"),
+            TypeExpr::Object(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, ty)| format!("{}: {}", name, ty))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            TypeExpr::Function(params, result) => {
+                let params: Vec<String> = params.iter().map(ToString::to_string).collect();
+                write!(f, "({}) -> {}", params.join(", "), result)
+            }
+        }
+    }
+}
+
+impl Signature {
+    pub fn is_empty(&self) -> bool {
+        self.result.is_none() && self.params.iter().all(Option::is_none)
+    }
+}
+
+impl UseStatement {
+    pub fn module_path(&self) -> &str {
+        match self {
+            UseStatement::Single { module_path, .. }
+            | UseStatement::Namespace { module_path, .. }
+            | UseStatement::Selective { module_path, .. }
+            | UseStatement::Glob { module_path } => module_path,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ExportStatement {
     pub name: String,
 }
@@ -0,0 +1,139 @@
+//! Queues behind `channel!`, `send!`, `receive!`, and `select!`. Tasks
+//! started by `spawn!` run in interpreters of their own, so an interpreter
+//! shares its [`Channels`] with every worker it starts and a channel value
+//! only carries its number in that table, which copies to another thread
+//! like a number. Messages are [`Package`]s, copied in by the sender and
+//! rebuilt by the receiver. Channels are never closed; the table is freed
+//! with the last interpreter holding it.
+
+use crate::portable::Package;
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// The channels of one interpreter and the workers it started.
+#[derive(Default)]
+pub struct Channels {
+    /// The waiting messages of every channel, by channel number.
+    queues: Mutex<Vec<VecDeque<Package>>>,
+    /// Signalled whenever a message is sent to any channel.
+    arrived: Condvar,
+}
+
+impl Channels {
+    fn queues(&self) -> MutexGuard<'_, Vec<VecDeque<Package>>> {
+        // A thread that panicked while holding the lock left the queues whole
+        self.queues
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Makes a new, empty channel and returns its number.
+    pub fn open(&self) -> usize {
+        let mut queues = self.queues();
+        queues.push(VecDeque::new());
+        queues.len() - 1
+    }
+
+    /// Adds `message` to the end of `channel`. Never waits.
+    pub fn send(&self, channel: usize, message: Package) -> Result<(), String> {
+        let mut queues = self.queues();
+        let queue = queues
+            .get_mut(channel)
+            .ok_or_else(|| format!("No channel numbered {}", channel))?;
+        queue.push_back(message);
+        self.arrived.notify_all();
+        Ok(())
+    }
+
+    /// Takes the oldest message from the first of `channels` that has one,
+    /// waiting until one arrives or `timeout` passes. Returns the position of
+    /// that channel in `channels` with the message, or `None` on timeout.
+    pub fn receive(
+        &self,
+        channels: &[usize],
+        timeout: Option<Duration>,
+    ) -> Result<Option<(usize, Package)>, String> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut queues = self.queues();
+        if let Some(missing) = channels.iter().find(|&&channel| channel >= queues.len()) {
+            return Err(format!("No channel numbered {}", missing));
+        }
+        loop {
+            for (position, &channel) in channels.iter().enumerate() {
+                if let Some(message) = queues[channel].pop_front() {
+                    return Ok(Some((position, message)));
+                }
+            }
+            queues = match deadline {
+                None => self
+                    .arrived
+                    .wait(queues)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Ok(None);
+                    }
+                    self.arrived
+                        .wait_timeout(queues, left)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portable::Portable;
+    use std::sync::Arc;
+
+    fn message(n: i64) -> Package {
+        Package {
+            functions: Vec::new(),
+            values: vec![Portable::Number(n)],
+        }
+    }
+
+    #[test]
+    fn messages_arrive_in_order_from_other_threads() -> Result<(), String> {
+        let channels = Arc::new(Channels::default());
+        let first = channels.open();
+        let second = channels.open();
+        assert_eq!(
+            channels.receive(&[first, second], Some(Duration::from_millis(1)))?,
+            None
+        );
+
+        let producer = {
+            let channels = Arc::clone(&channels);
+            std::thread::spawn(move || {
+                for n in 1..=3 {
+                    channels.send(second, message(n))?;
+                }
+                Ok::<(), String>(())
+            })
+        };
+        for n in 1..=3 {
+            assert_eq!(
+                channels.receive(&[first, second], None)?,
+                Some((1, message(n)))
+            );
+        }
+        producer.join().expect("producer finished")?;
+
+        channels.send(first, message(4))?;
+        assert_eq!(
+            channels.receive(&[second, first], None)?,
+            Some((1, message(4)))
+        );
+        assert!(channels.receive(&[usize::MAX], None).is_err());
+        assert!(Channels::default().send(first, message(5)).is_err());
+        Ok(())
+    }
+}
@@ -1,16 +1,32 @@
 use std::{
+    cell::RefCell,
+    collections::HashSet,
     env, fs,
+    io::{self, Read, Write},
+    ops::RangeInclusive,
     path::{Path, PathBuf},
+    rc::Rc,
 };
 
-use fippli_lang::ast::{
-    BinaryOperator, Expression, Function, ObjectField, ObjectPatternField, Pattern, Program,
-    Statement, StringSegment, UseStatement,
-};
-use fippli_lang::error::LangError;
-use fippli_lang::interpreter::Interpreter;
+use fippli_lang::ast::{Statement, UseStatement};
+use fippli_lang::docgen;
+use fippli_lang::error::{byte_offset_to_line, LangError};
+use fippli_lang::formatter::{FormatOptions, Formatter};
+use fippli_lang::heap;
+use fippli_lang::interpreter::{Capability, Interpreter, OutputBuffer, Value};
+use fippli_lang::json;
 use fippli_lang::lexer::Lexer;
+use fippli_lang::loader::{check_module_tree, FileDiagnostics, FileSystemLoader};
+use fippli_lang::logging::{LogFormat, LogLevel};
+use fippli_lang::module_graph::ModuleGraph;
 use fippli_lang::parser::Parser as FipParser;
+use fippli_lang::pretty::pretty;
+use fippli_lang::profile::Profiler;
+use fippli_lang::refactor;
+use fippli_lang::rename;
+use fippli_lang::report::{Reporter, Severity};
+use fippli_lang::trace::CallTracer;
+use fippli_lang::types;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -31,42 +47,252 @@ fn main() {
             Ok(())
         }
         "run" => {
-            if args.len() < 3 {
-                eprintln!("Error: 'run' command requires a file argument");
-                eprintln!("Usage: fip run <file.fip>");
+            let valued = [
+                "--profile-folded",
+                "--seed",
+                "--allow",
+                "--log-level",
+                "--log-format",
+            ];
+            let Some(file) = positional_arg(&args, &valued) else {
+                report_error("'run' command requires a file argument");
+                eprintln!(
+                    "Usage: fip run <file.fip | -> [--enable-experimental] [--trace] [--profile] [--profile-folded <file>] [--heap-stats] [--deterministic] [--seed <n>] [--update-snapshots] [--allow <capabilities>] [--deny-all] [--log-level <level>] [--log-format text|json]"
+                );
                 std::process::exit(1);
-            }
-            run_command(&args[2])
+            };
+            let profile_folded = match profile_folded_arg(&args) {
+                Ok(path) => path,
+                Err(message) => {
+                    report_error(&message);
+                    std::process::exit(1);
+                }
+            };
+            let seed = match seed_arg(&args) {
+                Ok(seed) => seed,
+                Err(message) => {
+                    report_error(&message);
+                    std::process::exit(1);
+                }
+            };
+            let capabilities = match capabilities_arg(&args) {
+                Ok(capabilities) => capabilities,
+                Err(message) => {
+                    report_error(&message);
+                    std::process::exit(1);
+                }
+            };
+            let (log_level, log_format) = match log_args(&args) {
+                Ok(log) => log,
+                Err(message) => {
+                    report_error(&message);
+                    std::process::exit(1);
+                }
+            };
+            let options = RunOptions {
+                enable_experimental: args.contains(&"--enable-experimental".to_string()),
+                trace: args.contains(&"--trace".to_string()),
+                profile: args.contains(&"--profile".to_string()),
+                profile_folded,
+                heap_stats: args.contains(&"--heap-stats".to_string()),
+                seed,
+                update_snapshots: args.contains(&"--update-snapshots".to_string()),
+                capabilities,
+                log_level,
+                log_format,
+            };
+            run_command(file, &options)
         }
         "format" => {
             if args.len() < 3 {
-                eprintln!("Error: 'format' command requires a file argument");
-                eprintln!("Usage: fip format <file.fip> [--write]");
+                report_error("'format' command requires a file argument");
+                eprintln!("Usage: fip format <file.fip> [--write] [--max-width <n>] [--range <start>:<end>]");
                 std::process::exit(1);
             }
             let write = args.contains(&"--write".to_string()) || args.contains(&"-w".to_string());
-            format_command(&args[2], write)
+            let max_width = match max_width_arg(&args) {
+                Ok(max_width) => max_width,
+                Err(message) => {
+                    report_error(&message);
+                    std::process::exit(1);
+                }
+            };
+            let range = match range_arg(&args) {
+                Ok(range) => range,
+                Err(message) => {
+                    report_error(&message);
+                    std::process::exit(1);
+                }
+            };
+            format_command(&args[2], write, max_width, range)
+        }
+        "check" => {
+            if args.len() < 3 {
+                report_error("'check' command requires a file argument");
+                eprintln!("Usage: fip check <file.fip>");
+                std::process::exit(1);
+            }
+            check_command(&args[2])
+        }
+        "graph" => {
+            if args.len() < 3 {
+                report_error("'graph' command requires a file argument");
+                eprintln!("Usage: fip graph <file.fip> [--dot]");
+                std::process::exit(1);
+            }
+            graph_command(&args[2], args.contains(&"--dot".to_string()))
+        }
+        "rename" => {
+            if args.len() < 5 {
+                report_error("'rename' command requires a file, the old name, and the new name");
+                eprintln!("Usage: fip rename <file.fip> <old-name> <new-name> [--across-modules]");
+                std::process::exit(1);
+            }
+            rename_command(
+                &args[2],
+                &args[3],
+                &args[4],
+                args.contains(&"--across-modules".to_string()),
+            )
+        }
+        "extract" => {
+            if args.len() < 5 {
+                report_error("'extract' command requires a file, a selection, and a name");
+                eprintln!("Usage: fip extract <file.fip> <line>:<column>-<line>:<column> <name>");
+                std::process::exit(1);
+            }
+            extract_command(&args[2], &args[3], &args[4])
+        }
+        "inline" => {
+            if args.len() < 4 {
+                report_error("'inline' command requires a file and a binding name");
+                eprintln!("Usage: fip inline <file.fip> <name>");
+                std::process::exit(1);
+            }
+            inline_command(&args[2], &args[3])
+        }
+        "eval" => {
+            if args.len() < 3 {
+                report_error("'eval' command requires an expression");
+                eprintln!("Usage: fip eval '<expression or statements>'");
+                std::process::exit(1);
+            }
+            eval_command(&args[2])
+        }
+        "filter" => {
+            if args.len() < 3 {
+                report_error("'filter' command requires an expression");
+                eprintln!("Usage: fip filter '<expression>' < input.json");
+                std::process::exit(1);
+            }
+            filter_command(&args[2])
+        }
+        "doctest" => {
+            let path = args.get(2).map(String::as_str).unwrap_or("syntax");
+            doctest_command(path)
+        }
+        "run-md" => {
+            if args.len() < 3 {
+                report_error("'run-md' command requires a markdown file");
+                eprintln!("Usage: fip run-md <notes.md> [--out <file>]");
+                std::process::exit(1);
+            }
+            let out = match args.iter().position(|arg| arg == "--out") {
+                None => None,
+                Some(index) => match args.get(index + 1) {
+                    Some(out) => Some(PathBuf::from(out)),
+                    None => {
+                        report_error("--out requires a file");
+                        std::process::exit(1);
+                    }
+                },
+            };
+            run_markdown_command(Path::new(&args[2]), out.as_deref())
+        }
+        "doc" => {
+            if args.len() < 3 {
+                report_error("'doc' command requires a directory argument");
+                eprintln!("Usage: fip doc <dir> [--out <dir>]");
+                std::process::exit(1);
+            }
+            let out = match args.iter().position(|arg| arg == "--out") {
+                None => Path::new(&args[2]).join("doc"),
+                Some(index) => match args.get(index + 1) {
+                    Some(out) => PathBuf::from(out),
+                    None => {
+                        report_error("--out requires a directory");
+                        std::process::exit(1);
+                    }
+                },
+            };
+            doc_command(Path::new(&args[2]), &out)
         }
         _ => {
-            eprintln!("Error: Unknown command '{}'", command);
+            report_error(&format!("Unknown command '{}'", command));
             print_usage();
             std::process::exit(1);
         }
     };
 
     if let Err(e) = result {
-        eprintln!("Error: {}", e);
+        report_error(&e.to_string());
         std::process::exit(1);
     }
 }
 
+fn report_error(message: &str) {
+    eprintln!(
+        "{}",
+        Reporter::stderr().diagnostic(Severity::Error, None, message)
+    );
+}
+
 fn print_usage() {
     eprintln!("FIP (Functional Intuitive Programming) language tool");
     eprintln!();
     eprintln!("Usage:");
     eprintln!("  fip run <file.fip>        Run a FIP program");
+    eprintln!("  fip run -                 Run a FIP program read from stdin");
+    eprintln!("      --enable-experimental   Allow calls to experimental builtins");
+    eprintln!("      --trace                 Log every function call and result to stderr");
+    eprintln!("      --profile               Print time spent per function to stderr");
+    eprintln!("      --profile-folded <file> Write folded stacks for flamegraph tools");
+    eprintln!("      --heap-stats            Print value counts, sizes, and clones to stderr");
+    eprintln!("      --deterministic         Seed uuid!, freeze now!, and refuse network builtins");
+    eprintln!("      --seed <n>              Seed for --deterministic (default 0; implies it)");
+    eprintln!("      --update-snapshots      Overwrite .snap files that expect-snapshot! rejects");
+    eprintln!(
+        "      --allow <list>          Install only these builtin groups: fs, net, clock, random"
+    );
+    eprintln!(
+        "      --deny-all              Install none of them; combine with --allow to add some"
+    );
+    eprintln!("      --log-level <level>     Drop log-*! lines below debug, info, warn, or error");
+    eprintln!("      --log-format json       Write log-*! lines as JSON objects instead of text");
+    eprintln!("  fip eval '<code>'         Run a snippet and print its last expression");
+    eprintln!(
+        "  fip filter '<code>'       Run code on JSON from stdin (bound to input), print JSON"
+    );
     eprintln!("  fip format <file.fip>     Format a FIP source file (prints to stdout)");
     eprintln!("  fip format <file.fip> -w  Format a FIP source file (writes to file)");
+    eprintln!("      --max-width <n>         Wrap at n columns (default: 80 or .fipfmt.toml)");
+    eprintln!("      --range <start>:<end>   Format only the statements on those lines, from 1");
+    eprintln!("  fip check <file.fip>      Type-check a FIP program without running it");
+    eprintln!(
+        "  fip graph <file.fip>      Print the modules it imports, their load order, and cycles"
+    );
+    eprintln!("      --dot                   Print the graph in Graphviz DOT instead");
+    eprintln!("  fip rename <file.fip> <old> <new>  Rename a top-level binding and its uses");
+    eprintln!(
+        "      --across-modules        Also update files under this directory that import it"
+    );
+    eprintln!("  fip extract <file.fip> <from>-<to> <name>  Move an expression into a function");
+    eprintln!("  fip inline <file.fip> <name>  Replace a binding's uses with its value");
+    eprintln!("  fip doctest [path]        Run ```fip examples in markdown docs (default: syntax)");
+    eprintln!("  fip run-md <notes.md>     Run its ```fip blocks in order, print it with results");
+    eprintln!("      --out <file>            Write the result there instead");
+    eprintln!("  fip doc <dir>             Write markdown API docs from doc comments");
+    eprintln!("      --out <dir>             Where to write them (default: <dir>/doc)");
     eprintln!("  fip help                  Show this help message");
     eprintln!("  fip version               Show version information");
 }
@@ -75,384 +301,866 @@ fn print_version() {
     println!("fip {}", env!("CARGO_PKG_VERSION"));
 }
 
-fn run_command(file: &str) -> Result<(), LangError> {
-    let source_path = Path::new(file);
-    if !source_path.exists() {
+struct RunOptions {
+    enable_experimental: bool,
+    trace: bool,
+    profile: bool,
+    profile_folded: Option<PathBuf>,
+    heap_stats: bool,
+    /// Set by `--deterministic` or `--seed`.
+    seed: Option<u64>,
+    update_snapshots: bool,
+    /// Set by `--allow` or `--deny-all`; `None` allows everything.
+    capabilities: Option<Vec<Capability>>,
+    log_level: LogLevel,
+    log_format: LogFormat,
+}
+
+fn run_command(file: &str, options: &RunOptions) -> Result<(), LangError> {
+    let (source_path, source, entry_point_dir) = if file == "-" {
+        // A program piped in has no file of its own: errors name it
+        // `<stdin>` and modules resolve from the current directory
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        (PathBuf::from("<stdin>"), source, env::current_dir()?)
+    } else {
+        let source_path = Path::new(file);
+        if !source_path.exists() {
+            return Err(LangError::Runtime(
+                format!("Source file '{}' not found", file),
+                None,
+            ));
+        }
+        let source = fs::read_to_string(source_path)?;
+
+        // Set entry point directory for module resolution
+        let entry_point_dir = source_path
+            .parent()
+            .ok_or_else(|| {
+                LangError::Runtime("Cannot determine entry point directory".to_string(), None)
+            })?
+            .to_path_buf();
+        (source_path.to_path_buf(), source, entry_point_dir)
+    };
+    let source_path = source_path.as_path();
+
+    // Parse the whole module tree up front so every syntax error is reported
+    // in one run instead of stopping at the first module that fails.
+    let diagnostics = check_module_tree(
+        &FileSystemLoader,
+        Some(&entry_point_dir),
+        source_path,
+        &source,
+    );
+    if !diagnostics.is_empty() {
+        let error_count: usize = diagnostics.iter().map(|file| file.errors.len()).sum();
+        print_diagnostics(&diagnostics);
         return Err(LangError::Runtime(
-            format!("Source file '{}' not found", file),
+            format!(
+                "Aborting due to {} error(s) in {} file(s)",
+                error_count,
+                diagnostics.len()
+            ),
             None,
         ));
     }
 
-    let source = fs::read_to_string(source_path)?;
     let tokens =
         Lexer::with_source_and_file(&source, source.clone(), source_path.to_path_buf()).lex()?;
     let mut parser =
         FipParser::with_source_and_file(tokens, source.clone(), source_path.to_path_buf());
     let program = parser.parse_program()?;
 
-    // Set entry point directory for module resolution
-    let entry_point_dir = source_path
-        .parent()
-        .ok_or_else(|| {
-            LangError::Runtime("Cannot determine entry point directory".to_string(), None)
-        })?
-        .to_path_buf();
-
     let mut interpreter = Interpreter::with_entry_point_dir(entry_point_dir);
-    interpreter.eval_program(&program)?;
-    Ok(())
-}
-
-fn format_command(file: &str, write: bool) -> Result<(), LangError> {
-    let source = fs::read_to_string(file)
-        .map_err(|e| LangError::Runtime(format!("Failed to read file: {}", e), None))?;
-
-    let tokens = Lexer::with_source_and_file(&source, source.clone(), PathBuf::from(file))
-        .lex()
-        .map_err(|e| LangError::Runtime(format!("Parse error: {}", e), None))?;
-
-    let mut parser = FipParser::with_source_and_file(tokens, source.clone(), PathBuf::from(file));
-    let program = parser
-        .parse_program()
-        .map_err(|e| LangError::Runtime(format!("Parse error: {}", e), None))?;
-
-    let mut formatter = Formatter::new();
-    let formatted = formatter.format_program(&program);
-
-    if write {
-        fs::write(file, formatted)
-            .map_err(|e| LangError::Runtime(format!("Failed to write file: {}", e), None))?;
-        println!("Formatted: {}", file);
+    interpreter.set_enable_experimental(options.enable_experimental);
+    // Always there, so `set-trace!` can switch tracing on without --trace
+    interpreter.add_hooks(Box::new(CallTracer::new(
+        Box::new(io::stderr()),
+        options.trace,
+    )));
+    interpreter.set_deterministic(options.seed);
+    interpreter.set_update_snapshots(options.update_snapshots);
+    interpreter.set_log_level(options.log_level);
+    interpreter.set_log_format(options.log_format);
+    if let Some(capabilities) = &options.capabilities {
+        interpreter.set_capabilities(capabilities);
+    }
+    let profiler = (options.profile || options.profile_folded.is_some())
+        .then(|| Rc::new(RefCell::new(Profiler::new())));
+    if let Some(profiler) = &profiler {
+        interpreter.add_hooks(Box::new(Rc::clone(profiler)));
+    }
+    let (outcome, clones) = if options.heap_stats {
+        heap::count_clones(|| interpreter.eval_owned_program(program).map(|_| ()))
     } else {
-        print!("{}", formatted);
+        (
+            interpreter.eval_owned_program(program).map(|_| ()),
+            Default::default(),
+        )
+    };
+    // Report the profile even when the run fails; the hot spot may be why.
+    if let Some(profiler) = profiler {
+        let profile = profiler.borrow();
+        if options.profile {
+            eprint!("{}", profile.report());
+        }
+        if let Some(path) = &options.profile_folded {
+            fs::write(path, profile.folded_stacks())?;
+        }
     }
+    if options.heap_stats {
+        let mut stats = interpreter.heap_stats();
+        stats.clones = clones;
+        eprint!("{}", stats.report());
+    }
+    outcome
+}
 
+/// Runs a snippet from the command line and prints its last expression
+/// with the pretty printer. Modules resolve from the current directory.
+fn eval_command(source: &str) -> Result<(), LangError> {
+    let path = PathBuf::from("<eval>");
+    let tokens = Lexer::with_source_and_file(source, source.to_string(), path.clone()).lex()?;
+    let program =
+        FipParser::with_source_and_file(tokens, source.to_string(), path).parse_program()?;
+    let mut interpreter = Interpreter::with_entry_point_dir(env::current_dir()?);
+    // A snippet of bindings alone has no last expression to print
+    if let Some(value) = interpreter.eval_owned_program(program)? {
+        println!("{}", pretty(&value));
+    }
     Ok(())
 }
 
-// Formatter implementation (copied from tools/format)
-struct Formatter {
-    indent_level: usize,
-    indent_size: usize,
+/// Reads JSON from stdin, binds it to `input`, runs `source`, and prints
+/// its last expression as JSON, like a `jq` filter.
+fn filter_command(source: &str) -> Result<(), LangError> {
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text)?;
+    let input = json::parse(&text)
+        .map_err(|err| LangError::Runtime(format!("Invalid JSON on stdin: {}", err), None))?;
+
+    let path = PathBuf::from("<filter>");
+    let tokens = Lexer::with_source_and_file(source, source.to_string(), path.clone()).lex()?;
+    let program =
+        FipParser::with_source_and_file(tokens, source.to_string(), path).parse_program()?;
+    let mut interpreter = Interpreter::with_entry_point_dir(env::current_dir()?);
+    interpreter.define_global("input", input)?;
+    let output = interpreter
+        .eval_owned_program(program)?
+        .unwrap_or(Value::Null);
+    let encoded = json::encode(&output)
+        .map_err(|err| LangError::Runtime(format!("Cannot encode the result: {}", err), None))?;
+    println!("{}", encoded);
+    Ok(())
 }
 
-impl Formatter {
-    fn new() -> Self {
-        Self {
-            indent_level: 0,
-            indent_size: 2,
-        }
-    }
+fn check_command(file: &str) -> Result<(), LangError> {
+    let source_path = Path::new(file);
+    let source = fs::read_to_string(source_path)?;
+    let tokens =
+        Lexer::with_source_and_file(&source, source.clone(), source_path.to_path_buf()).lex()?;
+    let mut parser =
+        FipParser::with_source_and_file(tokens, source.clone(), source_path.to_path_buf());
+    let program = parser.parse_program()?;
 
-    fn indent(&self) -> String {
-        " ".repeat(self.indent_level * self.indent_size)
+    let report = types::check_program(&program);
+    if report.errors.is_empty() {
+        println!("No type errors found.");
+        return Ok(());
+    }
+    let reporter = Reporter::stderr();
+    for error in &report.errors {
+        let offset = parser
+            .statement_starts()
+            .get(error.statement)
+            .copied()
+            .unwrap_or(0);
+        let location = format!(
+            "{}:{}",
+            source_path.display(),
+            byte_offset_to_line(&source, offset)
+        );
+        eprintln!(
+            "{}",
+            reporter.diagnostic(
+                Severity::Error,
+                Some(&location),
+                &format!("Type error: {}", error.message)
+            )
+        );
     }
+    Err(LangError::Runtime(
+        format!("Found {} type error(s)", report.errors.len()),
+        None,
+    ))
+}
 
-    fn format_program(&mut self, program: &Program) -> String {
-        let mut output = Vec::new();
+/// Prints the modules `file` imports, directly or not, without running
+/// anything, and fails when an import is missing or part of a cycle.
+fn graph_command(file: &str, dot: bool) -> Result<(), LangError> {
+    let source_path = Path::new(file);
+    let source = fs::read_to_string(source_path)?;
+    let entry_point_dir = source_path.parent().unwrap_or(Path::new("."));
+    let graph = ModuleGraph::build(
+        &FileSystemLoader,
+        Some(entry_point_dir),
+        source_path,
+        &source,
+    );
+    let names = |modules: &[usize]| -> Vec<&str> {
+        modules
+            .iter()
+            .map(|&module| graph.modules[module].name.as_str())
+            .collect()
+    };
 
-        for (i, stmt) in program.statements.iter().enumerate() {
-            if i > 0 {
-                output.push(String::new());
+    let cycles = graph.cycles();
+    if dot {
+        print!("{}", graph.dot());
+    } else {
+        print!("{}", graph.tree());
+        println!();
+        println!("Initialization order:");
+        for (position, name) in names(&graph.initialization_order()).iter().enumerate() {
+            println!("  {}. {}", position + 1, name);
+        }
+        if !cycles.is_empty() {
+            println!();
+            println!("Cycles:");
+            for cycle in &cycles {
+                println!("  {}", names(cycle).join(" -> "));
             }
-            output.push(self.format_statement(stmt));
         }
-
-        output.join("\n")
     }
 
-    fn format_statement(&mut self, stmt: &Statement) -> String {
-        match stmt {
-            Statement::Assignment { pattern, expr } => {
-                format!(
-                    "{}: {}",
-                    self.format_pattern(pattern),
-                    self.format_expression(expr)
-                )
-            }
-            Statement::Function(func) => self.format_function(func),
-            Statement::Expression(expr) => self.format_expression(expr),
-            Statement::Use(use_stmt) => self.format_use_statement(use_stmt),
-            Statement::Export(export) => format!("export {}", export.name),
+    let missing = graph.missing();
+    for &module in &missing {
+        if let Some(error) = &graph.modules[module].error {
+            report_error(error);
         }
     }
+    let mut problems = Vec::new();
+    if !cycles.is_empty() {
+        problems.push(format!("{} import cycle(s)", cycles.len()));
+    }
+    if !missing.is_empty() {
+        problems.push(format!("{} missing module(s)", missing.len()));
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(LangError::Runtime(
+        format!("Found {}", problems.join(" and ")),
+        None,
+    ))
+}
 
-    fn format_pattern(&mut self, pattern: &Pattern) -> String {
-        match pattern {
-            Pattern::Identifier(name) => name.clone(),
-            Pattern::List(patterns) => {
-                let formatted: Vec<String> =
-                    patterns.iter().map(|p| self.format_pattern(p)).collect();
-                format!("[{}]", formatted.join(", "))
+/// Renames a top-level binding of `file`. An exported binding is only
+/// renamed with `across_modules`, which updates the files under the current
+/// directory that import it too; their
+/// module paths are resolved from their own directory and from the current
+/// one, either of which may be the entry point's. Every edit is worked out
+/// before anything is written, so a rename that fails anywhere changes
+/// nothing.
+fn rename_command(file: &str, old: &str, new: &str, across_modules: bool) -> Result<(), LangError> {
+    let failed = |path: &Path, message: String| {
+        LangError::Runtime(format!("{}: {}", path.display(), message), None)
+    };
+    let source_path = Path::new(file);
+    let source = fs::read_to_string(source_path)?;
+    let renamed =
+        rename::rename_binding(&source, old, new).map_err(|err| failed(source_path, err))?;
+    if renamed.exported && !across_modules {
+        return Err(failed(
+            source_path,
+            format!(
+                "'{}' is exported; pass --across-modules to update the files that import it",
+                old
+            ),
+        ));
+    }
+    let mut count = renamed.edits.len();
+    let mut changes = vec![(
+        source_path.to_path_buf(),
+        rename::apply(&source, &renamed.edits),
+    )];
+
+    if renamed.exported {
+        let target = fs::canonicalize(source_path)?;
+        let root = env::current_dir()?;
+        let mut files = Vec::new();
+        collect_fip_files(&root, &mut files)?;
+        for path in files {
+            if fs::canonicalize(&path)? == target {
+                continue;
             }
-            Pattern::Object(fields) => {
-                let formatted: Vec<String> = fields
-                    .iter()
-                    .map(|f| match f {
-                        ObjectPatternField::Shorthand(name) => name.clone(),
-                        ObjectPatternField::Field { name, pattern } => {
-                            format!("{}: {}", name, self.format_pattern(pattern))
-                        }
+            let imports_target = |module_path: &str| {
+                [path.parent(), Some(root.as_path())]
+                    .into_iter()
+                    .flatten()
+                    .any(|dir| {
+                        let mut module = dir.join(module_path);
+                        module.set_extension("fip");
+                        fs::canonicalize(module).is_ok_and(|module| module == target)
                     })
-                    .collect();
-                format!("{{ {} }}", formatted.join(", "))
+            };
+            let source = fs::read_to_string(&path)?;
+            let edits = rename::rename_import(&source, old, new, &imports_target)
+                .map_err(|err| failed(&path, err))?;
+            if !edits.is_empty() {
+                count += edits.len();
+                changes.push((path, rename::apply(&source, &edits)));
             }
         }
     }
 
-    fn format_function(&mut self, func: &Function) -> String {
-        let notation = if func.impure {
-            "!"
-        } else if func.name.ends_with('?') {
-            "?"
-        } else {
-            ""
-        };
+    for (path, text) in &changes {
+        write_atomically(path, text)?;
+    }
+    println!(
+        "Renamed '{}' to '{}' in {} place(s) across {} file(s)",
+        old,
+        new,
+        count,
+        changes.len()
+    );
+    Ok(())
+}
 
-        let name = if func.impure {
-            func.name.strip_suffix('!').unwrap_or(&func.name)
-        } else if func.name.ends_with('?') {
-            func.name.strip_suffix('?').unwrap_or(&func.name)
-        } else {
-            &func.name
-        };
+/// Moves the expression selected in `file` into a new function.
+fn extract_command(file: &str, selection: &str, name: &str) -> Result<(), LangError> {
+    let source_path = Path::new(file);
+    let source = fs::read_to_string(source_path)?;
+    let failed = |message: String| {
+        LangError::Runtime(format!("{}: {}", source_path.display(), message), None)
+    };
+    let range = selection_arg(&source, selection).map_err(failed)?;
+    let edits = refactor::extract_function(&source, range, name).map_err(failed)?;
+    write_atomically(source_path, &rename::apply(&source, &edits))?;
+    println!("Extracted '{}'", name);
+    Ok(())
+}
 
-        let params_str = func.params.join(", ");
-        let old_indent = self.indent_level;
-        self.indent_level += 1;
-        let body_str = self.format_expression_with_indent(&func.body);
-        self.indent_level = old_indent;
+fn inline_command(file: &str, name: &str) -> Result<(), LangError> {
+    let source_path = Path::new(file);
+    let source = fs::read_to_string(source_path)?;
+    let edits = refactor::inline_binding(&source, name).map_err(|message| {
+        LangError::Runtime(format!("{}: {}", source_path.display(), message), None)
+    })?;
+    write_atomically(source_path, &rename::apply(&source, &edits))?;
+    println!("Inlined '{}' in {} place(s)", name, edits.len() - 1);
+    Ok(())
+}
 
+/// Reads `<line>:<column>-<line>:<column>` as a byte range of `source`.
+/// Lines and columns count from 1, columns in characters, and the end is
+/// the position just after the selection, as editors show it.
+fn selection_arg(source: &str, selection: &str) -> Result<std::ops::Range<usize>, String> {
+    let position = |text: &str| -> Option<usize> {
+        let (line, column) = text.split_once(':')?;
+        let (line, column): (usize, usize) = (line.parse().ok()?, column.parse().ok()?);
+        let line_start = if line == 1 {
+            0
+        } else {
+            source.match_indices('\n').nth(line.checked_sub(2)?)?.0 + 1
+        };
+        let line_text = source[line_start..].split('\n').next()?;
+        let offset = line_text
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([line_text.len()])
+            .nth(column.checked_sub(1)?)?;
+        Some(line_start + offset)
+    };
+    let invalid = || {
         format!(
-            "{}{}: ({}) {{\n{}\n}}",
-            name, notation, params_str, body_str
+            "Invalid selection '{}'; expected <line>:<column>-<line>:<column>",
+            selection
         )
+    };
+    let (start, end) = selection.split_once('-').ok_or_else(invalid)?;
+    match (position(start), position(end)) {
+        (Some(start), Some(end)) if start <= end => Ok(start..end),
+        _ => Err(invalid()),
     }
+}
 
-    fn format_use_statement(&mut self, use_stmt: &UseStatement) -> String {
-        match use_stmt {
-            UseStatement::Single { name, module_path } => {
-                format!("use {} from \"{}\"", name, module_path)
-            }
-            UseStatement::Namespace { alias, module_path } => {
-                format!("use {} as \"{}\"", alias, module_path)
-            }
-            UseStatement::Selective { names, module_path } => {
-                let names_str = names.join(", ");
-                format!("use {{ {} }} from \"{}\"", names_str, module_path)
-            }
-        }
-    }
+/// Replaces `path` with `text` by writing beside it first and renaming,
+/// so the file is never left half written.
+fn write_atomically(path: &Path, text: &str) -> Result<(), LangError> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{}.tmp", name));
+    fs::write(&temporary, text)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
 
-    fn format_expression(&mut self, expr: &Expression) -> String {
-        match expr {
-            Expression::Number(n) => n.to_string(),
-            Expression::String(template) => self.format_string_template(template),
-            Expression::Boolean(b) => b.to_string(),
-            Expression::Null => "null".to_string(),
-            Expression::Identifier(name) => name.clone(),
-            Expression::Block(exprs) => {
-                if exprs.is_empty() {
-                    return "{}".to_string();
+fn print_diagnostics(diagnostics: &[FileDiagnostics]) {
+    let reporter = Reporter::stderr();
+    for file in diagnostics {
+        for error in &file.errors {
+            let (message, location) = match error {
+                LangError::Lexer(message, location) => {
+                    (format!("Lex error: {}", message), location)
                 }
-                let old_indent = self.indent_level;
-                self.indent_level += 1;
-                let formatted: Vec<String> = exprs
-                    .iter()
-                    .map(|e| format!("{}{}", self.indent(), self.format_expression(e)))
-                    .collect();
-                self.indent_level = old_indent;
-                format!("{{\n{}\n{}}}", formatted.join("\n"), self.indent())
-            }
-            Expression::Lambda {
-                params,
-                body,
-                impure,
-            } => {
-                let notation = if *impure { "!" } else { "" };
-                let params_str = params.join(", ");
-                let body_str = self.format_lambda_body(body);
-                format!("({}){} {}", params_str, notation, body_str)
-            }
-            Expression::Object(fields) => {
-                if fields.is_empty() {
-                    return "{}".to_string();
+                LangError::Parser(message, location) => {
+                    (format!("Parse error: {}", message), location)
                 }
-                let old_indent = self.indent_level;
-                self.indent_level += 1;
-                let formatted: Vec<String> = fields
-                    .iter()
-                    .map(|f| match f {
-                        ObjectField::Field { name, value } => {
-                            format!(
-                                "{}{}: {}",
-                                self.indent(),
-                                name,
-                                self.format_expression(value)
-                            )
-                        }
-                        ObjectField::Spread(expr) => {
-                            format!("{}...{}", self.indent(), self.format_expression(expr))
-                        }
-                    })
-                    .collect();
-                self.indent_level = old_indent;
-                format!("{{\n{}\n{}}}", formatted.join(",\n"), self.indent())
-            }
-            Expression::List(elements) => {
-                if elements.is_empty() {
-                    return "[]".to_string();
+                LangError::Runtime(message, location) => (message.clone(), location),
+                err @ (LangError::Io(_) | LangError::LimitExceeded(_)) => {
+                    let location = file.file.display().to_string();
+                    eprintln!(
+                        "{}",
+                        reporter.diagnostic(Severity::Error, Some(&location), &err.to_string())
+                    );
+                    continue;
                 }
-                let formatted: Vec<String> = elements
-                    .iter()
-                    .map(|e| match e {
-                        Expression::Spread(expr) => {
-                            format!("...{}", self.format_expression(expr.as_ref()))
-                        }
-                        other => self.format_expression(other),
-                    })
-                    .collect();
-                format!("[{}]", formatted.join(", "))
-            }
-            Expression::Spread(expr) => {
-                format!("...{}", self.format_expression(expr.as_ref()))
-            }
-            Expression::Call { callee, args } => {
-                let callee_str = self.format_expression(callee);
-                let args_str: Vec<String> =
-                    args.iter().map(|a| self.format_expression(a)).collect();
-                format!("{}({})", callee_str, args_str.join(", "))
-            }
-            Expression::PropertyAccess { object, property } => {
-                format!("{}.{}", self.format_expression(object), property)
-            }
-            Expression::Binary { left, op, right } => {
-                let left_str = self.format_expression(left);
-                let right_str = self.format_expression(right);
-                let op_str = match op {
-                    BinaryOperator::Add => "+",
-                    BinaryOperator::Sub => "-",
-                    BinaryOperator::Mul => "*",
-                    BinaryOperator::Div => "/",
-                    BinaryOperator::Eq => "=",
-                    BinaryOperator::NotEq => "!=",
-                    BinaryOperator::LessThan => "<",
-                    BinaryOperator::LessThanEq => "<=",
-                    BinaryOperator::GreaterThan => ">",
-                    BinaryOperator::GreaterThanEq => ">=",
-                    BinaryOperator::And => "&",
-                    BinaryOperator::Or => "|",
-                };
-                format!("{} {} {}", left_str, op_str, right_str)
-            }
+            };
+            let location = match location {
+                Some(location) => format!("{}:{}", file.file.display(), location.line),
+                None => file.file.display().to_string(),
+            };
+            eprintln!(
+                "{}",
+                reporter.diagnostic(Severity::Error, Some(&location), &message)
+            );
         }
     }
+}
 
-    fn format_lambda_body(&mut self, body: &Expression) -> String {
-        match body {
-            Expression::Block(exprs) => {
-                if exprs.is_empty() {
-                    return "{}".to_string();
-                }
-                // Check if body is simple (single expression, not too complex)
-                if exprs.len() == 1 && self.is_simple_expression(&exprs[0]) {
-                    let body_str = self.format_expression(&exprs[0]);
-                    format!("{{ {} }}", body_str)
-                } else {
-                    let old_indent = self.indent_level;
-                    self.indent_level += 1;
-                    let formatted: Vec<String> = exprs
-                        .iter()
-                        .map(|e| format!("{}{}", self.indent(), self.format_expression(e)))
-                        .collect();
-                    self.indent_level = old_indent;
-                    format!("{{\n{}\n{}}}", formatted.join("\n"), self.indent())
+struct DocExample {
+    file: PathBuf,
+    line: usize,
+    source: String,
+}
+
+fn doctest_command(path: &str) -> Result<(), LangError> {
+    let mut files = Vec::new();
+    collect_markdown_files(Path::new(path), &mut files)?;
+    files.sort();
+
+    let mut examples = Vec::new();
+    for file in &files {
+        let markdown = fs::read_to_string(file)?;
+        examples.extend(extract_examples(file, &markdown));
+    }
+
+    let reporter = Reporter::stdout();
+    let mut failures = 0;
+    for example in &examples {
+        // Keep what examples log out of the report unless they fail
+        let output = OutputBuffer::default();
+        match run_example(example, &output) {
+            Ok(()) => println!(
+                "{}      {}:{}",
+                reporter.ok("ok"),
+                example.file.display(),
+                example.line
+            ),
+            Err(err) => {
+                failures += 1;
+                println!(
+                    "{}  {}:{}",
+                    reporter.failed("FAILED"),
+                    example.file.display(),
+                    example.line
+                );
+                for line in output.contents().lines().chain(err.to_string().lines()) {
+                    println!("        {}", line);
                 }
             }
-            _ => {
-                let body_str = self.format_expression(body);
-                format!("{{ {} }}", body_str)
-            }
         }
     }
 
-    fn is_simple_expression(&self, expr: &Expression) -> bool {
-        match expr {
-            Expression::Number(_)
-            | Expression::String(_)
-            | Expression::Boolean(_)
-            | Expression::Null
-            | Expression::Identifier(_) => true,
-            Expression::Binary { left, right, .. } => {
-                self.is_simple_expression(left) && self.is_simple_expression(right)
-            }
-            Expression::PropertyAccess { object, .. } => {
-                matches!(**object, Expression::Identifier(_))
-            }
-            Expression::Call { callee, args } => {
-                matches!(**callee, Expression::Identifier(_))
-                    && args.len() <= 2
-                    && args.iter().all(|a| self.is_simple_expression(a))
+    println!();
+    println!(
+        "doctest: {} passed, {} failed, {} total",
+        examples.len() - failures,
+        failures,
+        examples.len()
+    );
+
+    if failures > 0 {
+        return Err(LangError::Runtime(
+            format!("{} doc example(s) failed", failures),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+/// Runs the ```fip blocks of a markdown file in order in one interpreter,
+/// like the cells of a notebook, so later blocks see what earlier ones
+/// defined. Each block is followed by a ```output block with what it
+/// logged and the value of its last expression; output blocks already
+/// there from an earlier run are replaced. A failing block shows its error
+/// and the rest still run. Blocks tagged ```fip,ignore are left alone, as
+/// in `fip doctest`.
+fn run_markdown_command(path: &Path, out: Option<&Path>) -> Result<(), LangError> {
+    let markdown = fs::read_to_string(path)?;
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut interpreter = Interpreter::with_entry_point_dir(base_dir);
+
+    let mut blocks = extract_examples(path, &markdown).into_iter().peekable();
+    let mut defined = HashSet::new();
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut document = String::new();
+    let mut failures = 0;
+    let mut index = 0;
+    while index < lines.len() {
+        document.push_str(lines[index]);
+        document.push('\n');
+        index += 1;
+        // `line` is where the opening fence is, counting from one
+        let closed = blocks
+            .peek()
+            .is_some_and(|block| index == block.line + block.source.lines().count() + 1);
+        if !closed {
+            continue;
+        }
+        let Some(block) = blocks.next() else {
+            continue;
+        };
+
+        let mut results = OutputBuffer::default();
+        interpreter.set_output(Box::new(results.clone()));
+        interpreter.set_error_output(Box::new(results.clone()));
+        match run_block(&mut interpreter, &block, &mut defined) {
+            Ok(Some(value)) => writeln!(results, "{}", pretty(&value))?,
+            Ok(None) => {}
+            Err(err) => {
+                failures += 1;
+                writeln!(results, "{}", err)?;
             }
-            _ => false,
+        }
+        if let Some(end) = previous_output_end(&lines, index) {
+            index = end;
+        }
+        let results = results.contents();
+        if !results.is_empty() {
+            document.push_str(&format!("\n```output\n{}```\n", results));
         }
     }
 
-    fn format_expression_with_indent(&mut self, expr: &Expression) -> String {
-        match expr {
-            Expression::Block(exprs) => {
-                if exprs.is_empty() {
-                    return format!("{}", self.indent());
-                }
-                let formatted: Vec<String> = exprs
-                    .iter()
-                    .map(|e| format!("{}{}", self.indent(), self.format_expression(e)))
-                    .collect();
-                formatted.join("\n")
+    match out {
+        Some(out) => fs::write(out, &document)?,
+        None => print!("{}", document),
+    }
+    if failures > 0 {
+        return Err(LangError::Runtime(
+            format!("{} block(s) in {} failed", failures, path.display()),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+/// Runs one notebook block, returning its last expression unless that is
+/// `null`, which is what `log!` and friends return. Names in `defined` were
+/// bound by earlier blocks, and the block may bind them again.
+fn run_block(
+    interpreter: &mut Interpreter,
+    block: &DocExample,
+    defined: &mut HashSet<String>,
+) -> Result<Option<Value>, LangError> {
+    let tokens =
+        Lexer::with_source_and_file(&block.source, block.source.clone(), block.file.clone())
+            .lex()?;
+    let program = FipParser::with_source_and_file(tokens, block.source.clone(), block.file.clone())
+        .parse_program()?;
+    for statement in &program.statements {
+        let names: Vec<&str> = match statement {
+            Statement::Assignment { pattern, .. } => pattern.names(),
+            Statement::Function(function) => vec![&function.name],
+            Statement::Use(UseStatement::Single { name, .. }) => vec![name],
+            Statement::Use(UseStatement::Namespace { alias, .. }) => vec![alias],
+            Statement::Use(UseStatement::Selective { names, .. }) => {
+                names.iter().map(String::as_str).collect()
             }
-            _ => {
-                format!("{}{}", self.indent(), self.format_expression(expr))
+            _ => Vec::new(),
+        };
+        for name in names {
+            if !defined.insert(name.to_string()) {
+                interpreter.forget_global(name);
             }
         }
     }
+    Ok(interpreter
+        .eval_owned_program(program)?
+        .filter(|value| !matches!(value, Value::Null)))
+}
 
-    fn format_string_template(&self, template: &fippli_lang::ast::StringTemplate) -> String {
-        let mut result = String::from("\"");
-        for segment in &template.segments {
-            match segment {
-                StringSegment::Literal(s) => {
-                    // Escape special characters
-                    let escaped = s
-                        .replace('\\', "\\\\")
-                        .replace('"', "\\\"")
-                        .replace('\n', "\\n")
-                        .replace('\r', "\\r")
-                        .replace('\t', "\\t");
-                    result.push_str(&escaped);
+/// When the lines from `start` are a blank line and a ```output block, as
+/// `fip run-md` writes them, the index of the line after that block.
+fn previous_output_end(lines: &[&str], start: usize) -> Option<usize> {
+    if lines.get(start)?.trim() != "" || lines.get(start + 1)?.trim() != "```output" {
+        return None;
+    }
+    let close = lines[start + 2..]
+        .iter()
+        .position(|line| line.trim() == "```")?;
+    Some(start + 2 + close + 1)
+}
+
+/// Writes a markdown page per documented module of the library in `dir`,
+/// plus an `index.md` listing them.
+fn doc_command(dir: &Path, out: &Path) -> Result<(), LangError> {
+    if !dir.is_dir() {
+        return Err(LangError::Runtime(
+            format!("Directory '{}' not found", dir.display()),
+            None,
+        ));
+    }
+    let modules = docgen::library_docs(dir)?;
+    for module in &modules {
+        let path = out.join(format!("{}.md", module.name));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, module.markdown())?;
+    }
+    fs::create_dir_all(out)?;
+    fs::write(out.join("index.md"), docgen::index_markdown(&modules))?;
+    println!(
+        "Documented {} module(s) in {}",
+        modules.len(),
+        out.display()
+    );
+    Ok(())
+}
+
+fn collect_fip_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), LangError> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_fip_files(&entry?.path(), files)?;
+        }
+    } else if path.extension().and_then(|ext| ext.to_str()) == Some("fip") {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn collect_markdown_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), LangError> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_markdown_files(&entry?.path(), files)?;
+        }
+    } else if path.extension().and_then(|ext| ext.to_str()) == Some("md") {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+/// Collects ```fip code blocks. Blocks tagged ```fip,ignore are skipped.
+fn extract_examples(file: &Path, markdown: &str) -> Vec<DocExample> {
+    let mut examples = Vec::new();
+    let mut current: Option<DocExample> = None;
+
+    for (index, line) in markdown.lines().enumerate() {
+        let trimmed = line.trim();
+        match current.as_mut() {
+            Some(example) => {
+                if trimmed.starts_with("```") {
+                    examples.extend(current.take());
+                } else {
+                    example.source.push_str(line);
+                    example.source.push('\n');
                 }
-                StringSegment::Expr(expr) => {
-                    result.push('<');
-                    result.push_str(&self.format_expression_inline(expr));
-                    result.push('>');
+            }
+            None => {
+                if let Some(info) = trimmed.strip_prefix("```") {
+                    let mut tags = info.split(',').map(str::trim);
+                    if tags.next() == Some("fip") && !tags.any(|tag| tag == "ignore") {
+                        current = Some(DocExample {
+                            file: file.to_path_buf(),
+                            line: index + 1,
+                            source: String::new(),
+                        });
+                    }
                 }
             }
         }
-        result.push('"');
-        result
     }
 
-    fn format_expression_inline(&self, expr: &Expression) -> String {
-        match expr {
-            Expression::Identifier(name) => name.clone(),
-            Expression::PropertyAccess { object, property } => {
-                format!("{}.{}", self.format_expression_inline(object), property)
-            }
-            _ => {
-                // For complex expressions, just format normally
-                let mut formatter = Formatter::new();
-                formatter.format_expression(expr)
-            }
+    examples
+}
+
+fn run_example(example: &DocExample, output: &OutputBuffer) -> Result<(), LangError> {
+    let tokens = Lexer::with_source_and_file(
+        &example.source,
+        example.source.clone(),
+        example.file.clone(),
+    )
+    .lex()?;
+    let mut parser =
+        FipParser::with_source_and_file(tokens, example.source.clone(), example.file.clone());
+    let program = parser.parse_program()?;
+    let base_dir = example
+        .file
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let mut interpreter = Interpreter::with_entry_point_dir(base_dir);
+    interpreter.set_output(Box::new(output.clone()));
+    interpreter.set_error_output(Box::new(output.clone()));
+    interpreter.set_deterministic(Some(0));
+    interpreter.eval_program(&program)
+}
+
+/// The first argument after the command that is not a flag or the value
+/// of one of the `valued` flags, wherever the flags are placed. A lone `-`
+/// is not a flag.
+fn positional_arg<'a>(args: &'a [String], valued: &[&str]) -> Option<&'a str> {
+    let mut rest = args.iter().skip(2);
+    while let Some(arg) = rest.next() {
+        if valued.contains(&arg.as_str()) {
+            rest.next();
+        } else if !arg.starts_with("--") {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+/// Reads `--profile-folded <file>` from the command line.
+fn profile_folded_arg(args: &[String]) -> Result<Option<PathBuf>, String> {
+    match args.iter().position(|arg| arg == "--profile-folded") {
+        None => Ok(None),
+        Some(index) => args
+            .get(index + 1)
+            .filter(|value| !value.starts_with("--"))
+            .map(|value| Some(PathBuf::from(value)))
+            .ok_or_else(|| "--profile-folded requires a file path".to_string()),
+    }
+}
+
+/// Reads `--seed <n>`, falling back to seed 0 under a bare `--deterministic`.
+fn seed_arg(args: &[String]) -> Result<Option<u64>, String> {
+    match args.iter().position(|arg| arg == "--seed") {
+        None if args.iter().any(|arg| arg == "--deterministic") => Ok(Some(0)),
+        None => Ok(None),
+        Some(index) => args
+            .get(index + 1)
+            .and_then(|value| value.parse().ok())
+            .map(Some)
+            .ok_or_else(|| "--seed requires a non-negative number".to_string()),
+    }
+}
+
+fn capabilities_arg(args: &[String]) -> Result<Option<Vec<Capability>>, String> {
+    let deny_all = args.iter().any(|arg| arg == "--deny-all");
+    match args.iter().position(|arg| arg == "--allow") {
+        None if deny_all => Ok(Some(Vec::new())),
+        None => Ok(None),
+        Some(index) => {
+            let list = args
+                .get(index + 1)
+                .ok_or_else(|| "--allow requires a list such as fs,net".to_string())?;
+            list.split(',')
+                .map(|name| {
+                    Capability::from_name(name.trim()).ok_or_else(|| {
+                        format!(
+                            "Unknown capability '{}' in --allow; expected fs, net, clock, or random",
+                            name.trim()
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(Some)
+        }
+    }
+}
+
+/// Reads `--log-level <level>` and `--log-format <format>`.
+fn log_args(args: &[String]) -> Result<(LogLevel, LogFormat), String> {
+    let value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .map(|index| args.get(index + 1).map(String::as_str).unwrap_or(""))
+    };
+    let level = match value("--log-level") {
+        None => LogLevel::default(),
+        Some(name) => LogLevel::from_name(name)
+            .ok_or_else(|| "--log-level requires debug, info, warn, or error".to_string())?,
+    };
+    let format = match value("--log-format") {
+        None => LogFormat::default(),
+        Some(name) => LogFormat::from_name(name)
+            .ok_or_else(|| "--log-format requires text or json".to_string())?,
+    };
+    Ok((level, format))
+}
+
+fn max_width_arg(args: &[String]) -> Result<Option<usize>, String> {
+    match args.iter().position(|arg| arg == "--max-width") {
+        None => Ok(None),
+        Some(index) => args
+            .get(index + 1)
+            .and_then(|value| value.parse().ok())
+            .filter(|&width| width > 0)
+            .map(Some)
+            .ok_or_else(|| "--max-width requires a positive number".to_string()),
+    }
+}
+
+/// The lines after `--range`, as `<start>:<end>` counting from 1.
+fn range_arg(args: &[String]) -> Result<Option<RangeInclusive<usize>>, String> {
+    let Some(index) = args.iter().position(|arg| arg == "--range") else {
+        return Ok(None);
+    };
+    let lines = args
+        .get(index + 1)
+        .and_then(|value| value.split_once(':'))
+        .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)));
+    match lines {
+        Some((start, end)) if 1 <= start && start <= end => Ok(Some(start..=end)),
+        _ => Err("--range requires lines as <start>:<end>, such as 10:40".to_string()),
+    }
+}
+
+/// Formats `file` with the settings from the nearest `.fipfmt.toml`;
+/// `max_width` overrides the configured width. With `range`, only the
+/// statements on those lines are formatted.
+fn format_command(
+    file: &str,
+    write: bool,
+    max_width: Option<usize>,
+    range: Option<RangeInclusive<usize>>,
+) -> Result<(), LangError> {
+    let source = fs::read_to_string(file)
+        .map_err(|e| LangError::Runtime(format!("Failed to read file: {}", e), None))?;
+
+    let tokens = Lexer::with_source_and_file(&source, source.clone(), PathBuf::from(file))
+        .lex()
+        .map_err(|e| LangError::Runtime(format!("Parse error: {}", e), None))?;
+
+    let mut parser =
+        FipParser::with_source_and_file(tokens.clone(), source.clone(), PathBuf::from(file));
+    let program = parser
+        .parse_program()
+        .map_err(|e| LangError::Runtime(format!("Parse error: {}", e), None))?;
+
+    let (mut options, _) = FormatOptions::discover(Path::new(file))
+        .map_err(|message| LangError::Runtime(message, None))?;
+    if let Some(max_width) = max_width {
+        options.max_width = max_width;
+    }
+    let mut formatter =
+        Formatter::with_options(options).with_source(&source, parser.statement_starts());
+    let formatted = match range {
+        Some(lines) => {
+            formatter.format_lines(&program, &source, &tokens, parser.statement_starts(), lines)
         }
+        None => formatter.format_program(&program),
+    };
+
+    if write {
+        fs::write(file, formatted)
+            .map_err(|e| LangError::Runtime(format!("Failed to write file: {}", e), None))?;
+        println!("Formatted: {}", file);
+    } else {
+        print!("{}", formatted);
     }
+
+    Ok(())
 }
@@ -0,0 +1,146 @@
+use std::cmp::Ordering;
+
+// Latin letters with diacritics mapped to their base letter. Used for the
+// primary collation level so that "é" sorts next to "e" instead of after "z".
+const DIACRITIC_BASES: &[(&str, char)] = &[
+    ("àáâãäåāăą", 'a'),
+    ("çćĉċč", 'c'),
+    ("ďđ", 'd'),
+    ("èéêëēĕėęě", 'e'),
+    ("ĝğġģ", 'g'),
+    ("ĥħ", 'h'),
+    ("ìíîïĩīĭįı", 'i'),
+    ("ĵ", 'j'),
+    ("ķ", 'k'),
+    ("ĺļľŀł", 'l'),
+    ("ñńņňŉ", 'n'),
+    ("òóôõöøōŏő", 'o'),
+    ("ŕŗř", 'r'),
+    ("śŝşš", 's'),
+    ("ţťŧ", 't'),
+    ("ùúûüũūŭůűų", 'u'),
+    ("ŵ", 'w'),
+    ("ýÿŷ", 'y'),
+    ("źżž", 'z'),
+];
+
+/// Folds `text` for caseless comparison using full Unicode lowercase mapping
+/// plus the special foldings that lowercasing alone does not cover.
+pub fn casefold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            'ſ' => folded.push('s'),
+            'ﬀ' => folded.push_str("ff"),
+            'ﬁ' => folded.push_str("fi"),
+            'ﬂ' => folded.push_str("fl"),
+            _ => folded.extend(ch.to_lowercase()),
+        }
+    }
+    folded
+}
+
+/// Compares two strings using a three-level collation (base letter, accent,
+/// case) with optional per-locale tailoring.
+pub fn compare(locale: &str, left: &str, right: &str) -> Ordering {
+    let tailoring = tailoring_for(locale);
+    let left_key = sort_key(left, tailoring);
+    let right_key = sort_key(right, tailoring);
+    left_key.cmp(&right_key).then_with(|| left.cmp(right))
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct SortKey {
+    primary: Vec<u32>,
+    secondary: Vec<u32>,
+    tertiary: Vec<u8>,
+}
+
+fn sort_key(text: &str, tailoring: &[(char, char, u32)]) -> SortKey {
+    let mut primary = Vec::new();
+    let mut secondary = Vec::new();
+    let mut tertiary = Vec::new();
+
+    for ch in text.chars() {
+        let is_upper = ch.is_uppercase();
+        for folded in casefold(&ch.to_string()).chars() {
+            if let Some((_, anchor, rank)) = tailoring.iter().find(|(c, _, _)| *c == folded) {
+                // Tailored letters sort as distinct letters after their anchor.
+                primary.push(((*anchor as u32) << 8) | rank);
+                secondary.push(0);
+            } else {
+                let base = base_letter(folded);
+                primary.push((base as u32) << 8);
+                secondary.push(if base == folded { 0 } else { folded as u32 });
+            }
+            tertiary.push(u8::from(is_upper));
+        }
+    }
+
+    SortKey {
+        primary,
+        secondary,
+        tertiary,
+    }
+}
+
+fn base_letter(ch: char) -> char {
+    DIACRITIC_BASES
+        .iter()
+        .find(|(variants, _)| variants.contains(ch))
+        .map(|(_, base)| *base)
+        .unwrap_or(ch)
+}
+
+#[cfg(feature = "locale-data")]
+fn language_of(locale: &str) -> String {
+    locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+#[cfg(feature = "locale-data")]
+fn tailoring_for(locale: &str) -> &'static [(char, char, u32)] {
+    // (letter, sorts after, rank among letters tailored after the same anchor)
+    match language_of(locale).as_str() {
+        "sv" | "fi" => &[
+            ('å', 'z', 1),
+            ('ä', 'z', 2),
+            ('æ', 'z', 2),
+            ('ö', 'z', 3),
+            ('ø', 'z', 3),
+        ],
+        "da" | "nb" | "nn" | "no" => &[('æ', 'z', 1), ('ø', 'z', 2), ('å', 'z', 3)],
+        "es" => &[('ñ', 'n', 1)],
+        "et" => &[('õ', 'w', 1), ('ä', 'w', 2), ('ö', 'w', 3), ('ü', 'w', 4)],
+        "pl" => &[
+            ('ą', 'a', 1),
+            ('ć', 'c', 1),
+            ('ę', 'e', 1),
+            ('ł', 'l', 1),
+            ('ń', 'n', 1),
+            ('ó', 'o', 1),
+            ('ś', 's', 1),
+            ('ź', 'z', 1),
+            ('ż', 'z', 2),
+        ],
+        "tr" | "az" => &[
+            ('ç', 'c', 1),
+            ('ğ', 'g', 1),
+            ('ı', 'h', 1),
+            ('ö', 'o', 1),
+            ('ş', 's', 1),
+            ('ü', 'u', 1),
+        ],
+        _ => &[],
+    }
+}
+
+#[cfg(not(feature = "locale-data"))]
+fn tailoring_for(_locale: &str) -> &'static [(char, char, u32)] {
+    &[]
+}
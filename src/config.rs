@@ -0,0 +1,477 @@
+use crate::{interpreter::Value, json};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Parses the configuration file `path`, whose contents are `text`, overlays
+/// environment variables starting with `prefix` and then applies `overrides`. Returns `{ config, sources }` where
+/// `sources` maps every dotted leaf path to the layer that set it.
+///
+/// Environment variables are mapped as `PREFIX_DATABASE__HOST` ->
+/// `database.host`: the prefix and one `_` are stripped, `__` separates
+/// nesting levels, and the remaining `_` become `-`.
+pub fn load(
+    path: &Path,
+    text: &str,
+    prefix: &str,
+    env: impl IntoIterator<Item = (String, String)>,
+    overrides: &Value,
+) -> Result<Value, String> {
+    let file_value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => json::parse(text),
+        Some("toml") => parse_toml(text),
+        Some("yaml") | Some("yml") => parse_yaml(text),
+        _ => Err("unsupported extension, expected .json, .toml, .yaml, or .yml".to_string()),
+    }
+    .map_err(|err| format!("Failed to parse config '{}': {}", path.display(), err))?;
+
+    let mut config = BTreeMap::new();
+    let mut sources = BTreeMap::new();
+    let file_source = format!("file:{}", path.display());
+    match file_value {
+        Value::Object(fields) => merge(&mut config, &mut sources, "", fields, &file_source),
+        other => {
+            return Err(format!(
+                "Config '{}' must contain an object at the top level, found {:?}",
+                path.display(),
+                other
+            ))
+        }
+    }
+
+    let env_prefix = format!("{}_", prefix);
+    let mut env_vars: Vec<(String, String)> = env
+        .into_iter()
+        .filter(|(name, _)| !prefix.is_empty() && name.starts_with(&env_prefix))
+        .collect();
+    env_vars.sort();
+    for (name, raw) in env_vars {
+        let segments: Vec<String> = name[env_prefix.len()..]
+            .split("__")
+            .map(|segment| segment.to_ascii_lowercase().replace('_', "-"))
+            .collect();
+        if segments.iter().any(|segment| segment.is_empty()) {
+            continue;
+        }
+        let layer = nest(&segments, parse_scalar(&raw));
+        let source = format!("env:{}", name);
+        merge(&mut config, &mut sources, "", layer, &source);
+    }
+
+    match overrides {
+        Value::Null => {}
+        Value::Object(fields) => merge(&mut config, &mut sources, "", fields.clone(), "override"),
+        Value::List(items) => {
+            for item in items {
+                let (key, raw) = match item {
+                    Value::String(text) => text.split_once('=').ok_or_else(|| {
+                        format!("Override '{}' must have the form key.path=value", text)
+                    })?,
+                    other => return Err(format!("Override must be a string, found {:?}", other)),
+                };
+                let segments: Vec<String> = key.split('.').map(str::to_string).collect();
+                let layer = nest(&segments, parse_scalar(raw));
+                merge(&mut config, &mut sources, "", layer, "override");
+            }
+        }
+        other => {
+            return Err(format!(
+                "Overrides must be an object or a list of 'key=value' strings, found {:?}",
+                other
+            ))
+        }
+    }
+
+    let mut result = BTreeMap::new();
+    result.insert("config".to_string(), Value::Object(config));
+    result.insert(
+        "sources".to_string(),
+        Value::Object(
+            sources
+                .into_iter()
+                .map(|(path, source)| (path, Value::String(source)))
+                .collect(),
+        ),
+    );
+    Ok(Value::Object(result))
+}
+
+fn nest(segments: &[String], leaf: Value) -> BTreeMap<String, Value> {
+    let mut value = leaf;
+    for segment in segments.iter().skip(1).rev() {
+        let mut map = BTreeMap::new();
+        map.insert(segment.clone(), value);
+        value = Value::Object(map);
+    }
+    let mut root = BTreeMap::new();
+    root.insert(segments[0].clone(), value);
+    root
+}
+
+fn merge(
+    target: &mut BTreeMap<String, Value>,
+    sources: &mut BTreeMap<String, String>,
+    prefix: &str,
+    layer: BTreeMap<String, Value>,
+    source: &str,
+) {
+    for (key, value) in layer {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match (target.get_mut(&key), value) {
+            (Some(Value::Object(existing)), Value::Object(nested)) => {
+                merge(existing, sources, &path, nested, source);
+            }
+            (_, value) => {
+                let dotted = format!("{}.", path);
+                sources.retain(|existing, _| !existing.starts_with(&dotted));
+                record_sources(sources, &path, &value, source);
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+fn record_sources(sources: &mut BTreeMap<String, String>, path: &str, value: &Value, source: &str) {
+    match value {
+        Value::Object(fields) if !fields.is_empty() => {
+            sources.remove(path);
+            for (key, nested) in fields {
+                record_sources(sources, &format!("{}.{}", path, key), nested, source);
+            }
+        }
+        _ => {
+            sources.insert(path.to_string(), source.to_string());
+        }
+    }
+}
+
+/// Interprets an untyped value from the environment or the command line.
+fn parse_scalar(raw: &str) -> Value {
+    match raw {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        "null" => Value::Null,
+        _ => raw
+            .parse::<i64>()
+            .map(Value::Number)
+            .unwrap_or_else(|_| Value::String(raw.to_string())),
+    }
+}
+
+/// Parses the TOML subset used by configuration files: tables, dotted keys,
+/// strings, integers, booleans, and single-line arrays.
+pub fn parse_toml(text: &str) -> Result<Value, String> {
+    let mut root = BTreeMap::new();
+    let mut table: Vec<String> = Vec::new();
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with("[[") {
+            return Err(format!(
+                "line {}: arrays of tables are not supported",
+                line_number
+            ));
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .strip_suffix(']')
+                .ok_or_else(|| format!("line {}: unterminated table header", line_number))?;
+            table = split_key(header, line_number)?;
+            insert_path(&mut root, &table, None, line_number)?;
+            continue;
+        }
+        let (key, raw_value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected 'key = value'", line_number))?;
+        let mut path = table.clone();
+        path.extend(split_key(key, line_number)?);
+        let value = parse_toml_value(raw_value.trim(), line_number)?;
+        insert_path(&mut root, &path, Some(value), line_number)?;
+    }
+
+    Ok(Value::Object(root))
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, ch) in line.char_indices() {
+        if std::mem::take(&mut escaped) {
+            continue;
+        }
+        match (quote, ch) {
+            // Only basic strings have escapes
+            (Some('"'), '\\') => escaped = true,
+            (None, '"') | (None, '\'') => quote = Some(ch),
+            (Some(open), _) if ch == open => quote = None,
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn split_key(key: &str, line_number: usize) -> Result<Vec<String>, String> {
+    key.split('.')
+        .map(|segment| {
+            let segment = segment.trim();
+            let unquoted = segment
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .unwrap_or(segment);
+            if unquoted.is_empty() {
+                Err(format!("line {}: empty key", line_number))
+            } else {
+                Ok(unquoted.to_string())
+            }
+        })
+        .collect()
+}
+
+fn insert_path(
+    root: &mut BTreeMap<String, Value>,
+    path: &[String],
+    value: Option<Value>,
+    line_number: usize,
+) -> Result<(), String> {
+    let (last, parents) = match path.split_last() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    let mut current = root;
+    for segment in parents {
+        let entry = current
+            .entry(segment.clone())
+            .or_insert_with(|| Value::Object(BTreeMap::new()));
+        current = match entry {
+            Value::Object(map) => map,
+            _ => {
+                return Err(format!(
+                    "line {}: key '{}' is already a value",
+                    line_number, segment
+                ))
+            }
+        };
+    }
+    match value {
+        Some(value) => {
+            if current.contains_key(last) {
+                return Err(format!(
+                    "line {}: duplicate key '{}'",
+                    line_number,
+                    path.join(".")
+                ));
+            }
+            current.insert(last.clone(), value);
+        }
+        None => {
+            current
+                .entry(last.clone())
+                .or_insert_with(|| Value::Object(BTreeMap::new()));
+        }
+    }
+    Ok(())
+}
+
+fn parse_toml_value(raw: &str, line_number: usize) -> Result<Value, String> {
+    if let Some(inner) = raw.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or_else(|| format!("line {}: arrays must fit on one line", line_number))?;
+        let mut items = Vec::new();
+        for item in split_top_level(inner) {
+            let item = item.trim();
+            if !item.is_empty() {
+                items.push(parse_toml_value(item, line_number)?);
+            }
+        }
+        return Ok(Value::List(items));
+    }
+    if let Some(inner) = raw.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or_else(|| format!("line {}: unterminated string", line_number))?;
+        return json::parse(&format!("\"{}\"", inner))
+            .map_err(|err| format!("line {}: {}", line_number, err));
+    }
+    if let Some(inner) = raw.strip_prefix('\'') {
+        return inner
+            .strip_suffix('\'')
+            .map(|s| Value::String(s.to_string()))
+            .ok_or_else(|| format!("line {}: unterminated string", line_number));
+    }
+    match raw {
+        "true" => Ok(Value::Boolean(true)),
+        "false" => Ok(Value::Boolean(false)),
+        _ => raw
+            .replace('_', "")
+            .parse::<i64>()
+            .map(Value::Number)
+            .map_err(|_| format!("line {}: unsupported value '{}'", line_number, raw)),
+    }
+}
+
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, ch) in text.char_indices() {
+        if std::mem::take(&mut escaped) {
+            continue;
+        }
+        match (quote, ch) {
+            (Some('"'), '\\') => escaped = true,
+            (None, '"') | (None, '\'') => quote = Some(ch),
+            (Some(open), _) if ch == open => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(&text[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Parses the block-style YAML subset used by configuration files: nested
+/// mappings by indentation, `- item` lists, and scalar values.
+pub fn parse_yaml(text: &str) -> Result<Value, String> {
+    let lines: Vec<(usize, usize, &str)> = text
+        .lines()
+        .enumerate()
+        .filter_map(|(index, raw)| {
+            let content = strip_comment(raw).trim_end();
+            let trimmed = content.trim_start();
+            if trimmed.is_empty() || trimmed == "---" {
+                None
+            } else {
+                Some((index + 1, content.len() - trimmed.len(), trimmed))
+            }
+        })
+        .collect();
+    let mut position = 0;
+    let value = parse_yaml_block(&lines, &mut position, 0)?;
+    match value {
+        Value::Null => Ok(Value::Object(BTreeMap::new())),
+        value => Ok(value),
+    }
+}
+
+fn parse_yaml_block(
+    lines: &[(usize, usize, &str)],
+    position: &mut usize,
+    indent: usize,
+) -> Result<Value, String> {
+    let Some(&(_, first_indent, first)) = lines.get(*position) else {
+        return Ok(Value::Null);
+    };
+    if first_indent < indent {
+        return Ok(Value::Null);
+    }
+    let block_indent = first_indent;
+
+    if first.starts_with("- ") || first == "-" {
+        let mut items = Vec::new();
+        while let Some(&(line_number, line_indent, line)) = lines.get(*position) {
+            if line_indent != block_indent {
+                if line_indent > block_indent {
+                    return Err(format!("line {}: unexpected indentation", line_number));
+                }
+                break;
+            }
+            let Some(item) = line.strip_prefix('-') else {
+                break;
+            };
+            *position += 1;
+            let item = item.trim();
+            if item.is_empty() {
+                items.push(parse_yaml_block(lines, position, block_indent + 1)?);
+            } else {
+                items.push(parse_yaml_scalar(item));
+            }
+        }
+        return Ok(Value::List(items));
+    }
+
+    let mut fields = BTreeMap::new();
+    while let Some(&(line_number, line_indent, line)) = lines.get(*position) {
+        if line_indent != block_indent {
+            if line_indent > block_indent {
+                return Err(format!("line {}: unexpected indentation", line_number));
+            }
+            break;
+        }
+        let (key, rest) = line
+            .split_once(':')
+            .ok_or_else(|| format!("line {}: expected 'key: value'", line_number))?;
+        *position += 1;
+        let key = key.trim().trim_matches('"').to_string();
+        let rest = rest.trim();
+        let value = if rest.is_empty() {
+            parse_yaml_block(lines, position, block_indent + 1)?
+        } else {
+            parse_yaml_scalar(rest)
+        };
+        if fields.insert(key.clone(), value).is_some() {
+            return Err(format!("line {}: duplicate key '{}'", line_number, key));
+        }
+    }
+    Ok(Value::Object(fields))
+}
+
+fn parse_yaml_scalar(raw: &str) -> Value {
+    if let Some(inner) = raw.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        return json::parse(&format!("\"{}\"", inner))
+            .unwrap_or_else(|_| Value::String(inner.to_string()));
+    }
+    if let Some(inner) = raw.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        return Value::String(inner.replace("''", "'"));
+    }
+    match raw {
+        "~" | "null" => Value::Null,
+        "true" | "yes" => Value::Boolean(true),
+        "false" | "no" => Value::Boolean(false),
+        _ => raw
+            .parse::<i64>()
+            .map(Value::Number)
+            .unwrap_or_else(|_| Value::String(raw.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_strings_keep_hashes_and_escaped_quotes() -> Result<(), String> {
+        let config = parse_toml(
+            "key = \"a \\\" # b\" # a comment\n\
+             literal = 'c:\\dir' # another\n\
+             list = [\"x \\\", # y\", \"z\"]\n",
+        )?;
+        let Value::Object(fields) = config else {
+            panic!("expected a table, found {:?}", config);
+        };
+        assert!(matches!(&fields["key"], Value::String(s) if s == "a \" # b"));
+        assert!(matches!(&fields["literal"], Value::String(s) if s == "c:\\dir"));
+        let Value::List(items) = &fields["list"] else {
+            panic!("expected a list, found {:?}", fields["list"]);
+        };
+        assert!(
+            matches!(&items[..], [Value::String(x), Value::String(z)] if x == "x \", # y" && z == "z")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+//! Differences between two values, behind `diff` and the failure messages
+//! of `assert-equal!` and `expect-snapshot!`. Records and maps are compared
+//! field by field and key by key, lists are aligned on their longest common
+//! run of equal elements so an insertion does not shift everything after
+//! it, and strings that span lines are compared line by line.
+
+use crate::{interpreter::Interpreter, interpreter::Value, pretty::pretty, set};
+use std::cmp::Ordering;
+
+/// One place where two values differ.
+#[derive(Debug, Clone)]
+pub struct Difference {
+    /// Where the difference is, such as `items[2].name`; empty for the
+    /// values themselves.
+    pub path: String,
+    /// The line, counting from 1, of a multi-line string at `path`. Lines
+    /// that were removed count in the old string and added ones in the new.
+    pub line: Option<usize>,
+    pub kind: DifferenceKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum DifferenceKind {
+    Changed { from: Value, to: Value },
+    Added(Value),
+    Removed(Value),
+}
+
+impl Difference {
+    /// A line for a failure message, such as `items[2]: 3 -> 4`.
+    pub fn describe(&self) -> String {
+        let mut location = if self.path.is_empty() {
+            "value".to_string()
+        } else {
+            self.path.clone()
+        };
+        if let Some(line) = self.line {
+            location.push_str(&format!(" line {}", line));
+        }
+        match &self.kind {
+            DifferenceKind::Changed { from, to } => {
+                format!("{}: {} -> {}", location, pretty(from), pretty(to))
+            }
+            DifferenceKind::Added(value) => format!("{}: added {}", location, pretty(value)),
+            DifferenceKind::Removed(value) => format!("{}: removed {}", location, pretty(value)),
+        }
+    }
+}
+
+/// Where `from` and `to` differ, in path order; empty when they are equal.
+pub fn diff(from: &Value, to: &Value) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    walk(from, to, String::new(), &mut differences);
+    differences
+}
+
+/// A `differences:` section for a failure message that already shows both
+/// values, or nothing when they simply differ as a whole.
+pub fn section(from: &Value, to: &Value) -> String {
+    let differences = diff(from, to);
+    if differences
+        .iter()
+        .all(|difference| difference.path.is_empty() && difference.line.is_none())
+    {
+        return String::new();
+    }
+    let lines: Vec<String> = differences.iter().map(Difference::describe).collect();
+    format!("\ndifferences:\n  {}", lines.join("\n  "))
+}
+
+fn walk(from: &Value, to: &Value, path: String, out: &mut Vec<Difference>) {
+    if Interpreter::values_equal(from, to) {
+        return;
+    }
+    let push = |out: &mut Vec<Difference>, path: String, kind| {
+        out.push(Difference {
+            path,
+            line: None,
+            kind,
+        })
+    };
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let field = |key: &str| {
+                if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                }
+            };
+            let mut keys: Vec<&String> = from.keys().chain(to.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                match (from.get(key), to.get(key)) {
+                    (Some(from), Some(to)) => walk(from, to, field(key), out),
+                    (Some(from), None) => {
+                        push(out, field(key), DifferenceKind::Removed(from.clone()))
+                    }
+                    (None, Some(to)) => push(out, field(key), DifferenceKind::Added(to.clone())),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::List(from), Value::List(to)) => {
+            let index = |index: usize| format!("{}[{}]", path, index);
+            let mut removed = Vec::new();
+            let mut added = Vec::new();
+            // Elements replaced between two equal ones are compared with
+            // each other; the rest were removed or added
+            let mut flush = |removed: &mut Vec<usize>, added: &mut Vec<usize>| {
+                for pair in 0..removed.len().max(added.len()) {
+                    match (removed.get(pair), added.get(pair)) {
+                        (Some(&old), Some(&new)) => walk(&from[old], &to[new], index(old), out),
+                        (Some(&old), None) => out.push(Difference {
+                            path: index(old),
+                            line: None,
+                            kind: DifferenceKind::Removed(from[old].clone()),
+                        }),
+                        (None, Some(&new)) => out.push(Difference {
+                            path: index(new),
+                            line: None,
+                            kind: DifferenceKind::Added(to[new].clone()),
+                        }),
+                        (None, None) => {}
+                    }
+                }
+                removed.clear();
+                added.clear();
+            };
+            for step in align(from, to, Interpreter::values_equal) {
+                match step {
+                    Step::Same(..) => flush(&mut removed, &mut added),
+                    Step::Removed(old) => removed.push(old),
+                    Step::Added(new) => added.push(new),
+                }
+            }
+            flush(&mut removed, &mut added);
+        }
+        (Value::Map(from), Value::Map(to)) => {
+            let key_path = |key: &Value| format!("{}[{}]", path, pretty(key));
+            merge(
+                from,
+                to,
+                |entry| &entry.0,
+                |side| match side {
+                    Side::Both(from, to) => walk(&from.1, &to.1, key_path(&from.0), out),
+                    Side::From(from) => push(
+                        out,
+                        key_path(&from.0),
+                        DifferenceKind::Removed(from.1.clone()),
+                    ),
+                    Side::To(to) => push(out, key_path(&to.0), DifferenceKind::Added(to.1.clone())),
+                },
+            );
+        }
+        (Value::Set(from), Value::Set(to)) => {
+            merge(
+                from,
+                to,
+                |element| element,
+                |side| match side {
+                    Side::Both(..) => {}
+                    Side::From(from) => {
+                        push(out, path.clone(), DifferenceKind::Removed(from.clone()))
+                    }
+                    Side::To(to) => push(out, path.clone(), DifferenceKind::Added(to.clone())),
+                },
+            );
+        }
+        (Value::String(from), Value::String(to)) if from.contains('\n') || to.contains('\n') => {
+            let (from, to): (Vec<&str>, Vec<&str>) = (from.lines().collect(), to.lines().collect());
+            for step in align(&from, &to, |a, b| a == b) {
+                let (line, kind) = match step {
+                    Step::Same(..) => continue,
+                    Step::Removed(old) => (old, DifferenceKind::Removed(text(from[old]))),
+                    Step::Added(new) => (new, DifferenceKind::Added(text(to[new]))),
+                };
+                out.push(Difference {
+                    path: path.clone(),
+                    line: Some(line + 1),
+                    kind,
+                });
+            }
+        }
+        _ => push(
+            out,
+            path,
+            DifferenceKind::Changed {
+                from: from.clone(),
+                to: to.clone(),
+            },
+        ),
+    }
+}
+
+fn text(line: &str) -> Value {
+    Value::String(line.to_string())
+}
+
+/// One step of an alignment, by position in the old and new sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// Aligns `from` with `to` on their longest common subsequence, listing
+/// removals before the additions that replace them.
+pub fn align<T>(from: &[T], to: &[T], equal: impl Fn(&T, &T) -> bool) -> Vec<Step> {
+    // lengths[i][j]: the longest common subsequence of from[i..] and to[j..]
+    let mut lengths = vec![vec![0usize; to.len() + 1]; from.len() + 1];
+    for i in (0..from.len()).rev() {
+        for j in (0..to.len()).rev() {
+            lengths[i][j] = if equal(&from[i], &to[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut steps = Vec::with_capacity(from.len().max(to.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < from.len() || j < to.len() {
+        if i < from.len() && j < to.len() && equal(&from[i], &to[j]) {
+            steps.push(Step::Same(i, j));
+            i += 1;
+            j += 1;
+        } else if j == to.len() || (i < from.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            steps.push(Step::Removed(i));
+            i += 1;
+        } else {
+            steps.push(Step::Added(j));
+            j += 1;
+        }
+    }
+    steps
+}
+
+enum Side<'a, T> {
+    Both(&'a T, &'a T),
+    From(&'a T),
+    To(&'a T),
+}
+
+/// Walks two sequences sorted in set order together.
+fn merge<'a, T>(
+    from: &'a [T],
+    to: &'a [T],
+    key: impl Fn(&T) -> &Value,
+    mut visit: impl FnMut(Side<'a, T>),
+) {
+    let (mut from, mut to) = (from.iter().peekable(), to.iter().peekable());
+    loop {
+        let side = match (from.peek(), to.peek()) {
+            (None, None) => return,
+            (Some(_), None) => Side::From(from.next().expect("peeked")),
+            (None, Some(_)) => Side::To(to.next().expect("peeked")),
+            (Some(left), Some(right)) => match set::order(key(left), key(right)) {
+                Ordering::Less => Side::From(from.next().expect("peeked")),
+                Ordering::Greater => Side::To(to.next().expect("peeked")),
+                Ordering::Equal => {
+                    Side::Both(from.next().expect("peeked"), to.next().expect("peeked"))
+                }
+            },
+        };
+        visit(side);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn described(from: &Value, to: &Value) -> Vec<String> {
+        diff(from, to).iter().map(Difference::describe).collect()
+    }
+
+    #[test]
+    fn differences_are_found_by_path() {
+        let record = |name: &str, tags: Vec<Value>| {
+            Value::Object(BTreeMap::from([
+                ("name".to_string(), Value::String(name.to_string())),
+                ("tags".to_string(), Value::List(tags)),
+            ]))
+        };
+        let n = Value::Number;
+        assert!(diff(&record("Ada", vec![n(1)]), &record("Ada", vec![n(1)])).is_empty());
+        assert_eq!(
+            described(
+                &record("Ada", vec![n(1), n(2), n(3), n(4)]),
+                &record("Bob", vec![n(0), n(1), n(2), n(5), n(4)])
+            ),
+            [
+                "name: \"Ada\" -> \"Bob\"",
+                "tags[0]: added 0",
+                "tags[2]: 3 -> 5",
+            ]
+        );
+        assert_eq!(described(&n(1), &Value::Null), ["value: 1 -> null"]);
+
+        let text = |text: &str| Value::String(text.to_string());
+        assert_eq!(
+            described(&text("a\nb\nc"), &text("a\nB\nc\nd")),
+            [
+                "value line 2: removed \"b\"",
+                "value line 2: added \"B\"",
+                "value line 4: added \"d\"",
+            ]
+        );
+
+        let from = Value::Set(vec![n(1), n(2)]);
+        let to = Value::Set(vec![n(2), n(3)]);
+        assert_eq!(
+            described(&from, &to),
+            ["value: removed 1", "value: added 3"]
+        );
+    }
+}
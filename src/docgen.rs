@@ -0,0 +1,302 @@
+//! API reference pages for FIP libraries, built from doc comments: `//!`
+//! lines open a module and `///` lines sit above a function or binding.
+//! `fip doc` writes the pages as markdown and the docs builder renders them
+//! to HTML with `--api`.
+
+use crate::{
+    ast::{Function, Program, Statement, UseStatement},
+    error::LangResult,
+    lexer::Lexer,
+    parser::Parser,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// What a module documents: its module doc and its exports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleDoc {
+    /// The import path of the module, such as `geometry/shapes`.
+    pub name: String,
+    /// The `//!` lines at the top of the module.
+    pub doc: Option<String>,
+    /// The exports, in the order they are defined.
+    pub symbols: Vec<SymbolDoc>,
+}
+
+/// One exported name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolDoc {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The `///` lines above the definition.
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+    /// A function; `signature` lists the parameters with any annotations,
+    /// such as `(width: Number, height) -> Number`.
+    Function { signature: String, impure: bool },
+    /// A value bound with `name: expression`.
+    Binding,
+    /// A name imported from `module` and exported again.
+    Reexport { module: String },
+}
+
+impl ModuleDoc {
+    /// The first paragraph of the module doc, on one line, for listings.
+    pub fn summary(&self) -> Option<String> {
+        let paragraph = self.doc.as_deref()?.split("\n\n").next()?;
+        Some(paragraph.replace('\n', " "))
+    }
+
+    /// The reference page: the module doc, then a section per export.
+    pub fn markdown(&self) -> String {
+        let mut markdown = format!("# {}\n", self.name);
+        if let Some(doc) = &self.doc {
+            markdown.push_str(&format!("\n{}\n", doc));
+        }
+        for symbol in &self.symbols {
+            markdown.push_str(&format!("\n## {}\n", symbol.name));
+            match &symbol.kind {
+                SymbolKind::Function { signature, impure } => {
+                    let purity = if *impure { " (impure)" } else { "" };
+                    markdown.push_str(&format!(
+                        "\n**Signature** `{}: {}`{}\n",
+                        symbol.name, signature, purity
+                    ));
+                }
+                SymbolKind::Binding => {}
+                SymbolKind::Reexport { module } => {
+                    markdown.push_str(&format!("\nRe-exported from `{}`.\n", module));
+                }
+            }
+            if let Some(doc) = &symbol.doc {
+                markdown.push_str(&format!("\n{}\n", doc));
+            }
+        }
+        markdown
+    }
+}
+
+/// Modules for every `.fip` file under `dir` that has a module doc or
+/// exports something, sorted by import path.
+pub fn library_docs(dir: &Path) -> LangResult<Vec<ModuleDoc>> {
+    let mut files = Vec::new();
+    collect_sources(dir, &mut files)?;
+    files.sort();
+
+    let mut modules = Vec::new();
+    for file in files {
+        let source = fs::read_to_string(&file)?;
+        let tokens = Lexer::with_source_and_file(&source, source.clone(), file.clone()).lex()?;
+        let program =
+            Parser::with_source_and_file(tokens, source.clone(), file.clone()).parse_program()?;
+        let relative = file.strip_prefix(dir).unwrap_or(&file).with_extension("");
+        let name = relative
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        modules.extend(module_doc(&name, &program));
+    }
+    Ok(modules)
+}
+
+/// What the module `name` documents, or `None` when it has neither a module
+/// doc nor exports.
+pub fn module_doc(name: &str, program: &Program) -> Option<ModuleDoc> {
+    let exports: Vec<&str> = program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Export(export) => Some(export.name.as_str()),
+            _ => None,
+        })
+        .collect();
+    if program.doc.is_none() && exports.is_empty() {
+        return None;
+    }
+
+    let mut symbols = Vec::new();
+    for statement in &program.statements {
+        let defined: Vec<(&str, SymbolKind, &Option<String>)> = match statement {
+            Statement::Function(function) => vec![(
+                function.name.as_str(),
+                SymbolKind::Function {
+                    signature: signature(function),
+                    impure: function.impure,
+                },
+                &function.doc,
+            )],
+            // A destructuring binding documents every name it binds
+            Statement::Assignment { pattern, doc, .. } => pattern
+                .names()
+                .into_iter()
+                .map(|name| (name, SymbolKind::Binding, doc))
+                .collect(),
+            Statement::Use(use_statement) => imported_names(use_statement)
+                .into_iter()
+                .map(|name| {
+                    let module = use_statement.module_path().to_string();
+                    (name, SymbolKind::Reexport { module }, &None)
+                })
+                .collect(),
+            _ => continue,
+        };
+        for (name, kind, doc) in defined {
+            if exports.contains(&name) {
+                symbols.push(SymbolDoc {
+                    name: name.to_string(),
+                    kind,
+                    doc: doc.clone(),
+                });
+            }
+        }
+    }
+
+    Some(ModuleDoc {
+        name: name.to_string(),
+        doc: program.doc.clone(),
+        symbols,
+    })
+}
+
+/// A page listing every module with its summary and exports, linking to
+/// `<name>.md`.
+pub fn index_markdown(modules: &[ModuleDoc]) -> String {
+    let mut markdown = String::from("# API Reference\n\n");
+    for module in modules {
+        markdown.push_str(&format!("- [{}]({}.md)", module.name, module.name));
+        if let Some(summary) = module.summary() {
+            markdown.push_str(&format!(" — {}", summary));
+        }
+        markdown.push('\n');
+        if !module.symbols.is_empty() {
+            let names: Vec<String> = module
+                .symbols
+                .iter()
+                .map(|symbol| format!("`{}`", symbol.name))
+                .collect();
+            markdown.push_str(&format!("  - {}\n", names.join(", ")));
+        }
+    }
+    markdown
+}
+
+/// The names a `use` binds; a glob binds names only the imported module
+/// knows, so it contributes none.
+fn imported_names(use_statement: &UseStatement) -> Vec<&str> {
+    match use_statement {
+        UseStatement::Single { name, .. } => vec![name],
+        UseStatement::Namespace { alias, .. } => vec![alias],
+        UseStatement::Selective { names, .. } => names.iter().map(String::as_str).collect(),
+        UseStatement::Glob { .. } => Vec::new(),
+    }
+}
+
+/// `(width: Number, height) -> Number`, with the annotations as written.
+fn signature(function: &Function) -> String {
+    let params: Vec<String> = function
+        .params
+        .iter()
+        .enumerate()
+        .map(
+            |(index, param)| match function.signature.params.get(index) {
+                Some(Some(ty)) => format!("{}: {}", param, ty),
+                _ => param.clone(),
+            },
+        )
+        .collect();
+    match &function.signature.result {
+        Some(result) => format!("({}) -> {}", params.join(", "), result),
+        None => format!("({})", params.join(", ")),
+    }
+}
+
+fn collect_sources(path: &Path, files: &mut Vec<PathBuf>) -> LangResult<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_sources(&entry?.path(), files)?;
+        }
+    } else if path.extension().and_then(|ext| ext.to_str()) == Some("fip") {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_exports_with_their_signatures_and_comments() -> LangResult<()> {
+        let source = r#"//! Shapes and their measurements.
+//!
+//! All lengths are whole numbers.
+
+/// The area of a rectangle.
+///
+/// Both sides must be non-negative.
+area: (width: Number, height: Number): Number {
+  width * height
+}
+
+/// Not exported, so not documented.
+helper: (n) { n }
+
+// A plain comment is not a doc comment.
+/// The unit square.
+unit: { width: 1, height: 1 }
+
+use sum from "math"
+
+/// Prints a shape.
+show!: (shape) { log!(shape) }
+
+export area
+export unit
+export sum
+export show!
+"#;
+        let tokens = Lexer::new(source).lex()?;
+        let program = Parser::with_source_and_file(tokens, source.to_string(), "shapes.fip".into())
+            .parse_program()?;
+        let module = module_doc("geometry/shapes", &program).expect("documented");
+        assert_eq!(
+            module.summary().as_deref(),
+            Some("Shapes and their measurements.")
+        );
+        assert_eq!(
+            module.symbols[3].kind,
+            SymbolKind::Function {
+                signature: "(shape)".to_string(),
+                impure: true
+            }
+        );
+        assert_eq!(
+            module.markdown(),
+            "# geometry/shapes\n\
+             \nShapes and their measurements.\n\nAll lengths are whole numbers.\n\
+             \n## area\n\
+             \n**Signature** `area: (width: Number, height: Number) -> Number`\n\
+             \nThe area of a rectangle.\n\nBoth sides must be non-negative.\n\
+             \n## unit\n\
+             \nThe unit square.\n\
+             \n## sum\n\
+             \nRe-exported from `math`.\n\
+             \n## show!\n\
+             \n**Signature** `show!: (shape)` (impure)\n\
+             \nPrints a shape.\n"
+        );
+        assert!(index_markdown(&[module]).contains(
+            "- [geometry/shapes](geometry/shapes.md) — Shapes and their measurements.\n  - `area`, `unit`, `sum`, `show!`\n"
+        ));
+
+        let script = Parser::new(Lexer::new("log!(1)").lex()?).parse_program()?;
+        assert_eq!(module_doc("main", &script), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,352 @@
+//! Digests and byte encodings for the `sha256`, `md5`, `base64-*` and `hex-*`
+//! builtins, plus the SHA-1 digest behind `uuid-v5` and the text encodings
+//! used to convert between strings and bytes. Unless an encoding is named,
+//! strings are hashed and encoded as their UTF-8 bytes.
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The SHA-256 digest of `bytes` (FIPS 180-4).
+pub fn sha256(bytes: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    for block in pad(bytes, |length| length.to_be_bytes()).chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (word, chunk) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+    let mut digest = [0u8; 32];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// The SHA-1 digest of `bytes` (FIPS 180-4). Name-based UUIDs are defined in
+/// terms of it; like MD5 it should not be relied on to resist tampering.
+pub fn sha1(bytes: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    for block in pad(bytes, |length| length.to_be_bytes()).chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (word, chunk) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in w.iter().enumerate() {
+            let (mixed, constant) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5a827999),
+                1 => (b ^ c ^ d, 0x6ed9eba1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let next = a
+                .rotate_left(5)
+                .wrapping_add(mixed)
+                .wrapping_add(e)
+                .wrapping_add(constant)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = next;
+        }
+        for (word, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+    let mut digest = [0u8; 20];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// The MD5 digest of `bytes` (RFC 1321). Only fit for checksums, not for
+/// anything that must resist tampering.
+pub fn md5(bytes: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    let constants: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32)
+        .collect();
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for block in pad(bytes, |length| length.to_le_bytes()).chunks_exact(64) {
+        let mut m = [0u32; 16];
+        for (word, chunk) in m.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (mixed, index) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(mixed)
+                .wrapping_add(constants[i])
+                .wrapping_add(m[index])
+                .rotate_left(SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (word, add) in state.iter_mut().zip([a, b, c, d]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+    let mut digest = [0u8; 16];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+/// Merkle–Damgård padding shared by both digests: a one bit, zeros up to 56
+/// bytes into the last block, then the message length in bits.
+fn pad(bytes: &[u8], length: impl Fn(u64) -> [u8; 8]) -> Vec<u8> {
+    let mut padded = bytes.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&length((bytes.len() as u64).wrapping_mul(8)));
+    padded
+}
+
+/// Standard base64 with `=` padding (RFC 4648).
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes standard base64. Padding is optional; whitespace is not allowed.
+pub fn base64_decode(text: &str) -> Result<Vec<u8>, String> {
+    let trimmed = text.trim_end_matches('=');
+    if text.len() - trimmed.len() > 2
+        || (text.len() != trimmed.len() && !text.len().is_multiple_of(4))
+    {
+        return Err("Invalid base64 padding".to_string());
+    }
+    let mut out = Vec::with_capacity(trimmed.len() / 4 * 3 + 2);
+    let mut group = 0u32;
+    let mut bits = 0;
+    for (offset, ch) in trimmed.char_indices() {
+        let Some(sextet) = BASE64_ALPHABET.iter().position(|&c| c as char == ch) else {
+            return Err(format!(
+                "Invalid base64 character '{}' at offset {}",
+                ch, offset
+            ));
+        };
+        group = group << 6 | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((group >> bits) as u8);
+            group &= (1 << bits) - 1;
+        }
+    }
+    if bits >= 6 {
+        return Err("Invalid base64 length".to_string());
+    }
+    Ok(out)
+}
+
+/// Lowercase hexadecimal, two digits per byte.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes hexadecimal in either case.
+pub fn hex_decode(text: &str) -> Result<Vec<u8>, String> {
+    if let Some((offset, ch)) = text.char_indices().find(|(_, ch)| !ch.is_ascii_hexdigit()) {
+        return Err(format!("Invalid hex digit '{}' at offset {}", ch, offset));
+    }
+    if !text.len().is_multiple_of(2) {
+        return Err("Hex text must have an even number of digits".to_string());
+    }
+    text.as_bytes()
+        .chunks_exact(2)
+        .map(|pair| {
+            u8::from_str_radix(std::str::from_utf8(pair).unwrap_or_default(), 16)
+                .map_err(|err| err.to_string())
+        })
+        .collect()
+}
+
+/// Turns decoded bytes back into a string, for decoders that return text.
+pub fn into_text(bytes: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(bytes).map_err(|_| "Decoded bytes are not valid UTF-8".to_string())
+}
+
+/// Turns `text` into bytes with a named encoding: `utf-8`, `latin-1`,
+/// `ascii`, `hex`, or `base64`. Names are case-insensitive. For `hex` and
+/// `base64` the text is the encoded form, so the result is the decoded bytes.
+pub fn encode_text(encoding: &str, text: &str) -> Result<Vec<u8>, String> {
+    let narrow = |limit: u32| {
+        text.chars()
+            .map(|ch| {
+                u8::try_from(ch as u32)
+                    .ok()
+                    .filter(|byte| u32::from(*byte) <= limit)
+                    .ok_or_else(|| format!("'{}' cannot be encoded as {}", ch, encoding))
+            })
+            .collect()
+    };
+    match encoding.to_ascii_lowercase().as_str() {
+        "utf-8" | "utf8" => Ok(text.as_bytes().to_vec()),
+        "latin-1" | "latin1" | "iso-8859-1" => narrow(0xff),
+        "ascii" => narrow(0x7f),
+        "hex" => hex_decode(text),
+        "base64" => base64_decode(text),
+        _ => Err(format!("Unknown encoding '{}'", encoding)),
+    }
+}
+
+/// The inverse of [`encode_text`].
+pub fn decode_text(encoding: &str, bytes: &[u8]) -> Result<String, String> {
+    match encoding.to_ascii_lowercase().as_str() {
+        "utf-8" | "utf8" => std::str::from_utf8(bytes)
+            .map(str::to_string)
+            .map_err(|err| format!("Bytes are not valid UTF-8: {}", err)),
+        "latin-1" | "latin1" | "iso-8859-1" => Ok(bytes.iter().map(|&byte| byte as char).collect()),
+        "ascii" => match bytes.iter().position(|byte| !byte.is_ascii()) {
+            Some(offset) => Err(format!(
+                "Byte {:#04x} at offset {} is not ASCII",
+                bytes[offset], offset
+            )),
+            None => Ok(bytes.iter().map(|&byte| byte as char).collect()),
+        },
+        "hex" => Ok(hex_encode(bytes)),
+        "base64" => Ok(base64_encode(bytes)),
+        _ => Err(format!("Unknown encoding '{}'", encoding)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_published_digests_and_round_trips_encodings() {
+        assert_eq!(
+            hex_encode(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex_encode(&sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex_encode(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(hex_encode(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            hex_encode(&md5(b"The quick brown fox jumps over the lazy dog")),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+
+        for (plain, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64_encode(plain.as_bytes()), encoded);
+            assert_eq!(base64_decode(encoded), Ok(plain.as_bytes().to_vec()));
+        }
+        assert_eq!(base64_decode("Zm8"), Ok(b"fo".to_vec()));
+        assert!(base64_decode("Zm9v!").is_err());
+        assert!(base64_decode("Z").is_err());
+
+        assert_eq!(hex_decode("00fFa0"), Ok(vec![0x00, 0xff, 0xa0]));
+        assert!(hex_decode("abc").is_err());
+        assert!(hex_decode("zz").is_err());
+        assert!(hex_decode("é0").is_err());
+
+        assert_eq!(
+            encode_text("Latin-1", "café"),
+            Ok(vec![b'c', b'a', b'f', 0xe9])
+        );
+        assert_eq!(
+            decode_text("latin-1", &[b'c', b'a', b'f', 0xe9]),
+            Ok("café".to_string())
+        );
+        assert!(encode_text("ascii", "café").is_err());
+        assert!(decode_text("utf-8", &[0xe9]).is_err());
+        assert_eq!(decode_text("hex", &[0xca, 0xfe]), Ok("cafe".to_string()));
+        assert!(encode_text("ebcdic", "x").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Location {
@@ -19,6 +20,16 @@ pub enum LangError {
     Lexer(String, Option<Location>),
     Parser(String, Option<Location>),
     Runtime(String, Option<Location>),
+    /// Evaluation was stopped by a limit set with `Interpreter::set_limits`.
+    LimitExceeded(Limit),
+}
+
+/// The evaluation limit that stopped a program, with its configured value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    CallDepth(usize),
+    Steps(u64),
+    Timeout(Duration),
 }
 
 pub type LangResult<T> = Result<T, LangError>;
@@ -27,6 +38,7 @@ impl fmt::Display for LangError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             LangError::Io(err) => write!(f, "I/O error: {}", err),
+            LangError::LimitExceeded(limit) => write!(f, "Evaluation stopped: {}", limit),
             LangError::Lexer(msg, location) => {
                 if let Some(loc) = location {
                     // Extract just the filename from the path
@@ -82,6 +94,18 @@ impl fmt::Display for LangError {
     }
 }
 
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::CallDepth(max) => write!(f, "exceeded the maximum call depth of {}", max),
+            Limit::Steps(max) => write!(f, "exceeded the maximum of {} evaluation steps", max),
+            Limit::Timeout(timeout) => {
+                write!(f, "exceeded the timeout of {} ms", timeout.as_millis())
+            }
+        }
+    }
+}
+
 impl std::error::Error for LangError {}
 
 impl From<std::io::Error> for LangError {
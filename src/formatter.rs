@@ -0,0 +1,1028 @@
+use crate::{
+    ast::{
+        BinaryOperator, Expression, Function, ObjectField, ObjectPatternField, Pattern, Program,
+        Signature, Statement, StringSegment, StringTemplate, UseStatement,
+    },
+    config,
+    error::byte_offset_to_line,
+    interpreter::Value,
+    lexer::{Token, TokenKind},
+    syntax::SyntaxTree,
+};
+use std::{
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+};
+
+/// Name of the formatter configuration file, looked up from the formatted
+/// file's directory upwards.
+pub const CONFIG_FILE_NAME: &str = ".fipfmt.toml";
+
+/// Whether lists, call arguments, and objects that are broken across lines
+/// end with a comma after the last item. Single-line lists never do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingComma {
+    Never,
+    Always,
+}
+
+/// Where blank lines go between top-level statements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlankLines {
+    /// One blank line between every pair of statements.
+    Always,
+    /// Consecutive imports, assignments, or expressions stay together; a
+    /// blank line separates groups and surrounds function definitions.
+    Grouped,
+    /// One blank line where the source has one or more, so statements
+    /// written together stay together. Statements that span several lines
+    /// once formatted, such as function definitions, are still set apart.
+    Preserve,
+}
+
+/// Formatter settings shared by `fip format` and `fip-format`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub indent_size: usize,
+    pub max_width: usize,
+    pub trailing_comma: TrailingComma,
+    pub blank_lines: BlankLines,
+    /// Pad the field names of an object so the values line up.
+    pub align_fields: bool,
+    /// Sort the `use` statements at the top of the file by module path.
+    pub sort_imports: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_size: 2,
+            max_width: 80,
+            trailing_comma: TrailingComma::Never,
+            blank_lines: BlankLines::Preserve,
+            align_fields: false,
+            sort_imports: false,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Parses a `.fipfmt.toml` document. Every key is optional:
+    ///
+    /// ```toml
+    /// indent-size = 2
+    /// max-width = 80
+    /// trailing-comma = "never"   # or "always"
+    /// blank-lines = "preserve"   # or "always" or "grouped"
+    /// align-fields = false
+    /// sort-imports = false
+    /// ```
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let fields = match config::parse_toml(text)? {
+            Value::Object(fields) => fields,
+            other => return Err(format!("expected a table, found {:?}", other)),
+        };
+
+        let mut options = Self::default();
+        for (key, value) in fields {
+            match (key.as_str(), value) {
+                ("indent-size", Value::Number(n)) if n > 0 => options.indent_size = n as usize,
+                ("max-width", Value::Number(n)) if n > 0 => options.max_width = n as usize,
+                ("trailing-comma", Value::String(s)) if s == "never" => {
+                    options.trailing_comma = TrailingComma::Never
+                }
+                ("trailing-comma", Value::String(s)) if s == "always" => {
+                    options.trailing_comma = TrailingComma::Always
+                }
+                ("blank-lines", Value::String(s)) if s == "always" => {
+                    options.blank_lines = BlankLines::Always
+                }
+                ("blank-lines", Value::String(s)) if s == "grouped" => {
+                    options.blank_lines = BlankLines::Grouped
+                }
+                ("blank-lines", Value::String(s)) if s == "preserve" => {
+                    options.blank_lines = BlankLines::Preserve
+                }
+                ("align-fields", Value::Boolean(align)) => options.align_fields = align,
+                ("sort-imports", Value::Boolean(sort)) => options.sort_imports = sort,
+                ("indent-size" | "max-width", value) => {
+                    return Err(format!(
+                        "'{}' must be a positive integer, found {:?}",
+                        key, value
+                    ))
+                }
+                ("trailing-comma", value) => {
+                    return Err(format!(
+                        "'trailing-comma' must be \"never\" or \"always\", found {:?}",
+                        value
+                    ))
+                }
+                ("blank-lines", value) => {
+                    return Err(format!(
+                    "'blank-lines' must be \"preserve\", \"always\", or \"grouped\", found {:?}",
+                    value
+                ))
+                }
+                ("align-fields" | "sort-imports", value) => {
+                    return Err(format!(
+                        "'{}' must be true or false, found {:?}",
+                        key, value
+                    ))
+                }
+                (other, _) => return Err(format!("unknown setting '{}'", other)),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Finds the nearest `.fipfmt.toml` in `file`'s directory or one of its
+    /// ancestors and loads it. Returns the defaults and no path when there is
+    /// none.
+    pub fn discover(file: &Path) -> Result<(Self, Option<PathBuf>), String> {
+        let start = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+        for dir in start.ancestors().skip(1) {
+            let candidate = dir.join(CONFIG_FILE_NAME);
+            if candidate.is_file() {
+                let text = std::fs::read_to_string(&candidate)
+                    .map_err(|err| format!("Failed to read '{}': {}", candidate.display(), err))?;
+                let options = Self::from_toml(&text)
+                    .map_err(|err| format!("Invalid '{}': {}", candidate.display(), err))?;
+                return Ok((options, Some(candidate)));
+            }
+        }
+        Ok((Self::default(), None))
+    }
+}
+
+/// Pretty-prints a parsed program. Comments are not part of the AST, so
+/// only doc comments are preserved unless the formatter is given the source
+/// with [`Formatter::with_source`], which keeps the comments between
+/// top-level statements and at the end of their last lines as well, and
+/// leaves statements with comments inside them as they were written.
+pub struct Formatter {
+    indent_level: usize,
+    options: FormatOptions,
+    // Width of the text already written on the current line before the
+    // expression being formatted, past the indentation.
+    line_prefix: usize,
+    // Whether the source has a blank line above each top-level statement,
+    // for `BlankLines::Preserve`; empty when the source is not known.
+    blank_before: Vec<bool>,
+    // The `//` comments above each top-level statement, and last those
+    // after the final one; empty when the source is not known.
+    comments_above: Vec<Vec<String>>,
+    // The comment at the end of each top-level statement's last line.
+    trailing_comments: Vec<Option<String>>,
+    // The source text of each top-level statement with comments inside it,
+    // which has nowhere to keep them once formatted.
+    verbatim: Vec<Option<String>>,
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Self::with_options(FormatOptions::default())
+    }
+
+    pub fn with_options(options: FormatOptions) -> Self {
+        Self {
+            indent_level: 0,
+            options,
+            line_prefix: 0,
+            blank_before: Vec::new(),
+            comments_above: Vec::new(),
+            trailing_comments: Vec::new(),
+            verbatim: Vec::new(),
+        }
+    }
+
+    /// Tells the formatter where `source` has blank lines between top-level
+    /// statements, which [`BlankLines::Preserve`] keeps, and which comments
+    /// it has outside of them. `statement_starts` are from the parser that
+    /// read the program from `source`. A blank line above a statement's
+    /// comments counts as one above the statement, and a statement with
+    /// comments inside it is kept as written.
+    pub fn with_source(mut self, source: &str, statement_starts: &[usize]) -> Self {
+        self.blank_before = statement_starts
+            .iter()
+            .map(|&start| {
+                let line_start = source[..start].rfind('\n').map_or(0, |newline| newline + 1);
+                source[..line_start]
+                    .lines()
+                    .rev()
+                    .map(str::trim)
+                    .find(|line| !line.starts_with("//"))
+                    .is_some_and(str::is_empty)
+            })
+            .collect();
+        if let Ok(tree) = SyntaxTree::parse(source) {
+            // Docs are already part of the program
+            let comments = |range| -> Vec<String> {
+                tree.comments(range)
+                    .into_iter()
+                    .filter(|comment| !comment.starts_with("///") && !comment.starts_with("//!"))
+                    .map(str::to_string)
+                    .collect()
+            };
+            self.comments_above = vec![comments(
+                0..statement_starts.first().map_or(0, |&start| start),
+            )];
+            for (index, &start) in statement_starts.iter().enumerate() {
+                let next = statement_starts
+                    .get(index + 1)
+                    .map_or(usize::MAX, |&next| next);
+                let last = last_token(tree.tokens().iter().map(|token| &token.token), start, next);
+                self.trailing_comments.push(comments(last.clone()).pop());
+                self.comments_above.push(comments(last.end..next));
+                let inside = !tree.comments(start..last.start).is_empty();
+                self.verbatim
+                    .push(inside.then(|| source[start..last.end].to_string()));
+            }
+        }
+        self
+    }
+
+    fn indent(&self) -> String {
+        " ".repeat(self.indent_level * self.options.indent_size)
+    }
+
+    /// Whether `text` can stay on the current line. Only the first line
+    /// counts; blocks and objects inside it already span several lines.
+    fn fits(&self, text: &str) -> bool {
+        let first_line = text.lines().next().unwrap_or("");
+        self.indent_level * self.options.indent_size + self.line_prefix + first_line.chars().count()
+            <= self.options.max_width
+    }
+
+    fn with_line_prefix(&mut self, prefix: usize, f: impl FnOnce(&mut Self) -> String) -> String {
+        let old_prefix = self.line_prefix;
+        self.line_prefix = prefix;
+        let result = f(self);
+        self.line_prefix = old_prefix;
+        result
+    }
+
+    /// Puts each item on its own line, one level deeper than the brackets.
+    fn format_broken(&mut self, open: &str, items: &[&Expression], close: &str) -> String {
+        let old_indent = self.indent_level;
+        self.indent_level += 1;
+        let formatted: Vec<String> = items
+            .iter()
+            .map(|item| {
+                let text = self.with_line_prefix(0, |f| f.format_list_item(item));
+                format!("{}{}", self.indent(), text)
+            })
+            .collect();
+        self.indent_level = old_indent;
+        let trailing = match self.options.trailing_comma {
+            TrailingComma::Always => ",",
+            TrailingComma::Never => "",
+        };
+        format!(
+            "{}\n{}{}\n{}{}",
+            open,
+            formatted.join(",\n"),
+            trailing,
+            self.indent(),
+            close
+        )
+    }
+
+    /// Formats only the top-level statements of `program` that touch
+    /// `lines`, counting from 1, and leaves the rest of `source` byte for
+    /// byte as it was, comments and blank lines between statements
+    /// included. `tokens` and `statement_starts` are the ones `program` was
+    /// parsed from. Imports are not sorted. A statement with comments
+    /// inside it is left as it is too, since formatting it would drop them.
+    pub fn format_lines(
+        &mut self,
+        program: &Program,
+        source: &str,
+        tokens: &[Token],
+        statement_starts: &[usize],
+        lines: RangeInclusive<usize>,
+    ) -> String {
+        let tree = SyntaxTree::from_tokens(source, tokens.to_vec());
+        let mut output = String::new();
+        let mut copied = 0;
+        for (index, (stmt, &start)) in program.statements.iter().zip(statement_starts).enumerate() {
+            let next = statement_starts
+                .get(index + 1)
+                .map_or(usize::MAX, |&next| next);
+            let last = last_token(tokens.iter(), start, next);
+            let touched = byte_offset_to_line(source, start) <= *lines.end()
+                && byte_offset_to_line(source, last.end) >= *lines.start();
+            if touched && tree.comments(start..last.start).is_empty() {
+                output.push_str(&source[copied..start]);
+                output.push_str(&self.format_statement(stmt));
+                copied = last.end;
+            }
+        }
+        output.push_str(&source[copied..]);
+        output
+    }
+
+    fn format_list_item(&mut self, expr: &Expression) -> String {
+        match expr {
+            Expression::Spread(expr) => format!("...{}", self.format_expression(expr.as_ref())),
+            other => self.format_expression(other),
+        }
+    }
+
+    pub fn format_program(&mut self, program: &Program) -> String {
+        let mut output = Vec::new();
+
+        if let Some(doc) = &program.doc {
+            output.push(Self::format_doc("//!", doc));
+            if !program.statements.is_empty() {
+                output.push(String::new());
+            }
+        }
+        let mut statements: Vec<(usize, &Statement)> =
+            program.statements.iter().enumerate().collect();
+        let imports = if self.options.sort_imports {
+            statements
+                .iter()
+                .take_while(|(_, stmt)| matches!(stmt, Statement::Use(_)))
+                .count()
+        } else {
+            0
+        };
+        statements[..imports].sort_by_key(|(_, stmt)| match stmt {
+            Statement::Use(use_stmt) => use_stmt.module_path(),
+            _ => "",
+        });
+        let mut previous: Option<(&Statement, String)> = None;
+        for (i, (index, stmt)) in statements.into_iter().enumerate() {
+            let mut text = match self.verbatim.get(index) {
+                Some(Some(text)) => text.clone(),
+                _ => self.format_statement(stmt),
+            };
+            if let Some(Some(comment)) = self.trailing_comments.get(index) {
+                text = format!("{} {}", text, comment);
+            }
+            if let Some((previous, previous_text)) = &previous {
+                // Sorted imports lose the blank lines they had between them
+                let blank_in_source = i >= imports && self.blank_before.get(index) == Some(&true);
+                if self.blank_line_between(blank_in_source, previous, previous_text, stmt, &text) {
+                    output.push(String::new());
+                }
+            }
+            let doc = match stmt {
+                Statement::Function(Function { doc, .. }) | Statement::Assignment { doc, .. } => {
+                    doc.as_deref()
+                }
+                _ => None,
+            };
+            if let Some(comments) = self.comments_above.get(index) {
+                output.extend(comments.iter().cloned());
+            }
+            if let Some(doc) = doc {
+                output.push(Self::format_doc("///", doc));
+            }
+            output.push(text.clone());
+            previous = Some((stmt, text));
+        }
+        if let Some(comments) = self.comments_above.get(program.statements.len()) {
+            output.extend(comments.iter().cloned());
+        }
+
+        output.join("\n")
+    }
+
+    fn format_doc(marker: &str, doc: &str) -> String {
+        doc.lines()
+            .map(|line| {
+                if line.is_empty() {
+                    marker.to_string()
+                } else {
+                    format!("{} {}", marker, line)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Whether a blank line goes between two statements, given them
+    /// formatted and whether the source has one there.
+    fn blank_line_between(
+        &self,
+        blank_in_source: bool,
+        previous: &Statement,
+        previous_text: &str,
+        next: &Statement,
+        next_text: &str,
+    ) -> bool {
+        // Statements of the same group stay together; functions stand alone
+        fn group(stmt: &Statement) -> Option<u8> {
+            match stmt {
+                Statement::Use(_) | Statement::ConditionalUse { .. } => Some(0),
+                Statement::Assignment { .. } => Some(1),
+                Statement::Expression(_) => Some(2),
+                Statement::Export(_) => Some(3),
+                Statement::Function(_) => None,
+            }
+        }
+        match self.options.blank_lines {
+            BlankLines::Always => true,
+            BlankLines::Grouped => group(previous).is_none() || group(previous) != group(next),
+            BlankLines::Preserve => {
+                blank_in_source || previous_text.contains('\n') || next_text.contains('\n')
+            }
+        }
+    }
+
+    fn format_statement(&mut self, stmt: &Statement) -> String {
+        match stmt {
+            Statement::Assignment { pattern, expr, .. } => {
+                let pattern = self.format_pattern(pattern);
+                let expr = self
+                    .with_line_prefix(pattern.chars().count() + 2, |f| f.format_expression(expr));
+                format!("{}: {}", pattern, expr)
+            }
+            Statement::Function(func) => self.format_function(func),
+            Statement::Expression(expr) => self.format_expression(expr),
+            Statement::Use(use_stmt) => self.format_use_statement(use_stmt),
+            Statement::ConditionalUse {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let then_text = self.format_use_statement(then);
+                let then_path = format!("\"{}\"", then.module_path());
+                let head = then_text.strip_suffix(&then_path).unwrap_or(&then_text);
+                let condition = self.format_expression(condition);
+                match otherwise {
+                    Some(otherwise) => format!(
+                        "{}when({}, {}, \"{}\")",
+                        head,
+                        condition,
+                        then_path,
+                        otherwise.module_path()
+                    ),
+                    None => format!("{}when({}, {})", head, condition, then_path),
+                }
+            }
+            Statement::Export(export) => format!("export {}", export.name),
+        }
+    }
+
+    fn format_pattern(&mut self, pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Identifier(name) => name.clone(),
+            Pattern::List(patterns) => {
+                let formatted: Vec<String> =
+                    patterns.iter().map(|p| self.format_pattern(p)).collect();
+                format!("[{}]", formatted.join(", "))
+            }
+            Pattern::Object(fields) => {
+                let formatted: Vec<String> = fields
+                    .iter()
+                    .map(|f| match f {
+                        ObjectPatternField::Shorthand(name) => name.clone(),
+                        ObjectPatternField::Field { name, pattern } => {
+                            format!("{}: {}", name, self.format_pattern(pattern))
+                        }
+                    })
+                    .collect();
+                format!("{{ {} }}", formatted.join(", "))
+            }
+        }
+    }
+
+    fn format_function(&mut self, func: &Function) -> String {
+        let notation = if func.impure {
+            "!"
+        } else if func.name.ends_with('?') {
+            "?"
+        } else {
+            ""
+        };
+
+        let name = if func.impure {
+            func.name.strip_suffix('!').unwrap_or(&func.name)
+        } else if func.name.ends_with('?') {
+            func.name.strip_suffix('?').unwrap_or(&func.name)
+        } else {
+            &func.name
+        };
+
+        let params_str = Self::format_params(&func.params, &func.signature);
+        let old_indent = self.indent_level;
+        self.indent_level += 1;
+        let body_str = self.format_expression_with_indent(&func.body);
+        self.indent_level = old_indent;
+
+        format!(
+            "{}{}: ({}){} {{\n{}\n}}",
+            name,
+            notation,
+            params_str,
+            Self::format_result(&func.signature),
+            body_str
+        )
+    }
+
+    /// Renders a parameter list, keeping any type annotations.
+    fn format_params(params: &[String], signature: &Signature) -> String {
+        let params: Vec<String> = params
+            .iter()
+            .enumerate()
+            .map(|(index, name)| match signature.params.get(index) {
+                Some(Some(ty)) => format!("{}: {}", name, ty),
+                _ => name.clone(),
+            })
+            .collect();
+        params.join(", ")
+    }
+
+    /// The `: Type` that follows the parameter list, if annotated.
+    fn format_result(signature: &Signature) -> String {
+        match &signature.result {
+            Some(result) => format!(": {}", result),
+            None => String::new(),
+        }
+    }
+
+    fn format_use_statement(&mut self, use_stmt: &UseStatement) -> String {
+        match use_stmt {
+            UseStatement::Single { name, module_path } => {
+                format!("use {} from \"{}\"", name, module_path)
+            }
+            UseStatement::Namespace { alias, module_path } => {
+                format!("use {} as \"{}\"", alias, module_path)
+            }
+            UseStatement::Selective { names, module_path } => {
+                let names_str = names.join(", ");
+                format!("use {{ {} }} from \"{}\"", names_str, module_path)
+            }
+            UseStatement::Glob { module_path } => {
+                format!("use * from \"{}\"", module_path)
+            }
+        }
+    }
+
+    /// Formats one expression on its own, as it would appear at the start
+    /// of a line.
+    pub fn format_expression(&mut self, expr: &Expression) -> String {
+        match expr {
+            Expression::Number { value, spelling } => match spelling {
+                Some(spelling) => spelling.clone(),
+                None => value.to_string(),
+            },
+            Expression::String(template) => self.format_string_template(template),
+            Expression::Boolean(b) => b.to_string(),
+            Expression::Null => "null".to_string(),
+            Expression::Identifier(name) => name.clone(),
+            Expression::Block(exprs) => {
+                if exprs.is_empty() {
+                    return "{}".to_string();
+                }
+                let old_indent = self.indent_level;
+                self.indent_level += 1;
+                let formatted: Vec<String> = exprs
+                    .iter()
+                    .map(|e| format!("{}{}", self.indent(), self.format_expression(e)))
+                    .collect();
+                self.indent_level = old_indent;
+                format!("{{\n{}\n{}}}", formatted.join("\n"), self.indent())
+            }
+            Expression::Lambda {
+                params,
+                signature,
+                body,
+                impure,
+            } => {
+                let notation = if *impure { "!" } else { "" };
+                let params_str = Self::format_params(params, signature);
+                let body_str = self.format_lambda_body(body);
+                format!(
+                    "({}){}{} {}",
+                    params_str,
+                    notation,
+                    Self::format_result(signature),
+                    body_str
+                )
+            }
+            Expression::Object(fields) => match fields.split_first() {
+                Some((ObjectField::Base(base), rest)) => {
+                    let base = self.format_postfix_target(base);
+                    format!("{} with {}", base, self.format_object(rest))
+                }
+                _ => self.format_object(fields),
+            },
+            Expression::List(elements) => {
+                if elements.is_empty() {
+                    return "[]".to_string();
+                }
+                let formatted: Vec<String> =
+                    elements.iter().map(|e| self.format_list_item(e)).collect();
+                let flat = format!("[{}]", formatted.join(", "));
+                if self.fits(&flat) {
+                    flat
+                } else {
+                    let items: Vec<&Expression> = elements.iter().collect();
+                    self.format_broken("[", &items, "]")
+                }
+            }
+            Expression::Spread(expr) => {
+                format!("...{}", self.format_expression(expr.as_ref()))
+            }
+            // Resolved programs print the way they were written
+            Expression::Local(slot) => slot.name.clone(),
+            Expression::Closure { lambda, .. } => self.format_expression(lambda),
+            Expression::Not(expr) => {
+                let text = self.format_expression(expr);
+                match expr.as_ref() {
+                    Expression::Binary { .. } | Expression::Lambda { .. } => format!("!({})", text),
+                    _ => format!("!{}", text),
+                }
+            }
+            Expression::Call { callee, args } => {
+                let callee_str = self.format_postfix_target(callee);
+                let args_str: Vec<String> =
+                    args.iter().map(|a| self.format_expression(a)).collect();
+                let flat = format!("{}({})", callee_str, args_str.join(", "));
+                if args.is_empty() || self.fits(&flat) {
+                    flat
+                } else {
+                    let items: Vec<&Expression> = args.iter().collect();
+                    self.format_broken(&format!("{}(", callee_str), &items, ")")
+                }
+            }
+            Expression::PropertyAccess {
+                object,
+                property,
+                optional,
+            } => format!(
+                "{}{}{}",
+                self.format_postfix_target(object),
+                if *optional { "?." } else { "." },
+                property
+            ),
+            Expression::Binary { left, op, right } => {
+                let left_str = self.format_operand(left, op.precedence(), false);
+                let right_str = self.format_operand(right, op.precedence(), true);
+                let op_str = match op {
+                    BinaryOperator::Add => "+",
+                    BinaryOperator::Sub => "-",
+                    BinaryOperator::Mul => "*",
+                    BinaryOperator::Div => "/",
+                    BinaryOperator::Eq => "=",
+                    BinaryOperator::NotEq => "≠",
+                    BinaryOperator::LessThan => "<",
+                    BinaryOperator::LessThanEq => "<=",
+                    BinaryOperator::GreaterThan => ">",
+                    BinaryOperator::GreaterThanEq => ">=",
+                    BinaryOperator::And => "&",
+                    BinaryOperator::Or => "|",
+                    BinaryOperator::Coalesce => "??",
+                    BinaryOperator::PipeForward => "|>",
+                };
+                format!("{} {} {}", left_str, op_str, right_str)
+            }
+        }
+    }
+
+    /// Formats one side of a binary expression, adding the parentheses the
+    /// source needed. Operators are left-associative, so a right operand of
+    /// equal precedence needs them too.
+    fn format_operand(&mut self, operand: &Expression, parent: u8, is_right: bool) -> String {
+        let text = self.format_expression(operand);
+        match operand {
+            Expression::Binary { op, .. }
+                if op.precedence() < parent || (is_right && op.precedence() == parent) =>
+            {
+                format!("({})", text)
+            }
+            Expression::Lambda { .. } => format!("({})", text),
+            _ => text,
+        }
+    }
+
+    /// Formats an object literal's braces and fields, one field per line.
+    fn format_object(&mut self, fields: &[ObjectField]) -> String {
+        if fields.is_empty() {
+            return "{}".to_string();
+        }
+        // Aligned names are padded to the longest one
+        let aligned_width = fields
+            .iter()
+            .filter_map(|field| match field {
+                ObjectField::Field { name, .. } => Some(name.chars().count()),
+                _ => None,
+            })
+            .max()
+            .filter(|_| self.options.align_fields);
+        let old_indent = self.indent_level;
+        self.indent_level += 1;
+        let formatted: Vec<String> = fields
+            .iter()
+            .map(|f| match f {
+                ObjectField::Field { name, value } => {
+                    let width = aligned_width.unwrap_or(name.chars().count());
+                    let value = self.with_line_prefix(width + 2, |f| f.format_expression(value));
+                    format!(
+                        "{}{:width$} {}",
+                        self.indent(),
+                        format!("{}:", name),
+                        value,
+                        width = width + 1
+                    )
+                }
+                // `with` bases only come first, which `format_expression` handles
+                ObjectField::Spread(expr) | ObjectField::Base(expr) => {
+                    format!("{}...{}", self.indent(), self.format_expression(expr))
+                }
+            })
+            .collect();
+        self.indent_level = old_indent;
+        let trailing = match self.options.trailing_comma {
+            TrailingComma::Always => ",",
+            TrailingComma::Never => "",
+        };
+        format!(
+            "{{\n{}{}\n{}}}",
+            formatted.join(",\n"),
+            trailing,
+            self.indent()
+        )
+    }
+
+    /// Formats the callee of a call or the object of a property access,
+    /// which bind tighter than any operator, `!` included.
+    fn format_postfix_target(&mut self, expr: &Expression) -> String {
+        let text = self.format_expression(expr);
+        match expr {
+            Expression::Binary { .. } | Expression::Lambda { .. } | Expression::Not(_) => {
+                format!("({})", text)
+            }
+            _ => text,
+        }
+    }
+
+    fn format_lambda_body(&mut self, body: &Expression) -> String {
+        match body {
+            Expression::Block(exprs) => {
+                if exprs.is_empty() {
+                    return "{}".to_string();
+                }
+                // Check if body is simple (single expression, not too complex)
+                if exprs.len() == 1 && self.is_simple_expression(&exprs[0]) {
+                    let body_str = self.format_expression(&exprs[0]);
+                    format!("{{ {} }}", body_str)
+                } else {
+                    let old_indent = self.indent_level;
+                    self.indent_level += 1;
+                    let formatted: Vec<String> = exprs
+                        .iter()
+                        .map(|e| format!("{}{}", self.indent(), self.format_expression(e)))
+                        .collect();
+                    self.indent_level = old_indent;
+                    format!("{{\n{}\n{}}}", formatted.join("\n"), self.indent())
+                }
+            }
+            _ => {
+                let body_str = self.format_expression(body);
+                format!("{{ {} }}", body_str)
+            }
+        }
+    }
+
+    fn is_simple_expression(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Number { .. }
+            | Expression::String(_)
+            | Expression::Boolean(_)
+            | Expression::Null
+            | Expression::Identifier(_) => true,
+            Expression::Binary { left, right, .. } => {
+                self.is_simple_expression(left) && self.is_simple_expression(right)
+            }
+            Expression::PropertyAccess { object, .. } => {
+                matches!(**object, Expression::Identifier(_))
+            }
+            Expression::Call { callee, args } => {
+                matches!(**callee, Expression::Identifier(_))
+                    && args.len() <= 2
+                    && args.iter().all(|a| self.is_simple_expression(a))
+            }
+            _ => false,
+        }
+    }
+
+    fn format_expression_with_indent(&mut self, expr: &Expression) -> String {
+        match expr {
+            Expression::Block(exprs) => {
+                if exprs.is_empty() {
+                    return self.indent();
+                }
+                let formatted: Vec<String> = exprs
+                    .iter()
+                    .map(|e| format!("{}{}", self.indent(), self.format_expression(e)))
+                    .collect();
+                formatted.join("\n")
+            }
+            _ => {
+                format!("{}{}", self.indent(), self.format_expression(expr))
+            }
+        }
+    }
+
+    fn format_string_template(&self, template: &StringTemplate) -> String {
+        let mut result = String::from("\"");
+        for segment in &template.segments {
+            match segment {
+                StringSegment::Literal(s) => {
+                    // Escape special characters
+                    let escaped = s
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\"")
+                        .replace('\n', "\\n")
+                        .replace('\r', "\\r")
+                        .replace('\t', "\\t");
+                    result.push_str(&escaped);
+                }
+                StringSegment::Expr(expr) => {
+                    result.push('<');
+                    result.push_str(&self.format_expression_inline(expr));
+                    result.push('>');
+                }
+            }
+        }
+        result.push('"');
+        result
+    }
+
+    fn format_expression_inline(&self, expr: &Expression) -> String {
+        match expr {
+            Expression::Identifier(name) => name.clone(),
+            Expression::PropertyAccess {
+                object,
+                property,
+                optional,
+            } => format!(
+                "{}{}{}",
+                self.format_expression_inline(object),
+                if *optional { "?." } else { "." },
+                property
+            ),
+            _ => {
+                // For complex expressions, just format normally
+                let mut formatter = Formatter::new();
+                formatter.format_expression(expr)
+            }
+        }
+    }
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The span of the last token of the top-level statement that starts at
+/// `start`, with the next one starting at `next`.
+fn last_token<'a>(
+    tokens: impl DoubleEndedIterator<Item = &'a Token>,
+    start: usize,
+    next: usize,
+) -> Range<usize> {
+    tokens
+        .rev()
+        .filter(|token| (start..next).contains(&token.span.start))
+        .find(|token| !matches!(token.kind, TokenKind::Newline | TokenKind::Eof))
+        .map_or(start..start, |token| token.span.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn reads_settings_and_rejects_unknown_keys() {
+        let options = FormatOptions::from_toml(
+            "indent-size = 4\ntrailing-comma = \"always\"\nblank-lines = \"grouped\"\n\
+             sort-imports = true\n",
+        )
+        .expect("valid config");
+        assert_eq!(
+            options,
+            FormatOptions {
+                indent_size: 4,
+                max_width: 80,
+                trailing_comma: TrailingComma::Always,
+                blank_lines: BlankLines::Grouped,
+                align_fields: false,
+                sort_imports: true,
+            }
+        );
+
+        let err = FormatOptions::from_toml("max-width = \"wide\"\n").expect_err("bad width");
+        assert!(err.contains("positive integer"));
+        let err = FormatOptions::from_toml("tabs = true\n").expect_err("unknown key");
+        assert!(err.contains("unknown setting 'tabs'"));
+        let err = FormatOptions::from_toml("align-fields = 1\n").expect_err("not a boolean");
+        assert!(err.contains("true or false"));
+    }
+
+    #[test]
+    fn preserve_keeps_one_blank_line_where_the_source_has_any() {
+        let source = "a: 1\nb: 2\n\n\n// c\nc: 3\nf: (x) { x }\nlog!(a) // done\n// end\n";
+        let mut parser = Parser::new(Lexer::new(source).lex().expect("lexes"));
+        let program = parser.parse_program().expect("parses");
+        let format = |blank_lines| {
+            let options = FormatOptions {
+                blank_lines,
+                ..FormatOptions::default()
+            };
+            Formatter::with_options(options)
+                .with_source(source, parser.statement_starts())
+                .format_program(&program)
+        };
+        assert_eq!(
+            format(BlankLines::Preserve),
+            "a: 1\nb: 2\n\n// c\nc: 3\n\nf: (x) {\n  x\n}\n\nlog!(a) // done\n// end"
+        );
+        assert_eq!(
+            format(BlankLines::Always),
+            "a: 1\n\nb: 2\n\n// c\nc: 3\n\nf: (x) {\n  x\n}\n\nlog!(a) // done\n// end"
+        );
+    }
+
+    #[test]
+    fn statements_with_comments_inside_are_kept_as_written() {
+        let source = "// Doubles.\nf: (x) {\n  // inner\n  x  * 2 } // after\ng:   2\n";
+        let mut parser = Parser::new(Lexer::new(source).lex().expect("lexes"));
+        let program = parser.parse_program().expect("parses");
+        assert_eq!(
+            Formatter::new()
+                .with_source(source, parser.statement_starts())
+                .format_program(&program),
+            "// Doubles.\nf: (x) {\n  // inner\n  x  * 2 } // after\n\ng: 2"
+        );
+    }
+
+    #[test]
+    fn aligns_fields_adds_trailing_commas_and_sorts_imports() {
+        let source = "use * from \"text\"\n\nuse { max } from \"math\"\n\
+                      point: { x: 1, label: \"a\" }\n";
+        let mut parser = Parser::new(Lexer::new(source).lex().expect("lexes"));
+        let program = parser.parse_program().expect("parses");
+        let options = FormatOptions {
+            trailing_comma: TrailingComma::Always,
+            align_fields: true,
+            sort_imports: true,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            Formatter::with_options(options)
+                .with_source(source, parser.statement_starts())
+                .format_program(&program),
+            "use { max } from \"math\"\nuse * from \"text\"\n\n\
+             point: {\n  x:     1,\n  label: \"a\",\n}"
+        );
+    }
+
+    #[test]
+    fn formats_only_the_statements_on_the_given_lines() {
+        let source = "a:   1\n// kept\nf: (x) {   x }\n\n\nb:   [1,2]\n";
+        let tokens = Lexer::new(source).lex().expect("lexes");
+        let mut parser = Parser::new(tokens.clone());
+        let program = parser.parse_program().expect("parses");
+        let format = |lines| {
+            Formatter::new().format_lines(
+                &program,
+                source,
+                &tokens,
+                parser.statement_starts(),
+                lines,
+            )
+        };
+        assert_eq!(
+            format(3..=3),
+            "a:   1\n// kept\nf: (x) {\n  x\n}\n\n\nb:   [1,2]\n"
+        );
+        assert_eq!(
+            format(1..=6),
+            "a: 1\n// kept\nf: (x) {\n  x\n}\n\n\nb: [1, 2]\n"
+        );
+        assert_eq!(format(4..=5), source);
+
+        let source = "f: (x) {\n  // inner\n  x   }\ng:   2\n";
+        let tokens = Lexer::new(source).lex().expect("lexes");
+        let mut parser = Parser::new(tokens.clone());
+        let program = parser.parse_program().expect("parses");
+        assert_eq!(
+            Formatter::new().format_lines(
+                &program,
+                source,
+                &tokens,
+                parser.statement_starts(),
+                1..=4,
+            ),
+            "f: (x) {\n  // inner\n  x   }\ng: 2\n"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Memory diagnostics behind `fip run --heap-stats`. Values are plain owned
+//! data, so copying a big list or record copies every element; these counts
+//! show which kinds of values a program keeps alive and how much it copies.
+//!
+//! Sizes are approximate: each value counts the bytes it owns directly, such
+//! as a string's buffer or a list's element slots, not allocator overhead.
+
+use crate::interpreter::Value;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::mem::size_of;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+impl Tally {
+    fn add(&mut self, bytes: usize) {
+        self.count += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// What a program holds at one point, plus the clones made while it ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Values reachable from the global scope and loaded modules, by kind.
+    /// Functions and lazy sequences are shared, so each counts once.
+    pub values: BTreeMap<&'static str, Tally>,
+    /// Scopes kept alive by the global scope, modules, and closures.
+    pub environments: Tally,
+    pub bindings: u64,
+    /// The most scopes a name lookup can walk through.
+    pub longest_chain: usize,
+    /// Values copied, by kind, including the elements of copied lists and
+    /// records. Empty unless collected with [`count_clones`].
+    pub clones: BTreeMap<&'static str, Tally>,
+}
+
+impl HeapStats {
+    pub(crate) fn record_value(&mut self, value: &Value) {
+        self.values
+            .entry(kind(value))
+            .or_default()
+            .add(owned_bytes(value));
+    }
+
+    pub(crate) fn record_environment(&mut self, bindings: usize, bytes: usize, chain: usize) {
+        self.environments.add(bytes);
+        self.bindings += bindings as u64;
+        self.longest_chain = self.longest_chain.max(chain);
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let mut table = |title: &str, rows: &BTreeMap<&'static str, Tally>| {
+            report.push_str(&format!(
+                "{:<16}  {:>10}  {:>12}\n",
+                title, "count", "bytes"
+            ));
+            for (kind, tally) in rows {
+                report.push_str(&format!(
+                    "  {:<14}  {:>10}  {:>12}\n",
+                    kind, tally.count, tally.bytes
+                ));
+            }
+        };
+        table("reachable values", &self.values);
+        table("clones", &self.clones);
+        report.push_str(&format!(
+            "environments      {:>10}  {:>12}\n  bindings        {:>10}\n  longest chain   {:>10}\n",
+            self.environments.count, self.environments.bytes, self.bindings, self.longest_chain
+        ));
+        report
+    }
+}
+
+pub(crate) fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Number(_) => "numbers",
+        Value::String(_) => "strings",
+        Value::Bytes(_) => "bytes",
+        Value::Boolean(_) => "booleans",
+        Value::List(_) => "lists",
+        Value::Set(_) => "sets",
+        Value::Map(_) => "maps",
+        Value::Object(_) => "records",
+        Value::Function(_) => "functions",
+        Value::Builtin(_) => "builtins",
+        Value::Partial { .. } => "partial applications",
+        Value::Lazy(_) => "lazy sequences",
+        Value::Task(_) => "tasks",
+        Value::Channel(_) => "channels",
+        Value::Null | Value::Unit => "null",
+    }
+}
+
+/// Bytes the value owns outside its own slot. Elements of lists and records
+/// are values of their own and are counted separately.
+pub(crate) fn owned_bytes(value: &Value) -> usize {
+    match value {
+        Value::String(text) => text.capacity(),
+        Value::Bytes(bytes) => bytes.capacity(),
+        Value::List(items) | Value::Set(items) => items.capacity() * size_of::<Value>(),
+        Value::Map(entries) => entries.capacity() * size_of::<(Value, Value)>(),
+        Value::Object(fields) => fields
+            .keys()
+            .map(|key| size_of::<String>() + key.capacity() + size_of::<Value>())
+            .sum(),
+        Value::Partial { applied, .. } => applied.len() * size_of::<Value>(),
+        _ => 0,
+    }
+}
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static CLONES: RefCell<BTreeMap<&'static str, Tally>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Runs `run` while counting every `Value` clone on this thread.
+pub fn count_clones<T>(run: impl FnOnce() -> T) -> (T, BTreeMap<&'static str, Tally>) {
+    CLONES.with(|clones| clones.borrow_mut().clear());
+    COUNTING.with(|counting| counting.set(true));
+    let result = run();
+    COUNTING.with(|counting| counting.set(false));
+    (result, CLONES.with(|clones| clones.take()))
+}
+
+/// Called from `Value::clone`; does nothing unless inside [`count_clones`].
+pub(crate) fn record_clone(value: &Value) {
+    if COUNTING.with(Cell::get) {
+        CLONES.with(|clones| {
+            clones
+                .borrow_mut()
+                .entry(kind(value))
+                .or_default()
+                .add(owned_bytes(value))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_nested_clones_only_while_counting() {
+        let list = Value::List(vec![
+            Value::String("ab".to_string()),
+            Value::List(vec![Value::Number(1)]),
+        ]);
+        let _ = list.clone();
+        let (_, clones) = count_clones(|| list.clone());
+
+        assert_eq!(clones.get("lists").map(|tally| tally.count), Some(2));
+        assert_eq!(clones.get("numbers").map(|tally| tally.count), Some(1));
+        assert_eq!(clones.get("strings"), Some(&Tally { count: 1, bytes: 2 }));
+        let (_, clones) = count_clones(|| ());
+        assert!(clones.is_empty());
+    }
+}
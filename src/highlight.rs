@@ -0,0 +1,253 @@
+//! What each name in a file refers to, for editors that color names by
+//! meaning rather than by spelling. Names are classified with the same
+//! scoping rules `fip rename` follows, so a parameter that hides a
+//! top-level binding is colored as a parameter. Impurity is reported apart
+//! from the kind, so an editor can mark impure calls whatever they are.
+
+use crate::{
+    ast::{Expression, Pattern, Statement, UseStatement},
+    interpreter::Interpreter,
+    rename::{self, Kind, Parsed},
+    source_file::SourceFile,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NameKind {
+    /// A top-level function, or a binding whose value is a function.
+    Function,
+    Parameter,
+    Builtin,
+    /// A name brought in by `use`, including the alias of a namespace.
+    Import,
+    /// Any other top-level binding.
+    Variable,
+    /// A field read with `.` or `?.`.
+    Property,
+}
+
+impl NameKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            NameKind::Function => "function",
+            NameKind::Parameter => "parameter",
+            NameKind::Builtin => "builtin",
+            NameKind::Import => "import",
+            NameKind::Variable => "variable",
+            NameKind::Property => "property",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticToken {
+    /// Byte offsets of the name in the file's text.
+    pub span: Range<usize>,
+    pub kind: NameKind,
+    /// Whether the name ends in `!`, marking an impure function.
+    pub impure: bool,
+}
+
+/// What a name does where it appears.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// Bound by a top-level statement, a pattern, or a `use`.
+    Binding,
+    /// Declared in a function's parameter list.
+    Parameter,
+    Read,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameSpan {
+    pub name: String,
+    /// Byte offsets of the name in the file's text.
+    pub span: Range<usize>,
+    /// Index of the top-level statement the name is in.
+    pub statement: usize,
+    pub role: Role,
+}
+
+/// Every name of `file` that is bound or read, in source order, leaving
+/// out object keys and fields read with `.`. Tools that walk the syntax
+/// tree, which keeps no positions, can count the names they pass in a
+/// statement to find where each one is.
+pub fn name_spans(file: &SourceFile) -> Vec<NameSpan> {
+    let parsed = Parsed {
+        tokens: file.tokens().to_vec(),
+        starts: file.statement_starts().to_vec(),
+        statements: file.program().statements.clone(),
+    };
+    let mut names: Vec<NameSpan> = rename::mentions(file.text(), &parsed, &[])
+        .into_iter()
+        .filter(|mention| !matches!(mention.kind, Kind::Member(_)))
+        .map(|mention| NameSpan {
+            statement: parsed
+                .starts
+                .partition_point(|&start| start <= mention.span.start)
+                .saturating_sub(1),
+            role: match mention.binds {
+                true if mention.params.contains(&mention.name) => Role::Parameter,
+                true => Role::Binding,
+                false => Role::Read,
+            },
+            name: mention.name,
+            span: mention.span,
+        })
+        .collect();
+    names.sort_by_key(|name| name.span.start);
+    names
+}
+
+/// The names of `file` that refer to something, in source order. Names the
+/// file neither binds nor imports by name are taken to come from a
+/// `use *` when it has one and are left out otherwise. Statements that
+/// failed to parse are classified as far as their tokens allow.
+pub fn classify(file: &SourceFile) -> Vec<SemanticToken> {
+    let parsed = Parsed {
+        tokens: file.tokens().to_vec(),
+        starts: file.statement_starts().to_vec(),
+        statements: file.program().statements.clone(),
+    };
+    let mut bound: HashMap<&str, NameKind> = HashMap::new();
+    let mut glob = false;
+    for statement in &parsed.statements {
+        let uses = match statement {
+            Statement::Function(function) => {
+                bound.insert(&function.name, NameKind::Function);
+                continue;
+            }
+            Statement::Assignment { pattern, expr, .. } => {
+                let kind = match (pattern, expr) {
+                    (Pattern::Identifier(_), Expression::Lambda { .. }) => NameKind::Function,
+                    _ => NameKind::Variable,
+                };
+                for name in pattern.names() {
+                    bound.insert(name, kind);
+                }
+                continue;
+            }
+            Statement::Use(use_stmt) => vec![use_stmt],
+            Statement::ConditionalUse {
+                then, otherwise, ..
+            } => std::iter::once(then).chain(otherwise).collect(),
+            Statement::Export(_) | Statement::Expression(_) => continue,
+        };
+        for use_stmt in uses {
+            match use_stmt {
+                UseStatement::Single { name, .. } => {
+                    bound.insert(name, NameKind::Import);
+                }
+                UseStatement::Selective { names, .. } => {
+                    for name in names {
+                        bound.insert(name, NameKind::Import);
+                    }
+                }
+                UseStatement::Namespace { alias, .. } => {
+                    bound.insert(alias, NameKind::Import);
+                }
+                UseStatement::Glob { .. } => glob = true,
+            }
+        }
+    }
+    let builtins: HashSet<String> = Interpreter::new()
+        .builtins()
+        .iter()
+        .map(|builtin| builtin.name.clone())
+        .collect();
+
+    let mut tokens: Vec<SemanticToken> = rename::mentions(file.text(), &parsed, &[])
+        .into_iter()
+        .filter_map(|mention| {
+            let kind = match mention.kind {
+                Kind::Member(_) => NameKind::Property,
+                _ if mention.params.contains(&mention.name) => NameKind::Parameter,
+                _ => match bound.get(mention.name.as_str()) {
+                    Some(&kind) => kind,
+                    None if builtins.contains(&mention.name) => NameKind::Builtin,
+                    None if glob => NameKind::Import,
+                    None => return None,
+                },
+            };
+            Some(SemanticToken {
+                impure: mention.name.ends_with('!'),
+                span: mention.span,
+                kind,
+            })
+        })
+        .collect();
+    tokens.sort_by_key(|token| token.span.start);
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_spans_tell_bindings_parameters_and_reads_apart() {
+        let source = "limit: 10\n\
+                      clamp: (n, limit) { min(n, limit) }\n\
+                      label: \"limit <limit>\"\n";
+        let file = SourceFile::new("spans.fip", source);
+        let spans: Vec<(usize, &str, Role, usize)> = name_spans(&file)
+            .into_iter()
+            .map(|name| {
+                (
+                    name.span.start,
+                    &source[name.span],
+                    name.role,
+                    name.statement,
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (0, "limit", Role::Binding, 0),
+                (10, "clamp", Role::Binding, 1),
+                (18, "n", Role::Parameter, 1),
+                (21, "limit", Role::Parameter, 1),
+                (30, "min", Role::Read, 1),
+                (34, "n", Role::Read, 1),
+                (37, "limit", Role::Read, 1),
+                (46, "label", Role::Binding, 2),
+                (61, "limit", Role::Read, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_are_classified_by_what_they_refer_to() {
+        let source = "use { clamp } from \"math\"\n\
+                      limit: 10\n\
+                      show!: (n) { log!(\"<clamp(n, limit)>\") }\n\
+                      point: { x: 1 }\n\
+                      show!(point.x)\n";
+        let file = SourceFile::new("colors.fip", source);
+        let classified: Vec<(&str, &str, bool)> = classify(&file)
+            .into_iter()
+            .map(|token| (&source[token.span], token.kind.name(), token.impure))
+            .collect();
+        assert_eq!(
+            classified,
+            [
+                ("clamp", "import", false),
+                ("limit", "variable", false),
+                ("show!", "function", true),
+                ("n", "parameter", false),
+                ("log!", "builtin", true),
+                ("clamp", "import", false),
+                ("n", "parameter", false),
+                ("limit", "variable", false),
+                ("point", "variable", false),
+                ("show!", "function", true),
+                ("point", "variable", false),
+                ("x", "property", false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Instrumentation for embedders. A [`Hooks`] set with
+//! `Interpreter::set_hooks` hears about every function and builtin call and
+//! every top-level statement as the program runs, which is enough to build
+//! profilers, debuggers, tracers, and coverage on without touching the
+//! evaluator. The profiler behind `fip run --profile` and the tracer behind
+//! `--trace` are two.
+
+use crate::{ast::Statement, error::LangError, interpreter::Value};
+use std::{cell::RefCell, rc::Rc};
+
+/// Callbacks for the events of a run. Every method does nothing by default,
+/// so an implementation only overrides the events it needs. Calls made
+/// while a hook runs are not reported, since hooks cannot reach the
+/// interpreter.
+pub trait Hooks {
+    /// A function or builtin named `name` is about to run with `args`.
+    /// `depth` is how many reported calls are already running.
+    fn on_call(&mut self, _name: &str, _args: &[Value], _depth: usize) {}
+
+    /// The call announced by the last `on_call` at the same `depth` has
+    /// returned or failed.
+    fn on_return(&mut self, _name: &str, _result: Result<&Value, &LangError>, _depth: usize) {}
+
+    /// A top-level statement of the program or of a module is about to run.
+    /// Function definitions, which run before everything else, are not
+    /// reported.
+    fn on_statement(&mut self, _statement: &Statement) {}
+
+    /// The program called `set-trace!` to switch call tracing on or off.
+    fn on_set_trace(&mut self, _enabled: bool) {}
+}
+
+/// Shared hooks, so whoever set them can read what they recorded while the
+/// interpreter still holds them.
+impl<H: Hooks> Hooks for Rc<RefCell<H>> {
+    fn on_call(&mut self, name: &str, args: &[Value], depth: usize) {
+        self.borrow_mut().on_call(name, args, depth);
+    }
+
+    fn on_return(&mut self, name: &str, result: Result<&Value, &LangError>, depth: usize) {
+        self.borrow_mut().on_return(name, result, depth);
+    }
+
+    fn on_statement(&mut self, statement: &Statement) {
+        self.borrow_mut().on_statement(statement);
+    }
+
+    fn on_set_trace(&mut self, enabled: bool) {
+        self.borrow_mut().on_set_trace(enabled);
+    }
+}
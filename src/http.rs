@@ -0,0 +1,315 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Response {
+    pub status: u16,
+    /// Header names are lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// A request received by [`serve`].
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Sends a POST request over plain HTTP/1.1 and waits for the full response.
+/// Only `http://` URLs are supported; there is no TLS implementation.
+pub fn post(url: &str, content_type: &str, body: &str) -> Result<Response, String> {
+    let target = Url::parse(url)?;
+    let mut stream = TcpStream::connect((target.host.as_str(), target.port))
+        .map_err(|err| format!("Failed to connect to {}: {}", url, err))?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+        .map_err(|err| format!("Failed to configure connection to {}: {}", url, err))?;
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept: */*\r\nConnection: close\r\n\r\n{}",
+        target.path,
+        target.host_header(),
+        content_type,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|err| format!("Failed to send request to {}: {}", url, err))?;
+
+    let mut raw = Vec::new();
+    stream
+        .read_to_end(&mut raw)
+        .map_err(|err| format!("Failed to read response from {}: {}", url, err))?;
+    parse_response(&raw)
+}
+
+/// Binds a listener on `127.0.0.1:port` for [`serve`].
+pub fn listen(port: u16) -> Result<TcpListener, String> {
+    TcpListener::bind(("127.0.0.1", port))
+        .map_err(|err| format!("Failed to listen on port {}: {}", port, err))
+}
+
+/// Answers connections one at a time, one request per connection. Malformed
+/// requests get a 400 and broken connections are dropped; serving only stops
+/// when `handle` fails, after replying 500 to the request that caused it.
+pub fn serve<E>(
+    listener: &TcpListener,
+    handle: &mut dyn FnMut(Request) -> Result<Response, E>,
+) -> Result<(), E> {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let request = stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|err| err.to_string())
+            .and_then(|_| read_request(&mut stream));
+        let request = match request {
+            Ok(request) => request,
+            Err(message) => {
+                let _ = write_response(&mut stream, &plain_response(400, message));
+                continue;
+            }
+        };
+        match handle(request) {
+            Ok(response) => {
+                let _ = write_response(&mut stream, &response);
+            }
+            Err(err) => {
+                let response = plain_response(500, "Internal Server Error".to_string());
+                let _ = write_response(&mut stream, &response);
+                return Err(err);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn plain_response(status: u16, body: String) -> Response {
+    Response {
+        status,
+        headers: vec![(
+            "content-type".to_string(),
+            "text/plain; charset=utf-8".to_string(),
+        )],
+        body,
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<Request, String> {
+    let mut raw = Vec::new();
+    let mut buffer = [0; 4096];
+    let header_end = loop {
+        if let Some(index) = raw.windows(4).position(|window| window == b"\r\n\r\n") {
+            break index;
+        }
+        let read = stream.read(&mut buffer).map_err(|err| err.to_string())?;
+        if read == 0 {
+            return Err("Malformed HTTP request: missing header terminator".to_string());
+        }
+        raw.extend_from_slice(&buffer[..read]);
+    };
+    let head = String::from_utf8_lossy(&raw[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or("");
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(format!("Malformed HTTP request line '{}'", request_line)),
+    };
+    let headers = parse_headers(lines);
+    let length = match headers.iter().find(|(name, _)| name == "content-length") {
+        Some((_, value)) => value
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid Content-Length '{}'", value))?,
+        None => 0,
+    };
+
+    let mut body = raw.split_off(header_end + 4);
+    while body.len() < length {
+        let read = stream.read(&mut buffer).map_err(|err| err.to_string())?;
+        if read == 0 {
+            return Err("Truncated HTTP request body".to_string());
+        }
+        body.extend_from_slice(&buffer[..read]);
+    }
+    body.truncate(length);
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+/// Fails unless `name` is an HTTP token and `value` has no control
+/// characters other than tabs, so neither can end the header line early
+/// and add headers or a body of its own.
+pub fn check_header(name: &str, value: &str) -> Result<(), String> {
+    let token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if name.is_empty() || !name.chars().all(token) {
+        return Err(format!("invalid response header name {:?}", name));
+    }
+    if value.chars().any(|c| c.is_control() && c != '\t') {
+        return Err(format!(
+            "response header '{}' must not contain line breaks or other control characters",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        response.status,
+        reason_phrase(response.status)
+    );
+    for (name, value) in &response.headers {
+        if name != "content-length" && name != "connection" {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    head.push_str(&format!(
+        "content-length: {}\r\nconnection: close\r\n\r\n",
+        response.body.len()
+    ));
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+fn parse_headers<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<(String, String)> {
+    lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect()
+}
+
+struct Url {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Self, String> {
+        let rest = match url.strip_prefix("http://") {
+            Some(rest) => rest,
+            None if url.starts_with("https://") => {
+                return Err(format!(
+                    "Cannot request {}: https is not supported, use an http:// endpoint",
+                    url
+                ))
+            }
+            None => return Err(format!("Invalid URL '{}': expected http://", url)),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>()
+                    .map_err(|_| format!("Invalid port in URL '{}'", url))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("Invalid URL '{}': missing host", url));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    fn host_header(&self) -> String {
+        if self.port == 80 {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+fn parse_response(raw: &[u8]) -> Result<Response, String> {
+    let header_end = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| "Malformed HTTP response: missing header terminator".to_string())?;
+    let head = String::from_utf8_lossy(&raw[..header_end]);
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or("");
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| format!("Malformed HTTP status line '{}'", status_line))?;
+
+    let headers = parse_headers(lines);
+    let chunked = headers.iter().any(|(name, value)| {
+        name == "transfer-encoding" && value.to_ascii_lowercase().contains("chunked")
+    });
+    let payload = &raw[header_end + 4..];
+    let body = if chunked {
+        decode_chunked(payload)?
+    } else {
+        payload.to_vec()
+    };
+
+    Ok(Response {
+        status,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+fn decode_chunked(mut payload: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = payload
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| "Malformed chunked response".to_string())?;
+        let size_text = String::from_utf8_lossy(&payload[..line_end]);
+        let size_text = size_text.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_text, 16)
+            .map_err(|_| format!("Invalid chunk size '{}'", size_text))?;
+        payload = &payload[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if payload.len() < size {
+            return Err("Truncated chunked response".to_string());
+        }
+        body.extend_from_slice(&payload[..size]);
+        payload = payload.get(size + 2..).unwrap_or(&[]);
+    }
+}
@@ -1,55 +1,205 @@
 use std::{
-    cell::RefCell,
-    collections::{BTreeMap, HashMap, HashSet},
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt,
+    io::{BufRead, Write},
     path::PathBuf,
     rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use crate::{
     ast::{
         BinaryOperator, ExportStatement, Expression, Function as FunctionAst, ObjectField,
-        ObjectPatternField, Pattern, Program, Statement, StringSegment, StringTemplate,
+        ObjectPatternField, Pattern, Program, Slot, Statement, StringSegment, StringTemplate,
         UseStatement,
     },
-    error::{LangError, LangResult},
+    channel::Channels,
+    collation, config,
+    diff::{self, DifferenceKind},
+    encoding,
+    error::{LangError, LangResult, Limit},
+    heap::{self, HeapStats},
+    hooks::Hooks,
+    http, json,
     lexer::Lexer,
+    loader::{FileSystemLoader, ModuleLoader},
+    logging::{self, LogFormat, LogLevel},
+    map,
     parser::Parser,
+    portable::{self, Package, Portable, PortableFunction},
+    pretty::pretty,
+    property::{self, Choices},
+    resolve, semantic, set, suggest,
+    text_format::{self, NumberFormat, TableStyle},
+    time, uuid,
+    vfs::{FileSystem, RealFileSystem},
 };
 
-#[derive(Clone)]
 pub enum Value {
     Number(i64),
     String(String),
+    Bytes(Vec<u8>),
     Boolean(bool),
     List(Vec<Value>),
+    /// Sorted and without repeats; see [`crate::set`].
+    Set(Vec<Value>),
+    /// Sorted by key; see [`crate::map`].
+    Map(map::Entries),
     Object(BTreeMap<String, Value>),
     Function(Rc<FunctionValue>),
     Builtin(Rc<BuiltinFunction>),
+    /// A function or builtin given fewer arguments than it takes. Calling
+    /// it passes `applied` followed by the new arguments to `callee`, which
+    /// is never itself a partial.
+    Partial {
+        callee: Rc<Value>,
+        applied: Rc<[Value]>,
+    },
+    Lazy(Rc<Sequence>),
+    /// Work started by `spawn!`, by its place among the tasks of the
+    /// interpreter that started it. Programs cannot make one themselves.
+    Task(usize),
+    /// A channel made by `channel!`, by its number among the channels the
+    /// interpreter shares with its workers. Programs cannot make one
+    /// themselves.
+    Channel(usize),
     Null,
     Unit,
 }
 
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        heap::record_clone(self);
+        match self {
+            Value::Number(n) => Value::Number(*n),
+            Value::String(s) => Value::String(s.clone()),
+            Value::Bytes(bytes) => Value::Bytes(bytes.clone()),
+            Value::Boolean(b) => Value::Boolean(*b),
+            Value::List(values) => Value::List(values.clone()),
+            Value::Set(values) => Value::Set(values.clone()),
+            Value::Map(entries) => Value::Map(entries.clone()),
+            Value::Object(fields) => Value::Object(fields.clone()),
+            Value::Function(func) => Value::Function(Rc::clone(func)),
+            Value::Builtin(b) => Value::Builtin(Rc::clone(b)),
+            Value::Partial { callee, applied } => Value::Partial {
+                callee: Rc::clone(callee),
+                applied: Rc::clone(applied),
+            },
+            Value::Lazy(sequence) => Value::Lazy(Rc::clone(sequence)),
+            Value::Task(id) => Value::Task(*id),
+            Value::Channel(id) => Value::Channel(*id),
+            Value::Null => Value::Null,
+            Value::Unit => Value::Unit,
+        }
+    }
+}
+
 impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Number(n) => write!(f, "{}", n),
             Value::String(s) => write!(f, "\"{}\"", s),
+            Value::Bytes(bytes) => write!(f, "<{} bytes>", bytes.len()),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::List(values) => write!(f, "{:?}", values),
+            Value::Set(values) => write!(f, "<set {:?}>", values),
+            Value::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{:?}: {:?}", key, value))
+                    .collect();
+                write!(f, "<map {{{}}}>", entries.join(", "))
+            }
             Value::Object(fields) => write!(f, "{:?}", fields),
             Value::Function(func) => write!(f, "<fn {}>", func.name),
             Value::Builtin(b) => write!(f, "<builtin {}>", b.name),
+            Value::Partial { callee, .. } => write!(f, "<partial {:?}>", callee),
+            Value::Lazy(_) => write!(f, "<lazy>"),
+            Value::Task(_) => write!(f, "<task>"),
+            Value::Channel(_) => write!(f, "<channel>"),
             Value::Null => write!(f, "null"),
             Value::Unit => write!(f, "()"),
         }
     }
 }
 
+impl Value {
+    /// The name `type-of` reports for this value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Boolean(_) => "boolean",
+            Value::List(_) => "list",
+            Value::Set(_) => "set",
+            Value::Map(_) => "map",
+            Value::Object(_) => "object",
+            Value::Function(_) | Value::Builtin(_) | Value::Partial { .. } => "function",
+            Value::Lazy(_) => "lazy",
+            Value::Task(_) => "task",
+            Value::Channel(_) => "channel",
+            Value::Null => "null",
+            Value::Unit => "unit",
+        }
+    }
+
+    /// `callee` waiting for more arguments after `applied`.
+    fn partial(callee: Value, applied: Vec<Value>) -> Value {
+        Value::Partial {
+            callee: Rc::new(callee),
+            applied: applied.into(),
+        }
+    }
+
+    /// How many more arguments calling this value takes, or `None` when it
+    /// is not callable.
+    fn arity(&self) -> Option<usize> {
+        match self {
+            Value::Function(func) => Some(func.params.len()),
+            Value::Builtin(builtin) => Some(builtin.params.len()),
+            Value::Partial { callee, applied } => {
+                Some(callee.arity()?.saturating_sub(applied.len()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The name of the function this value calls, for messages.
+    fn callable_name(&self) -> &str {
+        match self {
+            Value::Function(func) => &func.name,
+            Value::Builtin(builtin) => &builtin.name,
+            Value::Partial { callee, .. } => callee.callable_name(),
+            _ => "<value>",
+        }
+    }
+
+    /// Whether calling this value may have effects, or `None` when it is
+    /// not callable. A partial is as impure as the function it completes.
+    fn impure(&self) -> Option<bool> {
+        match self {
+            Value::Function(func) => Some(func.impure),
+            Value::Builtin(builtin) => Some(builtin.impure),
+            Value::Partial { callee, .. } => callee.impure(),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
+// The older tests build their expected lists with `vec!`
+#[allow(clippy::useless_vec)]
 mod tests {
     use super::*;
-    use crate::{lexer::Lexer, parser::Parser};
+    use crate::{
+        lexer::Lexer, loader::MemoryLoader, parser::Parser, profile::Profiler, trace::CallTracer,
+        vfs::MemoryFileSystem,
+    };
 
     fn run_source(source: &str) -> LangResult<Interpreter> {
         let tokens = Lexer::new(source).lex()?;
@@ -124,14 +274,15 @@ mod tests {
             value: f(10)
         "#;
         let err = match run_source(source) {
-            Ok(_) => panic!("expected runtime error for impure call"),
+            Ok(_) => panic!("expected parse error for impure call"),
             Err(err) => err,
         };
         match err {
-            LangError::Runtime(message, None) => {
+            LangError::Parser(message, Some(_)) => {
                 assert!(message.contains("Function 'f' must be declared impure"));
+                assert!(message.contains("to call 'log!'"));
             }
-            other => panic!("expected runtime error, got {:?}", other),
+            other => panic!("expected parse error, got {:?}", other),
         }
     }
 
@@ -421,14 +572,14 @@ mod tests {
             bad!: (x) { x }
         "#;
         let err = match run_source(source) {
-            Ok(_) => panic!("expected runtime error for impure suffix without impure call"),
+            Ok(_) => panic!("expected parse error for impure suffix without impure call"),
             Err(err) => err,
         };
         match err {
-            LangError::Runtime(message, None) => {
+            LangError::Parser(message, Some(_)) => {
                 assert!(message.contains("marked impure"));
             }
-            other => panic!("expected runtime error, got {:?}", other),
+            other => panic!("expected parse error, got {:?}", other),
         }
     }
 
@@ -503,6 +654,251 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn time_builtins_parse_format_and_shift_timestamps() -> LangResult<()> {
+        let source = r#"
+            start: parse-iso("2024-02-28T23:30:00.25+01:00")
+            text: format-iso(start)
+            shifted: format-iso(add-duration({ days: 1, hours: 2 }, start))
+            invalid: parse-iso("2024-02-30")
+            before-epoch: format-iso(parse-iso("1969-12-31T23:59:59Z"))
+            gap: time-between(start, { year: 2024, month: 3, day: 1 })
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name).expect("binding exists");
+
+        assert_eq!(
+            format!("{:?}", get("start")),
+            r#"{"day": 28, "hour": 22, "millisecond": 250, "minute": 30, "month": 2, "second": 0, "year": 2024}"#
+        );
+        assert!(matches!(get("text"), Value::String(ref s) if s == "2024-02-28T22:30:00.250Z"));
+        assert!(matches!(get("shifted"), Value::String(ref s) if s == "2024-03-01T00:30:00.250Z"));
+        assert!(matches!(get("invalid"), Value::Null));
+        assert!(matches!(get("before-epoch"), Value::String(ref s) if s == "1969-12-31T23:59:59Z"));
+        assert_eq!(
+            format!("{:?}", get("gap")),
+            r#"{"days": 1, "hours": 1, "milliseconds": 750, "minutes": 29, "seconds": 59}"#
+        );
+
+        let err = run_source("bad: format-iso({ year: 2023, month: 2, day: 29 })")
+            .err()
+            .expect("invalid day fails");
+        assert!(
+            err.to_string().contains("'day' must be between 1 and 28"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn character_and_number_conversions() -> LangResult<()> {
+        let source = r#"
+            letters: chars("héllo")
+            codes: map(char-code, chars("Ab"))
+            too-long: char-code("ab")
+            accent: from-char-code(233)
+            surrogate: from-char-code(55296)
+            parsed: string-to-number("-42")
+            invalid: string-to-number("4x")
+            text: number-to-string(17)
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name).expect("binding exists");
+
+        assert_eq!(
+            format!("{:?}", get("letters")),
+            r#"["h", "é", "l", "l", "o"]"#
+        );
+        assert_eq!(format!("{:?}", get("codes")), "[65, 98]");
+        assert!(matches!(get("too-long"), Value::Null));
+        assert!(matches!(get("accent"), Value::String(ref s) if s == "é"));
+        assert!(matches!(get("surrogate"), Value::Null));
+        assert!(matches!(get("parsed"), Value::Number(-42)));
+        assert!(matches!(get("invalid"), Value::Null));
+        assert!(matches!(get("text"), Value::String(ref s) if s == "17"));
+        Ok(())
+    }
+
+    #[test]
+    fn find_and_index_of_return_the_first_match_or_null() -> LangResult<()> {
+        let source = r#"
+            people: [{ name: "Ada", age: 36 }, { name: "Bo", age: 17 }, { name: "Cy", age: 52 }]
+            adult: find((person) { person.age > 18 }, people)
+            nobody: find((person) { person.age > 99 }, people)
+            first-even: find((n) { n / 2 * 2 = n })(lazy-iterate(increment, 5))
+            position: index-of("c", ["a", "b", "c", "c"])
+            missing: index-of([1], [[2], [3]])
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name).expect("binding exists");
+
+        assert_eq!(
+            format!("{:?}", get("adult")),
+            r#"{"age": 36, "name": "Ada"}"#
+        );
+        assert!(matches!(get("nobody"), Value::Null));
+        assert!(matches!(get("first-even"), Value::Number(6)));
+        assert!(matches!(get("position"), Value::Number(2)));
+        assert!(matches!(get("missing"), Value::Null));
+        Ok(())
+    }
+
+    #[test]
+    fn list_grouping_builtins() -> LangResult<()> {
+        let source = r#"
+            people: [{ name: "Ada", team: "core" }, { name: "Bo", team: "docs" }, { name: "Cy", team: "core" }]
+            teams: group-by((person) { person.team }, people)
+            sizes: group-by((n) { n > 2 }, [1, 2, 3, 4])
+            distinct: unique([1, 2, 1, [1], [1], "a"])
+            flat: flatten([[1, 2], 3, [[4]], []])
+            split: partition((n) { n > 2 }, [1, 3, 2, 4])
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name).expect("binding exists");
+
+        assert_eq!(
+            format!("{:?}", get("teams")),
+            r#"{"core": [{"name": "Ada", "team": "core"}, {"name": "Cy", "team": "core"}], "docs": [{"name": "Bo", "team": "docs"}]}"#
+        );
+        assert_eq!(
+            format!("{:?}", get("sizes")),
+            r#"{"false": [1, 2], "true": [3, 4]}"#
+        );
+        assert_eq!(format!("{:?}", get("distinct")), r#"[1, 2, [1], "a"]"#);
+        assert_eq!(format!("{:?}", get("flat")), "[1, 2, 3, [4]]");
+        assert_eq!(format!("{:?}", get("split")), "[[3, 4], [1, 2]]");
+        Ok(())
+    }
+
+    #[test]
+    fn assoc_builtins_return_updated_copies() -> LangResult<()> {
+        let source = r#"
+            user: { name: "Ada", address: { city: "London" }, tags: ["a", "b"] }
+            aged: assoc(user, "age", 36)
+            moved: assoc-in(user, ["address", "city"], "Paris")
+            themed: assoc-in(user, ["settings", "theme"], "dark")
+            tagged: update-in(user, ["tags", 1], (tag) { "<tag>!" })
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name).expect("binding exists");
+
+        assert_eq!(
+            format!("{:?}", get("aged")),
+            r#"{"address": {"city": "London"}, "age": 36, "name": "Ada", "tags": ["a", "b"]}"#
+        );
+        assert_eq!(
+            format!("{:?}", get("moved")),
+            r#"{"address": {"city": "Paris"}, "name": "Ada", "tags": ["a", "b"]}"#
+        );
+        assert_eq!(
+            format!("{:?}", get("themed")),
+            r#"{"address": {"city": "London"}, "name": "Ada", "settings": {"theme": "dark"}, "tags": ["a", "b"]}"#
+        );
+        assert_eq!(
+            format!("{:?}", get("tagged")),
+            r#"{"address": {"city": "London"}, "name": "Ada", "tags": ["a", "b!"]}"#
+        );
+        // The original is untouched
+        assert_eq!(
+            format!("{:?}", get("user")),
+            r#"{"address": {"city": "London"}, "name": "Ada", "tags": ["a", "b"]}"#
+        );
+
+        let err = run_source("bad: assoc-in({ tags: [] }, [\"tags\", 0], 1)")
+            .err()
+            .expect("out of range index fails");
+        assert!(err.to_string().contains("out of range"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn comparisons_and_sort_order_strings_and_lists() -> LangResult<()> {
+        let source = r#"
+            words: "apple" < "banana"
+            upper-first: "Zebra" < "apple"
+            lists: [1, 2] < [1, 2, 0]
+            numbers: sort([3, 1, 2])
+            strings: sort(["pear", "apple", "fig"])
+            nested: sort([[2], [1, 5], [1]])
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name).expect("binding exists");
+
+        assert!(matches!(get("words"), Value::Boolean(true)));
+        assert!(matches!(get("upper-first"), Value::Boolean(true)));
+        assert!(matches!(get("lists"), Value::Boolean(true)));
+        assert_eq!(format!("{:?}", get("numbers")), "[1, 2, 3]");
+        assert_eq!(
+            format!("{:?}", get("strings")),
+            r#"["apple", "fig", "pear"]"#
+        );
+        assert_eq!(format!("{:?}", get("nested")), "[[1], [1, 5], [2]]");
+
+        for source in [
+            "bad: 1 < \"a\"",
+            "bad: sort([1, \"a\"])",
+            "bad: true < false",
+        ] {
+            let err = run_source(source).err().expect("mixed comparison fails");
+            assert!(err.to_string().contains("Cannot compare"), "{}", err);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn not_operator_and_builtin_negate_booleans() -> LangResult<()> {
+        let source = r#"
+            ready: false
+            negated: !ready
+            grouped: !(1 > 2) & !ready
+            double: !!true
+            kept: filter(not?, [true, false])
+            closed?: (door) { !door.open }
+            shut: closed?({ open: false })
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name).expect("binding exists");
+
+        assert!(matches!(get("negated"), Value::Boolean(true)));
+        assert!(matches!(get("grouped"), Value::Boolean(true)));
+        assert!(matches!(get("double"), Value::Boolean(true)));
+        assert!(matches!(get("kept"), Value::List(ref items) if items.len() == 1));
+        assert!(matches!(get("shut"), Value::Boolean(true)));
+
+        let err = run_source("bad: !5")
+            .err()
+            .expect("negating a number fails");
+        assert!(err.to_string().contains("Operand of not must be boolean"));
+        Ok(())
+    }
+
+    #[test]
+    fn safe_navigation_and_null_coalescing() -> LangResult<()> {
+        let source = r#"
+            user: { name: "Ada", greet: (x) { "hi <x>" } }
+            nobody: null
+
+            name: user?.name
+            skipped: nobody?.greet("you")
+            city: user.address?.city ?? "unknown"
+            kept: 0 ?? 5
+            short-circuit: 1 ?? 1 / 0
+            interpolated: "<nobody?.name ?? 0>"
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name).expect("binding exists");
+
+        assert!(matches!(get("name"), Value::String(ref s) if s == "Ada"));
+        assert!(matches!(get("skipped"), Value::Null));
+        assert!(matches!(get("city"), Value::String(ref s) if s == "unknown"));
+        assert!(matches!(get("kept"), Value::Number(0)));
+        assert!(matches!(get("short-circuit"), Value::Number(1)));
+        assert!(matches!(get("interpolated"), Value::String(ref s) if s == "0"));
+
+        Ok(())
+    }
+
     #[test]
     fn list_property_access_handles_indices() -> LangResult<()> {
         let source = r#"
@@ -870,6 +1266,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn higher_order_builtins_take_impure_callbacks_only_in_their_impure_variants() -> LangResult<()>
+    {
+        let interpreter = run_source(
+            r#"
+            double!: (n) { log!(n) n * 2 }
+            doubled: map!(double!, [1, 2])
+            kept: filter!((n)! { log!(n) n > 1 }, [1, 2, 3])
+            plain: map!((n) { n + 1 }, [1])
+        "#,
+        )?;
+        assert_eq!(
+            pretty(&interpreter.global.get("doubled").unwrap()),
+            "[2, 4]"
+        );
+        assert_eq!(pretty(&interpreter.global.get("kept").unwrap()), "[2, 3]");
+        assert_eq!(pretty(&interpreter.global.get("plain").unwrap()), "[2]");
+
+        let err = run_source("double!: (n) { log!(n) n * 2 }\nmap(double!, [1])")
+            .err()
+            .expect("map rejects impure callbacks");
+        assert!(matches!(err, LangError::Runtime(ref msg, _)
+            if msg == "Builtin 'map' cannot call impure function 'double!'; use 'map!' instead"));
+        Ok(())
+    }
+
     #[test]
     fn for_each_builtin_iterates_list() -> LangResult<()> {
         let source = r#"
@@ -1015,194 +1437,5091 @@ mod tests {
 
         Ok(())
     }
-}
 
-pub struct FunctionValue {
-    pub name: String,
-    pub params: Vec<String>,
-    pub body: Expression,
-    pub env: Rc<Environment>,
-    pub impure: bool,
-}
+    #[test]
+    fn casefold_handles_special_foldings() -> LangResult<()> {
+        let source = r#"
+            folded: casefold("Straße ΣΊΣΥΦΟΣ")
+        "#;
+        let interpreter = run_source(source)?;
+        let folded = interpreter
+            .global
+            .get("folded")
+            .expect("folded should exist");
+        assert!(matches!(folded, Value::String(s) if s == "strasse σίσυφοσ"));
+        Ok(())
+    }
 
-pub struct BuiltinFunction {
-    pub name: String,
-    pub impure: bool,
-    pub params: Vec<String>, // Parameter names for currying support
-    pub func: Rc<dyn Fn(&Interpreter, &[Value]) -> LangResult<Value>>,
-}
+    #[test]
+    fn locale_sort_orders_accents_next_to_base_letters() -> LangResult<()> {
+        let source = r#"
+            sorted: locale-sort("en", ["zebra", "Éclair", "eagle", "apple"])
+            order: locale-compare("en", "resume", "résumé")
+        "#;
+        let interpreter = run_source(source)?;
+        let sorted = interpreter
+            .global
+            .get("sorted")
+            .expect("sorted should exist");
+        assert_eq!(
+            format!("{:?}", sorted),
+            r#"["apple", "eagle", "Éclair", "zebra"]"#
+        );
+        let order = interpreter.global.get("order").expect("order should exist");
+        assert!(matches!(order, Value::Number(-1)));
+        Ok(())
+    }
 
-impl Clone for FunctionValue {
-    fn clone(&self) -> Self {
-        Self {
-            name: self.name.clone(),
-            params: self.params.clone(),
-            body: self.body.clone(),
-            env: Rc::clone(&self.env),
-            impure: self.impure,
-        }
+    #[cfg(feature = "locale-data")]
+    #[test]
+    fn locale_sort_applies_swedish_tailoring() -> LangResult<()> {
+        let source = r#"
+            root: locale-sort("en", ["öl", "zon", "ål", "arm"])
+            swedish: locale-sort("sv-SE", ["öl", "zon", "ål", "arm"])
+        "#;
+        let interpreter = run_source(source)?;
+        let root = interpreter.global.get("root").expect("root should exist");
+        assert_eq!(format!("{:?}", root), r#"["ål", "arm", "öl", "zon"]"#);
+        let swedish = interpreter
+            .global
+            .get("swedish")
+            .expect("swedish should exist");
+        assert_eq!(format!("{:?}", swedish), r#"["arm", "zon", "ål", "öl"]"#);
+        Ok(())
     }
-}
 
-impl Clone for BuiltinFunction {
-    fn clone(&self) -> Self {
-        Self {
-            name: self.name.clone(),
-            impure: self.impure,
-            params: self.params.clone(),
-            func: Rc::clone(&self.func),
-        }
+    #[test]
+    fn lazy_pipeline_only_computes_taken_elements() -> LangResult<()> {
+        let source = r#"
+            naturals: lazy-iterate(increment, 1)
+            evens: lazy-filter((n) { n / 2 * 2 = n }, naturals)
+            squares: lazy-map((n) { n * n }, evens)
+            first: take(3, squares)
+        "#;
+        let interpreter = run_source(source)?;
+        let first = interpreter.global.get("first").expect("first should exist");
+        assert_eq!(format!("{:?}", first), "[4, 16, 36]");
+
+        // The step after the last element taken would divide by zero
+        let source = r#"
+            countdown: take(3, lazy-iterate((n) { n - 1 + 0 * (10 / n) }, 2))
+            short: take(1000000000000000, [1, 2])
+        "#;
+        let interpreter = run_source(source)?;
+        let countdown = interpreter
+            .global
+            .get("countdown")
+            .expect("countdown should exist");
+        assert_eq!(format!("{:?}", countdown), "[2, 1, 0]");
+        let short = interpreter.global.get("short").expect("short should exist");
+        assert_eq!(format!("{:?}", short), "[1, 2]");
+        Ok(())
     }
-}
 
-#[derive(Clone)]
-pub struct Environment {
-    values: RefCell<HashMap<String, Value>>,
-    parent: Option<Rc<Environment>>,
-}
+    #[test]
+    fn strict_builtins_force_finite_lazy_sequences() -> LangResult<()> {
+        let source = r#"
+            doubled: lazy-map((n) { n * 2 }, [1, 2, 3])
+            total: reduce((acc, n) { acc + n }, 0, doubled)
+        "#;
+        let interpreter = run_source(source)?;
+        let total = interpreter.global.get("total").expect("total should exist");
+        assert!(matches!(total, Value::Number(12)));
 
-impl Environment {
-    pub fn new(parent: Option<Rc<Environment>>) -> Rc<Self> {
-        Rc::new(Self {
-            values: RefCell::new(HashMap::new()),
-            parent,
-        })
+        let err = match run_source("count: reduce(add, 0, lazy-iterate(increment, 0))") {
+            Ok(_) => panic!("expected error when forcing an infinite sequence"),
+            Err(err) => err,
+        };
+        assert!(matches!(err, LangError::Runtime(message, None) if message.contains("infinite")));
+        Ok(())
     }
 
-    pub fn define(&self, name: String, value: Value) -> LangResult<()> {
-        let mut values = self.values.borrow_mut();
-        if values.contains_key(&name) {
-            return Err(LangError::Runtime(
-                format!("Mutation error: trying to mutate binding {}", name),
-                None,
-            ));
-        }
-        values.insert(name, value);
+    #[test]
+    fn json_parse_and_encode_round_trip() -> LangResult<()> {
+        let source = r#"
+            parsed: json-parse("{\"name\": \"Ada\", \"tags\": [1, true, null]}")
+            name: parsed.name
+            encoded: json-encode(parsed)
+        "#;
+        let interpreter = run_source(source)?;
+        let name = interpreter.global.get("name").expect("name should exist");
+        assert!(matches!(name, Value::String(s) if s == "Ada"));
+        let encoded = interpreter
+            .global
+            .get("encoded")
+            .expect("encoded should exist");
+        assert!(
+            matches!(encoded, Value::String(s) if s == r#"{"name":"Ada","tags":[1,true,null]}"#)
+        );
         Ok(())
     }
 
-    pub fn get(&self, name: &str) -> Option<Value> {
-        if let Some(value) = self.values.borrow().get(name) {
-            Some(value.clone())
-        } else if let Some(parent) = &self.parent {
-            parent.get(name)
-        } else {
-            None
+    #[test]
+    fn hashing_and_encoding_builtins_return_strings() -> LangResult<()> {
+        let source = r#"
+            digest: sha256("abc")
+            checksum: md5("abc")
+            encoded: base64-encode("héllo")
+            decoded: base64-decode(encoded)
+            hex: hex-encode("fip")
+            unhexed: hex-decode("666970")
+        "#;
+        let interpreter = run_source(source)?;
+        let text = |name: &str| match interpreter.global.get(name) {
+            Some(Value::String(text)) => text,
+            other => panic!("{} should be a string, got {:?}", name, other),
+        };
+        assert_eq!(
+            text("digest"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(text("checksum"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(text("encoded"), "aMOpbGxv");
+        assert_eq!(text("decoded"), "héllo");
+        assert_eq!(text("hex"), "666970");
+        assert_eq!(text("unhexed"), "fip");
+
+        match run_source("bad: hex-decode(\"ff\")") {
+            Err(LangError::Runtime(message, _)) => assert_eq!(
+                message,
+                "Builtin 'hex-decode' failed: Decoded bytes are not valid UTF-8"
+            ),
+            other => panic!("expected decode error, got {:?}", other.err()),
         }
+        Ok(())
     }
-}
 
-#[derive(Clone, Copy)]
-pub enum Purity {
-    Pure,
-    Impure,
-}
-
-impl Purity {
-    fn allow_impure(self) -> bool {
-        matches!(self, Purity::Impure)
+    #[test]
+    fn uuid_builtins_generate_random_and_name_based_ids() -> LangResult<()> {
+        let source = r#"
+            first: uuid!()
+            second: uuid!()
+            named: uuid-v5("6ba7b810-9dad-11d1-80b4-00c04fd430c8", "python.org")
+        "#;
+        let interpreter = run_source(source)?;
+        let text = |name: &str| match interpreter.global.get(name) {
+            Some(Value::String(text)) => text,
+            other => panic!("{} should be a string, got {:?}", name, other),
+        };
+        assert_eq!(text("named"), "886313e1-3b8a-5372-9b90-0c9aee199e5d");
+        assert_ne!(text("first"), text("second"));
+        assert_eq!(text("first").len(), 36);
+        assert_eq!(&text("first")[14..15], "4");
+        Ok(())
     }
-}
 
-pub struct Interpreter {
-    global: Rc<Environment>,
-    module_cache: RefCell<HashMap<String, Rc<Environment>>>,
-    entry_point_dir: Option<PathBuf>,
-    loading_modules: RefCell<HashSet<String>>,
-}
-
-impl Interpreter {
-    #[allow(dead_code)]
-    pub fn new() -> Self {
-        let global = Environment::new(None);
-        let mut interpreter = Self {
-            global,
-            module_cache: RefCell::new(HashMap::new()),
-            entry_point_dir: None,
-            loading_modules: RefCell::new(HashSet::new()),
+    #[test]
+    fn for_all_passes_or_reports_a_shrunk_counterexample() -> LangResult<()> {
+        let source = r#"
+            numbers: gen-list(gen-number(-50, 50))
+            sorted: for-all(numbers, (xs) { sort(sort(xs)) = sort(xs) })
+            small-sum: assert-throws!(() {
+              for-all(gen-list(gen-number(0, 1000)), (xs) { reduce(add, 0, xs) < 100 })
+            })
+            pairs: gen-map((n) { [n, n * 2] }, gen-number(1, 100))
+            doubled: assert-throws!(() { for-all(pairs, (pair) { pair.1 < 50 }) })
+            divides: assert-throws!(() { for-all(gen-number(-10, 10), (n) { 10 / n ≠ 7 }) })
+        "#;
+        let interpreter = run_source(source)?;
+        let message = |name: &str| match interpreter.global.get(name) {
+            Some(Value::String(message)) => message,
+            other => panic!("expected a message for {}, got {:?}", name, other),
         };
-        interpreter.install_builtins();
-        interpreter
+        assert!(matches!(
+            interpreter.global.get("sorted"),
+            Some(Value::Null)
+        ));
+        assert!(message("small-sum")
+            .starts_with("Assertion failed: property does not hold for\n  [100]\n"));
+        assert!(message("doubled")
+            .starts_with("Assertion failed: property does not hold for\n  [25, 50]\n"));
+        assert!(message("divides").contains("\n  0\n"));
+        assert!(message("divides").ends_with("the property raised: Division by zero"));
+
+        match run_source("bad: gen-number(1, 2)()") {
+            Err(LangError::Runtime(message, _)) => {
+                assert_eq!(message, "Generators can only run inside for-all")
+            }
+            _ => panic!("expected a generator error"),
+        }
+        Ok(())
     }
 
-    pub fn with_entry_point_dir(entry_point_dir: PathBuf) -> Self {
-        let global = Environment::new(None);
-        let mut interpreter = Self {
-            global,
-            module_cache: RefCell::new(HashMap::new()),
-            entry_point_dir: Some(entry_point_dir),
-            loading_modules: RefCell::new(HashSet::new()),
+    #[test]
+    fn snapshots_are_recorded_compared_and_updated() -> LangResult<()> {
+        let dir = std::env::temp_dir().join(format!("fip-snapshots-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let run = |value: &str, update: bool| -> LangResult<()> {
+            let source = format!("expect-snapshot!(\"user\", {})", value);
+            let program = Parser::new(Lexer::new(&source).lex()?).parse_program()?;
+            let mut interpreter = Interpreter::with_entry_point_dir(dir.clone());
+            interpreter.set_update_snapshots(update);
+            interpreter.eval_program(&program)
+        };
+        let outcome = (|| {
+            run("{ name: \"Ada\" }", false)?;
+            let recorded = std::fs::read_to_string(dir.join("user.snap"))?;
+            run("{ name: \"Ada\" }", false)?;
+            let mismatch = run("{ name: \"Bob\" }", false);
+            run("{ name: \"Bob\" }", true)?;
+            let updated = std::fs::read_to_string(dir.join("user.snap"))?;
+            Ok::<_, LangError>((recorded, mismatch, updated))
+        })();
+        std::fs::remove_dir_all(&dir).ok();
+        let (recorded, mismatch, updated) = outcome?;
+        assert_eq!(recorded, "{ name: \"Ada\" }\n");
+        match mismatch {
+            Err(LangError::Runtime(message, _)) => assert_eq!(
+                message,
+                "Assertion failed: value does not match snapshot user.snap\nexpected:\n  { name: \"Ada\" }\nactual:\n  { name: \"Bob\" }\nrun with --update-snapshots to accept the new value"
+            ),
+            other => panic!("expected a snapshot mismatch, got {:?}", other),
+        }
+        assert_eq!(updated, "{ name: \"Bob\" }\n");
+        assert!(run_source("expect-snapshot!(\"../escape\", 1)").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn byte_builtins_read_slice_and_decode_files() -> LangResult<()> {
+        let dir = std::env::temp_dir().join(format!("fip-bytes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("data.bin"), [0x89, b'P', b'N', b'G', 0xff])?;
+        let source = r#"
+            data: read-bytes!("data.bin")
+            size: bytes-length(data)
+            first: byte-at(data, 0)
+            past-end: byte-at(data, 5)
+            magic: bytes-to-string("ascii", bytes-slice(data, 1, 4))
+            latin: string-to-bytes("latin-1", "é")
+            same?: bytes-slice(data, 1, 4) = string-to-bytes("utf-8", "PNG")
+        "#;
+        let tokens = Lexer::new(source).lex()?;
+        let program = Parser::new(tokens).parse_program()?;
+        let mut interpreter = Interpreter::with_entry_point_dir(dir.clone());
+        interpreter.eval_program(&program)?;
+        let get = |name: &str| interpreter.global.get(name);
+        assert!(matches!(get("size"), Some(Value::Number(5))));
+        assert!(matches!(get("first"), Some(Value::Number(0x89))));
+        assert!(matches!(get("past-end"), Some(Value::Null)));
+        assert!(matches!(get("magic"), Some(Value::String(s)) if s == "PNG"));
+        assert!(matches!(get("latin"), Some(Value::Bytes(b)) if b == [0xe9]));
+        assert!(matches!(get("same?"), Some(Value::Boolean(true))));
+
+        match run_source("bad: bytes-to-string(\"utf-8\", string-to-bytes(\"latin-1\", \"é\"))") {
+            Err(LangError::Runtime(message, _)) => {
+                assert!(message
+                    .starts_with("Builtin 'bytes-to-string' failed: Bytes are not valid UTF-8"))
+            }
+            other => panic!("expected decode error, got {:?}", other.err()),
+        }
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn set_builtins_dedup_and_combine() -> LangResult<()> {
+        let source = r#"
+            seen: to-set([3, 1, 3, 2])
+            more: to-set([2, 4])
+            both: set-to-list(set-intersection(seen, more))
+            either: set-to-list(set-union(seen, to-set([5])))
+            only-seen: set-to-list(set-difference(seen, more))
+            has-two?: set-contains?(seen, 2)
+            has-nine?: set-contains?(seen, 9)
+            same?: seen = to-set([1, 2, 3])
+            unique-words: set-to-list(to-set(["b", "a", "b"]))
+        "#;
+        let interpreter = run_source(source)?;
+        let list = |name: &str| match interpreter.global.get(name) {
+            Some(Value::List(items)) => format!("{:?}", items),
+            other => panic!("{} should be a list, got {:?}", name, other),
+        };
+        assert_eq!(list("both"), "[2]");
+        assert_eq!(list("either"), "[1, 2, 3, 5]");
+        assert_eq!(list("only-seen"), "[1, 3]");
+        assert_eq!(list("unique-words"), "[\"a\", \"b\"]");
+        let get = |name: &str| interpreter.global.get(name);
+        assert!(matches!(get("has-two?"), Some(Value::Boolean(true))));
+        assert!(matches!(get("has-nine?"), Some(Value::Boolean(false))));
+        assert!(matches!(get("same?"), Some(Value::Boolean(true))));
+
+        match run_source("bad: to-set([{ a: 1 }])") {
+            Err(LangError::Runtime(message, _)) => {
+                assert!(message.starts_with("Builtin 'to-set' failed: Set elements can only be"))
+            }
+            other => panic!("expected set error, got {:?}", other.err()),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn map_builtins_key_entries_by_any_orderable_value() -> LangResult<()> {
+        let source = r#"
+            edges: to-map([
+              { key: [1, 2], value: "a" },
+              { key: [2, 1], value: "b" },
+              { key: [1, 2], value: "c" },
+            ])
+            first: map-get(edges, [1, 2])
+            missing: map-get(edges, [9, 9])
+            grown: map-set(edges, 7, "d")
+            shrunk: map-remove(grown, [2, 1])
+            has?: map-has?(shrunk, 7)
+            keys: map((entry) { entry.key }, map-entries(shrunk))
+            unchanged?: edges = to-map([{ key: [2, 1], value: "b" }, { key: [1, 2], value: "c" }])
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name);
+        assert!(matches!(get("first"), Some(Value::String(s)) if s == "c"));
+        assert!(matches!(get("missing"), Some(Value::Null)));
+        assert!(matches!(get("has?"), Some(Value::Boolean(true))));
+        assert_eq!(format!("{:?}", get("keys")), "Some([7, [1, 2]])");
+        assert!(matches!(get("unchanged?"), Some(Value::Boolean(true))));
+
+        match run_source("bad: map-get(to-map([]), { a: 1 })") {
+            Err(LangError::Runtime(message, _)) => {
+                assert!(message.starts_with("Builtin 'map-get' failed: Map keys can only be"))
+            }
+            other => panic!("expected key error, got {:?}", other.err()),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn equality_and_comparison_builtins() -> LangResult<()> {
+        let source = r#"
+            same?: equal?({ a: [1, null] }, { a: [1, null] })
+            different?: equal?([1], [1, 2])
+            own?: equal?(increment, increment)
+            ordered: [compare(1, 2), compare("b", "b"), compare(null, false), compare([2], [1, 5])]
+            descending: sort-with((a, b) { compare(b.n, a.n) }, [{ n: 1 }, { n: 3 }, { n: 2 }])
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name);
+        assert!(matches!(get("same?"), Some(Value::Boolean(true))));
+        assert!(matches!(get("different?"), Some(Value::Boolean(false))));
+        assert!(matches!(get("own?"), Some(Value::Boolean(true))));
+        assert_eq!(format!("{:?}", get("ordered")), "Some([-1, 0, -1, 1])");
+        assert_eq!(
+            format!("{:?}", get("descending")),
+            r#"Some([{"n": 3}, {"n": 2}, {"n": 1}])"#
+        );
+
+        match run_source("bad: compare(increment, 1)") {
+            Err(LangError::Runtime(message, _)) => {
+                assert!(message.starts_with("Builtin 'compare' failed: Compared values can only be"))
+            }
+            other => panic!("expected comparison error, got {:?}", other.err()),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn type_of_and_type_predicates_name_value_kinds() -> LangResult<()> {
+        let source = r#"
+            names: map(type-of, [1, "a", true, null, [1], { a: 1 }, increment, (x) { x }])
+            lazy-name: type-of(lazy([1]))
+            checks: [number?(1), string?(1), list?([]), list?(lazy([])), object?({}), function?(map)]
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name);
+        assert_eq!(
+            format!("{:?}", get("names")),
+            r#"Some(["number", "string", "boolean", "null", "list", "object", "function", "function"])"#
+        );
+        assert!(matches!(get("lazy-name"), Some(Value::String(s)) if s == "lazy"));
+        assert_eq!(
+            format!("{:?}", get("checks")),
+            "Some([true, false, true, false, true, true])"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn assertions_report_expected_and_actual_values() -> LangResult<()> {
+        let source = r#"
+            check!: () {
+              assert!(1 < 2, "ordering")
+              assert-equal!({ a: [1] }, { a: [1] })
+              assert-throws!(() { 1 / 0 })
+            }
+            message: check!()
+        "#;
+        let interpreter = run_source(source)?;
+        assert!(
+            matches!(interpreter.global.get("message"), Some(Value::String(s)) if s == "Division by zero")
+        );
+
+        let failure = |source: &str| match run_source(source) {
+            Err(LangError::Runtime(message, _)) => message,
+            other => panic!("expected assertion failure, got {:?}", other.err()),
+        };
+        assert_eq!(
+            failure("go!: () { assert!(1 > 2, \"one is big\") }\nx: go!()"),
+            "Assertion failed: one is big"
+        );
+        assert_eq!(
+            failure("go!: () { assert-equal!([\"1\"], [1]) }\nx: go!()"),
+            "Assertion failed: values are not equal\nexpected:\n  [\"1\"]\nactual:\n  [1]\n\
+             differences:\n  [0]: \"1\" -> 1"
+        );
+        assert_eq!(
+            failure("go!: () { assert-equal!(1, 2) }\nx: go!()"),
+            "Assertion failed: values are not equal\nexpected:\n  1\nactual:\n  2"
+        );
+        assert_eq!(
+            failure("go!: () { assert-throws!(() { 1 }) }\nx: go!()"),
+            "Assertion failed: expected an error, but the function returned 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn named_functions_are_hoisted_in_programs_and_modules() -> LangResult<()> {
+        let dir = std::env::temp_dir().join(format!("fip-hoisting-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create module dir");
+        std::fs::write(
+            dir.join("lib.fip"),
+            "total: twice(3)\ntwice: (n) { plus-one(n) + plus-one(n) }\nplus-one: (n) { n + 1 }\nexport total\n",
+        )
+        .expect("write module");
+        let source = r#"
+            early: first(2)
+            first: (x) { second(x) }
+            second: (x) { x * 2 }
+            even?: (n) { if(n = 0, () { true }, () { odd?(n - 1) }) }
+            odd?: (n) { if(n = 0, () { false }, () { even?(n - 1) }) }
+            parity: [even?(4), odd?(4)]
+            use total from "lib"
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        let mut interpreter = Interpreter::with_entry_point_dir(dir.clone());
+        let result = interpreter.eval_program(&program);
+        std::fs::remove_dir_all(&dir).ok();
+        result?;
+        let get = |name: &str| interpreter.global.get(name);
+        assert!(matches!(get("early"), Some(Value::Number(4))));
+        assert_eq!(format!("{:?}", get("parity")), "Some([true, false])");
+        assert!(matches!(get("total"), Some(Value::Number(8))));
+        Ok(())
+    }
+
+    #[test]
+    fn iterate_and_until_loop_without_growing_the_stack() -> LangResult<()> {
+        let source = r#"
+            counted: iterate(increment, 0, 100000)
+            untouched: iterate(increment, 7, 0)
+            collatz-steps: until(
+              (state) { state.n = 1 },
+              (state) { { n: if(state.n / 2 * 2 = state.n, () { state.n / 2 }, () { 3 * state.n + 1 }), steps: state.steps + 1 } },
+              { n: 27, steps: 0 }
+            ).steps
+            already-done: until((n) { n > 0 }, increment, 5)
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name);
+        assert!(matches!(get("counted"), Some(Value::Number(100000))));
+        assert!(matches!(get("untouched"), Some(Value::Number(7))));
+        assert!(matches!(get("collatz-steps"), Some(Value::Number(111))));
+        assert!(matches!(get("already-done"), Some(Value::Number(5))));
+        assert!(run_source("bad: iterate(increment, 0, -1)").is_err());
+        assert!(run_source("bad: until(increment, increment, 0)").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn tagged_values_dispatch_on_their_tag() -> LangResult<()> {
+        let source = r#"
+            parsed: tag("ok", 42)
+            { tag: kind, value }: parsed
+            is-tagged: [tag?(parsed), tag?({ tag: "ok" }), tag?(1)]
+            name: tag-name(tag("err", "bad"))
+            handled: map(
+              match-tag({ ok: increment, _: (other) { tag-name(other) } }),
+              [parsed, tag("none", null)]
+            )
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name);
+        assert!(matches!(get("kind"), Some(Value::String(s)) if s == "ok"));
+        assert!(matches!(get("value"), Some(Value::Number(42))));
+        assert!(matches!(
+            get("is-tagged"),
+            Some(Value::List(flags)) if matches!(
+                flags.as_slice(),
+                [Value::Boolean(true), Value::Boolean(false), Value::Boolean(false)]
+            )
+        ));
+        assert!(matches!(get("name"), Some(Value::String(s)) if s == "err"));
+        assert!(matches!(
+            get("handled"),
+            Some(Value::List(values)) if matches!(
+                values.as_slice(),
+                [Value::Number(43), Value::String(s)] if s == "none"
+            )
+        ));
+        assert!(run_source(r#"bad: match-tag({ ok: increment }, tag("err", 1))"#).is_err());
+        assert!(run_source("bad: tag-name({ value: 1 })").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn result_helpers_pass_failures_through() -> LangResult<()> {
+        let source = r#"
+            positive: (n) { if(n > 0, () { tag("ok", n) }, () { tag("err", "not positive") }) }
+            unwrapped: [unwrap-or(0, tag("ok", 5)), unwrap-or(0, tag("none", null)), unwrap-or(0, null), unwrap-or(0, 7)]
+            mapped: map-ok(increment, tag("some", 1))
+            kept: map-ok(increment, tag("err", "bad"))
+            chained: unwrap-or(0, and-then(positive, tag("ok", 3)))
+            stopped: tag-name(and-then(positive, tag("ok", -3)))
+            skipped: and-then(positive, null)
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| interpreter.global.get(name);
+        assert!(matches!(
+            get("unwrapped"),
+            Some(Value::List(values)) if matches!(
+                values.as_slice(),
+                [Value::Number(5), Value::Number(0), Value::Number(0), Value::Number(7)]
+            )
+        ));
+        let pretty_of = |name: &str| get(name).map(|value| pretty(&value));
+        assert_eq!(
+            pretty_of("mapped").as_deref(),
+            Some(r#"{ tag: "some", value: 2 }"#)
+        );
+        assert_eq!(
+            pretty_of("kept").as_deref(),
+            Some(r#"{ tag: "err", value: "bad" }"#)
+        );
+        assert!(matches!(get("chained"), Some(Value::Number(3))));
+        assert!(matches!(get("stopped"), Some(Value::String(s)) if s == "err"));
+        assert!(matches!(get("skipped"), Some(Value::Null)));
+        Ok(())
+    }
+
+    #[test]
+    fn pipe_forward_calls_the_right_side_with_the_left() -> LangResult<()> {
+        let source = r#"
+            double: (n) { n * 2 }
+            piped: 3 |> increment |> double |> number-to-string
+            inline: [1 |> double, 2 + 3 |> double]
+            curried: [1, 2, 3] |> map(double)
+        "#;
+        let interpreter = run_source(source)?;
+        let get = |name: &str| format!("{:?}", interpreter.global.get(name).unwrap());
+        assert_eq!(get("piped"), "\"8\"");
+        assert_eq!(get("inline"), "[2, 10]");
+        assert_eq!(get("curried"), "[2, 4, 6]");
+        assert!(run_source("bad: 1 |> 2").is_err());
+        Ok(())
+    }
+
+    fn serve_once(response_body: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind test server");
+        let url = format!("http://{}/graphql", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept connection");
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            loop {
+                let read = stream.read(&mut buffer).expect("read request");
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Content-Length: "))
+                        .and_then(|value| value.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response_body.len(),
+                response_body
+            );
+            stream
+                .write_all(response.as_bytes())
+                .expect("write response");
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn graphql_builtin_posts_query_and_parses_response() -> LangResult<()> {
+        let (url, server) = serve_once(r#"{"data": {"user": {"id": 7}}}"#);
+        let source = format!(
+            r#"
+            fetch!: () {{ graphql!("{}", "query($id: ID) {{ user(id: $id) {{ id }} }}", {{ id: 7 }}) }}
+            result: fetch!()
+            id: result.data.user.id
+            errors: result.errors
+        "#,
+            url
+        );
+        let program = Parser::new(Lexer::new(&source).lex()?).parse_program()?;
+        let mut interpreter = Interpreter::new();
+        let err = interpreter
+            .eval_program(&program)
+            .expect_err("experimental builtin should be gated");
+        assert!(err.to_string().contains("--enable-experimental"));
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_enable_experimental(true);
+        interpreter.eval_program(&program)?;
+        let request = server.join().expect("server thread");
+        assert!(request.starts_with("POST /graphql HTTP/1.1"));
+        assert!(request.contains(r#""variables":{"id":7}"#));
+        let id = interpreter.global.get("id").expect("id should exist");
+        assert!(matches!(id, Value::Number(7)));
+        let errors = interpreter
+            .global
+            .get("errors")
+            .expect("errors should exist");
+        assert!(matches!(errors, Value::Null));
+        Ok(())
+    }
+
+    #[test]
+    fn serve_http_builtin_answers_requests_until_the_handler_fails() -> LangResult<()> {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("find a free port")
+            .port();
+        let client = std::thread::spawn(move || {
+            let post = |path: &str, body: &str| {
+                let url = format!("http://127.0.0.1:{}{}", port, path);
+                for _ in 0..100 {
+                    match http::post(&url, "text/plain", body) {
+                        Err(err) if err.starts_with("Failed to connect") => {
+                            std::thread::sleep(std::time::Duration::from_millis(20))
+                        }
+                        result => return result.expect("request succeeds"),
+                    }
+                }
+                panic!("server never started");
+            };
+            (
+                post("/echo", "hello"),
+                post("/created", ""),
+                post("/json", ""),
+                post("/stop", ""),
+            )
+        });
+        let source = format!(
+            r#"
+            handle: (request) {{
+              if(request.path = "/echo", () {{ "<request.method> <request.body>" }}, () {{
+                if(request.path = "/created", () {{ {{ status: 201, headers: {{ location: "/things/1" }} }} }}, () {{
+                  if(request.path = "/json", () {{ {{ ok: true }} }}, () {{ !request.path }})
+                }})
+              }})
+            }}
+            start!: () {{ serve-http!({}, handle) }}
+            start!()
+        "#,
+            port
+        );
+        let program = Parser::new(Lexer::new(&source).lex()?).parse_program()?;
+        let mut interpreter = Interpreter::new();
+        interpreter.set_enable_experimental(true);
+        let err = interpreter
+            .eval_program(&program)
+            .expect_err("a failing handler stops the server");
+        assert!(err.to_string().contains("Operand of not must be boolean"));
+
+        let (echo, created, json, stop) = client.join().expect("client thread");
+        assert_eq!((echo.status, echo.body.as_str()), (200, "POST hello"));
+        assert_eq!((created.status, created.body.as_str()), (201, ""));
+        assert!(created
+            .headers
+            .contains(&("location".to_string(), "/things/1".to_string())));
+        assert_eq!((json.status, json.body.as_str()), (200, r#"{"ok":true}"#));
+        assert_eq!(stop.status, 500);
+        Ok(())
+    }
+
+    #[test]
+    fn serve_http_refuses_headers_that_would_split_the_response() -> LangResult<()> {
+        let response = |headers: &str| -> LangResult<http::Response> {
+            let interpreter =
+                run_source(&format!("reply: {{ status: 200, headers: {} }}", headers))?;
+            Interpreter::response_from_value(&interpreter.global.get("reply").unwrap())
+        };
+        let injected = response(r#"{ x-note: "a\r\nset-cookie: evil" }"#)
+            .err()
+            .expect("a line break in a value is refused");
+        assert!(
+            injected
+                .to_string()
+                .contains("must not contain line breaks"),
+            "{}",
+            injected
+        );
+        let named = response(r#"assoc({}, "x\r\nevil", "1")"#)
+            .err()
+            .expect("a line break in a name is refused");
+        assert!(named.to_string().contains("invalid response header name"));
+        let ok = response(r#"{ x-note: "a\tb" }"#)?;
+        assert!(ok
+            .headers
+            .contains(&("x-note".to_string(), "a\tb".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn get_and_nth_look_up_computed_keys() -> LangResult<()> {
+        let source = r#"
+            user: { first-name: "Ada", langs: ["fip", "rust"] }
+            field: "first"
+            name: get(user, "<field>-name")
+            missing: get(user, "age")
+            second: nth(get(user, "langs"), 1)
+            out-of-range: nth(user.langs, 5)
+        "#;
+        let interpreter = run_source(source)?;
+        let name = interpreter.global.get("name").expect("name should exist");
+        assert!(matches!(name, Value::String(s) if s == "Ada"));
+        let missing = interpreter
+            .global
+            .get("missing")
+            .expect("missing should exist");
+        assert!(matches!(missing, Value::Null));
+        let second = interpreter
+            .global
+            .get("second")
+            .expect("second should exist");
+        assert!(matches!(second, Value::String(s) if s == "rust"));
+        let out_of_range = interpreter
+            .global
+            .get("out-of-range")
+            .expect("out-of-range should exist");
+        assert!(matches!(out_of_range, Value::Null));
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_map_and_tasks_copy_functions_to_other_threads() -> LangResult<()> {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_worker_threads(3);
+        let source = r#"
+            offset: 100
+            fib: (n) { if(n < 2, () { n }, () { fib(n - 1) + fib(n - 2) }) }
+            shift: (k) { (n) { fib(n) + k + offset } }
+            shifted: parallel-map!(shift(1), [1, 2, 3, 4, 5, 6, 7])
+            adders: parallel-map!((k) { (n) { n + k } }, [1, 2, 3, 4])
+            added: map((add) { add(10) }, adders)
+            task: spawn!(()! { to-set(parallel-map!(fib, [10, 10, 12])) })
+            results: await-all!([task, spawn!(() { add(1) }), task])
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        interpreter.eval_program(&program)?;
+        let get = |name: &str| pretty(&interpreter.global.get(name).unwrap());
+        assert_eq!(get("shifted"), "[102, 102, 103, 104, 106, 109, 114]");
+        assert_eq!(get("added"), "[11, 12, 13, 14]");
+        let results = interpreter.global.get("results").unwrap();
+        let Value::List(results) = results else {
+            panic!("expected a list");
+        };
+        assert_eq!(pretty(&results[0]), pretty(&results[2]));
+        assert_eq!(pretty(&results[0]), "<set [55, 144]>");
+        assert_eq!(results[1].arity(), Some(1));
+
+        let impure =
+            Parser::new(Lexer::new("parallel-map!(log!, [1, 2])").lex()?).parse_program()?;
+        let err = interpreter
+            .eval_program(&impure)
+            .expect_err("impure callback");
+        assert!(err
+            .to_string()
+            .contains("requires a pure function, found 'log!'"));
+        let failing =
+            Parser::new(Lexer::new("await-all!([spawn!(() { 1 / 0 })])").lex()?).parse_program()?;
+        let err = interpreter
+            .eval_program(&failing)
+            .expect_err("the task fails");
+        assert!(err.to_string().contains("zero"), "{}", err);
+
+        // A record shaped like an old task handle is not one
+        let faked = Parser::new(Lexer::new("await-all!([{ tag: \"task\", value: 0 }])").lex()?)
+            .parse_program()?;
+        let err = interpreter
+            .eval_program(&faked)
+            .expect_err("a record is not a task");
+        assert!(
+            err.to_string().contains("expected a task from spawn!"),
+            "{}",
+            err
+        );
+        assert_eq!(interpreter.global.get("task").unwrap().type_name(), "task");
+        Ok(())
+    }
+
+    #[test]
+    fn tasks_talk_over_channels() -> LangResult<()> {
+        let interpreter = run_source(
+            r#"
+            jobs: channel!()
+            results: channel!()
+            square: (n) { n * n }
+            worker: spawn!(()! {
+              for-each!((n)! { send!(results, square(n)) }, [receive!(jobs), receive!(jobs)])
+              "done"
+            })
+            for-each!((n)! { send!(jobs, n) }, [3, 4])
+            squares: [receive!(results), receive!(results)]
+            finished: await-all!([worker])
+            timed-out: select!([jobs, results], 5)
+            sent: send!(results, { unit: "cm" })
+            selected: select!([jobs, results], null)
+        "#,
+        )?;
+        let get = |name: &str| pretty(&interpreter.global.get(name).unwrap());
+        assert_eq!(get("squares"), "[9, 16]");
+        assert_eq!(get("finished"), "[\"done\"]");
+        assert_eq!(get("timed-out"), "{ tag: \"none\", value: null }");
+        let selected = interpreter.global.get("selected").unwrap();
+        let Some(("some", Value::Object(fields))) = Interpreter::tagged(&selected) else {
+            panic!("expected a value, found {}", pretty(&selected));
+        };
+        assert!(Interpreter::values_equal(
+            &fields["channel"],
+            &interpreter.global.get("results").unwrap()
+        ));
+        assert_eq!(pretty(&fields["value"]), "{ unit: \"cm\" }");
+        Ok(())
+    }
+
+    #[test]
+    fn channels_cannot_be_forged_or_shared_between_interpreters() -> LangResult<()> {
+        let forged = run_source(r#"send!({ tag: "channel", value: 0 }, 5)"#)
+            .err()
+            .expect("a record is not a channel");
+        assert!(
+            forged
+                .to_string()
+                .contains("expected a channel from channel!"),
+            "{}",
+            forged
+        );
+
+        let first = run_source("jobs: channel!()\nsent: send!(jobs, 1)")?;
+        let jobs = first.global.get("jobs").unwrap();
+        assert_eq!(jobs.type_name(), "channel");
+        // The second interpreter's first channel has the same number, but
+        // the value sent in the first never reaches it
+        let second = run_source("mine: channel!()")?;
+        let empty = second.call_callable(
+            second.global.get("select!").unwrap(),
+            vec![Value::List(vec![jobs]), Value::Number(1)],
+            Purity::Impure,
+        )?;
+        assert_eq!(pretty(&empty), "{ tag: \"none\", value: null }");
+        Ok(())
+    }
+
+    #[test]
+    fn file_builtins_can_use_an_in_memory_filesystem() -> LangResult<()> {
+        let files = MemoryFileSystem::new()
+            .with_file("/data/names.txt", "ada\nkit\n")
+            .with_file("/data/service.json", r#"{"port": 80}"#);
+        let mut interpreter = Interpreter::with_entry_point_dir(PathBuf::from("/data"));
+        interpreter.set_file_system(Box::new(files.clone()));
+        interpreter.set_output(Box::new(OutputBuffer::default()));
+        let source = r#"
+            names: read-file!("names.txt")
+            write-file!("out/copy.txt", names)
+            write-file!("/data/bytes.bin", string-to-bytes("hex", "cafe"))
+            for-each-line!((line)! { log!(line) }, "names.txt")
+            settings: load-config!("service.json", "FIP_VFS_TEST", null)
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        interpreter.eval_program(&program)?;
+
+        assert_eq!(
+            files.contents("/data/out/copy.txt"),
+            Some(b"ada\nkit\n".to_vec())
+        );
+        assert_eq!(files.contents("/data/bytes.bin"), Some(vec![0xca, 0xfe]));
+        let settings = interpreter.global.get("settings").expect("settings");
+        assert!(pretty(&settings).contains("port: 80"));
+        let missing =
+            Parser::new(Lexer::new("read-file!(\"nowhere.txt\")").lex()?).parse_program()?;
+        let err = interpreter
+            .eval_program(&missing)
+            .expect_err("no such file");
+        assert!(err.to_string().contains("failed to read /data/nowhere.txt"));
+        assert!(!std::path::Path::new("/data/out/copy.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn for_each_line_streams_a_file_into_an_impure_function() -> LangResult<()> {
+        let path = std::env::temp_dir().join(format!("fip-lines-{}.txt", std::process::id()));
+        std::fs::write(&path, "first\r\n\nstop\nnever").expect("write lines file");
+        let source = format!(
+            r#"
+            visit!: (line)! {{
+              log!(line)
+              if(line = "stop", () {{ !line }}, () {{ null }})
+            }}
+            done: for-each-line!(visit!, "{}")
+        "#,
+            path.display()
+        );
+        let stopped = run_source(&source);
+        let pure = run_source(&format!(
+            "done: for-each-line!((line) {{ line }}, \"{}\")",
+            path.display()
+        ));
+        std::fs::write(&path, "first\r\n\nlast").expect("write lines file");
+        let finished = run_source(&source);
+        std::fs::remove_file(&path).ok();
+
+        let err = stopped.err().expect("the failing line stops the loop");
+        assert!(err.to_string().contains(r#"found "stop""#));
+        let err = pure.err().expect("pure functions are rejected");
+        assert!(err.to_string().contains("requires impure function"));
+        let done = finished?.global.get("done").expect("done should exist");
+        assert!(matches!(done, Value::Null));
+
+        let err = run_source("done: for-each-line!(log!, \"/no/such/fip-file\")")
+            .err()
+            .expect("missing files are reported");
+        assert!(err.to_string().contains("failed to read /no/such/fip-file"));
+        Ok(())
+    }
+
+    #[test]
+    fn load_config_merges_file_env_and_overrides() -> LangResult<()> {
+        let path = std::env::temp_dir().join(format!("fip-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "name = \"svc\"\n[database]\nhost = \"localhost\"\nport = 5432\n",
+        )
+        .expect("write config file");
+        std::env::set_var("FIPTEST_DATABASE__HOST", "db.internal");
+        let source = format!(
+            r#"
+            load!: () {{ load-config!("{}", "FIPTEST", ["database.port=6543"]) }}
+            loaded: load!()
+            host: loaded.config.database.host
+            port: loaded.config.database.port
+            name: loaded.config.name
+            sources: loaded.sources
+        "#,
+            path.display()
+        );
+        let result = run_source(&source);
+        std::fs::remove_file(&path).ok();
+        let interpreter = result?;
+        let host = interpreter.global.get("host").expect("host should exist");
+        assert!(matches!(host, Value::String(s) if s == "db.internal"));
+        let port = interpreter.global.get("port").expect("port should exist");
+        assert!(matches!(port, Value::Number(6543)));
+        let name = interpreter.global.get("name").expect("name should exist");
+        assert!(matches!(name, Value::String(s) if s == "svc"));
+        let sources = match interpreter.global.get("sources") {
+            Some(Value::Object(sources)) => sources,
+            other => panic!("expected sources object, got {:?}", other),
+        };
+        assert!(
+            matches!(sources.get("database.host"), Some(Value::String(s)) if s == "env:FIPTEST_DATABASE__HOST")
+        );
+        assert!(matches!(sources.get("database.port"), Some(Value::String(s)) if s == "override"));
+        assert!(matches!(sources.get("name"), Some(Value::String(s)) if s.starts_with("file:")));
+        Ok(())
+    }
+
+    #[test]
+    fn program_output_goes_to_the_configured_sink() -> LangResult<()> {
+        let source = r#"
+            log!("hello")
+            doubled: trace!("n", multiply(2, 21))
+            log!({ total: doubled })
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        let output = OutputBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(output.clone()));
+        interpreter.eval_program(&program)?;
+        assert_eq!(output.contents(), "hello\n(trace) n: 42\n{total: 42}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn print_stays_on_the_line_and_elog_goes_to_stderr() -> LangResult<()> {
+        let source = r#"
+            print!("a")
+            elog!("working")
+            print!(1)
+            log!("")
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        let output = OutputBuffer::default();
+        let errors = OutputBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(output.clone()));
+        interpreter.set_error_output(Box::new(errors.clone()));
+        interpreter.eval_program(&program)?;
+        assert_eq!(output.contents(), "a1\n");
+        assert_eq!(errors.contents(), "working\n");
+        Ok(())
+    }
+
+    #[test]
+    fn deterministic_runs_repeat_ids_and_times_and_refuse_the_network() -> LangResult<()> {
+        let source = r#"
+            ids!: () { [uuid!(), uuid!()] }
+            ids: ids!()
+            clock!: () { format-iso(now!()) }
+            started: clock!()
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        let run = |seed| -> LangResult<(String, String)> {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_deterministic(Some(seed));
+            interpreter.eval_program(&program)?;
+            let get = |name| format!("{:?}", interpreter.global.get(name).unwrap());
+            Ok((get("ids"), get("started")))
+        };
+        let (ids, started) = run(7)?;
+        assert_eq!(run(7)?, (ids.clone(), started.clone()));
+        assert_ne!(run(8)?.0, ids);
+        assert_eq!(started, "\"1970-01-01T00:00:00Z\"");
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_enable_experimental(true);
+        interpreter.set_deterministic(Some(0));
+        let program =
+            Parser::new(Lexer::new("start!: () { serve-http!(0, identity) }\nstart!()").lex()?)
+                .parse_program()?;
+        let err = interpreter.eval_program(&program).unwrap_err();
+        assert!(err.to_string().contains("deterministic"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn capabilities_choose_which_impure_builtins_are_installed() -> LangResult<()> {
+        let all = Interpreter::new();
+        let mut none = Interpreter::new();
+        none.set_capabilities(&[]);
+        let gated: Vec<&str> = Capability::ALL
+            .into_iter()
+            .flat_map(Capability::builtins)
+            .copied()
+            .collect();
+        for name in &gated {
+            assert!(all.global.get(name).is_some(), "{} is not installed", name);
+            assert!(none.global.get(name).is_none(), "{} is not gated", name);
+        }
+        assert_eq!(
+            all.global.values.borrow().len() - none.global.values.borrow().len(),
+            gated.len()
+        );
+
+        let mut clock_only = Interpreter::new();
+        clock_only.set_capabilities(&[Capability::Clock]);
+        let program = Parser::new(
+            Lexer::new(
+                "when!: () { now!() }
+when!()",
+            )
+            .lex()?,
+        )
+        .parse_program()?;
+        clock_only.eval_program(&program)?;
+        let program = Parser::new(
+            Lexer::new(
+                "id!: () { uuid!() }
+id!()",
+            )
+            .lex()?,
+        )
+        .parse_program()?;
+        let err = clock_only.eval_program(&program).unwrap_err();
+        assert!(err.to_string().contains("--allow random"), "{}", err);
+        assert_eq!(Capability::from_name("net"), Some(Capability::Net));
+        assert_eq!(Capability::from_name("proc"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn call_tracing_logs_nested_calls_and_results() -> LangResult<()> {
+        let source = r#"
+            double: (x) { multiply(x, 2) }
+            untraced: double(1)
+            start!: () { set-trace!(true) }
+            stop!: () { set-trace!(false) }
+            start!()
+            traced: double(3)
+            stopped: stop!()
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        let output = OutputBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.add_hooks(Box::new(CallTracer::new(Box::new(output.clone()), false)));
+        interpreter.eval_program(&program)?;
+
+        // A call is traced when tracing was on as it started.
+        let trace = output.contents();
+        assert_eq!(
+            trace,
+            "double(3)\n  multiply(3, 2)\n  multiply -> 6\ndouble -> 6\n\
+             stop!()\n  set-trace!(false)\n  set-trace! -> null\nstop! -> null\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hooks_hear_calls_and_statements() -> LangResult<()> {
+        #[derive(Default)]
+        struct Recorder(Rc<RefCell<Vec<String>>>);
+        impl Hooks for Recorder {
+            fn on_call(&mut self, name: &str, args: &[Value], depth: usize) {
+                let args: Vec<String> = args.iter().map(pretty).collect();
+                let line = format!("{}{}({})", "  ".repeat(depth), name, args.join(", "));
+                self.0.borrow_mut().push(line);
+            }
+            fn on_return(&mut self, name: &str, result: Result<&Value, &LangError>, depth: usize) {
+                let outcome = result.map_or("failed".to_string(), pretty);
+                let line = format!("{}{} -> {}", "  ".repeat(depth), name, outcome);
+                self.0.borrow_mut().push(line);
+            }
+            fn on_statement(&mut self, statement: &Statement) {
+                let kind = match statement {
+                    Statement::Assignment { .. } => "assignment",
+                    Statement::Expression(_) => "expression",
+                    _ => "other",
+                };
+                self.0.borrow_mut().push(kind.to_string());
+            }
+        }
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_hooks(Box::new(Recorder(Rc::clone(&events))));
+        let program = Parser::new(
+            Lexer::new("double: (x) { multiply(x, 2) }\nfour: double(2)\nadd(1)(four)").lex()?,
+        )
+        .parse_program()?;
+        interpreter.eval_program(&program)?;
+        assert_eq!(
+            *events.borrow(),
+            [
+                "assignment",
+                "double(2)",
+                "  multiply(2, 2)",
+                "  multiply -> 4",
+                "double -> 4",
+                "expression",
+                "add(1)",
+                "add -> <partial <builtin add>>",
+                "add(1, 4)",
+                "add -> 5",
+            ]
+        );
+        assert!(interpreter.take_hooks().is_some());
+        assert!(interpreter.take_hooks().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn undefined_identifiers_suggest_a_near_miss() -> LangResult<()> {
+        let error = |source: &str| -> LangResult<String> {
+            let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+            match Interpreter::new().eval_program(&program) {
+                Err(LangError::Runtime(message, _)) => Ok(message),
+                other => panic!("expected a runtime error, got {:?}", other),
+            }
+        };
+        assert_eq!(
+            error("items: [[1]]\nflaten(itmes)")?,
+            "Undefined identifier 'flaten'. Did you mean 'flatten'?"
+        );
+        assert_eq!(
+            error("items: [[1]]\nflatten(itmes)")?,
+            "Undefined identifier 'itmes'. Did you mean 'items'?"
+        );
+        assert_eq!(
+            error("f: (count) { cuont + 1 }\nf(1)")?,
+            "Undefined identifier 'cuont'. Did you mean 'count'?"
+        );
+        assert_eq!(
+            error("outer: (total) { () { total + totl } }\nouter(1)()")?,
+            "Undefined identifier 'totl'. Did you mean 'total'?"
+        );
+        assert_eq!(error("quux(1)")?, "Undefined identifier 'quux'");
+        Ok(())
+    }
+
+    #[test]
+    fn log_lines_below_the_level_are_dropped() -> LangResult<()> {
+        let source = r#"
+            log-debug!("hidden")
+            log-info!({ message: "started", port: 8080 })
+            set-log-level!("error")
+            log-warn!("hidden too")
+            log-error!("failed")
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        let mut interpreter = Interpreter::new();
+        let logs = OutputBuffer::default();
+        interpreter.set_error_output(Box::new(logs.clone()));
+        interpreter.set_deterministic(Some(0));
+        interpreter.set_log_format(LogFormat::Json);
+        interpreter.eval_program(&program)?;
+        assert_eq!(
+            logs.contents(),
+            "{\"time\":\"1970-01-01T00:00:00Z\",\"level\":\"info\",\"message\":\"started\",\"port\":8080}\n\
+             {\"time\":\"1970-01-01T00:00:00Z\",\"level\":\"error\",\"message\":\"failed\"}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn profiling_counts_calls_per_function() -> LangResult<()> {
+        let source = r#"
+            double: (x) { multiply(x, 2) }
+            quad: (x) { double(double(x)) }
+            result: quad(3)
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        let mut interpreter = Interpreter::new();
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        interpreter.add_hooks(Box::new(Rc::clone(&profiler)));
+        interpreter.eval_program(&program)?;
+        let profile = profiler.borrow();
+
+        let mut calls: Vec<_> = profile
+            .functions()
+            .into_iter()
+            .map(|(name, stats)| (name.to_string(), stats.calls))
+            .collect();
+        calls.sort();
+        assert_eq!(
+            calls,
+            vec![
+                ("double".to_string(), 2),
+                ("multiply".to_string(), 2),
+                ("quad".to_string(), 1),
+            ]
+        );
+        assert!(profile.folded_stacks().contains("quad;double;multiply "));
+        Ok(())
+    }
+
+    #[test]
+    fn heap_stats_count_reachable_values_and_closure_scopes() -> LangResult<()> {
+        let source = r#"
+            xs: [1, 2, 3]
+            name: "ab"
+            make-adder: (n) { (x) { add(x, n) } }
+            add-two: make-adder(2)
+        "#;
+        let interpreter = run_source(source)?;
+        let (_, clones) = heap::count_clones(|| interpreter.global.get("xs"));
+        let stats = interpreter.heap_stats();
+
+        let count = |kind: &str| stats.values.get(kind).map_or(0, |tally| tally.count);
+        assert_eq!(count("lists"), 1);
+        assert_eq!(count("numbers"), 4);
+        assert_eq!(count("strings"), 1);
+        assert_eq!(count("functions"), 2);
+        // The adder's scope holds `n` and keeps the global scope as its parent.
+        assert_eq!(stats.environments.count, 2);
+        assert_eq!(stats.longest_chain, 2);
+        assert_eq!(clones.get("lists").map(|tally| tally.count), Some(1));
+        assert!(stats.clones.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn limits_stop_runaway_programs_with_a_dedicated_error() -> LangResult<()> {
+        let program = |source: &str| -> LangResult<Program> {
+            Parser::new(Lexer::new(source).lex()?).parse_program()
+        };
+        let countdown = "count: (n) { if(n = 0, () { 0 }, () { count(n - 1) }) }\n";
+        let deep = program(&format!("{}result: count(100)", countdown))?;
+        let shallow = program("again: count(2)")?;
+        let busy = program("doubled: map((x) { x * 2 }, take(3000, lazy-iterate(increment, 0)))")?;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits {
+            max_call_depth: Some(10),
+            ..Limits::default()
+        });
+        let err = interpreter
+            .eval_program(&deep)
+            .expect_err("recursion is too deep");
+        assert!(matches!(
+            err,
+            LangError::LimitExceeded(Limit::CallDepth(10))
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Evaluation stopped: exceeded the maximum call depth of 10"
+        );
+        // Depth is tracked per run, so the failed run leaves nothing behind.
+        interpreter.eval_program(&shallow)?;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits {
+            max_steps: Some(500),
+            ..Limits::default()
+        });
+        let err = interpreter.eval_program(&busy).expect_err("too many steps");
+        assert!(matches!(err, LangError::LimitExceeded(Limit::Steps(500))));
+        interpreter.eval_program(&program("small: map(increment, [1, 2])")?)?;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits {
+            timeout: Some(Duration::ZERO),
+            ..Limits::default()
+        });
+        let err = interpreter.eval_program(&busy).expect_err("out of time");
+        assert!(matches!(err, LangError::LimitExceeded(Limit::Timeout(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_and_restore_roll_back_globals() -> LangResult<()> {
+        let mut interpreter = run_source("base: 1\nplus-base: (x) { x + base }")?;
+        let snapshot = interpreter.snapshot();
+
+        let program = Parser::new(Lexer::new("extra: plus-base(1)").lex()?).parse_program()?;
+        interpreter.eval_program(&program)?;
+        assert!(matches!(
+            interpreter.global.get("extra"),
+            Some(Value::Number(2))
+        ));
+
+        interpreter.restore(&snapshot);
+        assert!(interpreter.global.get("extra").is_none());
+        // Restored bindings can be defined again without a mutation error.
+        let program = Parser::new(Lexer::new("extra: plus-base(41)").lex()?).parse_program()?;
+        interpreter.eval_program(&program)?;
+        assert!(matches!(
+            interpreter.global.get("extra"),
+            Some(Value::Number(42))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn glob_import_binds_all_exports() -> LangResult<()> {
+        let dir = std::env::temp_dir().join(format!("fip-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create module dir");
+        std::fs::write(
+            dir.join("utils.fip"),
+            "double: (x) { x * 2 }\nhidden: 1\nexport double\n",
+        )
+        .expect("write module");
+        let program = Parser::new(Lexer::new("use * from \"utils\"\nresult: double(21)").lex()?)
+            .parse_program()?;
+        let mut interpreter = Interpreter::with_entry_point_dir(dir.clone());
+        let outcome = interpreter.eval_program(&program);
+        std::fs::remove_dir_all(&dir).ok();
+        outcome?;
+        let result = interpreter
+            .global
+            .get("result")
+            .expect("result should exist");
+        assert!(matches!(result, Value::Number(42)));
+        assert!(interpreter.global.get("hidden").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn modules_can_come_from_a_custom_loader() -> LangResult<()> {
+        let loader = MemoryLoader::new().with_module(
+            "geometry/square",
+            "area: (side) { side * side }\nexport area\n",
+        );
+        let mut interpreter = Interpreter::new();
+        interpreter.set_module_loader(Box::new(loader));
+        let program =
+            Parser::new(Lexer::new("use area from \"geometry/square\"\nresult: area(6)").lex()?)
+                .parse_program()?;
+        interpreter.eval_program(&program)?;
+        let result = interpreter
+            .global
+            .get("result")
+            .expect("result should exist");
+        assert!(matches!(result, Value::Number(36)));
+
+        let missing = Parser::new(Lexer::new("use area from \"circle\"").lex()?).parse_program()?;
+        assert!(interpreter.eval_program(&missing).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn imports_only_evaluate_the_pure_bindings_they_need() -> LangResult<()> {
+        let dir = std::env::temp_dir().join(format!("fip-lazy-module-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create module dir");
+        std::fs::write(
+            dir.join("lib.fip"),
+            r#"broken: !5
+unused: !6
+base: 1
+plus-base: (n) { n + base }
+answer: 40
+export plus-base
+export answer
+export broken
+"#,
+        )
+        .expect("write module");
+        let run = |source: &str| -> LangResult<Interpreter> {
+            let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+            let mut interpreter = Interpreter::with_entry_point_dir(dir.clone());
+            interpreter.eval_program(&program)?;
+            Ok(interpreter)
+        };
+        let selective = run("use { plus-base, answer } from \"lib\"\nresult: plus-base(answer)");
+        let single = run("use broken from \"lib\"");
+        let namespace = run("use lib as l from \"lib\"");
+        std::fs::remove_dir_all(&dir).ok();
+
+        let result = selective?
+            .global
+            .get("result")
+            .expect("result should exist");
+        assert!(matches!(result, Value::Number(41)));
+        for outcome in [single, namespace] {
+            let err = outcome.err().expect("the broken export is evaluated");
+            assert!(err
+                .to_string()
+                .contains("Operand of not must be boolean, found 5"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn when_import_picks_module_by_condition() -> LangResult<()> {
+        let dir = std::env::temp_dir().join(format!("fip-when-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create module dir");
+        std::fs::write(dir.join("unix.fip"), "sep: \"/\"\nexport sep\n").expect("write module");
+        std::fs::write(dir.join("windows.fip"), "sep: \"\\\\\"\nexport sep\n")
+            .expect("write module");
+        let source = r#"
+            use sep from when(platform().family = "windows", "windows", "unix")
+            use { missing } from when(false, "does-not-exist")
+            os: platform().os
+        "#;
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        let mut interpreter = Interpreter::with_entry_point_dir(dir.clone());
+        let outcome = interpreter.eval_program(&program);
+        std::fs::remove_dir_all(&dir).ok();
+        outcome?;
+        let expected = if cfg!(windows) { "\\" } else { "/" };
+        let sep = interpreter.global.get("sep").expect("sep should exist");
+        assert!(matches!(sep, Value::String(s) if s == expected));
+        assert!(interpreter.global.get("missing").is_none());
+        let os = interpreter.global.get("os").expect("os should exist");
+        assert!(matches!(os, Value::String(s) if s == std::env::consts::OS));
+        Ok(())
+    }
+
+    #[test]
+    fn every_builtin_is_documented() {
+        let interpreter = Interpreter::new();
+        let builtins = interpreter.builtins();
+        assert!(builtins.windows(2).all(|pair| pair[0].name < pair[1].name));
+        for builtin in builtins {
+            assert!(!builtin.doc.is_empty(), "{} has no doc", builtin.name);
+        }
+    }
+
+    #[test]
+    fn deprecated_builtins_still_run_and_warn_once() -> LangResult<()> {
+        let mut interpreter = Interpreter::new();
+        interpreter.add_builtin(BuiltinFunction {
+            name: "inc".to_string(),
+            impure: false,
+            params: vec!["value".to_string()],
+            doc: "Old name for increment.",
+            stability: Stability::Deprecated {
+                replacement: "increment",
+            },
+            func: Rc::new(|_, args| match &args[0] {
+                Value::Number(n) => Ok(Value::Number(n + 1)),
+                _ => Ok(Value::Null),
+            }),
+        });
+        let warnings = OutputBuffer::default();
+        interpreter.set_error_output(Box::new(warnings.clone()));
+        let program = Parser::new(Lexer::new("a: inc(1)\nb: inc(a)").lex()?).parse_program()?;
+        interpreter.eval_program(&program)?;
+        let b = interpreter.global.get("b").expect("b should exist");
+        assert!(matches!(b, Value::Number(3)));
+        assert_eq!(interpreter.warned_deprecated.borrow().len(), 1);
+        assert_eq!(
+            warnings.contents(),
+            "Warning: builtin 'inc' is deprecated; use 'increment' instead\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn closures_capture_only_what_they_mention() -> LangResult<()> {
+        let interpreter = run_source(
+            r#"
+            c: 100
+            outer: (a, unused) { (b) { (d) { a + b + c + d } } }
+            middle: outer(1, [1, 2, 3])(2)
+            result: middle(3)
+        "#,
+        )?;
+        let result = interpreter.global.get("result").expect("result");
+        assert!(matches!(result, Value::Number(106)));
+
+        let Value::Function(middle) = interpreter.global.get("middle").expect("middle") else {
+            panic!("middle should be a function");
+        };
+        // One scope with the captured bindings, straight over the top level
+        let parent = middle.env.parent.as_ref().expect("captured scope");
+        assert!(Rc::ptr_eq(parent, &interpreter.global));
+        let captured: Vec<String> = middle.env.slots.iter().map(pretty).collect();
+        assert_eq!(captured, ["1", "2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn closures_share_the_body_of_their_lambda() -> LangResult<()> {
+        let interpreter = run_source(
+            r#"
+            adder: (n) { (x) { x + n } }
+            add-one: adder(1)
+            add-two: adder(2)
+            sum: (a, b, c) { a + b + c }
+            partial: sum(1)
+        "#,
+        )?;
+        let function = |name: &str| match interpreter.global.get(name) {
+            Some(Value::Function(func)) => func,
+            _ => panic!("{} should be a function", name),
+        };
+        assert!(Rc::ptr_eq(
+            &function("add-one").body,
+            &function("add-two").body
+        ));
+        let Some(Value::Partial { callee, .. }) = interpreter.global.get("partial") else {
+            panic!("partial should be a partial application");
+        };
+        let Value::Function(sum) = callee.as_ref() else {
+            panic!("partial should apply sum");
+        };
+        assert!(Rc::ptr_eq(&function("sum").body, &sum.body));
+        Ok(())
+    }
+
+    #[test]
+    fn partial_application_keeps_the_original_callee() -> LangResult<()> {
+        let interpreter = run_source(
+            r#"
+            sum: (a, b, c) { a + b + c }
+            add-three: sum(1)(2)
+            six: add-three(3)
+            kind: type-of(add-three)
+            steps: { 1 add-three }
+            shout!: (prefix, text) { log!("{prefix}{text}") }
+            greet!: shout!("hi ")
+            for-each!(greet!, ["a", "b"])
+        "#,
+        )?;
+        let Some(Value::Partial { callee, applied }) = interpreter.global.get("add-three") else {
+            panic!("add-three should be a partial application");
+        };
+        assert!(matches!(callee.as_ref(), Value::Function(func) if func.name == "sum"));
+        assert_eq!(applied.len(), 2);
+        assert_eq!(pretty(&interpreter.global.get("six").unwrap()), "6");
+        assert_eq!(
+            pretty(&interpreter.global.get("kind").unwrap()),
+            "\"function\""
+        );
+        assert_eq!(pretty(&interpreter.global.get("steps").unwrap()), "4");
+        Ok(())
+    }
+
+    #[test]
+    fn program_value_is_the_last_expression() -> LangResult<()> {
+        let mut interpreter = Interpreter::new();
+        let program = Parser::new(Lexer::new("double: (n) { n * 2 }\nx: 4\n[x, double(x)]").lex()?)
+            .parse_program()?;
+        let value = interpreter.eval_program_value(&program)?.expect("a value");
+        assert_eq!(pretty(&value), "[4, 8]");
+
+        let program = Parser::new(Lexer::new("[1, 2]\ny: 3").lex()?).parse_program()?;
+        assert!(interpreter.eval_program_value(&program)?.is_none());
+        Ok(())
+    }
+}
+
+pub struct FunctionValue {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Rc<Expression>,
+    pub env: Rc<Environment>,
+    pub impure: bool,
+}
+
+pub type BuiltinFn = Rc<dyn Fn(&Interpreter, &[Value]) -> LangResult<Value>>;
+
+pub struct BuiltinFunction {
+    pub name: String,
+    pub impure: bool,
+    pub params: Vec<String>, // Parameter names for currying support
+    /// One-line summary shown in the generated builtin reference.
+    pub doc: &'static str,
+    pub stability: Stability,
+    pub func: BuiltinFn,
+}
+
+/// How settled a builtin's behavior is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stability {
+    Stable,
+    /// May change or disappear; calling it requires opting in with
+    /// `Interpreter::set_enable_experimental`.
+    Experimental,
+    /// Still works, but warns once per interpreter and names the builtin to
+    /// use instead.
+    Deprecated {
+        replacement: &'static str,
+    },
+}
+
+impl Clone for FunctionValue {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: Rc::clone(&self.body),
+            env: Rc::clone(&self.env),
+            impure: self.impure,
+        }
+    }
+}
+
+impl Clone for BuiltinFunction {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            impure: self.impure,
+            params: self.params.clone(),
+            doc: self.doc,
+            stability: self.stability,
+            func: Rc::clone(&self.func),
+        }
+    }
+}
+
+/// Copies values into a [`Package`] so they can move to another thread,
+/// numbering each function the first time it is reached.
+#[derive(Default)]
+struct Packer {
+    package: Package,
+    numbered: HashMap<*const FunctionValue, usize>,
+    /// The functions numbered so far, kept alive so that a function
+    /// dropped while packing cannot free its address for another.
+    copied: Vec<Rc<FunctionValue>>,
+}
+
+impl Packer {
+    /// Copies `value` and adds it to the package's values.
+    fn push(&mut self, value: &Value) -> LangResult<()> {
+        let portable = self.value(value)?;
+        self.package.values.push(portable);
+        Ok(())
+    }
+
+    fn value(&mut self, value: &Value) -> LangResult<Portable> {
+        let copy = |packer: &mut Self, values: &[Value]| {
+            values
+                .iter()
+                .map(|value| packer.value(value))
+                .collect::<LangResult<Vec<_>>>()
+        };
+        Ok(match value {
+            Value::Number(n) => Portable::Number(*n),
+            Value::String(text) => Portable::String(text.clone()),
+            Value::Bytes(bytes) => Portable::Bytes(bytes.clone()),
+            Value::Boolean(b) => Portable::Boolean(*b),
+            Value::List(items) => Portable::List(copy(self, items)?),
+            Value::Set(items) => Portable::Set(copy(self, items)?),
+            Value::Map(entries) => Portable::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| Ok((self.value(key)?, self.value(value)?)))
+                    .collect::<LangResult<_>>()?,
+            ),
+            Value::Object(fields) => Portable::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), self.value(value)?)))
+                    .collect::<LangResult<_>>()?,
+            ),
+            Value::Function(func) => Portable::Function(self.function(func)?),
+            Value::Builtin(builtin) => Portable::Builtin(builtin.name.clone()),
+            Value::Partial { callee, applied } => Portable::Partial {
+                callee: Box::new(self.value(callee)?),
+                applied: copy(self, applied)?,
+            },
+            Value::Lazy(_) => return Err(LangError::Runtime(
+                "Lazy sequences cannot be handed to another thread; take a list from them first"
+                    .to_string(),
+                None,
+            )),
+            Value::Task(_) => {
+                return Err(LangError::Runtime(
+                    "Tasks cannot be handed to another thread; await them where they were spawned"
+                        .to_string(),
+                    None,
+                ))
+            }
+            Value::Channel(id) => Portable::Channel(*id),
+            Value::Null => Portable::Null,
+            Value::Unit => Portable::Unit,
+        })
+    }
+
+    fn function(&mut self, func: &Rc<FunctionValue>) -> LangResult<usize> {
+        if let Some(&index) = self.numbered.get(&Rc::as_ptr(func)) {
+            return Ok(index);
+        }
+        let index = self.package.functions.len();
+        self.numbered.insert(Rc::as_ptr(func), index);
+        self.copied.push(Rc::clone(func));
+        self.package.functions.push(PortableFunction {
+            name: func.name.clone(),
+            impure: func.impure,
+            source: portable::lambda_source(&func.params, &func.body, func.impure),
+            bindings: Vec::new(),
+        });
+
+        let free = portable::free_variables(&func.body);
+        let mut bindings = Vec::new();
+        for (name, index) in &free.captures {
+            if let Some(value) = func.env.slots.get(*index) {
+                bindings.push((name.clone(), self.value(value)?));
+            }
+        }
+        for name in free.globals {
+            if free.captures.contains_key(&name) {
+                continue;
+            }
+            if let Some(value) = func.env.get(&name) {
+                bindings.push((name, self.value(&value)?));
+            }
+        }
+        self.package.functions[index].bindings = bindings;
+        Ok(index)
+    }
+}
+
+/// Visits every reachable value once, following closures into the scopes
+/// they keep alive. Shared functions, sequences, and scopes are recognised
+/// by address, which also stops cycles through recursive closures.
+#[derive(Default)]
+struct HeapWalk {
+    stats: HeapStats,
+    seen: HashSet<*const ()>,
+}
+
+impl HeapWalk {
+    fn first_visit<T>(&mut self, shared: &Rc<T>) -> bool {
+        self.seen.insert(Rc::as_ptr(shared) as *const ())
+    }
+
+    fn first_visit_slice<T>(&mut self, shared: &Rc<[T]>) -> bool {
+        self.seen.insert(Rc::as_ptr(shared) as *const ())
+    }
+
+    fn environment(&mut self, env: &Rc<Environment>) {
+        if !self.first_visit(env) {
+            return;
+        }
+        let values = env.values.borrow();
+        let bytes = values
+            .keys()
+            .map(|name| std::mem::size_of::<String>() + name.capacity())
+            .sum::<usize>()
+            + (values.len() + env.slots.len()) * std::mem::size_of::<Value>();
+        let chain = std::iter::successors(Some(env), |env| env.parent.as_ref()).count();
+        self.stats
+            .record_environment(values.len() + env.slots.len(), bytes, chain);
+        for value in values.values().chain(&env.slots) {
+            self.value(value);
+        }
+        if let Some(parent) = &env.parent {
+            self.environment(parent);
+        }
+    }
+
+    fn value(&mut self, value: &Value) {
+        let first = match value {
+            Value::Function(func) => self.first_visit(func),
+            Value::Builtin(builtin) => self.first_visit(builtin),
+            Value::Partial { applied, .. } => self.first_visit_slice(applied),
+            Value::Lazy(sequence) => self.first_visit(sequence),
+            _ => true,
+        };
+        if !first {
+            return;
+        }
+        self.stats.record_value(value);
+        match value {
+            Value::List(items) | Value::Set(items) => {
+                items.iter().for_each(|item| self.value(item))
+            }
+            Value::Map(entries) => entries.iter().for_each(|(key, value)| {
+                self.value(key);
+                self.value(value);
+            }),
+            Value::Object(fields) => fields.values().for_each(|field| self.value(field)),
+            Value::Function(func) => self.environment(&func.env),
+            Value::Partial { callee, applied } => {
+                self.value(callee);
+                applied.iter().for_each(|arg| self.value(arg));
+            }
+            Value::Lazy(sequence) => self.sequence(sequence),
+            _ => {}
+        }
+    }
+
+    fn sequence(&mut self, sequence: &Sequence) {
+        match sequence {
+            Sequence::List(items) => items.iter().for_each(|item| self.value(item)),
+            Sequence::Iterate { step, seed } => {
+                self.value(step);
+                self.value(seed);
+            }
+            Sequence::Map {
+                func: stage,
+                source,
+            }
+            | Sequence::Filter {
+                predicate: stage,
+                source,
+            } => {
+                self.value(stage);
+                if self.first_visit(source) {
+                    self.sequence(source);
+                }
+            }
+        }
+    }
+}
+
+/// A lazily evaluated sequence. Stages are only run when the sequence is
+/// forced, one element at a time, so `take` never computes more elements
+/// than it returns.
+pub enum Sequence {
+    List(Vec<Value>),
+    Iterate {
+        step: Value,
+        seed: Value,
+    },
+    Map {
+        func: Value,
+        source: Rc<Sequence>,
+    },
+    Filter {
+        predicate: Value,
+        source: Rc<Sequence>,
+    },
+}
+
+impl Sequence {
+    /// Accepts either an existing lazy sequence or a list to wrap.
+    fn from_value(builtin: &str, value: &Value) -> LangResult<Rc<Sequence>> {
+        match value {
+            Value::Lazy(sequence) => Ok(Rc::clone(sequence)),
+            Value::List(items) => Ok(Rc::new(Sequence::List(items.clone()))),
+            other => Err(LangError::Runtime(
+                format!(
+                    "Builtin '{}' expected list or lazy sequence, found {:?}",
+                    builtin, other
+                ),
+                None,
+            )),
+        }
+    }
+
+    /// Whether forcing the whole sequence terminates.
+    fn is_finite(&self) -> bool {
+        match self {
+            Sequence::List(_) => true,
+            Sequence::Iterate { .. } => false,
+            Sequence::Map { source, .. } | Sequence::Filter { source, .. } => source.is_finite(),
+        }
+    }
+
+    fn cursor(&self) -> SequenceCursor<'_> {
+        match self {
+            Sequence::List(items) => SequenceCursor::List(items.iter()),
+            Sequence::Iterate { step, seed } => SequenceCursor::Iterate {
+                step,
+                seed,
+                last: None,
+            },
+            Sequence::Map { func, source } => SequenceCursor::Map {
+                func,
+                source: Box::new(source.cursor()),
+            },
+            Sequence::Filter { predicate, source } => SequenceCursor::Filter {
+                predicate,
+                source: Box::new(source.cursor()),
+            },
+        }
+    }
+}
+
+enum SequenceCursor<'a> {
+    List(std::slice::Iter<'a, Value>),
+    /// `last` is the element returned before, from which the next one is
+    /// computed when it is asked for.
+    Iterate {
+        step: &'a Value,
+        seed: &'a Value,
+        last: Option<Value>,
+    },
+    Map {
+        func: &'a Value,
+        source: Box<SequenceCursor<'a>>,
+    },
+    Filter {
+        predicate: &'a Value,
+        source: Box<SequenceCursor<'a>>,
+    },
+}
+
+impl SequenceCursor<'_> {
+    fn next(&mut self, interpreter: &Interpreter) -> LangResult<Option<Value>> {
+        match self {
+            SequenceCursor::List(items) => Ok(items.next().cloned()),
+            SequenceCursor::Iterate { step, seed, last } => {
+                let item = match last.take() {
+                    None => (*seed).clone(),
+                    Some(last) => {
+                        interpreter.call_callable((*step).clone(), vec![last], Purity::Pure)?
+                    }
+                };
+                *last = Some(item.clone());
+                Ok(Some(item))
+            }
+            SequenceCursor::Map { func, source } => match source.next(interpreter)? {
+                Some(item) => interpreter
+                    .call_callable((*func).clone(), vec![item], Purity::Pure)
+                    .map(Some),
+                None => Ok(None),
+            },
+            SequenceCursor::Filter { predicate, source } => {
+                while let Some(item) = source.next(interpreter)? {
+                    let keep = interpreter.call_callable(
+                        (*predicate).clone(),
+                        vec![item.clone()],
+                        Purity::Pure,
+                    )?;
+                    match keep {
+                        Value::Boolean(true) => return Ok(Some(item)),
+                        Value::Boolean(false) => {}
+                        other => {
+                            return Err(LangError::Runtime(
+                                format!(
+                                "Predicate passed to 'lazy-filter' must return boolean, found {:?}",
+                                other
+                            ),
+                                None,
+                            ))
+                        }
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Environment {
+    values: RefCell<HashMap<String, Value>>,
+    /// A function's arguments or a closure's captures, read by position
+    /// through `Expression::Local`.
+    slots: Vec<Value>,
+    /// The names of `slots`, only read to suggest one for a misspelled name.
+    slot_names: SlotNames,
+    parent: Option<Rc<Environment>>,
+}
+
+#[derive(Clone)]
+enum SlotNames {
+    None,
+    /// The parameters of the function this scope is a call of.
+    Params(Rc<FunctionValue>),
+    Captures(Rc<[String]>),
+}
+
+impl SlotNames {
+    fn names(&self) -> &[String] {
+        match self {
+            SlotNames::None => &[],
+            SlotNames::Params(func) => &func.params,
+            SlotNames::Captures(names) => names,
+        }
+    }
+}
+
+impl Environment {
+    pub fn new(parent: Option<Rc<Environment>>) -> Rc<Self> {
+        Self::with_slots(Vec::new(), SlotNames::None, parent)
+    }
+
+    fn with_slots(
+        slots: Vec<Value>,
+        slot_names: SlotNames,
+        parent: Option<Rc<Environment>>,
+    ) -> Rc<Self> {
+        Rc::new(Self {
+            values: RefCell::new(HashMap::new()),
+            slots,
+            slot_names,
+            parent,
+        })
+    }
+
+    pub fn define(&self, name: String, value: Value) -> LangResult<()> {
+        let mut values = self.values.borrow_mut();
+        if values.contains_key(&name) {
+            return Err(LangError::Runtime(
+                format!("Mutation error: trying to mutate binding {}", name),
+                None,
+            ));
+        }
+        values.insert(name, value);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.values.borrow().get(name) {
+            Some(value.clone())
+        } else if let Some(parent) = &self.parent {
+            parent.get(name)
+        } else {
+            None
+        }
+    }
+
+    /// Every name bound in this scope or the scopes around it, parameters
+    /// and captures included.
+    fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.values.borrow().keys().cloned().collect();
+        names.extend(self.slot_names.names().iter().cloned());
+        if let Some(parent) = &self.parent {
+            names.extend(parent.names());
+        }
+        names
+    }
+
+    fn slot(&self, slot: &Slot) -> Option<Value> {
+        let mut scope = self;
+        for _ in 0..slot.depth {
+            scope = scope.parent.as_deref()?;
+        }
+        scope.slots.get(slot.index).cloned()
+    }
+
+    /// The top-level scope of the program or module this scope belongs to.
+    fn root(self: &Rc<Self>) -> Rc<Self> {
+        let mut scope = self;
+        while let Some(parent) = &scope.parent {
+            scope = parent;
+        }
+        Rc::clone(scope)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Purity {
+    Pure,
+    Impure,
+}
+
+impl Purity {
+    fn allow_impure(self) -> bool {
+        matches!(self, Purity::Impure)
+    }
+}
+
+/// A checkpoint of an interpreter's global bindings and loaded modules,
+/// taken with [`Interpreter::snapshot`] and applied with
+/// [`Interpreter::restore`].
+#[derive(Clone)]
+pub struct Snapshot {
+    pub(crate) globals: HashMap<String, Value>,
+    module_cache: HashMap<String, Rc<Module>>,
+}
+
+/// A loaded module. Pure top-level assignments are left pending until an
+/// import or a statement that runs needs them, so bindings nobody uses are
+/// never evaluated.
+struct Module {
+    program: Program,
+    env: Rc<Environment>,
+    exports: BTreeSet<String>,
+    /// The names each top-level definition mentions, for finding everything
+    /// a requested binding depends on.
+    references: HashMap<String, HashSet<String>>,
+    /// Indices of the statements that have not been evaluated yet.
+    pending: RefCell<BTreeSet<usize>>,
+}
+
+pub struct Interpreter {
+    global: Rc<Environment>,
+    module_cache: RefCell<HashMap<String, Rc<Module>>>,
+    entry_point_dir: Option<PathBuf>,
+    loading_modules: RefCell<HashSet<String>>,
+    module_loader: Box<dyn ModuleLoader>,
+    file_system: Box<dyn FileSystem>,
+    enable_experimental: bool,
+    warned_deprecated: RefCell<HashSet<String>>,
+    limits: Limits,
+    call_depth: Cell<usize>,
+    steps: Cell<u64>,
+    deadline: Cell<Option<Instant>>,
+    /// How many calls reported to the hooks are running.
+    hook_depth: Cell<usize>,
+    output: RefCell<Box<dyn Write>>,
+    error_output: RefCell<Box<dyn Write>>,
+    /// The seeded generator's state in deterministic mode, `None` otherwise.
+    seed: Cell<Option<u64>>,
+    update_snapshots: bool,
+    capabilities: HashSet<Capability>,
+    /// Answers for the generator `for-all` is running, if any.
+    choices: RefCell<Option<Choices>>,
+    /// Everything that hears about the run, in the order it was added.
+    hooks: RefCell<Vec<Box<dyn Hooks>>>,
+    /// Whether `set_file_system` replaced the real filesystem. Workers only
+    /// have the real one, so they are not given the fs capability then.
+    custom_file_system: bool,
+    /// Tasks started by `spawn!`, numbered by position.
+    tasks: RefCell<Vec<Task>>,
+    /// Channels made by `channel!`, shared with the workers this
+    /// interpreter starts.
+    channels: Arc<Channels>,
+    /// How many threads `parallel-map!` uses; `None` means one per core.
+    worker_threads: Option<usize>,
+    /// Lines below this level are dropped by `log-debug!` and friends.
+    log_level: Cell<LogLevel>,
+    log_format: LogFormat,
+}
+
+/// A task started by `spawn!`.
+enum Task {
+    Running(std::thread::JoinHandle<LangResult<Package>>),
+    /// The result once `await-all!` has waited for it, or the message of
+    /// the error it failed with.
+    Finished(Result<Package, String>),
+}
+
+/// What a worker interpreter inherits from the one that starts it.
+#[derive(Clone)]
+struct WorkerSettings {
+    entry_point_dir: Option<PathBuf>,
+    enable_experimental: bool,
+    limits: Limits,
+    seed: Option<u64>,
+    capabilities: Vec<Capability>,
+    log_level: LogLevel,
+    log_format: LogFormat,
+    channels: Arc<Channels>,
+}
+
+/// One case tried by `for-all`.
+struct Case {
+    value: Value,
+    answers: Vec<u64>,
+    size: u64,
+    /// `None` when the property held, otherwise the error it raised, if any.
+    failure: Option<Option<String>>,
+}
+
+/// Fails when a pure higher-order builtin is handed an impure callback,
+/// pointing at the variant that may call it. Higher-order builtins call
+/// their callbacks in the purity they themselves have, not their caller's.
+fn require_pure_callback(builtin: &str, callback: &Value, instead: Option<&str>) -> LangResult<()> {
+    if callback.impure() != Some(true) {
+        return Ok(());
+    }
+    let hint = match instead {
+        Some(variant) => format!("; use '{}' instead", variant),
+        None => String::new(),
+    };
+    Err(LangError::Runtime(
+        format!(
+            "Builtin '{}' cannot call impure function '{}'{}",
+            builtin,
+            callback.callable_name(),
+            hint
+        ),
+        None,
+    ))
+}
+
+/// Wraps `generate` as a generator: a function of no arguments that
+/// `for-all` calls to build each case.
+fn generator(generate: impl Fn(&Interpreter) -> LangResult<Value> + 'static) -> Value {
+    Value::Builtin(Rc::new(BuiltinFunction {
+        name: "generator".to_string(),
+        impure: false,
+        params: vec![],
+        doc: "Builds one value for for-all.",
+        stability: Stability::Stable,
+        func: Rc::new(move |interpreter, args| {
+            if !args.is_empty() {
+                return Err(LangError::Runtime(
+                    "Generators expect no arguments".to_string(),
+                    None,
+                ));
+            }
+            generate(interpreter)
+        }),
+    }))
+}
+
+/// A group of impure builtins that reach outside the program. Every group
+/// is installed by default; `set_capabilities` installs only the listed
+/// ones, for sandboxing untrusted code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Reading and writing files.
+    Fs,
+    /// HTTP requests and servers.
+    Net,
+    /// Reading the current time.
+    Clock,
+    /// Random numbers.
+    Random,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::Fs,
+        Capability::Net,
+        Capability::Clock,
+        Capability::Random,
+    ];
+
+    /// The name used by `fip run --allow`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Fs => "fs",
+            Capability::Net => "net",
+            Capability::Clock => "clock",
+            Capability::Random => "random",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Capability::ALL
+            .into_iter()
+            .find(|capability| capability.name() == name)
+    }
+
+    /// The builtins this capability installs.
+    pub fn builtins(self) -> &'static [&'static str] {
+        match self {
+            Capability::Fs => &[
+                "expect-snapshot!",
+                "for-each-line!",
+                "read-bytes!",
+                "read-file!",
+                "write-file!",
+                "load-config!",
+            ],
+            Capability::Net => &["http-post!", "graphql!", "serve-http!"],
+            Capability::Clock => &["now!"],
+            Capability::Random => &["uuid!"],
+        }
+    }
+}
+
+/// Bounds on a single `eval_program` run, for embedding the interpreter or
+/// running untrusted code. `None` means unlimited, which is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// How many function calls may be nested inside each other.
+    pub max_call_depth: Option<usize>,
+    /// How many expressions may be evaluated.
+    pub max_steps: Option<u64>,
+    /// Wall-clock time allowed, checked between evaluation steps, so a single
+    /// slow builtin call is not interrupted. Ignored on wasm32, which has no
+    /// clock; use `max_steps` there.
+    pub timeout: Option<Duration>,
+}
+
+/// An in-memory sink for `set_output` and friends. Clones share the same
+/// buffer, so keep one and hand a clone to the interpreter.
+#[derive(Debug, Clone, Default)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    /// Everything written so far, with invalid UTF-8 replaced.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        let global = Environment::new(None);
+        let mut interpreter = Self {
+            global,
+            module_cache: RefCell::new(HashMap::new()),
+            entry_point_dir: None,
+            loading_modules: RefCell::new(HashSet::new()),
+            module_loader: Box::new(FileSystemLoader),
+            file_system: Box::new(RealFileSystem),
+            enable_experimental: false,
+            warned_deprecated: RefCell::new(HashSet::new()),
+            limits: Limits::default(),
+            call_depth: Cell::new(0),
+            steps: Cell::new(0),
+            deadline: Cell::new(None),
+            hook_depth: Cell::new(0),
+            output: RefCell::new(Box::new(std::io::stdout())),
+            error_output: RefCell::new(Box::new(std::io::stderr())),
+            seed: Cell::new(None),
+            update_snapshots: false,
+            capabilities: Capability::ALL.into_iter().collect(),
+            choices: RefCell::new(None),
+            hooks: RefCell::new(Vec::new()),
+            custom_file_system: false,
+            tasks: RefCell::new(Vec::new()),
+            channels: Arc::default(),
+            worker_threads: None,
+            log_level: Cell::new(LogLevel::default()),
+            log_format: LogFormat::default(),
+        };
+        interpreter.install_builtins();
+        interpreter
+    }
+
+    pub fn with_entry_point_dir(entry_point_dir: PathBuf) -> Self {
+        let global = Environment::new(None);
+        let mut interpreter = Self {
+            global,
+            module_cache: RefCell::new(HashMap::new()),
+            entry_point_dir: Some(entry_point_dir),
+            loading_modules: RefCell::new(HashSet::new()),
+            module_loader: Box::new(FileSystemLoader),
+            file_system: Box::new(RealFileSystem),
+            enable_experimental: false,
+            warned_deprecated: RefCell::new(HashSet::new()),
+            limits: Limits::default(),
+            call_depth: Cell::new(0),
+            steps: Cell::new(0),
+            deadline: Cell::new(None),
+            hook_depth: Cell::new(0),
+            output: RefCell::new(Box::new(std::io::stdout())),
+            error_output: RefCell::new(Box::new(std::io::stderr())),
+            seed: Cell::new(None),
+            update_snapshots: false,
+            capabilities: Capability::ALL.into_iter().collect(),
+            choices: RefCell::new(None),
+            hooks: RefCell::new(Vec::new()),
+            custom_file_system: false,
+            tasks: RefCell::new(Vec::new()),
+            channels: Arc::default(),
+            worker_threads: None,
+            log_level: Cell::new(LogLevel::default()),
+            log_format: LogFormat::default(),
         };
         interpreter.install_builtins();
         interpreter
     }
 
-    fn install_builtins(&mut self) {
+    fn install_builtins(&mut self) {
+        self.install_effect_builtins();
+        self.install_assertion_builtins();
+        self.install_property_builtins();
+        self.install_value_builtins();
+        self.install_math_builtins();
+        self.install_list_builtins();
+        self.install_set_builtins();
+        self.install_map_builtins();
+        self.install_tag_builtins();
+        self.install_control_builtins();
+        self.install_string_builtins();
+        self.install_bytes_builtins();
+        self.install_lazy_builtins();
+        self.install_concurrency_builtins();
+        self.install_channel_builtins();
+        self.install_encoding_builtins();
+        self.install_object_builtins();
+        self.install_time_builtins();
+        for capability in Capability::ALL {
+            if !self.capabilities.contains(&capability) {
+                continue;
+            }
+            match capability {
+                Capability::Fs => self.install_fs_builtins(),
+                Capability::Net => self.install_net_builtins(),
+                Capability::Clock => self.install_clock_builtins(),
+                Capability::Random => self.install_random_builtins(),
+            }
+        }
+    }
+
+    /// Output and iteration with side effects.
+    fn install_effect_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "log!".to_string(),
+            impure: true,
+            params: vec!["message".to_string()],
+            doc: "Prints the value followed by a newline and returns null.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'log!' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let message = interpreter.value_to_string(&args[0])?;
+                interpreter.print(format_args!("{}", message))?;
+                Ok(Value::Null)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "print!".to_string(),
+            impure: true,
+            params: vec!["message".to_string()],
+            doc: "Prints the value without a newline and returns null.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'print!' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let message = interpreter.value_to_string(&args[0])?;
+                // Flushed so a prompt shows before the program waits for input
+                let mut output = interpreter.output.borrow_mut();
+                write!(output, "{}", message)
+                    .and_then(|()| output.flush())
+                    .map_err(|err| {
+                        LangError::Runtime(format!("Failed to write output: {}", err), None)
+                    })?;
+                Ok(Value::Null)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "elog!".to_string(),
+            impure: true,
+            params: vec!["message".to_string()],
+            doc: "Prints the value followed by a newline to stderr and returns null.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'elog!' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let message = interpreter.value_to_string(&args[0])?;
+                writeln!(interpreter.error_output.borrow_mut(), "{}", message).map_err(|err| {
+                    LangError::Runtime(format!("Failed to write output: {}", err), None)
+                })?;
+                Ok(Value::Null)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "trace!".to_string(),
+            impure: true,
+            params: vec!["label".to_string(), "value".to_string()],
+            doc:
+                "Prints a labelled value and returns the value unchanged, for debugging pipelines.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'trace!' expects exactly 2 arguments (message, value)".to_string(),
+                        None,
+                    ));
+                }
+                let message = interpreter.value_to_string(&args[0])?;
+                let value_str = interpreter.value_to_string(&args[1])?;
+                interpreter.print(format_args!("(trace) {}: {}", message, value_str))?;
+                Ok(args[1].clone())
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "set-trace!".to_string(),
+            impure: true,
+            params: vec!["enabled".to_string()],
+            doc: "Turns call tracing to stderr on or off and returns null.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'set-trace!' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::Boolean(enabled) => {
+                        interpreter.notify(|hooks| hooks.on_set_trace(*enabled));
+                        Ok(Value::Null)
+                    }
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'set-trace!' expected boolean, found {:?}", other),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        for level in LogLevel::ALL {
+            self.add_builtin(BuiltinFunction {
+                name: format!("log-{}!", level.name()),
+                impure: true,
+                params: vec!["entry".to_string()],
+                doc: match level {
+                    LogLevel::Debug => "Writes a debug log line, with fields when given an object, to stderr and returns null.",
+                    LogLevel::Info => "Writes an info log line, with fields when given an object, to stderr and returns null.",
+                    LogLevel::Warn => "Writes a warning log line, with fields when given an object, to stderr and returns null.",
+                    LogLevel::Error => "Writes an error log line, with fields when given an object, to stderr and returns null.",
+                },
+                stability: Stability::Stable,
+                func: Rc::new(move |interpreter, args| interpreter.write_log(level, args)),
+            });
+        }
+
+        self.add_builtin(BuiltinFunction {
+            name: "set-log-level!".to_string(),
+            impure: true,
+            params: vec!["level".to_string()],
+            doc: "Drops log lines below a level (debug, info, warn, or error) and returns null.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'set-log-level!' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::String(name) => match LogLevel::from_name(name) {
+                        Some(level) => {
+                            interpreter.log_level.set(level);
+                            Ok(Value::Null)
+                        }
+                        None => Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'set-log-level!' expected debug, info, warn, or error, found '{}'",
+                                name
+                            ),
+                            None,
+                        )),
+                    },
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'set-log-level!' expected string, found {:?}", other),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "for-each!".to_string(),
+            impure: true,
+            params: vec!["fn".to_string(), "list".to_string()],
+            doc: "Calls an impure function for every list element and returns null.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'for-each!' expects 2 arguments (fn, list)".to_string(),
+                        None,
+                    ));
+                }
+                let func = args[0].clone();
+                let list = match &args[1] {
+                    Value::List(items) => items.clone(),
+                    Value::Lazy(sequence) => interpreter.force_sequence(sequence)?,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'for-each!' expected list as second argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                // Verify the function is impure
+                let is_impure = match func.impure() {
+                    Some(impure) => impure,
+                    None => {
+                        let other = &func;
+                        return Err(LangError::Runtime(
+                            format!(
+                            "Builtin 'for-each!' requires function as first argument, found {:?}",
+                            other
+                        ),
+                            None,
+                        ));
+                    }
+                };
+                if !is_impure {
+                    return Err(LangError::Runtime(
+                        "Builtin 'for-each!' requires impure function (marked with '!')"
+                            .to_string(),
+                        None,
+                    ));
+                }
+                // Iterate through list and call function for each element
+                for item in list {
+                    let _ = interpreter.call_callable(func.clone(), vec![item], Purity::Impure)?;
+                }
+                Ok(Value::Null)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "map!".to_string(),
+            impure: true,
+            params: vec!["fn".to_string(), "list".to_string()],
+            doc: "Like map, but the function may be impure; calls it in list order.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| interpreter.map_list("map!", args, Purity::Impure)),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "filter!".to_string(),
+            impure: true,
+            params: vec!["predicate".to_string(), "list".to_string()],
+            doc: "Like filter, but the predicate may be impure; calls it in list order.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                interpreter.filter_list("filter!", args, Purity::Impure)
+            }),
+        });
+    }
+
+    /// Runtime checks.
+    fn install_assertion_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "assert!".to_string(),
+            impure: true,
+            params: vec!["condition".to_string(), "message".to_string()],
+            doc: "Fails with a message unless the condition is true.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'assert!' expects 2 arguments (condition, message)".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::Boolean(true) => Ok(Value::Null),
+                    Value::Boolean(false) => Err(LangError::Runtime(
+                        format!(
+                            "Assertion failed: {}",
+                            interpreter.value_to_string(&args[1])?
+                        ),
+                        None,
+                    )),
+                    other => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'assert!' expected a boolean condition, found {}",
+                            pretty(other)
+                        ),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "assert-equal!".to_string(),
+            impure: true,
+            params: vec!["expected".to_string(), "actual".to_string()],
+            doc: "Fails, showing both values, unless they are equal.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'assert-equal!' expects 2 arguments (expected, actual)"
+                            .to_string(),
+                        None,
+                    ));
+                }
+                if Interpreter::values_equal(&args[0], &args[1]) {
+                    return Ok(Value::Null);
+                }
+                let indented = |value: &Value| pretty(value).replace('\n', "\n  ");
+                Err(LangError::Runtime(
+                    format!(
+                        "Assertion failed: values are not equal\nexpected:\n  {}\nactual:\n  {}{}",
+                        indented(&args[0]),
+                        indented(&args[1]),
+                        diff::section(&args[0], &args[1])
+                    ),
+                    None,
+                ))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "assert-throws!".to_string(),
+            impure: true,
+            params: vec!["fn".to_string()],
+            doc: "Calls a function with no arguments, fails unless it raises a runtime error, and returns the error message.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'assert-throws!' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match interpreter.call_callable(args[0].clone(), vec![], Purity::Impure) {
+                    Err(LangError::Runtime(message, _)) => Ok(Value::String(message)),
+                    Err(other) => Err(other),
+                    Ok(value) => Err(LangError::Runtime(
+                        format!(
+                            "Assertion failed: expected an error, but the function returned {}",
+                            pretty(&value)
+                        ),
+                        None,
+                    )),
+                }
+            }),
+        });
+    }
+
+    /// `for-all` and its generators; see [`crate::property`].
+    fn install_property_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "for-all".to_string(),
+            impure: false,
+            params: vec!["generator".to_string(), "property".to_string()],
+            doc: "Checks a property against generated values, failing with the simplest counterexample found.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'for-all' expects 2 arguments (generator, property)".to_string(),
+                        None,
+                    ));
+                }
+                let (generator, property) = (&args[0], &args[1]);
+                let seed = interpreter.seed.get().unwrap_or(0);
+                for case in 0..property::CASES {
+                    let outcome =
+                        interpreter.run_case(generator, property, Choices::random(seed, case))?;
+                    if outcome.failure.is_none() {
+                        continue;
+                    }
+                    let (smallest, shrinks) = interpreter.shrink(generator, property, outcome)?;
+                    let mut message = format!(
+                        "Assertion failed: property does not hold for\n  {}\n(case {} of {}, shrunk {} time(s))",
+                        pretty(&smallest.value).replace('\n', "\n  "),
+                        case + 1,
+                        property::CASES,
+                        shrinks
+                    );
+                    if let Some(Some(error)) = smallest.failure {
+                        message.push_str(&format!("\nthe property raised: {}", error));
+                    }
+                    return Err(LangError::Runtime(message, None));
+                }
+                Ok(Value::Null)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "gen-number".to_string(),
+            impure: false,
+            params: vec!["min".to_string(), "max".to_string()],
+            doc: "A generator of whole numbers from min to max, shrinking towards 0.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                let (min, max) = match args {
+                    [Value::Number(min), Value::Number(max)] if min <= max => (*min, *max),
+                    _ => {
+                        return Err(LangError::Runtime(
+                            "Builtin 'gen-number' expects 2 numbers (min, max) with min <= max"
+                                .to_string(),
+                            None,
+                        ))
+                    }
+                };
+                // Answers count away from the value nearest 0
+                let origin = 0.clamp(min, max);
+                let up = (i128::from(max) - i128::from(origin)) as u64;
+                let down = (i128::from(origin) - i128::from(min)) as u64;
+                Ok(generator(move |interpreter| {
+                    let negative = match (up, down) {
+                        (_, 0) => false,
+                        (0, _) => true,
+                        _ => interpreter.draw(1)? == 1,
+                    };
+                    let value = if negative {
+                        i128::from(origin) - i128::from(interpreter.draw(down)?)
+                    } else {
+                        i128::from(origin) + i128::from(interpreter.draw(up)?)
+                    };
+                    Ok(Value::Number(value as i64))
+                }))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "gen-string".to_string(),
+            impure: false,
+            params: vec!["alphabet".to_string()],
+            doc: "A generator of strings made from the alphabet's characters, shrinking towards \"\".",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                let alphabet: Vec<char> = match args {
+                    [Value::String(alphabet)] if !alphabet.is_empty() => alphabet.chars().collect(),
+                    _ => {
+                        return Err(LangError::Runtime(
+                            "Builtin 'gen-string' expects a non-empty alphabet string".to_string(),
+                            None,
+                        ))
+                    }
+                };
+                Ok(generator(move |interpreter| {
+                    let length = interpreter.draw_length()?;
+                    let last = alphabet.len() as u64 - 1;
+                    (0..length)
+                        .map(|_| Ok(alphabet[interpreter.draw(last)? as usize]))
+                        .collect::<LangResult<String>>()
+                        .map(Value::String)
+                }))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "gen-list".to_string(),
+            impure: false,
+            params: vec!["generator".to_string()],
+            doc: "A generator of lists of the given generator's values, shrinking towards [].",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'gen-list' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let element = args[0].clone();
+                Ok(generator(move |interpreter| {
+                    let length = interpreter.draw_length()?;
+                    (0..length)
+                        .map(|_| interpreter.call_callable(element.clone(), vec![], Purity::Pure))
+                        .collect::<LangResult<Vec<_>>>()
+                        .map(Value::List)
+                }))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "gen-object".to_string(),
+            impure: false,
+            params: vec!["fields".to_string()],
+            doc: "A generator of records, given a record of generators for their fields.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                let fields = match args {
+                    [Value::Object(fields)] => fields.clone(),
+                    _ => {
+                        return Err(LangError::Runtime(
+                            "Builtin 'gen-object' expects a record of generators".to_string(),
+                            None,
+                        ))
+                    }
+                };
+                Ok(generator(move |interpreter| {
+                    fields
+                        .iter()
+                        .map(|(name, field)| {
+                            let value =
+                                interpreter.call_callable(field.clone(), vec![], Purity::Pure)?;
+                            Ok((name.clone(), value))
+                        })
+                        .collect::<LangResult<BTreeMap<_, _>>>()
+                        .map(Value::Object)
+                }))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "gen-one-of".to_string(),
+            impure: false,
+            params: vec!["values".to_string()],
+            doc: "A generator that picks one of the listed values, shrinking towards the first.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                let values = match args {
+                    [Value::List(values)] if !values.is_empty() => values.clone(),
+                    _ => {
+                        return Err(LangError::Runtime(
+                            "Builtin 'gen-one-of' expects a non-empty list".to_string(),
+                            None,
+                        ))
+                    }
+                };
+                Ok(generator(move |interpreter| {
+                    let index = interpreter.draw(values.len() as u64 - 1)?;
+                    Ok(values[index as usize].clone())
+                }))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "gen-map".to_string(),
+            impure: false,
+            params: vec!["fn".to_string(), "generator".to_string()],
+            doc: "A generator that applies fn to another generator's values.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'gen-map' expects 2 arguments (fn, generator)".to_string(),
+                        None,
+                    ));
+                }
+                let (function, inner) = (args[0].clone(), args[1].clone());
+                Ok(generator(move |interpreter| {
+                    let value = interpreter.call_callable(inner.clone(), vec![], Purity::Pure)?;
+                    interpreter.call_callable(function.clone(), vec![value], Purity::Pure)
+                }))
+            }),
+        });
+    }
+
+    /// Helpers for single values of any kind.
+    fn install_value_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "identity".to_string(),
+            impure: false,
+            params: vec!["x".to_string()],
+            doc: "Returns its argument unchanged.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'identity' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                Ok(args[0].clone())
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "defined?".to_string(),
+            impure: false,
+            params: vec!["value".to_string()],
+            doc: "Returns false for null and true for any other value.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'defined?' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                Ok(Value::Boolean(!matches!(args[0], Value::Null)))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "type-of".to_string(),
+            impure: false,
+            params: vec!["value".to_string()],
+            doc: "Returns the name of a value's type, such as \"number\" or \"list\".",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'type-of' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                Ok(Value::String(args[0].type_name().to_string()))
+            }),
+        });
+
+        let type_predicates: [(&'static str, &'static str, &'static str); 5] = [
+            ("number?", "number", "Returns true for numbers."),
+            ("string?", "string", "Returns true for strings."),
+            (
+                "list?",
+                "list",
+                "Returns true for lists, but not lazy sequences.",
+            ),
+            ("object?", "object", "Returns true for records."),
+            (
+                "function?",
+                "function",
+                "Returns true for functions and builtins.",
+            ),
+        ];
+
+        for (name, type_name, doc) in type_predicates {
+            self.add_builtin(BuiltinFunction {
+                name: name.to_string(),
+                impure: false,
+                params: vec!["value".to_string()],
+                doc,
+                stability: Stability::Stable,
+                func: Rc::new(move |_, args| {
+                    if args.len() != 1 {
+                        return Err(LangError::Runtime(
+                            format!("Builtin '{}' expects exactly 1 argument", name),
+                            None,
+                        ));
+                    }
+                    Ok(Value::Boolean(args[0].type_name() == type_name))
+                }),
+            });
+        }
+
+        self.add_builtin(BuiltinFunction {
+            name: "equal?".to_string(),
+            impure: false,
+            params: vec!["a".to_string(), "b".to_string()],
+            doc: "Returns true when two values are equal, comparing lists and records deeply.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'equal?' expects 2 arguments (a, b)".to_string(),
+                        None,
+                    ));
+                }
+                Ok(Value::Boolean(Interpreter::values_equal(
+                    &args[0], &args[1],
+                )))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "diff".to_string(),
+            impure: false,
+            params: vec!["a".to_string(), "b".to_string()],
+            doc: "Lists where two values differ, by path, as records.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'diff' expects 2 arguments (a, b)".to_string(),
+                        None,
+                    ));
+                }
+                let differences = diff::diff(&args[0], &args[1])
+                    .into_iter()
+                    .map(|difference| {
+                        let mut fields =
+                            BTreeMap::from([("path".to_string(), Value::String(difference.path))]);
+                        if let Some(line) = difference.line {
+                            fields.insert("line".to_string(), Value::Number(line as i64));
+                        }
+                        let (kind, from, to) = match difference.kind {
+                            DifferenceKind::Changed { from, to } => {
+                                ("changed", Some(from), Some(to))
+                            }
+                            DifferenceKind::Added(to) => ("added", None, Some(to)),
+                            DifferenceKind::Removed(from) => ("removed", Some(from), None),
+                        };
+                        fields.insert("kind".to_string(), Value::String(kind.to_string()));
+                        fields.extend(from.map(|from| ("from".to_string(), from)));
+                        fields.extend(to.map(|to| ("to".to_string(), to)));
+                        Value::Object(fields)
+                    })
+                    .collect();
+                Ok(Value::List(differences))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "compare".to_string(),
+            impure: false,
+            params: vec!["a".to_string(), "b".to_string()],
+            doc: "Returns -1, 0, or 1 as a sorts before, with, or after b.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'compare' expects 2 arguments (a, b)".to_string(),
+                        None,
+                    ));
+                }
+                set::check(&args[0], "Compared values")
+                    .and_then(|_| set::check(&args[1], "Compared values"))
+                    .map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'compare' failed: {}", err), None)
+                    })?;
+                Ok(Value::Number(match set::order(&args[0], &args[1]) {
+                    Ordering::Less => -1,
+                    Ordering::Equal => 0,
+                    Ordering::Greater => 1,
+                }))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "platform".to_string(),
+            impure: false,
+            params: vec![],
+            doc: "Describes the operating system, OS family, and CPU architecture.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if !args.is_empty() {
+                    return Err(LangError::Runtime(
+                        "Builtin 'platform' expects no arguments".to_string(),
+                        None,
+                    ));
+                }
+                let mut info = BTreeMap::new();
+                info.insert(
+                    "os".to_string(),
+                    Value::String(std::env::consts::OS.to_string()),
+                );
+                info.insert(
+                    "family".to_string(),
+                    Value::String(std::env::consts::FAMILY.to_string()),
+                );
+                info.insert(
+                    "arch".to_string(),
+                    Value::String(std::env::consts::ARCH.to_string()),
+                );
+                Ok(Value::Object(info))
+            }),
+        });
+    }
+
+    /// Arithmetic and boolean helpers.
+    fn install_math_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "increment".to_string(),
+            impure: false,
+            params: vec!["number".to_string()],
+            doc: "Adds one to a number.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'increment' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::Number(n) => Ok(Value::Number(n + 1)),
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'increment' expected a number, found {:?}", other),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "decrement".to_string(),
+            impure: false,
+            params: vec!["number".to_string()],
+            doc: "Subtracts one from a number.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'decrement' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::Number(n) => Ok(Value::Number(n - 1)),
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'decrement' expected a number, found {:?}", other),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "add".to_string(),
+            impure: false,
+            params: vec!["a".to_string(), "b".to_string()],
+            doc: "Adds two numbers.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'add' expects exactly 2 arguments".to_string(),
+                        None,
+                    ));
+                }
+                let (lhs, rhs) = match (&args[0], &args[1]) {
+                    (Value::Number(a), Value::Number(b)) => (*a, *b),
+                    (a, b) => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'add' requires numeric operands, found {:?} and {:?}",
+                                a, b
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                Ok(Value::Number(lhs + rhs))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "subtract".to_string(),
+            impure: false,
+            params: vec!["a".to_string(), "b".to_string()],
+            doc: "Subtracts the second number from the first.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'subtract' expects exactly 2 arguments".to_string(),
+                        None,
+                    ));
+                }
+                let (lhs, rhs) = match (&args[0], &args[1]) {
+                    (Value::Number(a), Value::Number(b)) => (*a, *b),
+                    (a, b) => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'subtract' requires numeric operands, found {:?} and {:?}",
+                                a, b
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                Ok(Value::Number(lhs - rhs))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "multiply".to_string(),
+            impure: false,
+            params: vec!["a".to_string(), "b".to_string()],
+            doc: "Multiplies two numbers.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'multiply' expects exactly 2 arguments".to_string(),
+                        None,
+                    ));
+                }
+                let (lhs, rhs) = match (&args[0], &args[1]) {
+                    (Value::Number(a), Value::Number(b)) => (*a, *b),
+                    (a, b) => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'multiply' requires numeric operands, found {:?} and {:?}",
+                                a, b
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                Ok(Value::Number(lhs * rhs))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "divide".to_string(),
+            impure: false,
+            params: vec!["a".to_string(), "b".to_string()],
+            doc: "Divides the first number by the second. Errors on division by zero.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'divide' expects exactly 2 arguments".to_string(),
+                        None,
+                    ));
+                }
+                let (lhs, rhs) = match (&args[0], &args[1]) {
+                    (Value::Number(a), Value::Number(b)) => (*a, *b),
+                    (a, b) => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'divide' requires numeric operands, found {:?} and {:?}",
+                                a, b
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                if rhs == 0 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'divide' received division by zero".to_string(),
+                        None,
+                    ));
+                }
+                Ok(Value::Number(lhs / rhs))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "and?".to_string(),
+            impure: false,
+            params: vec!["a".to_string(), "b".to_string()],
+            doc: "Returns true when both booleans are true.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'and?' expects exactly 2 arguments".to_string(),
+                        None,
+                    ));
+                }
+                let (lhs, rhs) = match (&args[0], &args[1]) {
+                    (Value::Boolean(a), Value::Boolean(b)) => (*a, *b),
+                    (a, b) => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'and?' requires boolean operands, found {:?} and {:?}",
+                                a, b
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                Ok(Value::Boolean(lhs && rhs))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "or?".to_string(),
+            impure: false,
+            params: vec!["a".to_string(), "b".to_string()],
+            doc: "Returns true when at least one boolean is true.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'or?' expects exactly 2 arguments".to_string(),
+                        None,
+                    ));
+                }
+                let (lhs, rhs) = match (&args[0], &args[1]) {
+                    (Value::Boolean(a), Value::Boolean(b)) => (*a, *b),
+                    (a, b) => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'or?' requires boolean operands, found {:?} and {:?}",
+                                a, b
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                Ok(Value::Boolean(lhs || rhs))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "not?".to_string(),
+            impure: false,
+            params: vec!["value".to_string()],
+            doc: "Returns the opposite of a boolean.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'not?' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::Boolean(b) => Ok(Value::Boolean(!b)),
+                    other => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'not?' requires a boolean operand, found {:?}",
+                            other
+                        ),
+                        None,
+                    )),
+                }
+            }),
+        });
+    }
+
+    /// List helpers.
+    fn install_list_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "map".to_string(),
+            impure: false,
+            params: vec!["fn".to_string(), "list".to_string()],
+            doc: "Applies a function to every element of a list and returns the results.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                require_pure_callback("map", &args[0], Some("map!"))?;
+                interpreter.map_list("map", args, Purity::Pure)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "reduce".to_string(),
+            impure: false,
+            params: vec!["fn".to_string(), "init".to_string(), "list".to_string()],
+            doc: "Folds a list into one value by calling fn(accumulator, element) from left to right.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 3 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'reduce' expects 3 arguments (fn, init, list)".to_string(),
+                        None,
+                    ));
+                }
+                require_pure_callback("reduce", &args[0], None)?;
+                let func = args[0].clone();
+                let mut acc = args[1].clone();
+                let list = match &args[2] {
+                    Value::List(items) => items.clone(),
+                    Value::Lazy(sequence) => interpreter.force_sequence(sequence)?,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'reduce' expected list as third argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                for item in list {
+                    acc = interpreter.call_callable(func.clone(), vec![acc, item], Purity::Pure)?;
+                }
+                Ok(acc)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "filter".to_string(),
+            impure: false,
+            params: vec!["predicate".to_string(), "list".to_string()],
+            doc: "Keeps the list elements for which the predicate returns true.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                require_pure_callback("filter", &args[0], Some("filter!"))?;
+                interpreter.filter_list("filter", args, Purity::Pure)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "partition".to_string(),
+            impure: false,
+            params: vec!["predicate".to_string(), "list".to_string()],
+            doc: "Splits a list into [matching, rest] by a predicate.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'partition' expects 2 arguments (predicate, list)".to_string(),
+                        None,
+                    ));
+                }
+                let predicate = args[0].clone();
+                let list = interpreter.list_argument("partition", "second", &args[1])?;
+                let (mut matching, mut rest) = (Vec::new(), Vec::new());
+                for item in list {
+                    match interpreter.call_callable(
+                        predicate.clone(),
+                        vec![item.clone()],
+                        Purity::Pure,
+                    )? {
+                        Value::Boolean(true) => matching.push(item),
+                        Value::Boolean(false) => rest.push(item),
+                        other => {
+                            return Err(LangError::Runtime(
+                                format!(
+                                    "Partition predicate must return boolean, found {:?}",
+                                    other
+                                ),
+                                None,
+                            ))
+                        }
+                    }
+                }
+                Ok(Value::List(vec![Value::List(matching), Value::List(rest)]))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "find".to_string(),
+            impure: false,
+            params: vec!["predicate".to_string(), "list".to_string()],
+            doc: "Returns the first element for which the predicate returns true, or null.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'find' expects 2 arguments (predicate, list)".to_string(),
+                        None,
+                    ));
+                }
+                let sequence = Sequence::from_value("find", &args[1])?;
+                let mut cursor = sequence.cursor();
+                while let Some(item) = cursor.next(interpreter)? {
+                    match interpreter.call_callable(
+                        args[0].clone(),
+                        vec![item.clone()],
+                        Purity::Pure,
+                    )? {
+                        Value::Boolean(true) => return Ok(item),
+                        Value::Boolean(false) => {}
+                        other => {
+                            return Err(LangError::Runtime(
+                                format!("Find predicate must return boolean, found {:?}", other),
+                                None,
+                            ))
+                        }
+                    }
+                }
+                Ok(Value::Null)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "index-of".to_string(),
+            impure: false,
+            params: vec!["value".to_string(), "list".to_string()],
+            doc: "Returns the index of the first element equal to a value, or null.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'index-of' expects 2 arguments (value, list)".to_string(),
+                        None,
+                    ));
+                }
+                let sequence = Sequence::from_value("index-of", &args[1])?;
+                let mut cursor = sequence.cursor();
+                let mut index = 0;
+                while let Some(item) = cursor.next(interpreter)? {
+                    if Interpreter::values_equal(&item, &args[0]) {
+                        return Ok(Value::Number(index));
+                    }
+                    index += 1;
+                }
+                Ok(Value::Null)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "group-by".to_string(),
+            impure: false,
+            params: vec!["fn".to_string(), "list".to_string()],
+            doc: "Groups list elements into a record of lists keyed by fn(element).",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'group-by' expects 2 arguments (fn, list)".to_string(),
+                        None,
+                    ));
+                }
+                let func = args[0].clone();
+                let list = interpreter.list_argument("group-by", "second", &args[1])?;
+                let mut groups: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+                for item in list {
+                    let key = match interpreter.call_callable(
+                        func.clone(),
+                        vec![item.clone()],
+                        Purity::Pure,
+                    )? {
+                        Value::String(key) => key,
+                        key @ (Value::Number(_) | Value::Boolean(_)) => {
+                            interpreter.value_to_string(&key)?
+                        }
+                        other => {
+                            return Err(LangError::Runtime(
+                                format!(
+                                    "Builtin 'group-by' keys must be strings, numbers, or booleans, found {:?}",
+                                    other
+                                ),
+                                None,
+                            ))
+                        }
+                    };
+                    groups.entry(key).or_default().push(item);
+                }
+                Ok(Value::Object(
+                    groups
+                        .into_iter()
+                        .map(|(key, group)| (key, Value::List(group)))
+                        .collect(),
+                ))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "unique".to_string(),
+            impure: false,
+            params: vec!["list".to_string()],
+            doc: "Removes repeated elements from a list, keeping the first of each.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'unique' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let list = interpreter.list_argument("unique", "first", &args[0])?;
+                let mut result: Vec<Value> = Vec::with_capacity(list.len());
+                for item in list {
+                    if !result
+                        .iter()
+                        .any(|seen| Interpreter::values_equal(seen, &item))
+                    {
+                        result.push(item);
+                    }
+                }
+                Ok(Value::List(result))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "flatten".to_string(),
+            impure: false,
+            params: vec!["list".to_string()],
+            doc: "Splices the elements of nested lists into the outer list, one level deep.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'flatten' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let list = interpreter.list_argument("flatten", "first", &args[0])?;
+                let mut result = Vec::with_capacity(list.len());
+                for item in list {
+                    match item {
+                        Value::List(items) => result.extend(items),
+                        Value::Lazy(sequence) => {
+                            result.extend(interpreter.force_sequence(&sequence)?)
+                        }
+                        other => result.push(other),
+                    }
+                }
+                Ok(Value::List(result))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "every?".to_string(),
+            impure: false,
+            params: vec!["predicate".to_string(), "list".to_string()],
+            doc: "Returns true when the predicate holds for every list element.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'every?' expects 2 arguments (predicate, list)".to_string(),
+                        None,
+                    ));
+                }
+                let predicate = args[0].clone();
+                let list = match &args[1] {
+                    Value::List(items) => items.clone(),
+                    Value::Lazy(sequence) => interpreter.force_sequence(sequence)?,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'every?' expected list as second argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                // Returns true for empty list
+                for item in list {
+                    let result =
+                        interpreter.call_callable(predicate.clone(), vec![item], Purity::Pure)?;
+                    match result {
+                        Value::Boolean(true) => continue,
+                        Value::Boolean(false) => return Ok(Value::Boolean(false)),
+                        other => {
+                            return Err(LangError::Runtime(
+                                format!(
+                                    "Predicate passed to 'every?' must return boolean, found {:?}",
+                                    other
+                                ),
+                                None,
+                            ))
+                        }
+                    }
+                }
+                Ok(Value::Boolean(true))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "some?".to_string(),
+            impure: false,
+            params: vec!["predicate".to_string(), "list".to_string()],
+            doc: "Returns true when the predicate holds for at least one list element.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'some?' expects 2 arguments (predicate, list)".to_string(),
+                        None,
+                    ));
+                }
+                let predicate = args[0].clone();
+                let list = match &args[1] {
+                    Value::List(items) => items.clone(),
+                    Value::Lazy(sequence) => interpreter.force_sequence(sequence)?,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'some?' expected list as second argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                // Returns false for empty list
+                for item in list {
+                    let result =
+                        interpreter.call_callable(predicate.clone(), vec![item], Purity::Pure)?;
+                    match result {
+                        Value::Boolean(true) => return Ok(Value::Boolean(true)),
+                        Value::Boolean(false) => continue,
+                        other => {
+                            return Err(LangError::Runtime(
+                                format!(
+                                    "Predicate passed to 'some?' must return boolean, found {:?}",
+                                    other
+                                ),
+                                None,
+                            ))
+                        }
+                    }
+                }
+                Ok(Value::Boolean(false))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "none?".to_string(),
+            impure: false,
+            params: vec!["predicate".to_string(), "list".to_string()],
+            doc: "Returns true when the predicate holds for no list element.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'none?' expects 2 arguments (predicate, list)".to_string(),
+                        None,
+                    ));
+                }
+                let predicate = args[0].clone();
+                let list = match &args[1] {
+                    Value::List(items) => items.clone(),
+                    Value::Lazy(sequence) => interpreter.force_sequence(sequence)?,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'none?' expected list as second argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                // Returns true for empty list
+                for item in list {
+                    let result =
+                        interpreter.call_callable(predicate.clone(), vec![item], Purity::Pure)?;
+                    match result {
+                        Value::Boolean(false) => continue,
+                        Value::Boolean(true) => return Ok(Value::Boolean(false)),
+                        other => {
+                            return Err(LangError::Runtime(
+                                format!(
+                                    "Predicate passed to 'none?' must return boolean, found {:?}",
+                                    other
+                                ),
+                                None,
+                            ))
+                        }
+                    }
+                }
+                Ok(Value::Boolean(true))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "sort".to_string(),
+            impure: false,
+            params: vec!["list".to_string()],
+            doc: "Sorts numbers, strings, or lists in ascending order, as `<` orders them.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'sort' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let mut items = match &args[0] {
+                    Value::List(items) => items.clone(),
+                    Value::Lazy(sequence) => interpreter.force_sequence(sequence)?,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!("Builtin 'sort' expected list, found {:?}", other),
+                            None,
+                        ))
+                    }
+                };
+                let mut error = None;
+                items.sort_by(|a, b| {
+                    Interpreter::compare_values(a, b).unwrap_or_else(|err| {
+                        error.get_or_insert(err);
+                        Ordering::Equal
+                    })
+                });
+                match error {
+                    Some(err) => Err(err),
+                    None => Ok(Value::List(items)),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "sort-with".to_string(),
+            impure: false,
+            params: vec!["fn".to_string(), "list".to_string()],
+            doc: "Sorts a list with a comparator that returns a negative number, zero, or a positive number.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'sort-with' expects 2 arguments (fn, list)".to_string(),
+                        None,
+                    ));
+                }
+                let comparator = args[0].clone();
+                let mut items = interpreter.list_argument("sort-with", "second", &args[1])?;
+                let mut error = None;
+                items.sort_by(|a, b| {
+                    if error.is_some() {
+                        return Ordering::Equal;
+                    }
+                    let result = interpreter.call_callable(
+                        comparator.clone(),
+                        vec![a.clone(), b.clone()],
+                        Purity::Pure,
+                    );
+                    match result {
+                        Ok(Value::Number(n)) => n.cmp(&0),
+                        Ok(other) => {
+                            error = Some(LangError::Runtime(
+                                format!(
+                                    "Builtin 'sort-with' comparator must return a number, found {:?}",
+                                    other
+                                ),
+                                None,
+                            ));
+                            Ordering::Equal
+                        }
+                        Err(err) => {
+                            error = Some(err);
+                            Ordering::Equal
+                        }
+                    }
+                });
+                match error {
+                    Some(err) => Err(err),
+                    None => Ok(Value::List(items)),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "nth".to_string(),
+            impure: false,
+            params: vec!["list".to_string(), "index".to_string()],
+            doc: "Returns the list element at a zero-based index, or null.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'nth' expects 2 arguments (list, index)".to_string(),
+                        None,
+                    ));
+                }
+                match (&args[0], &args[1]) {
+                    (Value::List(items), Value::Number(index)) => Ok(usize::try_from(*index)
+                        .ok()
+                        .and_then(|index| items.get(index).cloned())
+                        .unwrap_or(Value::Null)),
+                    (Value::Null, _) => Ok(Value::Null),
+                    (Value::List(_), other) => Err(LangError::Runtime(
+                        format!("Builtin 'nth' expected number index, found {:?}", other),
+                        None,
+                    )),
+                    (other, _) => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'nth' expected list as first argument, found {:?}",
+                            other
+                        ),
+                        None,
+                    )),
+                }
+            }),
+        });
+    }
+
+    /// Sets; see [`crate::set`].
+    fn install_set_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "to-set".to_string(),
+            impure: false,
+            params: vec!["list".to_string()],
+            doc: "Builds a set from the elements of a list, dropping repeats.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'to-set' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let items = interpreter.set_argument("to-set", "first", &args[0])?;
+                Ok(Value::Set(items.into_owned()))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "set-to-list".to_string(),
+            impure: false,
+            params: vec!["set".to_string()],
+            doc: "Returns the elements of a set as a sorted list.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'set-to-list' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let items = interpreter.set_argument("set-to-list", "first", &args[0])?;
+                Ok(Value::List(items.into_owned()))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "set-contains?".to_string(),
+            impure: false,
+            params: vec!["set".to_string(), "value".to_string()],
+            doc: "Returns true when the set holds a value equal to the given one.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'set-contains?' expects 2 arguments (set, value)".to_string(),
+                        None,
+                    ));
+                }
+                let items = interpreter.set_argument("set-contains?", "first", &args[0])?;
+                set::contains(&items, &args[1])
+                    .map(Value::Boolean)
+                    .map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'set-contains?' failed: {}", err), None)
+                    })
+            }),
+        });
+
+        type SetOperation = fn(&[Value], &[Value]) -> Vec<Value>;
+
+        let set_operations: [(&'static str, &'static str, SetOperation); 3] = [
+            (
+                "set-union",
+                "Returns the elements found in either set.",
+                set::union,
+            ),
+            (
+                "set-intersection",
+                "Returns the elements found in both sets.",
+                set::intersection,
+            ),
+            (
+                "set-difference",
+                "Returns the elements of the first set that are not in the second.",
+                set::difference,
+            ),
+        ];
+
+        for (name, doc, operation) in set_operations {
+            self.add_builtin(BuiltinFunction {
+                name: name.to_string(),
+                impure: false,
+                params: vec!["left".to_string(), "right".to_string()],
+                doc,
+                stability: Stability::Stable,
+                func: Rc::new(move |interpreter, args| {
+                    if args.len() != 2 {
+                        return Err(LangError::Runtime(
+                            format!("Builtin '{}' expects 2 arguments (left, right)", name),
+                            None,
+                        ));
+                    }
+                    let left = interpreter.set_argument(name, "first", &args[0])?;
+                    let right = interpreter.set_argument(name, "second", &args[1])?;
+                    Ok(Value::Set(operation(&left, &right)))
+                }),
+            });
+        }
+    }
+
+    /// Maps; see [`crate::map`].
+    fn install_map_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "to-map".to_string(),
+            impure: false,
+            params: vec!["entries".to_string()],
+            doc: "Builds a map from a list of { key, value } records; later keys win.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'to-map' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let mut entries = map::Entries::new();
+                for entry in interpreter.list_argument("to-map", "first", &args[0])? {
+                    let (key, value) = match &entry {
+                        Value::Object(fields) => (fields.get("key"), fields.get("value")),
+                        _ => (None, None),
+                    };
+                    let (Some(key), Some(value)) = (key, value) else {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'to-map' expected records with key and value fields, found {:?}",
+                                entry
+                            ),
+                            None,
+                        ));
+                    };
+                    map::insert(&mut entries, key.clone(), value.clone()).map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'to-map' failed: {}", err), None)
+                    })?;
+                }
+                Ok(Value::Map(entries))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "map-entries".to_string(),
+            impure: false,
+            params: vec!["map".to_string()],
+            doc: "Returns the entries of a map as { key, value } records, sorted by key.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'map-entries' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                let entries = Self::map_argument("map-entries", &args[0])?;
+                Ok(Value::List(
+                    entries
+                        .iter()
+                        .map(|(key, value)| {
+                            Value::Object(BTreeMap::from([
+                                ("key".to_string(), key.clone()),
+                                ("value".to_string(), value.clone()),
+                            ]))
+                        })
+                        .collect(),
+                ))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "map-get".to_string(),
+            impure: false,
+            params: vec!["map".to_string(), "key".to_string()],
+            doc: "Returns the value stored under a key, or null.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'map-get' expects 2 arguments (map, key)".to_string(),
+                        None,
+                    ));
+                }
+                let entries = Self::map_argument("map-get", &args[0])?;
+                map::get(entries, &args[1])
+                    .map(|value| value.cloned().unwrap_or(Value::Null))
+                    .map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'map-get' failed: {}", err), None)
+                    })
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "map-has?".to_string(),
+            impure: false,
+            params: vec!["map".to_string(), "key".to_string()],
+            doc: "Returns true when the map has an entry for a key.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'map-has?' expects 2 arguments (map, key)".to_string(),
+                        None,
+                    ));
+                }
+                let entries = Self::map_argument("map-has?", &args[0])?;
+                map::get(entries, &args[1])
+                    .map(|value| Value::Boolean(value.is_some()))
+                    .map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'map-has?' failed: {}", err), None)
+                    })
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "map-set".to_string(),
+            impure: false,
+            params: vec!["map".to_string(), "key".to_string(), "value".to_string()],
+            doc: "Returns a copy of the map with a key set to a value.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 3 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'map-set' expects 3 arguments (map, key, value)".to_string(),
+                        None,
+                    ));
+                }
+                let mut entries = Self::map_argument("map-set", &args[0])?.clone();
+                map::insert(&mut entries, args[1].clone(), args[2].clone()).map_err(|err| {
+                    LangError::Runtime(format!("Builtin 'map-set' failed: {}", err), None)
+                })?;
+                Ok(Value::Map(entries))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "map-remove".to_string(),
+            impure: false,
+            params: vec!["map".to_string(), "key".to_string()],
+            doc: "Returns a copy of the map without the entry for a key.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'map-remove' expects 2 arguments (map, key)".to_string(),
+                        None,
+                    ));
+                }
+                let mut entries = Self::map_argument("map-remove", &args[0])?.clone();
+                map::remove(&mut entries, &args[1]).map_err(|err| {
+                    LangError::Runtime(format!("Builtin 'map-remove' failed: {}", err), None)
+                })?;
+                Ok(Value::Map(entries))
+            }),
+        });
+    }
+
+    /// Tagged values and the result helpers built on them.
+    fn install_tag_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "tag".to_string(),
+            impure: false,
+            params: vec!["name".to_string(), "value".to_string()],
+            doc: "Wraps a value in a tagged record such as { tag: \"ok\", value: 1 }.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'tag' expects 2 arguments (name, value)".to_string(),
+                        None,
+                    ));
+                }
+                let name = match &args[0] {
+                    Value::String(name) if !name.is_empty() => name.clone(),
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'tag' requires a non-empty string name, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                Ok(Interpreter::make_tagged(name, args[1].clone()))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "tag?".to_string(),
+            impure: false,
+            params: vec!["value".to_string()],
+            doc: "Returns true for records made by tag.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'tag?' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                Ok(Value::Boolean(Interpreter::tagged(&args[0]).is_some()))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "tag-name".to_string(),
+            impure: false,
+            params: vec!["tagged".to_string()],
+            doc: "Returns the tag of a tagged record.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'tag-name' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match Interpreter::tagged(&args[0]) {
+                    Some((name, _)) => Ok(Value::String(name.to_string())),
+                    None => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'tag-name' requires a tagged value, found {:?}",
+                            args[0]
+                        ),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "match-tag".to_string(),
+            impure: false,
+            params: vec!["handlers".to_string(), "tagged".to_string()],
+            doc: "Calls the handler named by a tagged value's tag with its value.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'match-tag' expects 2 arguments (handlers, tagged)".to_string(),
+                        None,
+                    ));
+                }
+                let Value::Object(handlers) = &args[0] else {
+                    return Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'match-tag' requires a record of handlers, found {:?}",
+                            args[0]
+                        ),
+                        None,
+                    ));
+                };
+                let Some((name, value)) = Interpreter::tagged(&args[1]) else {
+                    return Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'match-tag' requires a tagged value, found {:?}",
+                            args[1]
+                        ),
+                        None,
+                    ));
+                };
+                // A `_` handler catches every other tag and receives the
+                // whole tagged value, so it can still tell them apart.
+                let (handler, argument) = match (handlers.get(name), handlers.get("_")) {
+                    (Some(handler), _) => (handler, value.clone()),
+                    (None, Some(fallback)) => (fallback, args[1].clone()),
+                    (None, None) => {
+                        return Err(LangError::Runtime(
+                            format!("Builtin 'match-tag' has no handler for tag '{}'", name),
+                            None,
+                        ))
+                    }
+                };
+                interpreter.call_callable(handler.clone(), vec![argument], Purity::Pure)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "unwrap-or".to_string(),
+            impure: false,
+            params: vec!["default".to_string(), "value".to_string()],
+            doc: "Returns the value inside a success, or the default for null, err and none.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'unwrap-or' expects 2 arguments (default, value)".to_string(),
+                        None,
+                    ));
+                }
+                Ok(Interpreter::success(&args[1]).unwrap_or(&args[0]).clone())
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "map-ok".to_string(),
+            impure: false,
+            params: vec!["fn".to_string(), "result".to_string()],
+            doc: "Applies fn to the value inside a success and passes failures through.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'map-ok' expects 2 arguments (fn, result)".to_string(),
+                        None,
+                    ));
+                }
+                let Some(inner) = Interpreter::success(&args[1]) else {
+                    return Ok(args[1].clone());
+                };
+                let mapped = interpreter.call_callable(
+                    args[0].clone(),
+                    vec![inner.clone()],
+                    Purity::Pure,
+                )?;
+                // Keep the success tag, so `ok` stays `ok` and `some` stays
+                // `some`; a plain value stays plain.
+                Ok(match Interpreter::tagged(&args[1]) {
+                    Some((name, _)) => Interpreter::make_tagged(name.to_string(), mapped),
+                    None => mapped,
+                })
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "and-then".to_string(),
+            impure: false,
+            params: vec!["fn".to_string(), "result".to_string()],
+            doc: "Calls fn, which returns a new result, with the value inside a success and passes failures through.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'and-then' expects 2 arguments (fn, result)".to_string(),
+                        None,
+                    ));
+                }
+                match Interpreter::success(&args[1]) {
+                    Some(inner) => interpreter.call_callable(
+                        args[0].clone(),
+                        vec![inner.clone()],
+                        Purity::Pure,
+                    ),
+                    None => Ok(args[1].clone()),
+                }
+            }),
+        });
+    }
+
+    /// Branching and loops.
+    fn install_control_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "if".to_string(),
+            impure: false,
+            params: vec!["condition".to_string(), "then-fn".to_string(), "else-fn".to_string()],
+            doc: "Calls the then function when the condition is true, otherwise the else function.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 3 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'if' expects 3 arguments (condition, then-fn, else-fn)".to_string(),
+                        None,
+                    ));
+                }
+                let condition = match &args[0] {
+                    Value::Boolean(b) => *b,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'if' requires boolean condition, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                let then_fn = match &args[1] {
+                    Value::Function(f) => f.clone(),
+                    Value::Builtin(_) => {
+                        return Err(LangError::Runtime(
+                            "Builtin 'if' requires function as second argument (then-fn)".to_string(),
+                            None,
+                        ))
+                    }
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'if' requires function as second argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                let else_fn = match &args[2] {
+                    Value::Function(f) => f.clone(),
+                    Value::Builtin(_) => {
+                        return Err(LangError::Runtime(
+                            "Builtin 'if' requires function as third argument (else-fn)".to_string(),
+                            None,
+                        ))
+                    }
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'if' requires function as third argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                // Check that functions take zero arguments (thunks)
+                if !then_fn.params.is_empty() {
+                    return Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'if' requires zero-argument function as then-fn, found function with {} parameters",
+                            then_fn.params.len()
+                        ),
+                        None,
+                    ));
+                }
+                if !else_fn.params.is_empty() {
+                    return Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'if' requires zero-argument function as else-fn, found function with {} parameters",
+                            else_fn.params.len()
+                        ),
+                        None,
+                    ));
+                }
+                // Evaluate only the branch that matches the condition
+                if condition {
+                    interpreter.call_callable(Value::Function(then_fn), vec![], Purity::Pure)
+                } else {
+                    interpreter.call_callable(Value::Function(else_fn), vec![], Purity::Pure)
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "iterate".to_string(),
+            impure: false,
+            params: vec!["fn".to_string(), "initial".to_string(), "count".to_string()],
+            doc: "Applies fn to a value count times in a loop and returns the result.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 3 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'iterate' expects 3 arguments (fn, initial, count)".to_string(),
+                        None,
+                    ));
+                }
+                let count = match &args[2] {
+                    Value::Number(count) if *count >= 0 => *count,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'iterate' expected a non-negative count, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                let mut value = args[1].clone();
+                for _ in 0..count {
+                    value =
+                        interpreter.call_callable(args[0].clone(), vec![value], Purity::Pure)?;
+                }
+                Ok(value)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "until".to_string(),
+            impure: false,
+            params: vec!["predicate".to_string(), "fn".to_string(), "initial".to_string()],
+            doc: "Applies fn to a value in a loop until predicate holds for it, and returns that value.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 3 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'until' expects 3 arguments (predicate, fn, initial)".to_string(),
+                        None,
+                    ));
+                }
+                let mut value = args[2].clone();
+                loop {
+                    match interpreter.call_callable(
+                        args[0].clone(),
+                        vec![value.clone()],
+                        Purity::Pure,
+                    )? {
+                        Value::Boolean(true) => return Ok(value),
+                        Value::Boolean(false) => {}
+                        other => {
+                            return Err(LangError::Runtime(
+                                format!(
+                                    "Builtin 'until' predicate must return a boolean, found {:?}",
+                                    other
+                                ),
+                                None,
+                            ))
+                        }
+                    }
+                    value = interpreter.call_callable(args[1].clone(), vec![value], Purity::Pure)?;
+                }
+            }),
+        });
+    }
+
+    /// String helpers.
+    fn install_string_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "chars".to_string(),
+            impure: false,
+            params: vec!["string".to_string()],
+            doc: "Splits a string into a list of single-character strings.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'chars' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::String(text) => Ok(Value::List(
+                        text.chars()
+                            .map(|ch| Value::String(ch.to_string()))
+                            .collect(),
+                    )),
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'chars' expected a string, found {:?}", other),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "char-code".to_string(),
+            impure: false,
+            params: vec!["char".to_string()],
+            doc: "Returns the Unicode code point of a one-character string, or null.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'char-code' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::String(text) => {
+                        let mut chars = text.chars();
+                        Ok(match (chars.next(), chars.next()) {
+                            (Some(ch), None) => Value::Number(ch as i64),
+                            _ => Value::Null,
+                        })
+                    }
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'char-code' expected a string, found {:?}", other),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "from-char-code".to_string(),
+            impure: false,
+            params: vec!["code".to_string()],
+            doc: "Returns the one-character string for a Unicode code point, or null.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'from-char-code' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::Number(code) => Ok(u32::try_from(*code)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .map(|ch| Value::String(ch.to_string()))
+                        .unwrap_or(Value::Null)),
+                    other => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'from-char-code' expected a number, found {:?}",
+                            other
+                        ),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "string-to-number".to_string(),
+            impure: false,
+            params: vec!["string".to_string()],
+            doc: "Parses a whole number from a string, or returns null.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'string-to-number' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::String(text) => Ok(text
+                        .parse::<i64>()
+                        .map(Value::Number)
+                        .unwrap_or(Value::Null)),
+                    other => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'string-to-number' expected a string, found {:?}",
+                            other
+                        ),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "number-to-string".to_string(),
+            impure: false,
+            params: vec!["number".to_string()],
+            doc: "Formats a number as a string.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'number-to-string' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::Number(n) => Ok(Value::String(n.to_string())),
+                    other => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'number-to-string' expected a number, found {:?}",
+                            other
+                        ),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "format-number".to_string(),
+            impure: false,
+            params: vec!["number".to_string(), "options".to_string()],
+            doc: "Formats a number with separators, implied decimals, and padding.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'format-number' expects 2 arguments (number, options)".to_string(),
+                        None,
+                    ));
+                }
+                let Value::Number(number) = &args[0] else {
+                    return Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'format-number' expected a number, found {:?}",
+                            args[0]
+                        ),
+                        None,
+                    ));
+                };
+                let format = Self::number_format(&args[1]).map_err(|err| {
+                    LangError::Runtime(format!("Builtin 'format-number' {}", err), None)
+                })?;
+                Ok(Value::String(text_format::format_number(*number, &format)))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "format".to_string(),
+            impure: false,
+            params: vec!["template".to_string(), "values".to_string()],
+            doc: "Fills the {} placeholders of a template from a list or object.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'format' expects 2 arguments (template, values)".to_string(),
+                        None,
+                    ));
+                }
+                let Value::String(template) = &args[0] else {
+                    return Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'format' expected a template string, found {:?}",
+                            args[0]
+                        ),
+                        None,
+                    ));
+                };
+                let display = |value: &Value| {
+                    interpreter
+                        .value_to_string(value)
+                        .map_err(|err| err.to_string())
+                };
+                text_format::format_template(template, &args[1], &display)
+                    .map(Value::String)
+                    .map_err(|err| LangError::Runtime(format!("Builtin 'format': {}", err), None))
+            }),
+        });
+
+        for (name, style, doc) in [
+            (
+                "format-table",
+                TableStyle::Text,
+                "Lays out a list of objects as a text table with aligned columns.",
+            ),
+            (
+                "format-markdown-table",
+                TableStyle::Markdown,
+                "Lays out a list of objects as a markdown table.",
+            ),
+        ] {
+            self.add_builtin(BuiltinFunction {
+                name: name.to_string(),
+                impure: false,
+                params: vec!["rows".to_string()],
+                doc,
+                stability: Stability::Stable,
+                func: Rc::new(move |interpreter, args| {
+                    if args.len() != 1 {
+                        return Err(LangError::Runtime(
+                            format!("Builtin '{}' expects exactly 1 argument", name),
+                            None,
+                        ));
+                    }
+                    let display = |value: &Value| {
+                        interpreter
+                            .value_to_string(value)
+                            .map_err(|err| err.to_string())
+                    };
+                    text_format::format_table(&args[0], style, &display)
+                        .map(Value::String)
+                        .map_err(|err| {
+                            LangError::Runtime(format!("Builtin '{}' {}", name, err), None)
+                        })
+                }),
+            });
+        }
+
+        self.add_builtin(BuiltinFunction {
+            name: "casefold".to_string(),
+            impure: false,
+            params: vec!["string".to_string()],
+            doc: "Folds a string for caseless comparison.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'casefold' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::String(text) => Ok(Value::String(collation::casefold(text))),
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'casefold' expected a string, found {:?}", other),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "locale-compare".to_string(),
+            impure: false,
+            params: vec!["locale".to_string(), "a".to_string(), "b".to_string()],
+            doc: "Compares two strings using a locale's collation; returns -1, 0, or 1.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 3 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'locale-compare' expects 3 arguments (locale, a, b)".to_string(),
+                        None,
+                    ));
+                }
+                match (&args[0], &args[1], &args[2]) {
+                    (Value::String(locale), Value::String(a), Value::String(b)) => {
+                        let ordering = collation::compare(locale, a, b);
+                        Ok(Value::Number(ordering as i64))
+                    }
+                    (locale, a, b) => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'locale-compare' requires string arguments, found {:?}, {:?} and {:?}",
+                            locale, a, b
+                        ),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "locale-sort".to_string(),
+            impure: false,
+            params: vec!["locale".to_string(), "list".to_string()],
+            doc: "Sorts a list of strings using a locale's collation.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'locale-sort' expects 2 arguments (locale, list)".to_string(),
+                        None,
+                    ));
+                }
+                let locale = match &args[0] {
+                    Value::String(locale) => locale.clone(),
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'locale-sort' expected locale string as first argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                let items = match &args[1] {
+                    Value::List(items) => items.clone(),
+                    Value::Lazy(sequence) => interpreter.force_sequence(sequence)?,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'locale-sort' expected list as second argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                let mut strings = Vec::with_capacity(items.len());
+                for item in &items {
+                    match item {
+                        Value::String(text) => strings.push(text.clone()),
+                        other => {
+                            return Err(LangError::Runtime(
+                                format!("Builtin 'locale-sort' can only sort strings, found {:?}", other),
+                                None,
+                            ))
+                        }
+                    }
+                }
+                strings.sort_by(|a, b| collation::compare(&locale, a, b));
+                Ok(Value::List(strings.into_iter().map(Value::String).collect()))
+            }),
+        });
+    }
+
+    /// Byte strings.
+    fn install_bytes_builtins(&mut self) {
         self.add_builtin(BuiltinFunction {
-            name: "log!".to_string(),
-            impure: true,
-            params: vec!["message".to_string()],
-            func: Rc::new(|interpreter, args| {
-                if args.len() != 1 {
+            name: "string-to-bytes".to_string(),
+            impure: false,
+            params: vec!["encoding".to_string(), "text".to_string()],
+            doc: "Encodes a string as bytes with a named encoding.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'log!' expects exactly 1 argument".to_string(),
+                        "Builtin 'string-to-bytes' expects 2 arguments (encoding, text)"
+                            .to_string(),
                         None,
                     ));
                 }
-                let message = interpreter.value_to_string(&args[0])?;
-                println!("{}", message);
-                Ok(Value::Null)
+                match (&args[0], &args[1]) {
+                    (Value::String(name), Value::String(text)) => {
+                        encoding::encode_text(name, text)
+                            .map(Value::Bytes)
+                            .map_err(|err| {
+                                LangError::Runtime(
+                                    format!("Builtin 'string-to-bytes' failed: {}", err),
+                                    None,
+                                )
+                            })
+                    }
+                    (name, text) => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'string-to-bytes' expected an encoding name and a string, found {:?} and {:?}",
+                            name, text
+                        ),
+                        None,
+                    )),
+                }
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "trace!".to_string(),
-            impure: true,
-            params: vec!["label".to_string(), "value".to_string()],
-            func: Rc::new(|interpreter, args| {
+            name: "bytes-to-string".to_string(),
+            impure: false,
+            params: vec!["encoding".to_string(), "bytes".to_string()],
+            doc: "Decodes bytes into a string with a named encoding.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
                 if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'trace!' expects exactly 2 arguments (message, value)".to_string(),
+                        "Builtin 'bytes-to-string' expects 2 arguments (encoding, bytes)"
+                            .to_string(),
                         None,
                     ));
                 }
-                let message = interpreter.value_to_string(&args[0])?;
-                let value_str = interpreter.value_to_string(&args[1])?;
-                println!("(trace) {}: {}", message, value_str);
-                Ok(args[1].clone())
+                match (&args[0], &args[1]) {
+                    (Value::String(name), Value::Bytes(bytes)) => {
+                        encoding::decode_text(name, bytes)
+                            .map(Value::String)
+                            .map_err(|err| {
+                                LangError::Runtime(
+                                    format!("Builtin 'bytes-to-string' failed: {}", err),
+                                    None,
+                                )
+                            })
+                    }
+                    (name, bytes) => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'bytes-to-string' expected an encoding name and bytes, found {:?} and {:?}",
+                            name, bytes
+                        ),
+                        None,
+                    )),
+                }
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "identity".to_string(),
+            name: "bytes-length".to_string(),
             impure: false,
-            params: vec!["x".to_string()],
+            params: vec!["bytes".to_string()],
+            doc: "Returns the number of bytes.",
+            stability: Stability::Stable,
             func: Rc::new(|_, args| {
                 if args.len() != 1 {
                     return Err(LangError::Runtime(
-                        "Builtin 'identity' expects exactly 1 argument".to_string(),
+                        "Builtin 'bytes-length' expects exactly 1 argument".to_string(),
                         None,
                     ));
                 }
-                Ok(args[0].clone())
+                match &args[0] {
+                    Value::Bytes(bytes) => {
+                        i64::try_from(bytes.len()).map(Value::Number).map_err(|_| {
+                            LangError::Runtime(
+                                "Builtin 'bytes-length' result is out of range".to_string(),
+                                None,
+                            )
+                        })
+                    }
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'bytes-length' expected bytes, found {:?}", other),
+                        None,
+                    )),
+                }
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "increment".to_string(),
+            name: "byte-at".to_string(),
             impure: false,
-            params: vec!["number".to_string()],
+            params: vec!["bytes".to_string(), "index".to_string()],
+            doc: "Returns the byte at a zero-based index as a number from 0 to 255, or null.",
+            stability: Stability::Stable,
             func: Rc::new(|_, args| {
-                if args.len() != 1 {
+                if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'increment' expects exactly 1 argument".to_string(),
+                        "Builtin 'byte-at' expects 2 arguments (bytes, index)".to_string(),
                         None,
                     ));
                 }
-                match &args[0] {
-                    Value::Number(n) => Ok(Value::Number(n + 1)),
-                    other => Err(LangError::Runtime(
-                        format!("Builtin 'increment' expected a number, found {:?}", other),
+                match (&args[0], &args[1]) {
+                    (Value::Bytes(bytes), Value::Number(index)) => Ok(usize::try_from(*index)
+                        .ok()
+                        .and_then(|index| bytes.get(index))
+                        .map(|byte| Value::Number(i64::from(*byte)))
+                        .unwrap_or(Value::Null)),
+                    (Value::Bytes(_), other) => Err(LangError::Runtime(
+                        format!("Builtin 'byte-at' expected number index, found {:?}", other),
+                        None,
+                    )),
+                    (other, _) => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'byte-at' expected bytes as first argument, found {:?}",
+                            other
+                        ),
                         None,
                     )),
                 }
@@ -1210,617 +6529,1544 @@ impl Interpreter {
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "decrement".to_string(),
+            name: "bytes-slice".to_string(),
             impure: false,
-            params: vec!["number".to_string()],
+            params: vec!["bytes".to_string(), "start".to_string(), "end".to_string()],
+            doc: "Returns the bytes from start up to, but not including, end.",
+            stability: Stability::Stable,
             func: Rc::new(|_, args| {
-                if args.len() != 1 {
+                if args.len() != 3 {
                     return Err(LangError::Runtime(
-                        "Builtin 'decrement' expects exactly 1 argument".to_string(),
+                        "Builtin 'bytes-slice' expects 3 arguments (bytes, start, end)"
+                            .to_string(),
                         None,
                     ));
                 }
-                match &args[0] {
-                    Value::Number(n) => Ok(Value::Number(n - 1)),
-                    other => Err(LangError::Runtime(
-                        format!("Builtin 'decrement' expected a number, found {:?}", other),
+                match (&args[0], &args[1], &args[2]) {
+                    (Value::Bytes(bytes), Value::Number(start), Value::Number(end)) => {
+                        let range = usize::try_from(*start)
+                            .ok()
+                            .zip(usize::try_from(*end).ok())
+                            .filter(|(start, end)| start <= end && *end <= bytes.len());
+                        match range {
+                            Some((start, end)) => Ok(Value::Bytes(bytes[start..end].to_vec())),
+                            None => Err(LangError::Runtime(
+                                format!(
+                                    "Builtin 'bytes-slice' range {}..{} is out of bounds for {} bytes",
+                                    start,
+                                    end,
+                                    bytes.len()
+                                ),
+                                None,
+                            )),
+                        }
+                    }
+                    (bytes, start, end) => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'bytes-slice' expected bytes and two numbers, found {:?}, {:?} and {:?}",
+                            bytes, start, end
+                        ),
                         None,
                     )),
                 }
             }),
         });
+    }
 
+    /// Work on other threads, each running its own interpreter.
+    fn install_concurrency_builtins(&mut self) {
         self.add_builtin(BuiltinFunction {
-            name: "map".to_string(),
-            impure: false,
+            name: "parallel-map!".to_string(),
+            impure: true,
             params: vec!["fn".to_string(), "list".to_string()],
+            doc: "Like map, but splits the list across threads; the function must be pure.",
+            stability: Stability::Stable,
             func: Rc::new(|interpreter, args| {
                 if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'map' expects 2 arguments (fn, list)".to_string(),
+                        "Builtin 'parallel-map!' expects 2 arguments (fn, list)".to_string(),
                         None,
                     ));
                 }
-                let func = args[0].clone();
-                let list = match &args[1] {
-                    Value::List(items) => items.clone(),
+                match args[0].impure() {
+                    Some(false) => {}
+                    Some(true) => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'parallel-map!' requires a pure function, found '{}'; use 'map!' instead",
+                                args[0].callable_name()
+                            ),
+                            None,
+                        ))
+                    }
+                    None => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'parallel-map!' requires function as first argument, found {:?}",
+                                args[0]
+                            ),
+                            None,
+                        ))
+                    }
+                }
+                let list = interpreter.list_argument("parallel-map!", "second", &args[1])?;
+                let workers = interpreter
+                    .worker_threads
+                    .unwrap_or_else(|| {
+                        std::thread::available_parallelism().map_or(1, |count| count.get())
+                    })
+                    .min(list.len());
+                if workers <= 1 {
+                    return interpreter.map_list("parallel-map!", args, Purity::Pure);
+                }
+
+                // Each worker gets its own copy of the function and a run of
+                // the list, so results come back in order
+                let mut packages = Vec::with_capacity(workers);
+                for chunk in list.chunks(list.len().div_ceil(workers)) {
+                    let mut packer = Packer::default();
+                    packer.push(&args[0])?;
+                    for item in chunk {
+                        packer.push(item)?;
+                    }
+                    packages.push(packer.package);
+                }
+                let settings = interpreter.worker_settings();
+                let results: Vec<LangResult<Package>> = std::thread::scope(|scope| {
+                    let handles: Vec<_> = packages
+                        .iter()
+                        .map(|package| {
+                            let settings = settings.clone();
+                            scope.spawn(move || Interpreter::map_in_worker(package, settings))
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|handle| {
+                            handle.join().unwrap_or_else(|_| {
+                                Err(LangError::Runtime(
+                                    "A 'parallel-map!' worker panicked".to_string(),
+                                    None,
+                                ))
+                            })
+                        })
+                        .collect()
+                });
+                let mut mapped = Vec::with_capacity(list.len());
+                for result in results {
+                    mapped.extend(interpreter.unpack(&result?)?);
+                }
+                Ok(Value::List(mapped))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "spawn!".to_string(),
+            impure: true,
+            params: vec!["fn".to_string()],
+            doc: "Starts calling a function of no arguments on another thread and returns a task.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                let func = &args[0];
+                let (Some(impure), Some(0)) = (func.impure(), func.arity()) else {
+                    return Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'spawn!' requires a function of no arguments, found {:?}",
+                            func
+                        ),
+                        None,
+                    ));
+                };
+                let mut packer = Packer::default();
+                packer.push(func)?;
+                let package = packer.package;
+                let settings = interpreter.worker_settings();
+                let handle = std::thread::Builder::new()
+                    .spawn(move || {
+                        let worker = Interpreter::worker(settings);
+                        let func = worker.unpack(&package)?.remove(0);
+                        let purity = if impure { Purity::Impure } else { Purity::Pure };
+                        let result = worker.call_callable(func, vec![], purity)?;
+                        let mut packer = Packer::default();
+                        packer.push(&result)?;
+                        Ok(packer.package)
+                    })
+                    .map_err(|err| {
+                        LangError::Runtime(
+                            format!("Builtin 'spawn!' could not start a thread: {}", err),
+                            None,
+                        )
+                    })?;
+                let mut tasks = interpreter.tasks.borrow_mut();
+                tasks.push(Task::Running(handle));
+                Ok(Value::Task(tasks.len() - 1))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "await-all!".to_string(),
+            impure: true,
+            params: vec!["tasks".to_string()],
+            doc: "Waits for tasks started by spawn! and returns their results in order.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                let tasks = interpreter.list_argument("await-all!", "first", &args[0])?;
+                tasks
+                    .iter()
+                    .map(|task| interpreter.await_task(task))
+                    .collect::<LangResult<Vec<_>>>()
+                    .map(Value::List)
+            }),
+        });
+    }
+
+    /// Channels that tasks send each other values over.
+    fn install_channel_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "channel!".to_string(),
+            impure: true,
+            params: vec![],
+            doc: "Makes a new channel for tasks to send each other values over.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, _| Ok(Value::Channel(interpreter.channels.open()))),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "send!".to_string(),
+            impure: true,
+            params: vec!["channel".to_string(), "value".to_string()],
+            doc: "Sends a copy of a value over a channel without waiting and returns null.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                let id = Self::channel_argument("send!", &args[0])?;
+                let mut packer = Packer::default();
+                packer.push(&args[1])?;
+                interpreter
+                    .channels
+                    .send(id, packer.package)
+                    .map_err(|message| LangError::Runtime(message, None))?;
+                Ok(Value::Null)
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "receive!".to_string(),
+            impure: true,
+            params: vec!["channel".to_string()],
+            doc: "Waits for the next value sent over a channel and returns it.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                let id = Self::channel_argument("receive!", &args[0])?;
+                match interpreter
+                    .channels
+                    .receive(&[id], None)
+                    .map_err(|message| LangError::Runtime(message, None))?
+                {
+                    Some((_, package)) => Ok(interpreter.unpack(&package)?.remove(0)),
+                    None => unreachable!("receiving without a timeout waits for a value"),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "select!".to_string(),
+            impure: true,
+            params: vec!["channels".to_string(), "timeout".to_string()],
+            doc: "Waits for a value on any of several channels, for at most timeout milliseconds.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                let channels = interpreter.list_argument("select!", "first", &args[0])?;
+                let ids = channels
+                    .iter()
+                    .map(|channel| Self::channel_argument("select!", channel))
+                    .collect::<LangResult<Vec<_>>>()?;
+                let timeout = match &args[1] {
+                    Value::Null => None,
+                    Value::Number(ms) if *ms >= 0 => Some(Duration::from_millis(*ms as u64)),
                     other => {
                         return Err(LangError::Runtime(
                             format!(
-                                "Builtin 'map' expected list as second argument, found {:?}",
+                                "Builtin 'select!' expected a timeout in milliseconds or null, found {:?}",
                                 other
                             ),
                             None,
                         ))
                     }
                 };
-                let mut result = Vec::with_capacity(list.len());
-                for item in list {
-                    let mapped =
-                        interpreter.call_callable(func.clone(), vec![item], Purity::Pure)?;
-                    result.push(mapped);
+                let received = interpreter
+                    .channels
+                    .receive(&ids, timeout)
+                    .map_err(|message| LangError::Runtime(message, None))?;
+                let Some((position, package)) = received else {
+                    return Ok(Self::make_tagged("none".to_string(), Value::Null));
+                };
+                let mut fields = BTreeMap::new();
+                fields.insert("channel".to_string(), channels[position].clone());
+                fields.insert("value".to_string(), interpreter.unpack(&package)?.remove(0));
+                Ok(Self::make_tagged("some".to_string(), Value::Object(fields)))
+            }),
+        });
+    }
+
+    /// Lazy sequences.
+    fn install_lazy_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "lazy".to_string(),
+            impure: false,
+            params: vec!["list".to_string()],
+            doc: "Wraps a list in a lazy sequence.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'lazy' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
                 }
-                Ok(Value::List(result))
+                Ok(Value::Lazy(Sequence::from_value("lazy", &args[0])?))
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "reduce".to_string(),
+            name: "lazy-iterate".to_string(),
             impure: false,
-            params: vec!["fn".to_string(), "init".to_string(), "list".to_string()],
+            params: vec!["fn".to_string(), "seed".to_string()],
+            doc: "Returns the infinite lazy sequence seed, fn(seed), fn(fn(seed)), ...",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'lazy-iterate' expects 2 arguments (fn, seed)".to_string(),
+                        None,
+                    ));
+                }
+                Ok(Value::Lazy(Rc::new(Sequence::Iterate {
+                    step: args[0].clone(),
+                    seed: args[1].clone(),
+                })))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "lazy-map".to_string(),
+            impure: false,
+            params: vec!["fn".to_string(), "sequence".to_string()],
+            doc: "Returns a lazy sequence that applies fn to each element on demand.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'lazy-map' expects 2 arguments (fn, sequence)".to_string(),
+                        None,
+                    ));
+                }
+                Ok(Value::Lazy(Rc::new(Sequence::Map {
+                    func: args[0].clone(),
+                    source: Sequence::from_value("lazy-map", &args[1])?,
+                })))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "lazy-filter".to_string(),
+            impure: false,
+            params: vec!["predicate".to_string(), "sequence".to_string()],
+            doc: "Returns a lazy sequence of the elements for which the predicate holds.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'lazy-filter' expects 2 arguments (predicate, sequence)"
+                            .to_string(),
+                        None,
+                    ));
+                }
+                Ok(Value::Lazy(Rc::new(Sequence::Filter {
+                    predicate: args[0].clone(),
+                    source: Sequence::from_value("lazy-filter", &args[1])?,
+                })))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "take".to_string(),
+            impure: false,
+            params: vec!["count".to_string(), "sequence".to_string()],
+            doc: "Returns a list with at most the first count elements of a list or lazy sequence.",
+            stability: Stability::Stable,
             func: Rc::new(|interpreter, args| {
-                if args.len() != 3 {
+                if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'reduce' expects 3 arguments (fn, init, list)".to_string(),
+                        "Builtin 'take' expects 2 arguments (count, sequence)".to_string(),
                         None,
                     ));
                 }
-                let func = args[0].clone();
-                let mut acc = args[1].clone();
-                let list = match &args[2] {
-                    Value::List(items) => items.clone(),
+                let count = match &args[0] {
+                    Value::Number(n) if *n >= 0 => *n as usize,
                     other => {
                         return Err(LangError::Runtime(
                             format!(
-                                "Builtin 'reduce' expected list as third argument, found {:?}",
+                                "Builtin 'take' expected a non-negative number as first argument, found {:?}",
                                 other
                             ),
                             None,
                         ))
                     }
-                };
-                for item in list {
-                    acc = interpreter.call_callable(func.clone(), vec![acc, item], Purity::Pure)?;
+                };
+                let sequence = Sequence::from_value("take", &args[1])?;
+                Ok(Value::List(
+                    interpreter.take_from_sequence(&sequence, count)?,
+                ))
+            }),
+        });
+    }
+
+    /// JSON, hashes, text encodings, and name-based UUIDs.
+    fn install_encoding_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "json-parse".to_string(),
+            impure: false,
+            params: vec!["text".to_string()],
+            doc: "Parses a JSON document into a value.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'json-parse' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                match &args[0] {
+                    Value::String(text) => json::parse(text).map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'json-parse' failed: {}", err), None)
+                    }),
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'json-parse' expected a string, found {:?}", other),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "json-encode".to_string(),
+            impure: false,
+            params: vec!["value".to_string()],
+            doc: "Encodes a value as compact JSON.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'json-encode' expects exactly 1 argument".to_string(),
+                        None,
+                    ));
+                }
+                json::encode(&args[0]).map(Value::String).map_err(|err| {
+                    LangError::Runtime(format!("Builtin 'json-encode' failed: {}", err), None)
+                })
+            }),
+        });
+
+        type TextCodec = fn(&str) -> Result<String, String>;
+
+        let text_codecs: [(&'static str, &'static str, TextCodec); 6] = [
+            (
+                "sha256",
+                "Hashes a string's UTF-8 bytes with SHA-256 and returns lowercase hex.",
+                |text| Ok(encoding::hex_encode(&encoding::sha256(text.as_bytes()))),
+            ),
+            (
+                "md5",
+                "Hashes a string's UTF-8 bytes with MD5 and returns lowercase hex.",
+                |text| Ok(encoding::hex_encode(&encoding::md5(text.as_bytes()))),
+            ),
+            (
+                "base64-encode",
+                "Encodes a string's UTF-8 bytes as padded base64.",
+                |text| Ok(encoding::base64_encode(text.as_bytes())),
+            ),
+            (
+                "base64-decode",
+                "Decodes base64 text into a UTF-8 string.",
+                |text| encoding::base64_decode(text).and_then(encoding::into_text),
+            ),
+            (
+                "hex-encode",
+                "Encodes a string's UTF-8 bytes as lowercase hex.",
+                |text| Ok(encoding::hex_encode(text.as_bytes())),
+            ),
+            (
+                "hex-decode",
+                "Decodes hex text into a UTF-8 string.",
+                |text| encoding::hex_decode(text).and_then(encoding::into_text),
+            ),
+        ];
+
+        for (name, doc, transform) in text_codecs {
+            self.add_builtin(BuiltinFunction {
+                name: name.to_string(),
+                impure: false,
+                params: vec!["text".to_string()],
+                doc,
+                stability: Stability::Stable,
+                func: Rc::new(move |_, args| {
+                    if args.len() != 1 {
+                        return Err(LangError::Runtime(
+                            format!("Builtin '{}' expects exactly 1 argument", name),
+                            None,
+                        ));
+                    }
+                    match &args[0] {
+                        Value::String(text) => transform(text).map(Value::String).map_err(|err| {
+                            LangError::Runtime(format!("Builtin '{}' failed: {}", name, err), None)
+                        }),
+                        other => Err(LangError::Runtime(
+                            format!("Builtin '{}' expected a string, found {:?}", name, other),
+                            None,
+                        )),
+                    }
+                }),
+            });
+        }
+
+        self.add_builtin(BuiltinFunction {
+            name: "uuid-v5".to_string(),
+            impure: false,
+            params: vec!["namespace".to_string(), "name".to_string()],
+            doc: "Returns the name-based (version 5) UUID for a name within a namespace UUID.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'uuid-v5' expects exactly 2 arguments".to_string(),
+                        None,
+                    ));
+                }
+                match (&args[0], &args[1]) {
+                    (Value::String(namespace), Value::String(name)) => {
+                        uuid::v5(namespace, name).map(Value::String).map_err(|err| {
+                            LangError::Runtime(format!("Builtin 'uuid-v5' failed: {}", err), None)
+                        })
+                    }
+                    (namespace, name) => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'uuid-v5' expected two strings, found {:?} and {:?}",
+                            namespace, name
+                        ),
+                        None,
+                    )),
                 }
-                Ok(acc)
             }),
         });
+    }
 
+    /// Record helpers.
+    fn install_object_builtins(&mut self) {
         self.add_builtin(BuiltinFunction {
-            name: "filter".to_string(),
+            name: "get".to_string(),
             impure: false,
-            params: vec!["predicate".to_string(), "list".to_string()],
-            func: Rc::new(|interpreter, args| {
+            params: vec!["object".to_string(), "key".to_string()],
+            doc: "Looks up a record field by a string key computed at runtime.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
                 if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'filter' expects 2 arguments (predicate, list)".to_string(),
+                        "Builtin 'get' expects 2 arguments (object, key)".to_string(),
                         None,
                     ));
                 }
-                let predicate = args[0].clone();
-                let list = match &args[1] {
-                    Value::List(items) => items.clone(),
-                    other => {
-                        return Err(LangError::Runtime(
-                            format!(
-                                "Builtin 'filter' expected list as second argument, found {:?}",
-                                other
-                            ),
-                            None,
-                        ))
-                    }
-                };
-                let mut result = Vec::new();
-                for item in list {
-                    let keep = interpreter.call_callable(
-                        predicate.clone(),
-                        vec![item.clone()],
-                        Purity::Pure,
-                    )?;
-                    match keep {
-                        Value::Boolean(true) => result.push(item),
-                        Value::Boolean(false) => {}
-                        other => {
-                            return Err(LangError::Runtime(
-                                format!("Filter predicate must return boolean, found {:?}", other),
-                                None,
-                            ))
-                        }
+                match (&args[0], &args[1]) {
+                    (Value::Object(fields), Value::String(key)) => {
+                        Ok(fields.get(key).cloned().unwrap_or(Value::Null))
                     }
+                    (Value::Null, _) => Ok(Value::Null),
+                    (Value::Object(_), other) => Err(LangError::Runtime(
+                        format!("Builtin 'get' expected string key, found {:?}", other),
+                        None,
+                    )),
+                    (other, _) => Err(LangError::Runtime(
+                        format!(
+                            "Builtin 'get' expected object as first argument, found {:?}",
+                            other
+                        ),
+                        None,
+                    )),
                 }
-                Ok(Value::List(result))
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "add".to_string(),
+            name: "assoc".to_string(),
             impure: false,
-            params: vec!["a".to_string(), "b".to_string()],
+            params: vec!["object".to_string(), "key".to_string(), "value".to_string()],
+            doc: "Returns a copy of a record with one field set.",
+            stability: Stability::Stable,
             func: Rc::new(|_, args| {
-                if args.len() != 2 {
+                if args.len() != 3 {
                     return Err(LangError::Runtime(
-                        "Builtin 'add' expects exactly 2 arguments".to_string(),
+                        "Builtin 'assoc' expects 3 arguments (object, key, value)".to_string(),
                         None,
                     ));
                 }
-                let (lhs, rhs) = match (&args[0], &args[1]) {
-                    (Value::Number(a), Value::Number(b)) => (*a, *b),
-                    (a, b) => {
-                        return Err(LangError::Runtime(
-                            format!(
-                                "Builtin 'add' requires numeric operands, found {:?} and {:?}",
-                                a, b
-                            ),
-                            None,
-                        ))
-                    }
-                };
-                Ok(Value::Number(lhs + rhs))
+                if !matches!(args[1], Value::String(_)) {
+                    return Err(LangError::Runtime(
+                        format!("Builtin 'assoc' expected string key, found {:?}", args[1]),
+                        None,
+                    ));
+                }
+                let value = args[2].clone();
+                Interpreter::update_path("assoc", &args[0], &args[1..2], &mut |_| Ok(value.clone()))
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "subtract".to_string(),
+            name: "assoc-in".to_string(),
             impure: false,
-            params: vec!["a".to_string(), "b".to_string()],
+            params: vec![
+                "object".to_string(),
+                "path".to_string(),
+                "value".to_string(),
+            ],
+            doc: "Returns a copy of a nested structure with the value at a path set.",
+            stability: Stability::Stable,
             func: Rc::new(|_, args| {
-                if args.len() != 2 {
+                if args.len() != 3 {
                     return Err(LangError::Runtime(
-                        "Builtin 'subtract' expects exactly 2 arguments".to_string(),
+                        "Builtin 'assoc-in' expects 3 arguments (object, path, value)".to_string(),
                         None,
                     ));
                 }
-                let (lhs, rhs) = match (&args[0], &args[1]) {
-                    (Value::Number(a), Value::Number(b)) => (*a, *b),
-                    (a, b) => {
-                        return Err(LangError::Runtime(
-                            format!(
-                                "Builtin 'subtract' requires numeric operands, found {:?} and {:?}",
-                                a, b
-                            ),
-                            None,
-                        ))
-                    }
-                };
-                Ok(Value::Number(lhs - rhs))
+                let path = Interpreter::expect_path("assoc-in", &args[1])?;
+                let value = args[2].clone();
+                Interpreter::update_path("assoc-in", &args[0], path, &mut |_| Ok(value.clone()))
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "multiply".to_string(),
+            name: "update-in".to_string(),
             impure: false,
-            params: vec!["a".to_string(), "b".to_string()],
-            func: Rc::new(|_, args| {
-                if args.len() != 2 {
+            params: vec!["object".to_string(), "path".to_string(), "fn".to_string()],
+            doc: "Returns a copy of a nested structure with the value at a path passed through a function.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 3 {
                     return Err(LangError::Runtime(
-                        "Builtin 'multiply' expects exactly 2 arguments".to_string(),
+                        "Builtin 'update-in' expects 3 arguments (object, path, fn)".to_string(),
                         None,
                     ));
                 }
-                let (lhs, rhs) = match (&args[0], &args[1]) {
-                    (Value::Number(a), Value::Number(b)) => (*a, *b),
-                    (a, b) => {
-                        return Err(LangError::Runtime(
-                            format!(
-                                "Builtin 'multiply' requires numeric operands, found {:?} and {:?}",
-                                a, b
-                            ),
-                            None,
-                        ))
-                    }
-                };
-                Ok(Value::Number(lhs * rhs))
+                let path = Interpreter::expect_path("update-in", &args[1])?;
+                let func = args[2].clone();
+                Interpreter::update_path("update-in", &args[0], path, &mut |current| {
+                    interpreter.call_callable(func.clone(), vec![current], Purity::Pure)
+                })
             }),
         });
+    }
 
+    /// Timestamps that do not read the clock.
+    fn install_time_builtins(&mut self) {
         self.add_builtin(BuiltinFunction {
-            name: "divide".to_string(),
+            name: "parse-iso".to_string(),
             impure: false,
-            params: vec!["a".to_string(), "b".to_string()],
+            params: vec!["text".to_string()],
+            doc: "Parses an ISO-8601 date or date-time into a UTC timestamp record, or null.",
+            stability: Stability::Stable,
             func: Rc::new(|_, args| {
-                if args.len() != 2 {
+                if args.len() != 1 {
                     return Err(LangError::Runtime(
-                        "Builtin 'divide' expects exactly 2 arguments".to_string(),
+                        "Builtin 'parse-iso' expects exactly 1 argument".to_string(),
                         None,
                     ));
                 }
-                let (lhs, rhs) = match (&args[0], &args[1]) {
-                    (Value::Number(a), Value::Number(b)) => (*a, *b),
-                    (a, b) => {
-                        return Err(LangError::Runtime(
-                            format!(
-                                "Builtin 'divide' requires numeric operands, found {:?} and {:?}",
-                                a, b
-                            ),
-                            None,
-                        ))
-                    }
-                };
-                if rhs == 0 {
+                match &args[0] {
+                    Value::String(text) => Ok(time::parse_iso(text)
+                        .map(time::timestamp_to_value)
+                        .unwrap_or(Value::Null)),
+                    other => Err(LangError::Runtime(
+                        format!("Builtin 'parse-iso' expected a string, found {:?}", other),
+                        None,
+                    )),
+                }
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "format-iso".to_string(),
+            impure: false,
+            params: vec!["timestamp".to_string()],
+            doc: "Formats a timestamp record as an ISO-8601 UTC string.",
+            stability: Stability::Stable,
+            func: Rc::new(|_, args| {
+                if args.len() != 1 {
                     return Err(LangError::Runtime(
-                        "Builtin 'divide' received division by zero".to_string(),
+                        "Builtin 'format-iso' expects exactly 1 argument".to_string(),
                         None,
                     ));
                 }
-                Ok(Value::Number(lhs / rhs))
+                time::timestamp_from_value(&args[0])
+                    .map(|millis| Value::String(time::format_iso(millis)))
+                    .map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'format-iso' failed: {}", err), None)
+                    })
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "and?".to_string(),
+            name: "add-duration".to_string(),
             impure: false,
-            params: vec!["a".to_string(), "b".to_string()],
+            params: vec!["duration".to_string(), "timestamp".to_string()],
+            doc: "Moves a timestamp record by a duration record.",
+            stability: Stability::Stable,
             func: Rc::new(|_, args| {
                 if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'and?' expects exactly 2 arguments".to_string(),
+                        "Builtin 'add-duration' expects 2 arguments (duration, timestamp)"
+                            .to_string(),
                         None,
                     ));
                 }
-                let (lhs, rhs) = match (&args[0], &args[1]) {
-                    (Value::Boolean(a), Value::Boolean(b)) => (*a, *b),
-                    (a, b) => {
-                        return Err(LangError::Runtime(
-                            format!(
-                                "Builtin 'and?' requires boolean operands, found {:?} and {:?}",
-                                a, b
-                            ),
-                            None,
-                        ))
-                    }
-                };
-                Ok(Value::Boolean(lhs && rhs))
+                let duration = time::duration_from_value(&args[0]);
+                let start = time::timestamp_from_value(&args[1]);
+                duration
+                    .and_then(|duration| time::add_duration(start?, duration))
+                    .map(time::timestamp_to_value)
+                    .map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'add-duration' failed: {}", err), None)
+                    })
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "or?".to_string(),
+            name: "time-between".to_string(),
             impure: false,
-            params: vec!["a".to_string(), "b".to_string()],
+            params: vec!["start".to_string(), "end".to_string()],
+            doc: "Returns the duration from one timestamp record to another.",
+            stability: Stability::Stable,
             func: Rc::new(|_, args| {
                 if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'or?' expects exactly 2 arguments".to_string(),
+                        "Builtin 'time-between' expects 2 arguments (start, end)".to_string(),
                         None,
                     ));
                 }
-                let (lhs, rhs) = match (&args[0], &args[1]) {
-                    (Value::Boolean(a), Value::Boolean(b)) => (*a, *b),
-                    (a, b) => {
-                        return Err(LangError::Runtime(
-                            format!(
-                                "Builtin 'or?' requires boolean operands, found {:?} and {:?}",
-                                a, b
-                            ),
-                            None,
-                        ))
-                    }
-                };
-                Ok(Value::Boolean(lhs || rhs))
+                let start = time::timestamp_from_value(&args[0]);
+                let end = time::timestamp_from_value(&args[1]);
+                start
+                    .and_then(|start| Ok(end? - start))
+                    .map(time::duration_to_value)
+                    .map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'time-between' failed: {}", err), None)
+                    })
             }),
         });
+    }
 
+    /// Builtins that read or write files; installed with [`Capability::Fs`].
+    fn install_fs_builtins(&mut self) {
         self.add_builtin(BuiltinFunction {
-            name: "every?".to_string(),
-            impure: false,
-            params: vec!["predicate".to_string(), "list".to_string()],
+            name: "expect-snapshot!".to_string(),
+            impure: true,
+            params: vec!["name".to_string(), "value".to_string()],
+            doc: "Fails unless the value matches the snapshot stored in name.snap, recording it on first use.",
+            stability: Stability::Stable,
             func: Rc::new(|interpreter, args| {
                 if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'every?' expects 2 arguments (predicate, list)".to_string(),
+                        "Builtin 'expect-snapshot!' expects 2 arguments (name, value)".to_string(),
                         None,
                     ));
                 }
-                let predicate = args[0].clone();
-                let list = match &args[1] {
-                    Value::List(items) => items.clone(),
+                let name = match &args[0] {
+                    Value::String(name)
+                        if !name.is_empty()
+                            && name
+                                .chars()
+                                .all(|ch| ch.is_alphanumeric() || ch == '-' || ch == '_') =>
+                    {
+                        name
+                    }
                     other => {
                         return Err(LangError::Runtime(
                             format!(
-                                "Builtin 'every?' expected list as second argument, found {:?}",
-                                other
+                                "Builtin 'expect-snapshot!' expected a name of letters, digits, '-', and '_', found {}",
+                                pretty(other)
                             ),
                             None,
                         ))
                     }
                 };
-                // Returns true for empty list
-                for item in list {
-                    let result =
-                        interpreter.call_callable(predicate.clone(), vec![item], Purity::Pure)?;
-                    match result {
-                        Value::Boolean(true) => continue,
-                        Value::Boolean(false) => return Ok(Value::Boolean(false)),
-                        other => {
-                            return Err(LangError::Runtime(
+                let file = format!("{}.snap", name);
+                let path = interpreter.resolve_path(&file);
+                let actual = format!("{}\n", pretty(&args[1]));
+                let stored = match interpreter.file_system.read_to_string(&path) {
+                    Ok(stored) => Some(stored),
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(err) => {
+                        return Err(LangError::Runtime(
+                            format!("Builtin 'expect-snapshot!' could not read {}: {}", file, err),
+                            None,
+                        ))
+                    }
+                };
+                match stored {
+                    Some(stored) if stored == actual => Ok(Value::Null),
+                    Some(stored) if !interpreter.update_snapshots => {
+                        let indented = |text: &str| text.trim_end().replace('\n', "\n  ");
+                        // Values that fit on a line are easier to compare whole
+                        let differences = if actual.trim_end().contains('\n') {
+                            diff::section(
+                                &Value::String(stored.clone()),
+                                &Value::String(actual.clone()),
+                            )
+                        } else {
+                            String::new()
+                        };
+                        Err(LangError::Runtime(
+                            format!(
+                                "Assertion failed: value does not match snapshot {}\nexpected:\n  {}\nactual:\n  {}{}\nrun with --update-snapshots to accept the new value",
+                                file,
+                                indented(&stored),
+                                indented(&actual),
+                                differences
+                            ),
+                            None,
+                        ))
+                    }
+                    // Missing snapshots are recorded, and replaced when updating
+                    _ => interpreter
+                        .file_system
+                        .write(&path, actual.as_bytes())
+                        .map(|_| Value::Null)
+                        .map_err(|err| {
+                            LangError::Runtime(
                                 format!(
-                                    "Predicate passed to 'every?' must return boolean, found {:?}",
-                                    other
+                                    "Builtin 'expect-snapshot!' could not write {}: {}",
+                                    file, err
                                 ),
                                 None,
-                            ))
-                        }
-                    }
+                            )
+                        }),
                 }
-                Ok(Value::Boolean(true))
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "some?".to_string(),
-            impure: false,
-            params: vec!["predicate".to_string(), "list".to_string()],
+            name: "for-each-line!".to_string(),
+            impure: true,
+            params: vec!["fn".to_string(), "path".to_string()],
+            doc: "Calls an impure function for every line of a file, reading one line at a time.",
+            stability: Stability::Stable,
             func: Rc::new(|interpreter, args| {
                 if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'some?' expects 2 arguments (predicate, list)".to_string(),
+                        "Builtin 'for-each-line!' expects 2 arguments (fn, path)".to_string(),
                         None,
                     ));
                 }
-                let predicate = args[0].clone();
-                let list = match &args[1] {
-                    Value::List(items) => items.clone(),
-                    other => {
+                let func = args[0].clone();
+                let is_impure = match func.impure() {
+                    Some(impure) => impure,
+                    None => {
+                        let other = &func;
                         return Err(LangError::Runtime(
                             format!(
-                                "Builtin 'some?' expected list as second argument, found {:?}",
+                                "Builtin 'for-each-line!' requires function as first argument, found {:?}",
                                 other
                             ),
                             None,
                         ))
                     }
                 };
-                // Returns false for empty list
-                for item in list {
-                    let result =
-                        interpreter.call_callable(predicate.clone(), vec![item], Purity::Pure)?;
-                    match result {
-                        Value::Boolean(true) => return Ok(Value::Boolean(true)),
-                        Value::Boolean(false) => continue,
-                        other => {
-                            return Err(LangError::Runtime(
-                                format!(
-                                    "Predicate passed to 'some?' must return boolean, found {:?}",
-                                    other
-                                ),
-                                None,
-                            ))
-                        }
+                if !is_impure {
+                    return Err(LangError::Runtime(
+                        "Builtin 'for-each-line!' requires impure function (marked with '!')"
+                            .to_string(),
+                        None,
+                    ));
+                }
+                let path = match &args[1] {
+                    Value::String(path) => path,
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'for-each-line!' expected path string as second argument, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
                     }
+                };
+                let path = interpreter.resolve_path(path);
+                let read_error = |err: std::io::Error| {
+                    LangError::Runtime(
+                        format!(
+                            "Builtin 'for-each-line!' failed to read {}: {}",
+                            path.display(),
+                            err
+                        ),
+                        None,
+                    )
+                };
+                let file = interpreter.file_system.open(&path).map_err(read_error)?;
+                for line in file.lines() {
+                    let line = Value::String(line.map_err(read_error)?);
+                    interpreter.call_callable(func.clone(), vec![line], Purity::Impure)?;
                 }
-                Ok(Value::Boolean(false))
+                Ok(Value::Null)
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "none?".to_string(),
-            impure: false,
-            params: vec!["predicate".to_string(), "list".to_string()],
+            name: "read-bytes!".to_string(),
+            impure: true,
+            params: vec!["path".to_string()],
+            doc: "Reads a whole file as bytes.",
+            stability: Stability::Stable,
             func: Rc::new(|interpreter, args| {
-                if args.len() != 2 {
+                if args.len() != 1 {
                     return Err(LangError::Runtime(
-                        "Builtin 'none?' expects 2 arguments (predicate, list)".to_string(),
+                        "Builtin 'read-bytes!' expects exactly 1 argument".to_string(),
                         None,
                     ));
                 }
-                let predicate = args[0].clone();
-                let list = match &args[1] {
-                    Value::List(items) => items.clone(),
+                let path = match &args[0] {
+                    Value::String(path) => path,
                     other => {
                         return Err(LangError::Runtime(
                             format!(
-                                "Builtin 'none?' expected list as second argument, found {:?}",
+                                "Builtin 'read-bytes!' expected path string, found {:?}",
                                 other
                             ),
                             None,
                         ))
                     }
                 };
-                // Returns true for empty list
-                for item in list {
-                    let result =
-                        interpreter.call_callable(predicate.clone(), vec![item], Purity::Pure)?;
-                    match result {
-                        Value::Boolean(false) => continue,
-                        Value::Boolean(true) => return Ok(Value::Boolean(false)),
-                        other => {
-                            return Err(LangError::Runtime(
-                                format!(
-                                    "Predicate passed to 'none?' must return boolean, found {:?}",
-                                    other
-                                ),
-                                None,
-                            ))
-                        }
-                    }
-                }
-                Ok(Value::Boolean(true))
+                let path = interpreter.resolve_path(path);
+                interpreter
+                    .file_system
+                    .read(&path)
+                    .map(Value::Bytes)
+                    .map_err(|err| {
+                        LangError::Runtime(
+                            format!(
+                                "Builtin 'read-bytes!' failed to read {}: {}",
+                                path.display(),
+                                err
+                            ),
+                            None,
+                        )
+                    })
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "defined?".to_string(),
-            impure: false,
-            params: vec!["value".to_string()],
-            func: Rc::new(|_, args| {
+            name: "read-file!".to_string(),
+            impure: true,
+            params: vec!["path".to_string()],
+            doc: "Reads a whole UTF-8 file as a string.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
                 if args.len() != 1 {
                     return Err(LangError::Runtime(
-                        "Builtin 'defined?' expects exactly 1 argument".to_string(),
+                        "Builtin 'read-file!' expects exactly 1 argument".to_string(),
                         None,
                     ));
                 }
-                Ok(Value::Boolean(!matches!(args[0], Value::Null)))
+                let path = match &args[0] {
+                    Value::String(path) => interpreter.resolve_path(path),
+                    other => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'read-file!' expected path string, found {:?}",
+                                other
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                interpreter
+                    .file_system
+                    .read_to_string(&path)
+                    .map(Value::String)
+                    .map_err(|err| {
+                        LangError::Runtime(
+                            format!(
+                                "Builtin 'read-file!' failed to read {}: {}",
+                                path.display(),
+                                err
+                            ),
+                            None,
+                        )
+                    })
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "if".to_string(),
-            impure: false,
-            params: vec!["condition".to_string(), "then-fn".to_string(), "else-fn".to_string()],
+            name: "write-file!".to_string(),
+            impure: true,
+            params: vec!["path".to_string(), "contents".to_string()],
+            doc: "Writes a string or bytes to a file, replacing what it held.",
+            stability: Stability::Stable,
             func: Rc::new(|interpreter, args| {
-                if args.len() != 3 {
+                if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'if' expects 3 arguments (condition, then-fn, else-fn)".to_string(),
+                        "Builtin 'write-file!' expects 2 arguments (path, contents)".to_string(),
                         None,
                     ));
                 }
-                let condition = match &args[0] {
-                    Value::Boolean(b) => *b,
+                let path = match &args[0] {
+                    Value::String(path) => interpreter.resolve_path(path),
                     other => {
                         return Err(LangError::Runtime(
                             format!(
-                                "Builtin 'if' requires boolean condition, found {:?}",
+                                "Builtin 'write-file!' expected path string as first argument, found {:?}",
                                 other
                             ),
                             None,
                         ))
                     }
                 };
-                let then_fn = match &args[1] {
-                    Value::Function(f) => f.clone(),
-                    Value::Builtin(_) => {
-                        return Err(LangError::Runtime(
-                            "Builtin 'if' requires function as second argument (then-fn)".to_string(),
-                            None,
-                        ))
-                    }
+                let contents = match &args[1] {
+                    Value::String(text) => text.as_bytes(),
+                    Value::Bytes(bytes) => bytes.as_slice(),
                     other => {
                         return Err(LangError::Runtime(
                             format!(
-                                "Builtin 'if' requires function as second argument, found {:?}",
+                                "Builtin 'write-file!' expected string or bytes contents, found {:?}",
                                 other
                             ),
                             None,
                         ))
                     }
                 };
-                let else_fn = match &args[2] {
-                    Value::Function(f) => f.clone(),
-                    Value::Builtin(_) => {
+                interpreter
+                    .file_system
+                    .write(&path, contents)
+                    .map(|_| Value::Null)
+                    .map_err(|err| {
+                        LangError::Runtime(
+                            format!(
+                                "Builtin 'write-file!' failed to write {}: {}",
+                                path.display(),
+                                err
+                            ),
+                            None,
+                        )
+                    })
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "load-config!".to_string(),
+            impure: true,
+            params: vec![
+                "path".to_string(),
+                "prefix".to_string(),
+                "overrides".to_string(),
+            ],
+            doc: "Loads a config file and layers prefixed environment variables and overrides on top.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if args.len() != 3 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'load-config!' expects 3 arguments (path, prefix, overrides)"
+                            .to_string(),
+                        None,
+                    ));
+                }
+                let (path, prefix) = match (&args[0], &args[1]) {
+                    (Value::String(path), Value::String(prefix)) => (path, prefix),
+                    (path, prefix) => {
                         return Err(LangError::Runtime(
-                            "Builtin 'if' requires function as third argument (else-fn)".to_string(),
+                            format!(
+                                "Builtin 'load-config!' expected path and prefix strings, found {:?} and {:?}",
+                                path, prefix
+                            ),
                             None,
                         ))
                     }
+                };
+                let path = interpreter.resolve_path(path);
+                let text = interpreter.file_system.read_to_string(&path).map_err(|err| {
+                    LangError::Runtime(
+                        format!("Failed to read config '{}': {}", path.display(), err),
+                        None,
+                    )
+                })?;
+                config::load(&path, &text, prefix, std::env::vars(), &args[2])
+                    .map_err(|err| LangError::Runtime(err, None))
+            }),
+        });
+    }
+
+    /// Builtins that reach the network; installed with [`Capability::Net`].
+    fn install_net_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "http-post!".to_string(),
+            impure: true,
+            params: vec!["url".to_string(), "body".to_string()],
+            doc: "Sends an HTTP POST request and returns its status and body.",
+            stability: Stability::Experimental,
+            func: Rc::new(|_, args| {
+                if args.len() != 2 {
+                    return Err(LangError::Runtime(
+                        "Builtin 'http-post!' expects 2 arguments (url, body)".to_string(),
+                        None,
+                    ));
+                }
+                let url = match &args[0] {
+                    Value::String(url) => url,
                     other => {
                         return Err(LangError::Runtime(
                             format!(
-                                "Builtin 'if' requires function as third argument, found {:?}",
+                                "Builtin 'http-post!' expected url string as first argument, found {:?}",
                                 other
                             ),
                             None,
                         ))
                     }
                 };
-                // Check that functions take zero arguments (thunks)
-                if then_fn.params.len() != 0 {
+                // Strings are sent verbatim; anything else is sent as JSON.
+                let (content_type, body) = match &args[1] {
+                    Value::String(text) => ("text/plain; charset=utf-8", text.clone()),
+                    other => (
+                        "application/json",
+                        json::encode(other).map_err(|err| {
+                            LangError::Runtime(format!("Builtin 'http-post!' failed: {}", err), None)
+                        })?,
+                    ),
+                };
+                let response = http::post(url, content_type, &body)
+                    .map_err(|err| LangError::Runtime(err, None))?;
+                let mut result = BTreeMap::new();
+                result.insert(
+                    "status".to_string(),
+                    Value::Number(i64::from(response.status)),
+                );
+                result.insert("body".to_string(), Value::String(response.body));
+                Ok(Value::Object(result))
+            }),
+        });
+
+        self.add_builtin(BuiltinFunction {
+            name: "graphql!".to_string(),
+            impure: true,
+            params: vec![
+                "endpoint".to_string(),
+                "query".to_string(),
+                "variables".to_string(),
+            ],
+            doc: "Posts a GraphQL query with variables and returns the response's data and errors.",
+            stability: Stability::Experimental,
+            func: Rc::new(|_, args| {
+                if args.len() != 3 {
                     return Err(LangError::Runtime(
-                        format!(
-                            "Builtin 'if' requires zero-argument function as then-fn, found function with {} parameters",
-                            then_fn.params.len()
-                        ),
+                        "Builtin 'graphql!' expects 3 arguments (endpoint, query, variables)"
+                            .to_string(),
                         None,
                     ));
                 }
-                if else_fn.params.len() != 0 {
-                    return Err(LangError::Runtime(
+                let (endpoint, query) = match (&args[0], &args[1]) {
+                    (Value::String(endpoint), Value::String(query)) => (endpoint, query),
+                    (endpoint, query) => {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Builtin 'graphql!' expected endpoint and query strings, found {:?} and {:?}",
+                                endpoint, query
+                            ),
+                            None,
+                        ))
+                    }
+                };
+                let mut request = BTreeMap::new();
+                request.insert("query".to_string(), Value::String(query.clone()));
+                request.insert("variables".to_string(), args[2].clone());
+                let body = json::encode(&Value::Object(request)).map_err(|err| {
+                    LangError::Runtime(format!("Builtin 'graphql!' failed: {}", err), None)
+                })?;
+                let response = http::post(endpoint, "application/json", &body)
+                    .map_err(|err| LangError::Runtime(err, None))?;
+                let payload = json::parse(&response.body).map_err(|err| {
+                    LangError::Runtime(
                         format!(
-                            "Builtin 'if' requires zero-argument function as else-fn, found function with {} parameters",
-                            else_fn.params.len()
+                            "Builtin 'graphql!' received a non-JSON response (status {}): {}",
+                            response.status, err
                         ),
                         None,
-                    ));
-                }
-                // Evaluate only the branch that matches the condition
-                if condition {
-                    interpreter.call_callable(Value::Function(then_fn), vec![], Purity::Pure)
-                } else {
-                    interpreter.call_callable(Value::Function(else_fn), vec![], Purity::Pure)
-                }
+                    )
+                })?;
+                let field = |name: &str| match &payload {
+                    Value::Object(fields) => fields.get(name).cloned().unwrap_or(Value::Null),
+                    _ => Value::Null,
+                };
+                let mut result = BTreeMap::new();
+                result.insert("data".to_string(), field("data"));
+                result.insert("errors".to_string(), field("errors"));
+                Ok(Value::Object(result))
             }),
         });
 
         self.add_builtin(BuiltinFunction {
-            name: "for-each!".to_string(),
+            name: "serve-http!".to_string(),
             impure: true,
-            params: vec!["fn".to_string(), "list".to_string()],
+            params: vec!["port".to_string(), "handler".to_string()],
+            doc: "Serves HTTP on a local port, answering each request with the handler's result.",
+            stability: Stability::Experimental,
             func: Rc::new(|interpreter, args| {
                 if args.len() != 2 {
                     return Err(LangError::Runtime(
-                        "Builtin 'for-each!' expects 2 arguments (fn, list)".to_string(),
+                        "Builtin 'serve-http!' expects 2 arguments (port, handler)".to_string(),
                         None,
                     ));
                 }
-                let func = args[0].clone();
-                let list = match &args[1] {
-                    Value::List(items) => items.clone(),
+                let port = match &args[0] {
+                    Value::Number(port) if (1..=65535).contains(port) => *port as u16,
                     other => {
                         return Err(LangError::Runtime(
                             format!(
-                                "Builtin 'for-each!' expected list as second argument, found {:?}",
+                                "Builtin 'serve-http!' expected port number from 1 to 65535 as first argument, found {:?}",
                                 other
                             ),
                             None,
                         ))
                     }
                 };
-                // Verify the function is impure
-                let is_impure = match &func {
-                    Value::Function(f) => f.impure,
-                    Value::Builtin(b) => b.impure,
+                let handler = match &args[1] {
+                    Value::Function(_) | Value::Builtin(_) | Value::Partial { .. } => {
+                        args[1].clone()
+                    }
                     other => {
                         return Err(LangError::Runtime(
                             format!(
-                            "Builtin 'for-each!' requires function as first argument, found {:?}",
-                            other
-                        ),
+                                "Builtin 'serve-http!' requires function as second argument, found {:?}",
+                                other
+                            ),
                             None,
                         ))
                     }
                 };
-                if !is_impure {
+                let listener = http::listen(port).map_err(|err| {
+                    LangError::Runtime(format!("Builtin 'serve-http!' failed: {}", err), None)
+                })?;
+                http::serve(&listener, &mut |request| {
+                    let reply = interpreter.call_callable(
+                        handler.clone(),
+                        vec![Self::request_to_value(request)],
+                        Purity::Impure,
+                    )?;
+                    Self::response_from_value(&reply)
+                })?;
+                Ok(Value::Null)
+            }),
+        });
+    }
+
+    /// Builtins that read the clock; installed with [`Capability::Clock`].
+    fn install_clock_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "now!".to_string(),
+            impure: true,
+            params: vec![],
+            doc: "Returns the current UTC time as a timestamp record.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if !args.is_empty() {
                     return Err(LangError::Runtime(
-                        "Builtin 'for-each!' requires impure function (marked with '!')"
-                            .to_string(),
+                        "Builtin 'now!' expects no arguments".to_string(),
                         None,
                     ));
                 }
-                // Iterate through list and call function for each element
-                for item in list {
-                    let _ = interpreter.call_callable(func.clone(), vec![item], Purity::Impure)?;
+                if interpreter.seed.get().is_some() {
+                    return Ok(time::timestamp_to_value(0));
                 }
-                Ok(Value::Null)
+                time::now_millis()
+                    .map(time::timestamp_to_value)
+                    .map_err(|err| {
+                        LangError::Runtime(format!("Builtin 'now!' failed: {}", err), None)
+                    })
+            }),
+        });
+    }
+
+    /// Builtins that draw random numbers; installed with [`Capability::Random`].
+    fn install_random_builtins(&mut self) {
+        self.add_builtin(BuiltinFunction {
+            name: "uuid!".to_string(),
+            impure: true,
+            params: vec![],
+            doc: "Returns a new random (version 4) UUID string.",
+            stability: Stability::Stable,
+            func: Rc::new(|interpreter, args| {
+                if !args.is_empty() {
+                    return Err(LangError::Runtime(
+                        "Builtin 'uuid!' expects no arguments".to_string(),
+                        None,
+                    ));
+                }
+                if let (Some(high), Some(low)) =
+                    (interpreter.next_seeded(), interpreter.next_seeded())
+                {
+                    let bytes = ((u128::from(high) << 64) | u128::from(low)).to_be_bytes();
+                    return Ok(Value::String(uuid::v4_from(bytes)));
+                }
+                uuid::v4().map(Value::String).map_err(|err| {
+                    LangError::Runtime(format!("Builtin 'uuid!' failed: {}", err), None)
+                })
             }),
         });
     }
 
-    fn add_builtin(&mut self, builtin: BuiltinFunction) {
-        let name = builtin.name.clone();
-        self.global
-            .define(name.clone(), Value::Builtin(Rc::new(builtin)))
-            .unwrap_or_else(|_| panic!("failed to install builtin '{}'", name));
+    /// The tag and value of a record made by `tag`: exactly a string `tag`
+    /// field and a `value` field.
+    fn tagged(value: &Value) -> Option<(&str, &Value)> {
+        let Value::Object(fields) = value else {
+            return None;
+        };
+        match (fields.len(), fields.get("tag"), fields.get("value")) {
+            (2, Some(Value::String(name)), Some(value)) => Some((name, value)),
+            _ => None,
+        }
+    }
+
+    fn make_tagged(name: String, value: Value) -> Value {
+        let mut fields = BTreeMap::new();
+        fields.insert("tag".to_string(), Value::String(name));
+        fields.insert("value".to_string(), value);
+        Value::Object(fields)
+    }
+
+    /// The value a result or option carries on success: the value inside
+    /// an `ok` or `some`, or any value that is neither null nor tagged
+    /// `err` or `none`. Failures give `None`.
+    fn success(value: &Value) -> Option<&Value> {
+        match Interpreter::tagged(value) {
+            Some(("ok" | "some", inner)) => Some(inner),
+            Some(("err" | "none", _)) => None,
+            _ if matches!(value, Value::Null) => None,
+            _ => Some(value),
+        }
+    }
+
+    fn add_builtin(&mut self, builtin: BuiltinFunction) {
+        let name = builtin.name.clone();
+        self.global
+            .define(name.clone(), Value::Builtin(Rc::new(builtin)))
+            .unwrap_or_else(|_| panic!("failed to install builtin '{}'", name));
+    }
+
+    /// Replaces the loader used to resolve `use` imports.
+    pub fn set_module_loader(&mut self, loader: Box<dyn ModuleLoader>) {
+        self.module_loader = loader;
+    }
+
+    /// Replaces the filesystem the file builtins read and write.
+    pub fn set_file_system(&mut self, file_system: Box<dyn FileSystem>) {
+        self.file_system = file_system;
+        self.custom_file_system = true;
+    }
+
+    /// Where a path given to a file builtin points: relative paths resolve
+    /// like module imports, from the entry point directory.
+    fn resolve_path(&self, path: &str) -> PathBuf {
+        match &self.entry_point_dir {
+            Some(base_dir) => base_dir.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    /// Allows calls to builtins marked `Stability::Experimental`.
+    pub fn set_enable_experimental(&mut self, enable: bool) {
+        self.enable_experimental = enable;
+    }
+
+    /// Makes runs reproducible: `uuid!` draws from a generator seeded with
+    /// `seed`, `now!` always returns the Unix epoch, and builtins that reach
+    /// the network fail. `None` turns this off again.
+    pub fn set_deterministic(&mut self, seed: Option<u64>) {
+        self.seed.set(seed);
+    }
+
+    /// Installs only the builtins of `capabilities`; the rest are undefined
+    /// and calling one fails with a hint to allow it. This resets the global
+    /// scope, so call it before running programs.
+    pub fn set_capabilities(&mut self, capabilities: &[Capability]) {
+        self.capabilities = capabilities.iter().copied().collect();
+        self.global = Environment::new(None);
+        self.install_builtins();
+    }
+
+    /// Makes `expect-snapshot!` overwrite snapshots that no longer match
+    /// instead of failing.
+    pub fn set_update_snapshots(&mut self, update: bool) {
+        self.update_snapshots = update;
+    }
+
+    /// Sets how many threads `parallel-map!` splits a list across; by
+    /// default it uses one per core.
+    pub fn set_worker_threads(&mut self, threads: usize) {
+        self.worker_threads = Some(threads);
+    }
+
+    /// Binds `name` in the global scope, for hosts that hand a program its
+    /// input, like `fip filter` does with `input`.
+    pub fn define_global(&self, name: &str, value: Value) -> LangResult<()> {
+        self.global.define(name.to_string(), value)
+    }
+
+    /// Removes a global binding so the name can be bound again, as a
+    /// notebook lets a later cell redefine what an earlier one did.
+    pub fn forget_global(&self, name: &str) -> Option<Value> {
+        self.global.values.borrow_mut().remove(name)
+    }
+
+    /// Bounds every following `eval_program` run. Exceeding a limit stops
+    /// evaluation with `LangError::LimitExceeded`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Replaces where `log!`, `print!`, and `trace!` write; stdout by
+    /// default.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        *self.output.borrow_mut() = output;
+    }
+
+    /// Replaces where warnings, such as deprecated builtin notices, `elog!`,
+    /// and the lines of `log-info!` and friends are written; stderr by
+    /// default.
+    pub fn set_error_output(&mut self, output: Box<dyn Write>) {
+        *self.error_output.borrow_mut() = output;
+    }
+
+    /// Drops log lines below `level`; `info` by default. Programs can also
+    /// change it with `set-log-level!`.
+    pub fn set_log_level(&mut self, level: LogLevel) {
+        self.log_level.set(level);
+    }
+
+    /// Chooses between text and JSON log lines; text by default.
+    pub fn set_log_format(&mut self, format: LogFormat) {
+        self.log_format = format;
+    }
+
+    /// Reports calls and statements to `hooks` from now on, in place of any
+    /// hooks set before.
+    pub fn set_hooks(&mut self, hooks: Box<dyn Hooks>) {
+        *self.hooks.get_mut() = vec![hooks];
+    }
+
+    /// Reports calls and statements to `hooks` from now on, after the hooks
+    /// already set.
+    pub fn add_hooks(&mut self, hooks: Box<dyn Hooks>) {
+        self.hooks.get_mut().push(hooks);
+    }
+
+    /// Removes the hooks set last and returns them.
+    pub fn take_hooks(&mut self) -> Option<Box<dyn Hooks>> {
+        self.hooks.get_mut().pop()
+    }
+
+    /// Tallies the values and scopes reachable from the global scope and the
+    /// loaded modules. Clone counts are left empty; see `heap::count_clones`.
+    pub fn heap_stats(&self) -> HeapStats {
+        let mut walk = HeapWalk::default();
+        walk.environment(&self.global);
+        for module in self.module_cache.borrow().values() {
+            walk.environment(&module.env);
+        }
+        walk.stats
+    }
+
+    /// Returns the installed builtins sorted by name.
+    pub fn builtins(&self) -> Vec<Rc<BuiltinFunction>> {
+        let mut builtins: Vec<_> = self
+            .global
+            .values
+            .borrow()
+            .values()
+            .filter_map(|value| match value {
+                Value::Builtin(builtin) => Some(Rc::clone(builtin)),
+                _ => None,
+            })
+            .collect();
+        builtins.sort_by(|a, b| a.name.cmp(&b.name));
+        builtins
+    }
+
+    /// Captures the current global bindings and module cache. Values are
+    /// immutable, so the snapshot shares them with the live interpreter.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            globals: self.global.values.borrow().clone(),
+            module_cache: self.module_cache.borrow().clone(),
+        }
+    }
+
+    /// Rolls the interpreter back to `snapshot`. Bindings defined since then
+    /// disappear and can be defined again; builtins and modules that were
+    /// already loaded are kept without being rebuilt or re-imported.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        // The global environment is updated in place because closures
+        // defined before the snapshot hold a reference to it.
+        *self.global.values.borrow_mut() = snapshot.globals.clone();
+        *self.module_cache.borrow_mut() = snapshot.module_cache.clone();
+        self.loading_modules.borrow_mut().clear();
     }
 
     pub fn eval_program(&mut self, program: &Program) -> LangResult<()> {
+        self.eval_program_value(program).map(|_| ())
+    }
+
+    /// Runs `program` like `eval_program` and returns the value of its last
+    /// statement when that statement is an expression, for `fip eval`.
+    pub fn eval_program_value(&mut self, program: &Program) -> LangResult<Option<Value>> {
+        self.eval_owned_program(program.clone())
+    }
+
+    /// Runs `program` like `eval_program_value`, resolving it in place
+    /// rather than a copy of it, for callers that have no further use for it.
+    pub fn eval_owned_program(&mut self, mut program: Program) -> LangResult<Option<Value>> {
+        resolve::resolve_program(&mut program);
+        self.call_depth.set(0);
+        self.steps.set(0);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.deadline
+            .set(self.limits.timeout.map(|timeout| Instant::now() + timeout));
+        self.hoist_functions(&program.statements, &self.global)?;
+        let mut last = None;
         for statement in &program.statements {
-            self.eval_statement(statement, Rc::clone(&self.global))?;
+            last = match statement {
+                Statement::Function(_) => None,
+                Statement::Expression(expr) => {
+                    self.notify(|hooks| hooks.on_statement(statement));
+                    Some(self.eval_expression(expr, Rc::clone(&self.global), Purity::Impure)?)
+                }
+                _ => {
+                    self.run_statement(statement, Rc::clone(&self.global))?;
+                    None
+                }
+            };
+        }
+        Ok(last)
+    }
+
+    /// Defines every top-level function before any other statement runs, so
+    /// a function can be called above its definition and functions can call
+    /// each other regardless of order. Defining a function evaluates nothing,
+    /// so hoisting cannot change what a program computes.
+    fn hoist_functions(&self, statements: &[Statement], env: &Rc<Environment>) -> LangResult<()> {
+        for statement in statements {
+            if let Statement::Function(_) = statement {
+                self.eval_statement(statement, Rc::clone(env))?;
+            }
         }
         Ok(())
     }
 
     fn eval_statement(&self, statement: &Statement, env: Rc<Environment>) -> LangResult<()> {
         match statement {
-            Statement::Assignment { pattern, expr } => {
+            Statement::Assignment { pattern, expr, .. } => {
                 let value = self.eval_expression(expr, Rc::clone(&env), Purity::Impure)?;
                 self.destructure_pattern(pattern, value, Rc::clone(&env))
             }
@@ -1833,36 +8079,36 @@ impl Interpreter {
                 params,
                 body,
                 impure,
+                ..
             }) => {
-                if *impure {
-                    if Self::find_impure_call(body).is_none() {
-                        return Err(LangError::Runtime(
-                            format!(
-                                "Function '{}' is marked impure but performs no impure operations",
-                                name
-                            ),
-                            None,
-                        ));
-                    }
-                } else if let Some(impure_call) = Self::find_impure_call(body) {
-                    return Err(LangError::Runtime(
-                        format!(
-                            "Function '{}' must be declared impure (end the name with '!') to call '{}'",
-                            name, impure_call
-                        ),
-                        None,
-                    ));
-                }
                 let func = FunctionValue {
                     name: name.clone(),
                     params: params.clone(),
-                    body: body.clone(),
+                    body: Rc::clone(body),
                     env: Rc::clone(&env),
                     impure: *impure,
                 };
                 env.define(name.clone(), Value::Function(Rc::new(func)))
             }
             Statement::Use(use_stmt) => self.eval_use_statement(use_stmt, env),
+            Statement::ConditionalUse {
+                condition,
+                then,
+                otherwise,
+            } => match self.eval_expression(condition, Rc::clone(&env), Purity::Pure)? {
+                Value::Boolean(true) => self.eval_use_statement(then, env),
+                Value::Boolean(false) => match otherwise {
+                    Some(otherwise) => self.eval_use_statement(otherwise, env),
+                    None => Ok(()),
+                },
+                other => Err(LangError::Runtime(
+                    format!(
+                        "Condition in 'when' import must be boolean, found {:?}",
+                        other
+                    ),
+                    None,
+                )),
+            },
             Statement::Export(_export_stmt) => {
                 // Export statements are handled during module evaluation
                 // They mark bindings for export but don't do anything at statement level
@@ -1950,8 +8196,9 @@ impl Interpreter {
         env: Rc<Environment>,
         purity: Purity,
     ) -> LangResult<Value> {
+        self.count_step()?;
         match expr {
-            Expression::Number(n) => Ok(Value::Number(*n)),
+            Expression::Number { value, .. } => Ok(Value::Number(*value)),
             Expression::String(template) => {
                 let value = self.eval_string_template(template, env, purity)?;
                 Ok(Value::String(value))
@@ -1959,48 +8206,55 @@ impl Interpreter {
             Expression::Boolean(value) => Ok(Value::Boolean(*value)),
             Expression::Null => Ok(Value::Null),
             Expression::Block(expressions) => self.eval_block(expressions, env, purity),
-            Expression::Lambda {
-                params,
-                body,
-                impure,
-            } => {
-                // Validate impure notation - same rules as named functions
-                if *impure {
-                    if Self::find_impure_call(body.as_ref()).is_none() {
-                        return Err(LangError::Runtime(
-                            "Anonymous function is marked impure but performs no impure operations"
-                                .to_string(),
-                            None,
-                        ));
-                    }
-                } else if let Some(impure_call) = Self::find_impure_call(body.as_ref()) {
+            Expression::Closure { lambda, captures } => {
+                let Expression::Lambda {
+                    params,
+                    body,
+                    impure,
+                    ..
+                } = lambda.as_ref()
+                else {
                     return Err(LangError::Runtime(
-                        format!(
-                            "Anonymous function must be declared impure (use '!') to call '{}'",
-                            impure_call
-                        ),
+                        "Internal error: closure without a lambda".to_string(),
                         None,
                     ));
-                }
+                };
+                // A closure keeps only what it reads from enclosing calls,
+                // not their whole scopes
+                let env = if captures.is_empty() {
+                    env.root()
+                } else {
+                    let values = captures
+                        .iter()
+                        .map(|slot| self.eval_local(slot, &env))
+                        .collect::<LangResult<Vec<_>>>()?;
+                    let names = captures.iter().map(|slot| slot.name.clone()).collect();
+                    Environment::with_slots(values, SlotNames::Captures(names), Some(env.root()))
+                };
                 let func = FunctionValue {
                     name: "<lambda>".to_string(),
                     params: params.clone(),
-                    body: *body.clone(),
-                    env: Rc::clone(&env),
+                    body: Rc::clone(body),
+                    env,
                     impure: *impure,
                 };
                 Ok(Value::Function(Rc::new(func)))
             }
+            Expression::Lambda { .. } => Err(LangError::Runtime(
+                "Internal error: lambda was not resolved before running".to_string(),
+                None,
+            )),
+            Expression::Local(slot) => self.eval_local(slot, &env),
             Expression::Object(fields) => {
                 let mut map = BTreeMap::new();
                 for field in fields {
                     match field {
                         ObjectField::Field { name, value } => {
                             let field_value =
-                                self.eval_expression(&value, Rc::clone(&env), purity)?;
+                                self.eval_expression(value, Rc::clone(&env), purity)?;
                             map.insert(name.clone(), field_value);
                         }
-                        ObjectField::Spread(expr) => {
+                        ObjectField::Spread(expr) | ObjectField::Base(expr) => {
                             let spread_value =
                                 self.eval_expression(expr, Rc::clone(&env), purity)?;
                             match spread_value {
@@ -2055,10 +8309,16 @@ impl Interpreter {
                 }
                 Ok(Value::List(values))
             }
-            Expression::PropertyAccess { object, property } => {
-                let target = self.eval_expression(object, Rc::clone(&env), purity)?;
-                self.eval_property_access(target, property)
+            Expression::PropertyAccess { .. } | Expression::Call { .. } => {
+                Ok(self.eval_chain(expr, env, purity)?.unwrap_or(Value::Null))
             }
+            Expression::Not(expr) => match self.eval_expression(expr, env, purity)? {
+                Value::Boolean(b) => Ok(Value::Boolean(!b)),
+                other => Err(LangError::Runtime(
+                    format!("Operand of not must be boolean, found {:?}", other),
+                    None,
+                )),
+            },
             Expression::Spread(_) => {
                 // Spread expressions are only valid inside objects and lists
                 // This should not be reached in normal evaluation
@@ -2068,25 +8328,97 @@ impl Interpreter {
                 ))
             }
             Expression::Identifier(name) => env.get(name).ok_or_else(|| {
-                LangError::Runtime(format!("Undefined identifier '{}'", name), None)
+                let denied = Capability::ALL.into_iter().find(|capability| {
+                    !self.capabilities.contains(capability)
+                        && capability.builtins().contains(&name.as_str())
+                });
+                match denied {
+                    Some(capability) => LangError::Runtime(
+                        format!(
+                            "Builtin '{}' needs the {} capability; run with --allow {}",
+                            name,
+                            capability.name(),
+                            capability.name()
+                        ),
+                        None,
+                    ),
+                    None => {
+                        let names = env.names();
+                        let hint = suggest::hint(name, names.iter().map(String::as_str));
+                        LangError::Runtime(format!("Undefined identifier '{}'{}", name, hint), None)
+                    }
+                }
             }),
+            Expression::Binary {
+                left,
+                op: BinaryOperator::Coalesce,
+                right,
+            } => match self.eval_expression(left, Rc::clone(&env), purity)? {
+                Value::Null => self.eval_expression(right, env, purity),
+                value => Ok(value),
+            },
+            Expression::Binary {
+                left,
+                op: BinaryOperator::PipeForward,
+                right,
+            } => {
+                let value = self.eval_expression(left, Rc::clone(&env), purity)?;
+                let callee = self.eval_expression(right, env, purity)?;
+                self.call_callable(callee, vec![value], purity)
+            }
+            Expression::Binary { left, op, right } => {
+                let left_value = self.eval_expression(left, Rc::clone(&env), purity)?;
+                let right_value = self.eval_expression(right, env, purity)?;
+                self.eval_binary(op, left_value, right_value)
+            }
+        }
+    }
+
+    /// Evaluates a chain of property accesses and calls. Returns `None` when
+    /// a `?.` found a null object, which skips the rest of the chain.
+    fn eval_chain(
+        &self,
+        expr: &Expression,
+        env: Rc<Environment>,
+        purity: Purity,
+    ) -> LangResult<Option<Value>> {
+        match expr {
+            Expression::PropertyAccess {
+                object,
+                property,
+                optional,
+            } => match self.eval_chain(object, env, purity)? {
+                None => Ok(None),
+                Some(Value::Null) if *optional => Ok(None),
+                Some(target) => self.eval_property_access(target, property).map(Some),
+            },
             Expression::Call { callee, args } => {
-                let callee_value =
-                    self.eval_expression(callee.as_ref(), Rc::clone(&env), purity)?;
+                let Some(callee_value) = self.eval_chain(callee, Rc::clone(&env), purity)? else {
+                    return Ok(None);
+                };
                 let evaluated_args = args
                     .iter()
                     .map(|arg| self.eval_expression(arg, Rc::clone(&env), purity))
                     .collect::<LangResult<Vec<_>>>()?;
                 self.call_callable(callee_value, evaluated_args, purity)
+                    .map(Some)
             }
-            Expression::Binary { left, op, right } => {
-                let left_value = self.eval_expression(left, Rc::clone(&env), purity)?;
-                let right_value = self.eval_expression(right, env, purity)?;
-                self.eval_binary(op, left_value, right_value)
-            }
+            other => self.eval_expression(other, env, purity).map(Some),
         }
     }
 
+    fn eval_local(&self, slot: &Slot, env: &Environment) -> LangResult<Value> {
+        env.slot(slot).ok_or_else(|| {
+            LangError::Runtime(
+                format!(
+                    "Internal error: '{}' is not in its resolved slot",
+                    slot.name
+                ),
+                None,
+            )
+        })
+    }
+
     fn eval_block(
         &self,
         expressions: &[Expression],
@@ -2105,14 +8437,13 @@ impl Interpreter {
             let value = self.eval_expression(expr, Rc::clone(&env), purity)?;
             current = match value {
                 Value::Function(func) => {
-                    let mut args = Vec::with_capacity(1);
-                    args.push(current);
-                    self.call_callable(Value::Function(Rc::clone(&func)), args, purity)?
+                    self.call_callable(Value::Function(Rc::clone(&func)), vec![current], purity)?
                 }
                 Value::Builtin(builtin) => {
-                    let mut args = Vec::with_capacity(1);
-                    args.push(current);
-                    self.call_callable(Value::Builtin(Rc::clone(&builtin)), args, purity)?
+                    self.call_callable(Value::Builtin(Rc::clone(&builtin)), vec![current], purity)?
+                }
+                partial @ Value::Partial { .. } => {
+                    self.call_callable(partial, vec![current], purity)?
                 }
                 other => other,
             };
@@ -2165,12 +8496,17 @@ impl Interpreter {
                 let result = !Self::values_equal(&left, &right);
                 Ok(Value::Boolean(result))
             }
-            BinaryOperator::LessThan => self.eval_comparison(left, right, |l, r| l < r),
-            BinaryOperator::LessThanEq => self.eval_comparison(left, right, |l, r| l <= r),
-            BinaryOperator::GreaterThan => self.eval_comparison(left, right, |l, r| l > r),
-            BinaryOperator::GreaterThanEq => self.eval_comparison(left, right, |l, r| l >= r),
+            BinaryOperator::LessThan => self.eval_comparison(left, right, Ordering::is_lt),
+            BinaryOperator::LessThanEq => self.eval_comparison(left, right, Ordering::is_le),
+            BinaryOperator::GreaterThan => self.eval_comparison(left, right, Ordering::is_gt),
+            BinaryOperator::GreaterThanEq => self.eval_comparison(left, right, Ordering::is_ge),
             BinaryOperator::And => self.eval_logical("and", left, right, true),
             BinaryOperator::Or => self.eval_logical("or", left, right, false),
+            BinaryOperator::Coalesce => Ok(match left {
+                Value::Null => right,
+                value => value,
+            }),
+            BinaryOperator::PipeForward => self.call_callable(right, vec![left], Purity::Pure),
         }
     }
 
@@ -2222,10 +8558,38 @@ impl Interpreter {
 
     fn eval_comparison<F>(&self, left: Value, right: Value, cmp: F) -> LangResult<Value>
     where
-        F: FnOnce(i64, i64) -> bool,
+        F: FnOnce(Ordering) -> bool,
     {
-        let (l, r) = self.expect_numbers("comparison", left, right)?;
-        Ok(Value::Boolean(cmp(l, r)))
+        let ordering = Self::compare_values(&left, &right)?;
+        Ok(Value::Boolean(cmp(ordering)))
+    }
+
+    /// The ordering shared by the comparison operators and `sort`: numbers
+    /// by value, strings by code point, and lists element by element with a
+    /// shorter prefix first. Other values, or two values of different
+    /// types, cannot be ordered.
+    fn compare_values(left: &Value, right: &Value) -> LangResult<Ordering> {
+        match (left, right) {
+            (Value::Number(l), Value::Number(r)) => Ok(l.cmp(r)),
+            (Value::String(l), Value::String(r)) => Ok(l.cmp(r)),
+            (Value::Bytes(l), Value::Bytes(r)) => Ok(l.cmp(r)),
+            (Value::List(l), Value::List(r)) => {
+                for (a, b) in l.iter().zip(r) {
+                    let ordering = Self::compare_values(a, b)?;
+                    if ordering != Ordering::Equal {
+                        return Ok(ordering);
+                    }
+                }
+                Ok(l.len().cmp(&r.len()))
+            }
+            (l, r) => Err(LangError::Runtime(
+                format!(
+                    "Cannot compare {:?} with {:?}; only two numbers, two strings, two byte strings, or two lists can be ordered",
+                    l, r
+                ),
+                None,
+            )),
+        }
     }
 
     fn eval_logical(
@@ -2287,232 +8651,318 @@ impl Interpreter {
         }
     }
 
-    fn call_callable(&self, callee: Value, args: Vec<Value>, purity: Purity) -> LangResult<Value> {
-        match callee {
-            Value::Function(func) => {
-                // Check if this is a curried builtin function
-                if let (Some(captured_args_value), Some(builtin_value)) = (
-                    func.env.get("__curried_args__"),
-                    func.env.get("__curried_builtin__"),
-                ) {
-                    // This is a curried builtin function
-                    let captured_args = match captured_args_value {
-                        Value::List(args) => args,
-                        _ => {
-                            return Err(LangError::Runtime(
-                                "Internal error: invalid curried builtin state".to_string(),
-                                None,
-                            ));
-                        }
-                    };
+    fn check_stability(&self, builtin: &BuiltinFunction) -> LangResult<()> {
+        match builtin.stability {
+            Stability::Stable => Ok(()),
+            Stability::Experimental if self.enable_experimental => Ok(()),
+            Stability::Experimental => Err(LangError::Runtime(
+                format!(
+                    "Builtin '{}' is experimental; run with --enable-experimental to use it",
+                    builtin.name
+                ),
+                None,
+            )),
+            Stability::Deprecated { replacement } => {
+                if self
+                    .warned_deprecated
+                    .borrow_mut()
+                    .insert(builtin.name.clone())
+                {
+                    // A warning that cannot be written must not stop the program
+                    let _ = writeln!(
+                        self.error_output.borrow_mut(),
+                        "Warning: builtin '{}' is deprecated; use '{}' instead",
+                        builtin.name,
+                        replacement
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
 
-                    let builtin = match builtin_value {
-                        Value::Builtin(b) => b,
-                        _ => {
-                            return Err(LangError::Runtime(
-                                "Internal error: invalid builtin in curried function".to_string(),
-                                None,
-                            ));
-                        }
-                    };
+    fn check_deterministic(&self, builtin: &BuiltinFunction) -> LangResult<()> {
+        if self.seed.get().is_some() && Capability::Net.builtins().contains(&builtin.name.as_str())
+        {
+            return Err(LangError::Runtime(
+                format!(
+                    "Builtin '{}' reaches the network, which deterministic runs do not allow",
+                    builtin.name
+                ),
+                None,
+            ));
+        }
+        Ok(())
+    }
 
-                    // Combine captured args with new args
-                    let mut combined = captured_args;
-                    combined.extend(args);
-
-                    // Check if we have enough arguments now
-                    if combined.len() < builtin.params.len() {
-                        // Still not enough - create another curried function
-                        let remaining_params = builtin.params[combined.len()..].to_vec();
-                        let curried_env = Environment::new(None);
-                        curried_env.define(
-                            "__curried_builtin__".to_string(),
-                            Value::Builtin(Rc::clone(&builtin)),
-                        )?;
-                        curried_env
-                            .define("__curried_args__".to_string(), Value::List(combined))?;
-
-                        let curried_func = FunctionValue {
-                            name: format!("{} (curried)", builtin.name),
-                            params: remaining_params,
-                            body: Expression::Identifier("__placeholder__".to_string()),
-                            env: curried_env,
-                            impure: builtin.impure,
-                        };
+    /// The next value of the seeded generator (SplitMix64), or `None`
+    /// outside deterministic mode.
+    fn next_seeded(&self) -> Option<u64> {
+        let mut state = self.seed.get()?;
+        let value = property::splitmix64(&mut state);
+        self.seed.set(Some(state));
+        Some(value)
+    }
 
-                        return Ok(Value::Function(Rc::new(curried_func)));
-                    }
+    /// An answer between 0 and `bound` for the running generator.
+    fn draw(&self, bound: u64) -> LangResult<u64> {
+        match self.choices.borrow_mut().as_mut() {
+            Some(choices) => Ok(choices.draw(bound)),
+            None => Err(LangError::Runtime(
+                "Generators can only run inside for-all".to_string(),
+                None,
+            )),
+        }
+    }
 
-                    // Now we have enough arguments - call the builtin
-                    if builtin.impure && !purity.allow_impure() {
-                        return Err(LangError::Runtime(
-                            format!(
-                                "Cannot call impure builtin '{}' from pure context",
-                                builtin.name
-                            ),
-                            None,
-                        ));
-                    }
+    /// A length for the running generator, bounded by the case's size.
+    fn draw_length(&self) -> LangResult<usize> {
+        let size = match self.choices.borrow().as_ref() {
+            Some(choices) => choices.size,
+            None => 0,
+        };
+        Ok(self.draw(size)? as usize)
+    }
 
-                    let result = (builtin.func)(self, &combined)?;
-                    if builtin.name.ends_with('?') && !matches!(result, Value::Boolean(_)) {
-                        return Err(LangError::Runtime(
-                            format!("Builtin '{}' must return a boolean value", builtin.name),
-                            None,
-                        ));
-                    }
-                    return Ok(result);
-                }
+    /// Generates one value and checks the property against it.
+    fn run_case(&self, generator: &Value, property: &Value, choices: Choices) -> LangResult<Case> {
+        let outer = self.choices.replace(Some(choices));
+        let generated = self.call_callable(generator.clone(), vec![], Purity::Pure);
+        let choices = self
+            .choices
+            .replace(outer)
+            .expect("choices are set while generating");
+        let value = generated?;
+        let failure = match self.call_callable(property.clone(), vec![value.clone()], Purity::Pure)
+        {
+            Ok(Value::Boolean(true)) => None,
+            Ok(Value::Boolean(false)) => Some(None),
+            Ok(other) => {
+                return Err(LangError::Runtime(
+                    format!(
+                        "Builtin 'for-all' property must return a boolean, found {}",
+                        pretty(&other)
+                    ),
+                    None,
+                ))
+            }
+            // A property that raises fails like one that returns false
+            Err(LangError::Runtime(message, _)) => Some(Some(message)),
+            Err(other) => return Err(other),
+        };
+        Ok(Case {
+            value,
+            answers: choices.used,
+            size: choices.size,
+            failure,
+        })
+    }
 
-                // Check if this is a curried function (has captured args)
-                let (original_func, combined_args) = if let Some(captured_args_value) =
-                    func.env.get("__curried_args__")
-                {
-                    // This is a curried function - combine captured args with new args
-                    let captured_args = match captured_args_value {
-                        Value::List(args) => args,
-                        _ => {
-                            return Err(LangError::Runtime(
-                                "Internal error: invalid curried function state".to_string(),
-                                None,
-                            ));
-                        }
-                    };
+    /// Replays ever simpler answers while the property keeps failing.
+    fn shrink(
+        &self,
+        generator: &Value,
+        property: &Value,
+        mut best: Case,
+    ) -> LangResult<(Case, usize)> {
+        let mut shrinks = 0;
+        let mut budget = property::SHRINK_BUDGET;
+        'improve: loop {
+            for candidate in property::candidates(&best.answers) {
+                if budget == 0 {
+                    break 'improve;
+                }
+                budget -= 1;
+                let case =
+                    self.run_case(generator, property, Choices::replay(candidate, best.size))?;
+                if case.failure.is_some() && property::simpler(&case.answers, &best.answers) {
+                    best = case;
+                    shrinks += 1;
+                    continue 'improve;
+                }
+            }
+            break;
+        }
+        Ok((best, shrinks))
+    }
 
-                    let original_func_value =
-                        func.env.get("__curried_original__").ok_or_else(|| {
-                            LangError::Runtime(
-                                "Internal error: curried function missing original".to_string(),
-                                None,
-                            )
-                        })?;
+    fn count_step(&self) -> LangResult<()> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if let Some(max) = self.limits.max_steps {
+            if steps > max {
+                return Err(LangError::LimitExceeded(Limit::Steps(max)));
+            }
+        }
+        // Reading the clock costs more than a step, so only look every so often.
+        if let (Some(deadline), Some(timeout)) = (self.deadline.get(), self.limits.timeout) {
+            if steps.is_multiple_of(1024) && Instant::now() >= deadline {
+                return Err(LangError::LimitExceeded(Limit::Timeout(timeout)));
+            }
+        }
+        Ok(())
+    }
 
-                    let original_func = match original_func_value {
-                        Value::Function(f) => f,
-                        _ => {
-                            return Err(LangError::Runtime(
-                                "Internal error: invalid original function in curried function"
-                                    .to_string(),
-                                None,
-                            ));
-                        }
-                    };
+    fn call_callable(&self, callee: Value, args: Vec<Value>, purity: Purity) -> LangResult<Value> {
+        let hooked = !self.hooks.borrow().is_empty();
+        // A partial is reported as a call of the function it completes, with
+        // all of its arguments
+        let (callee, args) = match callee {
+            Value::Partial { callee, applied } if hooked => {
+                let mut combined = applied.to_vec();
+                combined.extend(args);
+                (Value::clone(&callee), combined)
+            }
+            callee => (callee, args),
+        };
+        let name = match &callee {
+            Value::Function(func) if hooked => func.name.clone(),
+            Value::Builtin(builtin) if hooked => builtin.name.clone(),
+            _ => return self.apply_callable(callee, args, purity),
+        };
+        let depth = self.hook_depth.get();
+        self.notify(|hooks| hooks.on_call(&name, &args, depth));
+        self.hook_depth.set(depth + 1);
+        let result = self.apply_callable(callee, args, purity);
+        self.hook_depth.set(depth);
+        self.notify(|hooks| hooks.on_return(&name, result.as_ref(), depth));
+        result
+    }
 
-                    // Combine captured args with new args
-                    let mut combined = captured_args;
-                    combined.extend(args);
+    /// Passes an event to every hook, in the order they were added.
+    fn notify(&self, event: impl Fn(&mut dyn Hooks)) {
+        for hooks in self.hooks.borrow_mut().iter_mut() {
+            event(hooks.as_mut());
+        }
+    }
 
-                    (original_func, combined)
-                } else {
-                    // Not a curried function - handle currying if needed
-                    if args.len() < func.params.len() {
-                        // Create a curried function that captures the provided arguments
-                        let captured_args = args;
-                        let remaining_params = func.params[captured_args.len()..].to_vec();
-
-                        // Create an environment for the curried function that stores:
-                        // - The original function
-                        // - The captured arguments
-                        let curried_env = Environment::new(Some(Rc::clone(&func.env)));
-
-                        // Store the original function and captured args in the environment
-                        // We'll use special names that won't conflict with user code
-                        curried_env.define(
-                            "__curried_original__".to_string(),
-                            Value::Function(Rc::clone(&func)),
-                        )?;
-
-                        // Store captured arguments as a list in the environment
-                        curried_env
-                            .define("__curried_args__".to_string(), Value::List(captured_args))?;
-
-                        // Create a curried function that captures the original function and args
-                        // When called, it will combine captured args with new args and call the original
-                        let curried_func = FunctionValue {
-                            name: format!("{} (curried)", func.name),
-                            params: remaining_params,
-                            body: func.body.clone(),
-                            env: curried_env,
-                            impure: func.impure,
-                        };
+    /// Runs a top-level statement, reporting it to the hooks first.
+    fn run_statement(&self, statement: &Statement, env: Rc<Environment>) -> LangResult<()> {
+        self.notify(|hooks| hooks.on_statement(statement));
+        self.eval_statement(statement, env)
+    }
 
-                        return Ok(Value::Function(Rc::new(curried_func)));
-                    }
+    /// Writes a line of program output, as `log!` and `trace!` do.
+    fn print(&self, line: fmt::Arguments) -> LangResult<()> {
+        writeln!(self.output.borrow_mut(), "{}", line)
+            .map_err(|err| LangError::Runtime(format!("Failed to write output: {}", err), None))
+    }
 
-                    // Normal function call - use the function as-is
-                    (Rc::clone(&func), args)
-                };
+    /// Writes a log line for `log-info!` and friends, unless `level` is
+    /// below the configured one. An object entry is the `message` field
+    /// with the other fields attached. The time is the epoch in
+    /// deterministic mode and is left out when the clock capability is not
+    /// granted.
+    fn write_log(&self, level: LogLevel, args: &[Value]) -> LangResult<Value> {
+        let name = format!("log-{}!", level.name());
+        if args.len() != 1 {
+            return Err(LangError::Runtime(
+                format!("Builtin '{}' expects exactly 1 argument", name),
+                None,
+            ));
+        }
+        if level < self.log_level.get() {
+            return Ok(Value::Null);
+        }
+        let (message, fields) = match &args[0] {
+            Value::Object(fields) => {
+                let mut fields = fields.clone();
+                let message = fields.remove("message").ok_or_else(|| {
+                    LangError::Runtime(
+                        format!("Builtin '{}' expected a 'message' field", name),
+                        None,
+                    )
+                })?;
+                (self.value_to_string(&message)?, fields)
+            }
+            other => (self.value_to_string(other)?, BTreeMap::new()),
+        };
+        let time = if self.seed.get().is_some() {
+            Some(0)
+        } else if self.capabilities.contains(&Capability::Clock) {
+            time::now_millis().ok()
+        } else {
+            None
+        };
+        let line = logging::format_line(self.log_format, level, time, &message, &fields)
+            .map_err(|err| LangError::Runtime(format!("Builtin '{}': {}", name, err), None))?;
+        writeln!(self.error_output.borrow_mut(), "{}", line)
+            .map_err(|err| LangError::Runtime(format!("Failed to write log: {}", err), None))?;
+        Ok(Value::Null)
+    }
 
-                // If too many arguments, return an error
-                if combined_args.len() > original_func.params.len() {
+    fn apply_callable(&self, callee: Value, args: Vec<Value>, purity: Purity) -> LangResult<Value> {
+        match callee {
+            Value::Partial { callee, applied } => {
+                let mut combined = applied.to_vec();
+                combined.extend(args);
+                self.apply_callable(Value::clone(&callee), combined, purity)
+            }
+            Value::Function(func) => {
+                // Too few arguments: wait for the rest
+                if args.len() < func.params.len() {
+                    return Ok(Value::partial(Value::Function(func), args));
+                }
+                if args.len() > func.params.len() {
                     return Err(LangError::Runtime(
                         format!(
                             "Function '{}' expected {} arguments but received {}",
-                            original_func.name,
-                            original_func.params.len(),
-                            combined_args.len()
+                            func.name,
+                            func.params.len(),
+                            args.len()
                         ),
                         None,
                     ));
                 }
 
-                // If still not enough arguments, create another curried function
-                if combined_args.len() < original_func.params.len() {
-                    let captured_args = combined_args;
-                    let remaining_params = original_func.params[captured_args.len()..].to_vec();
-
-                    let curried_env = Environment::new(Some(Rc::clone(&original_func.env)));
-                    curried_env.define(
-                        "__curried_original__".to_string(),
-                        Value::Function(Rc::clone(&original_func)),
-                    )?;
-                    curried_env
-                        .define("__curried_args__".to_string(), Value::List(captured_args))?;
-
-                    let curried_func = FunctionValue {
-                        name: format!("{} (curried)", original_func.name),
-                        params: remaining_params,
-                        body: original_func.body.clone(),
-                        env: curried_env,
-                        impure: original_func.impure,
-                    };
-
-                    return Ok(Value::Function(Rc::new(curried_func)));
-                }
-
-                if original_func.impure && !purity.allow_impure() {
+                if func.impure && !purity.allow_impure() {
                     return Err(LangError::Runtime(
                         format!(
                             "Cannot call impure function '{}' from pure context",
-                            original_func.name
+                            func.name
                         ),
                         None,
                     ));
                 }
 
-                let call_env = Environment::new(Some(Rc::clone(&original_func.env)));
-                for (param, value) in original_func.params.iter().zip(combined_args.into_iter()) {
-                    call_env.define(param.clone(), value)?;
-                }
+                let call_env = Environment::with_slots(
+                    args,
+                    SlotNames::Params(Rc::clone(&func)),
+                    Some(Rc::clone(&func.env)),
+                );
 
-                let next_purity = if original_func.impure {
+                let next_purity = if func.impure {
                     Purity::Impure
                 } else {
                     Purity::Pure
                 };
-                let result = self.eval_expression(&original_func.body, call_env, next_purity)?;
-                if original_func.name.ends_with('?') && !matches!(result, Value::Boolean(_)) {
+                let depth = self.call_depth.get() + 1;
+                if let Some(max) = self.limits.max_call_depth {
+                    if depth > max {
+                        return Err(LangError::LimitExceeded(Limit::CallDepth(max)));
+                    }
+                }
+                self.call_depth.set(depth);
+                let result = self.eval_expression(&func.body, call_env, next_purity);
+                self.call_depth.set(depth - 1);
+                let result = result?;
+                if func.name.ends_with('?') && !matches!(result, Value::Boolean(_)) {
                     return Err(LangError::Runtime(
-                        format!(
-                            "Function '{}' must return a boolean value",
-                            original_func.name
-                        ),
+                        format!("Function '{}' must return a boolean value", func.name),
                         None,
                     ));
                 }
                 Ok(result)
             }
             Value::Builtin(builtin) => {
+                // Too few arguments: wait for the rest. Nothing runs yet, so
+                // the checks below wait for the call that completes it.
+                if args.len() < builtin.params.len() {
+                    return Ok(Value::partial(Value::Builtin(builtin), args));
+                }
+
+                self.check_stability(&builtin)?;
+                self.check_deterministic(&builtin)?;
                 if builtin.impure && !purity.allow_impure() {
                     return Err(LangError::Runtime(
                         format!(
@@ -2523,36 +8973,6 @@ impl Interpreter {
                     ));
                 }
 
-                // Handle currying for builtin functions
-                if args.len() < builtin.params.len() {
-                    // Create a curried function that captures the provided arguments
-                    let captured_args = args;
-                    let remaining_params = builtin.params[captured_args.len()..].to_vec();
-
-                    // Create an environment for the curried function
-                    let curried_env = Environment::new(None);
-
-                    // Store the original builtin and captured args
-                    curried_env.define(
-                        "__curried_builtin__".to_string(),
-                        Value::Builtin(Rc::clone(&builtin)),
-                    )?;
-                    curried_env
-                        .define("__curried_args__".to_string(), Value::List(captured_args))?;
-
-                    // Create a curried function that will combine args when called
-                    let curried_func = FunctionValue {
-                        name: format!("{} (curried)", builtin.name),
-                        params: remaining_params,
-                        body: Expression::Identifier("__placeholder__".to_string()), // Will be handled specially
-                        env: curried_env,
-                        impure: builtin.impure,
-                    };
-
-                    return Ok(Value::Function(Rc::new(curried_func)));
-                }
-
-                // Call the builtin with all required arguments
                 let result = (builtin.func)(self, &args)?;
                 if builtin.name.ends_with('?') && !matches!(result, Value::Boolean(_)) {
                     return Err(LangError::Runtime(
@@ -2569,68 +8989,519 @@ impl Interpreter {
         }
     }
 
-    fn find_impure_call(expr: &Expression) -> Option<String> {
-        match expr {
-            Expression::Call { callee, args } => {
-                if let Some(name) = Self::identifier_name(callee.as_ref()) {
-                    if name.ends_with('!') {
-                        return Some(name.to_string());
-                    }
-                }
-                Self::find_impure_call(callee.as_ref())
-                    .or_else(|| args.iter().find_map(|arg| Self::find_impure_call(arg)))
+    /// `map` and `map!`: calls `fn` on every element with the given purity.
+    fn map_list(&self, builtin: &str, args: &[Value], purity: Purity) -> LangResult<Value> {
+        if args.len() != 2 {
+            return Err(LangError::Runtime(
+                format!("Builtin '{}' expects 2 arguments (fn, list)", builtin),
+                None,
+            ));
+        }
+        let func = args[0].clone();
+        let list = match &args[1] {
+            Value::List(items) => items.clone(),
+            Value::Lazy(sequence) => self.force_sequence(sequence)?,
+            other => {
+                return Err(LangError::Runtime(
+                    format!(
+                        "Builtin '{}' expected list as second argument, found {:?}",
+                        builtin, other
+                    ),
+                    None,
+                ))
             }
-            Expression::Identifier(name) => {
-                if name.ends_with('!') {
-                    Some(name.clone())
-                } else {
-                    None
+        };
+        let mut result = Vec::with_capacity(list.len());
+        for item in list {
+            result.push(self.call_callable(func.clone(), vec![item], purity)?);
+        }
+        Ok(Value::List(result))
+    }
+
+    /// `filter` and `filter!`: keeps the elements the predicate, called with
+    /// the given purity, returns true for.
+    fn filter_list(&self, builtin: &str, args: &[Value], purity: Purity) -> LangResult<Value> {
+        if args.len() != 2 {
+            return Err(LangError::Runtime(
+                format!(
+                    "Builtin '{}' expects 2 arguments (predicate, list)",
+                    builtin
+                ),
+                None,
+            ));
+        }
+        let predicate = args[0].clone();
+        let list = match &args[1] {
+            Value::List(items) => items.clone(),
+            Value::Lazy(sequence) => self.force_sequence(sequence)?,
+            other => {
+                return Err(LangError::Runtime(
+                    format!(
+                        "Builtin '{}' expected list as second argument, found {:?}",
+                        builtin, other
+                    ),
+                    None,
+                ))
+            }
+        };
+        let mut result = Vec::new();
+        for item in list {
+            match self.call_callable(predicate.clone(), vec![item.clone()], purity)? {
+                Value::Boolean(true) => result.push(item),
+                Value::Boolean(false) => {}
+                other => {
+                    return Err(LangError::Runtime(
+                        format!("Filter predicate must return boolean, found {:?}", other),
+                        None,
+                    ))
                 }
             }
-            Expression::Binary { left, right, .. } => {
-                Self::find_impure_call(left).or_else(|| Self::find_impure_call(right))
+        }
+        Ok(Value::List(result))
+    }
+
+    /// Maps the function at the front of `package` over the values after it,
+    /// in a fresh interpreter, and packs the results.
+    fn map_in_worker(package: &Package, settings: WorkerSettings) -> LangResult<Package> {
+        let worker = Interpreter::worker(settings);
+        let mut values = worker.unpack(package)?.into_iter();
+        let func = values.next().expect("the function comes first");
+        let mut packer = Packer::default();
+        for item in values {
+            let mapped = worker.call_callable(func.clone(), vec![item], Purity::Pure)?;
+            packer.push(&mapped)?;
+        }
+        Ok(packer.package)
+    }
+
+    /// The number of a channel made by `channel!`.
+    fn channel_argument(builtin: &str, value: &Value) -> LangResult<usize> {
+        match value {
+            Value::Channel(id) => Ok(*id),
+            _ => Err(LangError::Runtime(
+                format!(
+                    "Builtin '{}' expected a channel from channel!, found {:?}",
+                    builtin, value
+                ),
+                None,
+            )),
+        }
+    }
+
+    /// The result of a task started by `spawn!`, waiting for it to finish
+    /// the first time. Later waits get a fresh copy of the same result.
+    fn await_task(&self, task: &Value) -> LangResult<Value> {
+        let id = match task {
+            Value::Task(id) => Some(*id),
+            _ => None,
+        };
+        let mut tasks = self.tasks.borrow_mut();
+        let Some(slot) = id.and_then(|id| tasks.get_mut(id)) else {
+            return Err(LangError::Runtime(
+                format!(
+                    "Builtin 'await-all!' expected a task from spawn!, found {:?}",
+                    task
+                ),
+                None,
+            ));
+        };
+        let result = match std::mem::replace(slot, Task::Finished(Err(String::new()))) {
+            Task::Running(handle) => match handle.join() {
+                Ok(Ok(package)) => Ok(package),
+                Ok(Err(LangError::Runtime(message, _))) => Err(message),
+                Ok(Err(err)) => Err(err.to_string()),
+                Err(_) => Err("Task panicked".to_string()),
+            },
+            Task::Finished(result) => result,
+        };
+        *slot = Task::Finished(result.clone());
+        drop(tasks);
+        match result {
+            Ok(package) => Ok(self.unpack(&package)?.remove(0)),
+            Err(message) => Err(LangError::Runtime(message, None)),
+        }
+    }
+
+    /// The settings a worker started by this interpreter runs with.
+    fn worker_settings(&self) -> WorkerSettings {
+        WorkerSettings {
+            entry_point_dir: self.entry_point_dir.clone(),
+            enable_experimental: self.enable_experimental,
+            limits: self.limits,
+            seed: self.seed.get(),
+            capabilities: Capability::ALL
+                .into_iter()
+                .filter(|capability| self.capabilities.contains(capability))
+                .filter(|capability| !(self.custom_file_system && *capability == Capability::Fs))
+                .collect(),
+            log_level: self.log_level.get(),
+            log_format: self.log_format,
+            channels: Arc::clone(&self.channels),
+        }
+    }
+
+    /// A fresh interpreter for a worker thread.
+    fn worker(settings: WorkerSettings) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.entry_point_dir = settings.entry_point_dir;
+        interpreter.enable_experimental = settings.enable_experimental;
+        interpreter.limits = settings.limits;
+        interpreter.seed.set(settings.seed);
+        interpreter.set_capabilities(&settings.capabilities);
+        interpreter.log_level.set(settings.log_level);
+        interpreter.log_format = settings.log_format;
+        interpreter.channels = settings.channels;
+        interpreter
+    }
+
+    /// Rebuilds the values of `package` in this interpreter. Its functions
+    /// are rebuilt first, each with a top-level scope of its own holding
+    /// every binding it reads, builtins included, so functions that call
+    /// each other find each other.
+    fn unpack(&self, package: &Package) -> LangResult<Vec<Value>> {
+        let mut functions = Vec::with_capacity(package.functions.len());
+        for function in &package.functions {
+            let (params, body) = portable::parse_lambda(&function.source)?;
+            functions.push(Rc::new(FunctionValue {
+                name: function.name.clone(),
+                params,
+                body,
+                env: Environment::new(None),
+                impure: function.impure,
+            }));
+        }
+        for (func, function) in functions.iter().zip(&package.functions) {
+            for (name, value) in &function.bindings {
+                func.env
+                    .define(name.clone(), self.unpack_value(value, &functions)?)?;
             }
-            Expression::Block(expressions) => expressions
-                .iter()
-                .find_map(|expr| Self::find_impure_call(expr)),
-            Expression::Lambda { body, .. } => Self::find_impure_call(body.as_ref()),
-            Expression::String(template) => Self::find_impure_call_in_template(template),
-            Expression::Object(fields) => fields.iter().find_map(|field| match field {
-                ObjectField::Field { value, .. } => Self::find_impure_call(value),
-                ObjectField::Spread(expr) => Self::find_impure_call(expr),
-            }),
-            Expression::List(elements) => elements
-                .iter()
-                .find_map(|expr| Self::find_impure_call(expr)),
-            Expression::Spread(expr) => Self::find_impure_call(expr.as_ref()),
-            Expression::PropertyAccess { object, .. } => Self::find_impure_call(object),
-            Expression::Boolean(_) | Expression::Number(_) | Expression::Null => None,
         }
+        package
+            .values
+            .iter()
+            .map(|value| self.unpack_value(value, &functions))
+            .collect()
     }
 
-    fn find_impure_call_in_template(template: &StringTemplate) -> Option<String> {
-        for segment in &template.segments {
-            if let StringSegment::Expr(expr) = segment {
-                if let Some(name) = Self::find_impure_call(expr) {
-                    return Some(name);
+    fn unpack_value(&self, value: &Portable, functions: &[Rc<FunctionValue>]) -> LangResult<Value> {
+        let copy = |values: &[Portable]| {
+            values
+                .iter()
+                .map(|value| self.unpack_value(value, functions))
+                .collect::<LangResult<Vec<_>>>()
+        };
+        Ok(match value {
+            Portable::Number(n) => Value::Number(*n),
+            Portable::String(text) => Value::String(text.clone()),
+            Portable::Bytes(bytes) => Value::Bytes(bytes.clone()),
+            Portable::Boolean(b) => Value::Boolean(*b),
+            Portable::List(items) => Value::List(copy(items)?),
+            Portable::Set(items) => Value::Set(copy(items)?),
+            Portable::Map(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| {
+                        Ok((
+                            self.unpack_value(key, functions)?,
+                            self.unpack_value(value, functions)?,
+                        ))
+                    })
+                    .collect::<LangResult<_>>()?,
+            ),
+            Portable::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), self.unpack_value(value, functions)?)))
+                    .collect::<LangResult<_>>()?,
+            ),
+            Portable::Function(index) => Value::Function(Rc::clone(&functions[*index])),
+            Portable::Builtin(name) => match self.global.get(name) {
+                Some(builtin @ Value::Builtin(_)) => builtin,
+                _ => {
+                    return Err(LangError::Runtime(
+                        format!("Builtin '{}' is not available on this thread", name),
+                        None,
+                    ))
                 }
+            },
+            Portable::Partial { callee, applied } => Value::Partial {
+                callee: Rc::new(self.unpack_value(callee, functions)?),
+                applied: copy(applied)?.into(),
+            },
+            Portable::Channel(id) => Value::Channel(*id),
+            Portable::Null => Value::Null,
+            Portable::Unit => Value::Unit,
+        })
+    }
+
+    /// Reads a list argument of a builtin, forcing lazy sequences.
+    fn list_argument(
+        &self,
+        builtin: &str,
+        position: &str,
+        value: &Value,
+    ) -> LangResult<Vec<Value>> {
+        match value {
+            Value::List(items) | Value::Set(items) => Ok(items.clone()),
+            Value::Lazy(sequence) => self.force_sequence(sequence),
+            other => Err(LangError::Runtime(
+                format!(
+                    "Builtin '{}' expected list as {} argument, found {:?}",
+                    builtin, position, other
+                ),
+                None,
+            )),
+        }
+    }
+
+    /// Reads a set argument of a builtin. Lists and lazy sequences are turned
+    /// into sets; sets are borrowed so lookups do not copy them.
+    fn set_argument<'v>(
+        &self,
+        builtin: &str,
+        position: &str,
+        value: &'v Value,
+    ) -> LangResult<Cow<'v, [Value]>> {
+        if let Value::Set(items) = value {
+            return Ok(Cow::Borrowed(items));
+        }
+        let items = self.list_argument(builtin, position, value)?;
+        set::from_values(items).map(Cow::Owned).map_err(|err| {
+            LangError::Runtime(format!("Builtin '{}' failed: {}", builtin, err), None)
+        })
+    }
+
+    fn map_argument<'v>(builtin: &str, value: &'v Value) -> LangResult<&'v map::Entries> {
+        match value {
+            Value::Map(entries) => Ok(entries),
+            other => Err(LangError::Runtime(
+                format!(
+                    "Builtin '{}' expected map as first argument, found {:?}",
+                    builtin, other
+                ),
+                None,
+            )),
+        }
+    }
+
+    fn expect_path<'v>(name: &str, path: &'v Value) -> LangResult<&'v [Value]> {
+        match path {
+            Value::List(keys) => Ok(keys),
+            other => Err(LangError::Runtime(
+                format!("Builtin '{}' expected list path, found {:?}", name, other),
+                None,
+            )),
+        }
+    }
+
+    /// Returns a copy of `target` where the value at `path` is replaced by
+    /// what `update` returns for it. String keys step into records, creating
+    /// missing levels, and number keys step into existing list elements.
+    fn update_path(
+        name: &str,
+        target: &Value,
+        path: &[Value],
+        update: &mut dyn FnMut(Value) -> LangResult<Value>,
+    ) -> LangResult<Value> {
+        let Some((key, rest)) = path.split_first() else {
+            return update(target.clone());
+        };
+        match (target, key) {
+            (Value::Object(_) | Value::Null, Value::String(key)) => {
+                let mut fields = match target {
+                    Value::Object(fields) => fields.clone(),
+                    _ => BTreeMap::new(),
+                };
+                let current = fields.get(key).cloned().unwrap_or(Value::Null);
+                let updated = Self::update_path(name, &current, rest, update)?;
+                fields.insert(key.clone(), updated);
+                Ok(Value::Object(fields))
+            }
+            (Value::List(items), Value::Number(index)) => {
+                let position = usize::try_from(*index)
+                    .ok()
+                    .filter(|position| *position < items.len())
+                    .ok_or_else(|| {
+                        LangError::Runtime(
+                            format!(
+                                "Builtin '{}' index {} is out of range for a list of length {}",
+                                name,
+                                index,
+                                items.len()
+                            ),
+                            None,
+                        )
+                    })?;
+                let mut items = items.clone();
+                items[position] = Self::update_path(name, &items[position], rest, update)?;
+                Ok(Value::List(items))
             }
+            (target, key) => Err(LangError::Runtime(
+                format!(
+                    "Builtin '{}' cannot use key {:?} on {:?}; use string keys for records and number keys for lists",
+                    name, key, target
+                ),
+                None,
+            )),
         }
-        None
     }
 
-    fn identifier_name(expr: &Expression) -> Option<&str> {
-        if let Expression::Identifier(name) = expr {
-            Some(name.as_str())
-        } else {
-            None
+    /// Reads the options record of `format-number`. Every option may be
+    /// left out; `locale` picks the separators the others then override.
+    fn number_format(options: &Value) -> Result<NumberFormat, String> {
+        const OPTIONS: [&str; 7] = [
+            "locale", "grouping", "decimal", "decimals", "width", "fill", "sign",
+        ];
+        let Value::Object(options) = options else {
+            return Err(format!(
+                "expected an options object, found {}",
+                options.type_name()
+            ));
+        };
+        if let Some(unknown) = options.keys().find(|key| !OPTIONS.contains(&key.as_str())) {
+            return Err(format!(
+                "has no option '{}'{}",
+                unknown,
+                suggest::hint(unknown, OPTIONS)
+            ));
+        }
+        let string = |key: &str| match options.get(key) {
+            None => Ok(None),
+            Some(Value::String(text)) => Ok(Some(text.clone())),
+            Some(other) => Err(format!(
+                "option '{}' expected a string, found {}",
+                key,
+                other.type_name()
+            )),
+        };
+        let count = |key: &str| match options.get(key) {
+            None => Ok(0),
+            Some(Value::Number(n)) => usize::try_from(*n)
+                .map_err(|_| format!("option '{}' must not be negative, found {}", key, n)),
+            Some(other) => Err(format!(
+                "option '{}' expected a number, found {}",
+                key,
+                other.type_name()
+            )),
+        };
+
+        let mut format = NumberFormat::for_locale(&string("locale")?.unwrap_or_default());
+        if let Some(grouping) = string("grouping")? {
+            format.grouping = grouping;
+        }
+        if let Some(decimal) = string("decimal")? {
+            format.decimal = decimal;
+        }
+        format.decimals = count("decimals")?;
+        format.width = count("width")?;
+        if let Some(fill) = string("fill")? {
+            let mut chars = fill.chars();
+            format.fill = match (chars.next(), chars.next()) {
+                (Some(fill), None) => fill,
+                _ => {
+                    return Err(format!(
+                        "option 'fill' expected one character, found \"{}\"",
+                        fill
+                    ))
+                }
+            };
         }
+        format.sign = match options.get("sign") {
+            None => false,
+            Some(Value::Boolean(sign)) => *sign,
+            Some(other) => {
+                return Err(format!(
+                    "option 'sign' expected a boolean, found {}",
+                    other.type_name()
+                ))
+            }
+        };
+        Ok(format)
+    }
+
+    fn request_to_value(request: http::Request) -> Value {
+        let headers = request
+            .headers
+            .into_iter()
+            .map(|(name, value)| (name, Value::String(value)))
+            .collect();
+        let mut fields = BTreeMap::new();
+        fields.insert("method".to_string(), Value::String(request.method));
+        fields.insert("path".to_string(), Value::String(request.path));
+        fields.insert("headers".to_string(), Value::Object(headers));
+        fields.insert("body".to_string(), Value::String(request.body));
+        Value::Object(fields)
+    }
+
+    /// Turns a `serve-http!` handler's result into a response. A record with a
+    /// `status` field describes the response; any other value is the body.
+    fn response_from_value(value: &Value) -> LangResult<http::Response> {
+        let fail = |message: String| {
+            LangError::Runtime(format!("Builtin 'serve-http!' failed: {}", message), None)
+        };
+        let (status, body, extra) = match value {
+            Value::Object(fields) if fields.contains_key("status") => {
+                let status = match fields.get("status") {
+                    Some(Value::Number(status)) if (100..=599).contains(status) => *status as u16,
+                    other => {
+                        return Err(fail(format!(
+                            "response status must be a number from 100 to 599, found {:?}",
+                            other.unwrap_or(&Value::Null)
+                        )))
+                    }
+                };
+                let extra = match fields.get("headers") {
+                    None | Some(Value::Null) => Vec::new(),
+                    Some(Value::Object(headers)) => headers
+                        .iter()
+                        .map(|(name, value)| match value {
+                            Value::String(value) => {
+                                http::check_header(name, value).map_err(fail)?;
+                                Ok((name.to_ascii_lowercase(), value.clone()))
+                            }
+                            other => Err(fail(format!(
+                                "response header '{}' must be a string, found {:?}",
+                                name, other
+                            ))),
+                        })
+                        .collect::<LangResult<Vec<_>>>()?,
+                    Some(other) => {
+                        return Err(fail(format!(
+                            "response headers must be a record, found {:?}",
+                            other
+                        )))
+                    }
+                };
+                let body = match fields.get("body") {
+                    None | Some(Value::Null) => Value::String(String::new()),
+                    Some(body) => body.clone(),
+                };
+                (status, body, extra)
+            }
+            other => (200, other.clone(), Vec::new()),
+        };
+        // Strings are sent verbatim; anything else is sent as JSON.
+        let (content_type, body) = match body {
+            Value::String(text) => ("text/plain; charset=utf-8", text),
+            other => ("application/json", json::encode(&other).map_err(fail)?),
+        };
+        let mut headers = vec![("content-type".to_string(), content_type.to_string())];
+        for (name, value) in extra {
+            headers.retain(|(existing, _)| *existing != name);
+            headers.push((name, value));
+        }
+        Ok(http::Response {
+            status,
+            headers,
+            body,
+        })
     }
 
-    fn values_equal(left: &Value, right: &Value) -> bool {
+    pub(crate) fn values_equal(left: &Value, right: &Value) -> bool {
         match (left, right) {
             (Value::Number(l), Value::Number(r)) => l == r,
             (Value::String(l), Value::String(r)) => l == r,
+            (Value::Bytes(l), Value::Bytes(r)) => l == r,
             (Value::Boolean(l), Value::Boolean(r)) => l == r,
             (Value::Unit, Value::Unit) => true,
             (Value::Null, Value::Null) => true,
@@ -2652,61 +9523,94 @@ impl Interpreter {
                         .unwrap_or(false)
                 })
             }
+            (Value::Set(l), Value::Set(r)) => {
+                l.len() == r.len()
+                    && l.iter()
+                        .zip(r.iter())
+                        .all(|(lv, rv)| Self::values_equal(lv, rv))
+            }
+            (Value::Map(l), Value::Map(r)) => {
+                l.len() == r.len()
+                    && l.iter().zip(r.iter()).all(|((lk, lv), (rk, rv))| {
+                        Self::values_equal(lk, rk) && Self::values_equal(lv, rv)
+                    })
+            }
             (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
             (Value::Builtin(l), Value::Builtin(r)) => Rc::ptr_eq(l, r),
+            (Value::Partial { applied: l, .. }, Value::Partial { applied: r, .. }) => {
+                Rc::ptr_eq(l, r)
+            }
+            (Value::Lazy(l), Value::Lazy(r)) => Rc::ptr_eq(l, r),
+            (Value::Task(l), Value::Task(r)) => l == r,
+            (Value::Channel(l), Value::Channel(r)) => l == r,
             _ => false,
         }
     }
 
     fn eval_use_statement(&self, use_stmt: &UseStatement, env: Rc<Environment>) -> LangResult<()> {
-        let module_path = match use_stmt {
-            UseStatement::Single { module_path, .. } => module_path,
-            UseStatement::Namespace { module_path, .. } => module_path,
-            UseStatement::Selective { module_path, .. } => module_path,
+        let module_path = use_stmt.module_path();
+
+        let module = self.load_module(module_path)?;
+        let export = |name: &String| {
+            let value = module
+                .exports
+                .contains(name)
+                .then(|| module.env.get(name))
+                .flatten();
+            value.ok_or_else(|| {
+                LangError::Runtime(
+                    format!("Module '{}' does not export '{}'", module_path, name),
+                    None,
+                )
+            })
         };
 
-        let module_env = self.load_module(module_path)?;
-
         match use_stmt {
             UseStatement::Single { name, .. } => {
-                let value = module_env.get(name).ok_or_else(|| {
-                    LangError::Runtime(
-                        format!("Module '{}' does not export '{}'", module_path, name),
-                        None,
-                    )
-                })?;
-                env.define(name.clone(), value)
+                self.materialize(&module, [name])?;
+                env.define(name.clone(), export(name)?)
             }
             UseStatement::Namespace { alias, .. } => {
                 // Create an object with all exported values
+                self.materialize(&module, &module.exports)?;
                 let mut exports = BTreeMap::new();
-                let module_values = module_env.values.borrow();
-                for (key, value) in module_values.iter() {
-                    exports.insert(key.clone(), value.clone());
+                for name in &module.exports {
+                    exports.insert(name.clone(), export(name)?);
                 }
                 env.define(alias.clone(), Value::Object(exports))
             }
             UseStatement::Selective { names, .. } => {
+                self.materialize(&module, names)?;
                 for name in names {
-                    let value = module_env.get(name).ok_or_else(|| {
-                        LangError::Runtime(
-                            format!("Module '{}' does not export '{}'", module_path, name),
+                    env.define(name.clone(), export(name)?)?;
+                }
+                Ok(())
+            }
+            UseStatement::Glob { .. } => {
+                self.materialize(&module, &module.exports)?;
+                for name in &module.exports {
+                    if env.values.borrow().contains_key(name) {
+                        return Err(LangError::Runtime(
+                            format!(
+                                "Glob import from '{}' conflicts with existing binding '{}'",
+                                module_path, name
+                            ),
                             None,
-                        )
-                    })?;
-                    env.define(name.clone(), value)?;
+                        ));
+                    }
+                    env.define(name.clone(), export(name)?)?;
                 }
                 Ok(())
             }
         }
     }
 
-    fn load_module(&self, module_path: &str) -> LangResult<Rc<Environment>> {
+    fn load_module(&self, module_path: &str) -> LangResult<Rc<Module>> {
         // Check cache first
         {
             let cache = self.module_cache.borrow();
-            if let Some(cached_env) = cache.get(module_path) {
-                return Ok(Rc::clone(cached_env));
+            if let Some(cached) = cache.get(module_path) {
+                return Ok(Rc::clone(cached));
             }
         }
 
@@ -2727,21 +9631,10 @@ impl Interpreter {
             loading.insert(module_path.to_string());
         }
 
-        // Resolve file path
-        let file_path = self.resolve_module_path(module_path)?;
-
-        // Read and parse the module
-        let source = std::fs::read_to_string(&file_path).map_err(|e| {
-            LangError::Runtime(
-                format!(
-                    "Failed to read module '{}' (resolved to '{}'): {}",
-                    module_path,
-                    file_path.display(),
-                    e
-                ),
-                None,
-            )
-        })?;
+        // Resolve and read the module through the configured loader
+        let (file_path, source) = self
+            .module_loader
+            .load(self.entry_point_dir.as_deref(), module_path)?;
 
         let tokens = Lexer::with_source_and_file(&source, source.clone(), file_path.clone())
             .lex()
@@ -2753,35 +9646,76 @@ impl Interpreter {
             })?;
 
         let mut parser = Parser::with_source_and_file(tokens, source.clone(), file_path.clone());
-        let program = parser.parse_program().map_err(|e| {
+        let mut program = parser.parse_program().map_err(|e| {
             LangError::Runtime(
                 format!("Failed to parse module '{}': {}", module_path, e),
                 None,
             )
         })?;
+        resolve::resolve_program(&mut program);
 
-        // Create module environment
-        let module_env = Environment::new(None);
-
-        // Track exports
-        let mut exports = HashSet::new();
-
-        // Evaluate module statements
+        let mut exports = BTreeSet::new();
+        let mut references = HashMap::new();
         for statement in &program.statements {
             match statement {
                 Statement::Export(ExportStatement { name }) => {
                     exports.insert(name.clone());
                 }
-                _ => {
-                    self.eval_statement(statement, Rc::clone(&module_env))?;
+                Statement::Function(func) => {
+                    references.insert(func.name.clone(), semantic::referenced_names(&func.body));
+                }
+                Statement::Assignment { pattern, expr, .. } => {
+                    let mentioned = semantic::referenced_names(expr);
+                    for name in pattern.names() {
+                        references.insert(name.to_string(), mentioned.clone());
+                    }
                 }
+                _ => {}
             }
         }
+        let module = Module {
+            program,
+            env: Environment::new(None),
+            exports,
+            references,
+            pending: RefCell::new(BTreeSet::new()),
+        };
+
+        // Evaluate module statements. Functions are hoisted; pure
+        // assignments wait until something needs them; everything else runs
+        // now, in order, after the pending bindings it mentions.
+        self.hoist_functions(&module.program.statements, &module.env)?;
+        for (index, statement) in module.program.statements.iter().enumerate() {
+            let mentioned = match statement {
+                Statement::Export(_) | Statement::Function(_) => continue,
+                Statement::Assignment { expr, .. } => {
+                    if semantic::find_impure_call(expr).is_none() {
+                        module.pending.borrow_mut().insert(index);
+                        continue;
+                    }
+                    semantic::referenced_names(expr)
+                }
+                Statement::Expression(expr)
+                | Statement::ConditionalUse {
+                    condition: expr, ..
+                } => semantic::referenced_names(expr),
+                Statement::Use(_) => HashSet::new(),
+            };
+            self.materialize(&module, &mentioned)?;
+            self.run_statement(statement, Rc::clone(&module.env))?;
+        }
 
         // Verify all exports exist
-        let module_values = module_env.values.borrow();
-        for export_name in &exports {
-            if !module_values.contains_key(export_name) {
+        for export_name in &module.exports {
+            let pending = module.pending.borrow().iter().any(|&index| {
+                match &module.program.statements[index] {
+                    Statement::Assignment { pattern, .. } => {
+                        pattern.names().contains(&export_name.as_str())
+                    }
+                    _ => false,
+                }
+            });
+            if !pending && module.env.get(export_name).is_none() {
                 return Err(LangError::Runtime(
                     format!(
                         "Module '{}' exports '{}' but it is not defined",
@@ -2792,17 +9726,6 @@ impl Interpreter {
             }
         }
 
-        // Create export-only environment
-        let export_env = Environment::new(None);
-        {
-            let mut export_values = export_env.values.borrow_mut();
-            for export_name in &exports {
-                if let Some(value) = module_values.get(export_name) {
-                    export_values.insert(export_name.clone(), value.clone());
-                }
-            }
-        }
-
         // Remove from loading set
         {
             let mut loading = self.loading_modules.borrow_mut();
@@ -2810,47 +9733,84 @@ impl Interpreter {
         }
 
         // Cache and return
+        let module = Rc::new(module);
         {
             let mut cache = self.module_cache.borrow_mut();
-            cache.insert(module_path.to_string(), Rc::clone(&export_env));
+            cache.insert(module_path.to_string(), Rc::clone(&module));
         }
 
-        Ok(export_env)
+        Ok(module)
     }
 
-    fn resolve_module_path(&self, module_path: &str) -> LangResult<PathBuf> {
-        let base_dir = self
-            .entry_point_dir
-            .as_ref()
-            .ok_or_else(|| {
-                LangError::Runtime(
-                    "Module imports require entry point directory to be set".to_string(),
-                    None,
-                )
-            })?
-            .clone();
-
-        let mut path = base_dir.join(module_path);
-        path.set_extension("fip");
+    /// Evaluates the pending statements that `names` depend on, directly or
+    /// through the functions and bindings they mention, in source order.
+    fn materialize<'a>(
+        &self,
+        module: &Module,
+        names: impl IntoIterator<Item = &'a String>,
+    ) -> LangResult<()> {
+        let mut needed = HashSet::new();
+        let mut queue: Vec<&str> = names.into_iter().map(String::as_str).collect();
+        while let Some(name) = queue.pop() {
+            if needed.insert(name) {
+                if let Some(mentioned) = module.references.get(name) {
+                    queue.extend(mentioned.iter().map(String::as_str));
+                }
+            }
+        }
+        let ready: Vec<usize> = module
+            .pending
+            .borrow()
+            .iter()
+            .copied()
+            .filter(|&index| match &module.program.statements[index] {
+                Statement::Assignment { pattern, .. } => {
+                    pattern.names().iter().any(|name| needed.contains(name))
+                }
+                _ => false,
+            })
+            .collect();
+        for index in ready {
+            module.pending.borrow_mut().remove(&index);
+            self.run_statement(&module.program.statements[index], Rc::clone(&module.env))?;
+        }
+        Ok(())
+    }
 
-        if !path.exists() {
+    /// Forces a lazy sequence into a list. Infinite sequences are rejected
+    /// instead of looping forever; bound them with `take` first.
+    fn force_sequence(&self, sequence: &Sequence) -> LangResult<Vec<Value>> {
+        if !sequence.is_finite() {
             return Err(LangError::Runtime(
-                format!(
-                    "Module file not found: {} (resolved from '{}')",
-                    path.display(),
-                    module_path
-                ),
+                "Cannot force an infinite lazy sequence; bound it with 'take' first".to_string(),
                 None,
             ));
         }
+        let mut cursor = sequence.cursor();
+        let mut items = Vec::new();
+        while let Some(item) = cursor.next(self)? {
+            items.push(item);
+        }
+        Ok(items)
+    }
 
-        Ok(path)
+    fn take_from_sequence(&self, sequence: &Sequence, count: usize) -> LangResult<Vec<Value>> {
+        let mut cursor = sequence.cursor();
+        let mut items = Vec::new();
+        while items.len() < count {
+            match cursor.next(self)? {
+                Some(item) => items.push(item),
+                None => break,
+            }
+        }
+        Ok(items)
     }
 
-    fn value_to_string(&self, value: &Value) -> LangResult<String> {
+    pub(crate) fn value_to_string(&self, value: &Value) -> LangResult<String> {
         match value {
             Value::Number(n) => Ok(n.to_string()),
             Value::String(s) => Ok(s.clone()),
+            Value::Bytes(bytes) => Ok(format!("<{} bytes>", bytes.len())),
             Value::Boolean(b) => Ok(b.to_string()),
             Value::List(elements) => {
                 let mut parts = Vec::with_capacity(elements.len());
@@ -2859,6 +9819,24 @@ impl Interpreter {
                 }
                 Ok(format!("[{}]", parts.join(", ")))
             }
+            Value::Set(elements) => {
+                let mut parts = Vec::with_capacity(elements.len());
+                for element in elements {
+                    parts.push(self.value_to_string(element)?);
+                }
+                Ok(format!("<set [{}]>", parts.join(", ")))
+            }
+            Value::Map(entries) => {
+                let mut parts = Vec::with_capacity(entries.len());
+                for (key, value) in entries {
+                    parts.push(format!(
+                        "{}: {}",
+                        self.value_to_string(key)?,
+                        self.value_to_string(value)?
+                    ));
+                }
+                Ok(format!("<map {{{}}}>", parts.join(", ")))
+            }
             Value::Object(fields) => {
                 let mut parts = Vec::with_capacity(fields.len());
                 for (key, value) in fields {
@@ -2870,6 +9848,12 @@ impl Interpreter {
             Value::Unit => Ok("()".to_string()),
             Value::Function(func) => Ok(format!("<fn {}>", func.name)),
             Value::Builtin(builtin) => Ok(format!("<builtin {}>", builtin.name)),
+            Value::Partial { callee, .. } => {
+                Ok(format!("<partial {}>", self.value_to_string(callee)?))
+            }
+            Value::Lazy(_) => Ok("<lazy>".to_string()),
+            Value::Task(_) => Ok("<task>".to_string()),
+            Value::Channel(_) => Ok("<channel>".to_string()),
         }
     }
 }
@@ -0,0 +1,394 @@
+use crate::interpreter::Value;
+use std::collections::BTreeMap;
+
+/// How deeply arrays and objects may nest before parsing gives up, so
+/// hostile input cannot exhaust the stack.
+const MAX_DEPTH: usize = 128;
+
+/// Parses a JSON document into a fip value. Objects become records, arrays
+/// become lists. Numbers must stand for an integer, because fip numbers are
+/// 64-bit integers: `1e3` and `2.50e1` are read as 1000 and 25, and `1.5`
+/// is an error.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = JsonParser {
+        chars: text.char_indices().peekable(),
+        text,
+        depth: 0,
+    };
+    parser.skip_whitespace();
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some((offset, ch)) => Err(format!(
+            "Unexpected trailing character '{}' at offset {}",
+            ch, offset
+        )),
+    }
+}
+
+/// Encodes a fip value as compact JSON. Functions cannot be encoded.
+pub fn encode(value: &Value) -> Result<String, String> {
+    let mut out = String::new();
+    encode_into(value, &mut out)?;
+    Ok(out)
+}
+
+fn encode_into(value: &Value, out: &mut String) -> Result<(), String> {
+    match value {
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => encode_string(s, out),
+        Value::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Null | Value::Unit => out.push_str("null"),
+        Value::List(items) | Value::Set(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                encode_into(item, out)?;
+            }
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            out.push('{');
+            for (index, (key, item)) in fields.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                encode_string(key, out);
+                out.push(':');
+                encode_into(item, out)?;
+            }
+            out.push('}');
+        }
+        other => return Err(format!("Cannot encode {:?} as JSON", other)),
+    }
+    Ok(())
+}
+
+fn encode_string(text: &str, out: &mut String) {
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    text: &'a str,
+    /// Arrays and objects open around the current position.
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some((_, ch)) = self.chars.peek() {
+            if ch.is_whitespace() {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some((_, ch)) if ch == expected => Ok(()),
+            Some((offset, ch)) => Err(format!(
+                "Expected '{}' but found '{}' at offset {}",
+                expected, ch, offset
+            )),
+            None => Err(format!("Expected '{}' but reached end of input", expected)),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        match self.chars.peek().copied() {
+            Some((_, '{')) => self.parse_object(),
+            Some((_, '[')) => self.parse_array(),
+            Some((_, '"')) => self.parse_string().map(Value::String),
+            Some((_, 't')) => self.parse_keyword("true", Value::Boolean(true)),
+            Some((_, 'f')) => self.parse_keyword("false", Value::Boolean(false)),
+            Some((_, 'n')) => self.parse_keyword("null", Value::Null),
+            Some((_, ch)) if ch == '-' || ch.is_ascii_digit() => self.parse_number(),
+            Some((offset, ch)) => Err(format!(
+                "Unexpected character '{}' at offset {}",
+                ch, offset
+            )),
+            None => Err("Unexpected end of input".to_string()),
+        }
+    }
+
+    fn parse_keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        for expected in keyword.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.chars.peek().map(|(offset, _)| *offset).unwrap_or(0);
+        let mut end = start;
+        while let Some((offset, ch)) = self.chars.peek().copied() {
+            if ch == '-' || ch == '+' || ch == '.' || ch == 'e' || ch == 'E' || ch.is_ascii_digit()
+            {
+                end = offset + ch.len_utf8();
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        let literal = &self.text[start..end];
+        integer(literal)
+            .map(Value::Number)
+            .map_err(|problem| format!("Number '{}' at offset {} {}", literal, start, problem))
+    }
+
+    /// Enters an array or object, failing past [`MAX_DEPTH`].
+    fn nest(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            let offset = self
+                .chars
+                .peek()
+                .map_or(self.text.len(), |(offset, _)| *offset);
+            return Err(format!(
+                "JSON nests more than {} arrays and objects deep at offset {}",
+                MAX_DEPTH, offset
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut content = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(content),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => content.push('"'),
+                    Some((_, '\\')) => content.push('\\'),
+                    Some((_, '/')) => content.push('/'),
+                    Some((_, 'b')) => content.push('\u{8}'),
+                    Some((_, 'f')) => content.push('\u{c}'),
+                    Some((_, 'n')) => content.push('\n'),
+                    Some((_, 'r')) => content.push('\r'),
+                    Some((_, 't')) => content.push('\t'),
+                    Some((_, 'u')) => {
+                        let high = self.parse_hex_escape()?;
+                        let code = if (0xD800..0xDC00).contains(&high) {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.parse_hex_escape()?;
+                            0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+                        } else {
+                            high
+                        };
+                        content.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                    }
+                    Some((offset, other)) => {
+                        return Err(format!(
+                            "Unsupported escape sequence '\\{}' at offset {}",
+                            other, offset
+                        ))
+                    }
+                    None => return Err("Unterminated escape sequence".to_string()),
+                },
+                Some((_, ch)) => content.push(ch),
+                None => return Err("Unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn parse_hex_escape(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, ch)| ch.to_digit(16))
+                .ok_or_else(|| "Invalid \\u escape sequence".to_string())?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.nest()?;
+        let items = self.parse_items();
+        self.depth -= 1;
+        items
+    }
+
+    fn parse_items(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if matches!(self.chars.peek(), Some((_, ']'))) {
+            self.chars.next();
+            return Ok(Value::List(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Value::List(items)),
+                Some((offset, ch)) => {
+                    return Err(format!(
+                        "Expected ',' or ']' but found '{}' at offset {}",
+                        ch, offset
+                    ))
+                }
+                None => return Err("Unterminated array".to_string()),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.nest()?;
+        let fields = self.parse_fields();
+        self.depth -= 1;
+        fields
+    }
+
+    fn parse_fields(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut fields = BTreeMap::new();
+        self.skip_whitespace();
+        if matches!(self.chars.peek(), Some((_, '}'))) {
+            self.chars.next();
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let value = self.parse_value()?;
+            fields.insert(key, value);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Value::Object(fields)),
+                Some((offset, ch)) => {
+                    return Err(format!(
+                        "Expected ',' or '}}' but found '{}' at offset {}",
+                        ch, offset
+                    ))
+                }
+                None => return Err("Unterminated object".to_string()),
+            }
+        }
+    }
+}
+
+/// The integer a JSON number literal stands for, or what keeps it from
+/// being one.
+fn integer(literal: &str) -> Result<i64, &'static str> {
+    let (mantissa, exponent) = match literal.find(['e', 'E']) {
+        Some(e) => (&literal[..e], Some(&literal[e + 1..])),
+        None => (literal, None),
+    };
+    let (negative, unsigned) = match mantissa.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, mantissa),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let digits_only = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+    let exponent_digits = exponent.map(|e| e.strip_prefix(['+', '-']).unwrap_or(e));
+    let valid = digits_only(whole)
+        && !(whole.len() > 1 && whole.starts_with('0'))
+        && (fraction.is_empty() || digits_only(fraction))
+        && !unsigned.ends_with('.')
+        && exponent_digits.is_none_or(digits_only);
+    if !valid {
+        return Err("is not a valid JSON number");
+    }
+    let exponent: i64 = match exponent {
+        Some(e) => e
+            .trim_start_matches('+')
+            .parse()
+            .map_err(|_| "has an exponent out of range")?,
+        None => 0,
+    };
+
+    // Shift the decimal point to the end of the digits
+    let mut digits = format!("{}{}", whole, fraction);
+    let shift = exponent.saturating_sub(fraction.len() as i64);
+    if shift < 0 {
+        let keep = digits.len().saturating_sub(shift.unsigned_abs() as usize);
+        if digits[keep..].bytes().any(|b| b != b'0') {
+            return Err("has a fractional part, and fip numbers are integers");
+        }
+        digits.truncate(keep);
+    }
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    let too_large = "does not fit in a 64-bit integer";
+    if shift > 0 {
+        if shift as usize + digits.len() > 19 {
+            return Err(too_large);
+        }
+    } else if digits.len() > 19 {
+        return Err(too_large);
+    }
+    let zeros = "0".repeat(shift.max(0) as usize);
+    let magnitude: i128 = format!("{}{}", digits, zeros)
+        .parse()
+        .map_err(|_| too_large)?;
+    i64::try_from(if negative { -magnitude } else { magnitude }).map_err(|_| too_large)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_that_stand_for_integers_are_read() {
+        let number = |text: &str| match parse(text) {
+            Ok(Value::Number(n)) => Ok(n),
+            Ok(other) => panic!("expected a number, found {:?}", other),
+            Err(err) => Err(err),
+        };
+        assert_eq!(number("1e3"), Ok(1000));
+        assert_eq!(number("-2.50e1"), Ok(-25));
+        assert_eq!(number("4.0"), Ok(4));
+        assert_eq!(number("120e-1"), Ok(12));
+        assert_eq!(number("-9223372036854775808"), Ok(i64::MIN));
+        let err = number("1.5").expect_err("fraction");
+        assert!(
+            err.contains("'1.5'") && err.contains("fractional part"),
+            "{}",
+            err
+        );
+        assert!(number("1e19").expect_err("too large").contains("64-bit"));
+        assert!(number("01")
+            .expect_err("leading zero")
+            .contains("not a valid"));
+        assert!(number("1.").is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        let err = parse(&nested(100_000)).expect_err("too deep");
+        assert!(err.contains("nests more than 128"), "{}", err);
+    }
+}
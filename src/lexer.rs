@@ -10,7 +10,11 @@ pub struct Token {
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     Identifier(String),
-    Number(i64),
+    /// `spelling` is the source text when it is not plain decimal digits.
+    Number {
+        value: i64,
+        spelling: Option<String>,
+    },
     StringLiteral(String),
     Boolean(bool),
     Null,
@@ -39,6 +43,9 @@ pub enum TokenKind {
     GreaterThanEq,
     Exclamation,
     Question,
+    SafeDot,
+    Coalesce,
+    PipeForward,
     Eof,
 }
 
@@ -228,9 +235,17 @@ impl<'a> Lexer<'a> {
                 }
                 '|' => {
                     self.advance_char();
-                    Token {
-                        kind: TokenKind::Pipe,
-                        span: start..self.current_index,
+                    if matches!(self.peek_char(), Some('>')) {
+                        self.advance_char();
+                        Token {
+                            kind: TokenKind::PipeForward,
+                            span: start..self.current_index,
+                        }
+                    } else {
+                        Token {
+                            kind: TokenKind::Pipe,
+                            span: start..self.current_index,
+                        }
                     }
                 }
                 '=' => {
@@ -279,9 +294,25 @@ impl<'a> Lexer<'a> {
                 }
                 '?' => {
                     self.advance_char();
-                    Token {
-                        kind: TokenKind::Question,
-                        span: start..self.current_index,
+                    match self.peek_char() {
+                        Some('.') => {
+                            self.advance_char();
+                            Token {
+                                kind: TokenKind::SafeDot,
+                                span: start..self.current_index,
+                            }
+                        }
+                        Some('?') => {
+                            self.advance_char();
+                            Token {
+                                kind: TokenKind::Coalesce,
+                                span: start..self.current_index,
+                            }
+                        }
+                        _ => Token {
+                            kind: TokenKind::Question,
+                            span: start..self.current_index,
+                        },
                     }
                 }
                 '\u{2260}' => {
@@ -342,9 +373,11 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        // Consume ! or ? if they follow the identifier (for function names like log!)
+        // Consume ! or ? if they follow the identifier (for function names like log!),
+        // unless the ? starts a `?.` or `??` operator
         if let Some(ch) = self.peek_char() {
-            if ch == '!' || ch == '?' {
+            let starts_operator = ch == '?' && matches!(self.chars.clone().next(), Some('.' | '?'));
+            if (ch == '!' || ch == '?') && !starts_operator {
                 self.advance_char();
                 ident.push(ch);
             }
@@ -373,27 +406,67 @@ impl<'a> Lexer<'a> {
         })
     }
 
+    /// Reads a decimal, `0x` hexadecimal, or `0b` binary integer. Digits may
+    /// be grouped with single underscores, as in `1_000_000` or `0xff_ff`.
     fn read_number(&mut self, start: usize) -> LangResult<Token> {
-        let mut number = String::new();
+        let mut spelling = String::new();
+        let mut radix = 10;
+        if self.peek_char() == Some('0') {
+            spelling.push('0');
+            self.advance_char();
+            if let Some(marker @ ('x' | 'X' | 'b' | 'B')) = self.peek_char() {
+                radix = if marker.eq_ignore_ascii_case(&'x') {
+                    16
+                } else {
+                    2
+                };
+                spelling.push(marker);
+                self.advance_char();
+            }
+        }
 
         while let Some(ch) = self.peek_char() {
-            if ch.is_ascii_digit() {
-                number.push(ch);
+            if ch.is_digit(radix) || ch == '_' {
+                spelling.push(ch);
                 self.advance_char();
             } else {
                 break;
             }
         }
 
-        let value = number.parse::<i64>().map_err(|err| {
+        let stray = match radix {
+            10 => None,
+            _ => self.peek_char().filter(char::is_ascii_alphanumeric),
+        };
+        let digits = if radix == 10 {
+            &spelling[..]
+        } else {
+            &spelling[2..]
+        };
+        let invalid = |reason: String| {
             self.error_with_location(
-                format!("Invalid number literal '{}': {}", number, err),
+                format!("Invalid number literal '{}': {}", spelling, reason),
                 start,
             )
-        })?;
+        };
+        if let Some(ch) = stray {
+            return Err(invalid(format!("'{}' is not a base {} digit", ch, radix)));
+        }
+        if digits.is_empty() {
+            return Err(invalid("expected digits after the prefix".to_string()));
+        }
+        if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+            return Err(invalid("underscores must sit between digits".to_string()));
+        }
+        let value = i64::from_str_radix(&digits.replace('_', ""), radix)
+            .map_err(|err| invalid(err.to_string()))?;
 
+        let plain = radix == 10 && !spelling.contains('_');
         Ok(Token {
-            kind: TokenKind::Number(value),
+            kind: TokenKind::Number {
+                value,
+                spelling: (!plain).then_some(spelling),
+            },
             span: start..self.current_index,
         })
     }
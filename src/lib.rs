@@ -1,5 +1,44 @@
 pub mod ast;
+pub mod channel;
+pub mod collation;
+pub mod config;
+pub mod diff;
+pub mod docgen;
+pub mod encoding;
 pub mod error;
+pub mod formatter;
+pub mod heap;
+pub mod highlight;
+pub mod hooks;
+pub mod http;
 pub mod interpreter;
+pub mod json;
 pub mod lexer;
+pub mod loader;
+pub mod logging;
+pub mod map;
+pub mod module_graph;
+pub mod naming;
 pub mod parser;
+pub mod portable;
+pub mod pretty;
+pub mod profile;
+pub mod property;
+pub mod refactor;
+pub mod rename;
+pub mod report;
+pub mod resolve;
+pub mod semantic;
+pub mod set;
+pub mod source_file;
+pub mod suggest;
+pub mod syntax;
+pub mod text_format;
+pub mod time;
+pub mod trace;
+pub mod types;
+pub mod uuid;
+pub mod vfs;
+// Native builds do not export the C ABI even with the feature on
+#[cfg(all(feature = "wasm", any(target_arch = "wasm32", test)))]
+pub mod wasm;
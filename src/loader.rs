@@ -0,0 +1,207 @@
+use crate::{
+    ast::Statement,
+    error::{LangError, LangResult},
+    lexer::Lexer,
+    parser::Parser,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+/// Supplies module source code to the interpreter. The default loader reads
+/// `.fip` files from disk; embedders without a filesystem (such as the WASM
+/// build) install their own.
+pub trait ModuleLoader {
+    /// Where `module_path` lives, relative to the entry point directory
+    /// `base_dir` when one is set. The path names the module in error
+    /// messages.
+    fn resolve(&self, base_dir: Option<&Path>, module_path: &str) -> LangResult<PathBuf>;
+
+    /// The source text at a path returned by `resolve`.
+    fn read(&self, path: &Path) -> LangResult<String>;
+
+    /// Returns the resolved path and source text of `module_path`.
+    fn load(&self, base_dir: Option<&Path>, module_path: &str) -> LangResult<(PathBuf, String)> {
+        let path = self.resolve(base_dir, module_path)?;
+        let source = self.read(&path)?;
+        Ok((path, source))
+    }
+}
+
+/// Loads modules from the filesystem relative to the entry point directory.
+pub struct FileSystemLoader;
+
+impl ModuleLoader for FileSystemLoader {
+    fn resolve(&self, base_dir: Option<&Path>, module_path: &str) -> LangResult<PathBuf> {
+        let base_dir = base_dir.ok_or_else(|| {
+            LangError::Runtime(
+                "Module imports require entry point directory to be set".to_string(),
+                None,
+            )
+        })?;
+
+        let mut path = base_dir.join(module_path);
+        path.set_extension("fip");
+
+        if !path.exists() {
+            return Err(LangError::Runtime(
+                format!(
+                    "Module file not found: {} (resolved from '{}')",
+                    path.display(),
+                    module_path
+                ),
+                None,
+            ));
+        }
+        Ok(path)
+    }
+
+    fn read(&self, path: &Path) -> LangResult<String> {
+        std::fs::read_to_string(path).map_err(|e| {
+            LangError::Runtime(
+                format!("Failed to read module '{}': {}", path.display(), e),
+                None,
+            )
+        })
+    }
+}
+
+/// Serves modules from source text held in memory, for tests and embedders
+/// that keep their modules somewhere other than disk. Modules are named by
+/// their import path, such as `geometry/shapes`.
+#[derive(Default)]
+pub struct MemoryLoader {
+    modules: HashMap<String, String>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the module imported as `module_path`.
+    pub fn with_module(mut self, module_path: &str, source: &str) -> Self {
+        self.modules
+            .insert(module_path.to_string(), source.to_string());
+        self
+    }
+}
+
+impl ModuleLoader for MemoryLoader {
+    fn resolve(&self, _base_dir: Option<&Path>, module_path: &str) -> LangResult<PathBuf> {
+        if !self.modules.contains_key(module_path) {
+            return Err(LangError::Runtime(
+                format!("Module not found: '{}'", module_path),
+                None,
+            ));
+        }
+        Ok(PathBuf::from(format!("{}.fip", module_path)))
+    }
+
+    fn read(&self, path: &Path) -> LangResult<String> {
+        let module_path = path.with_extension("");
+        self.modules
+            .get(module_path.to_string_lossy().as_ref())
+            .cloned()
+            .ok_or_else(|| {
+                LangError::Runtime(format!("Module not found: '{}'", path.display()), None)
+            })
+    }
+}
+
+/// Rejects every import. Used where there is no module source to read from.
+pub struct NoModuleLoader;
+
+impl ModuleLoader for NoModuleLoader {
+    fn resolve(&self, _base_dir: Option<&Path>, module_path: &str) -> LangResult<PathBuf> {
+        Err(LangError::Runtime(
+            format!(
+                "Cannot import '{}': module imports are not available in this environment",
+                module_path
+            ),
+            None,
+        ))
+    }
+
+    fn read(&self, path: &Path) -> LangResult<String> {
+        Err(LangError::Runtime(
+            format!(
+                "Cannot read '{}': module imports are not available in this environment",
+                path.display()
+            ),
+            None,
+        ))
+    }
+}
+
+/// Parse diagnostics for one file of a module tree.
+pub struct FileDiagnostics {
+    pub file: PathBuf,
+    pub errors: Vec<LangError>,
+}
+
+/// Lexes and parses the entry file and every module it imports, directly or
+/// transitively, without evaluating anything. Parsing uses the parser's
+/// recovery mode, so each file reports all of its errors. Imports that cannot
+/// be loaded are reported against the file that imports them. Only files with
+/// errors are returned, in the order they were first reached.
+pub fn check_module_tree(
+    loader: &dyn ModuleLoader,
+    base_dir: Option<&Path>,
+    entry_file: &Path,
+    entry_source: &str,
+) -> Vec<FileDiagnostics> {
+    let mut diagnostics = Vec::new();
+    let mut seen = HashSet::new();
+    let mut pending = VecDeque::new();
+    pending.push_back((entry_file.to_path_buf(), entry_source.to_string()));
+
+    while let Some((file, source)) = pending.pop_front() {
+        let mut errors = Vec::new();
+        let statements = match Lexer::with_source_and_file(&source, source.clone(), file.clone())
+            .lex()
+        {
+            Ok(tokens) => {
+                let mut parser = Parser::with_source_and_file(tokens, source.clone(), file.clone());
+                let (program, parse_errors) = parser.parse_program_recovering();
+                errors.extend(parse_errors);
+                program.statements
+            }
+            Err(err) => {
+                errors.push(err);
+                Vec::new()
+            }
+        };
+
+        for statement in &statements {
+            // Conditional imports name modules for other platforms too, so a
+            // module that cannot be loaded is only an error once it is chosen
+            // at runtime.
+            let (uses, optional) = match statement {
+                Statement::Use(use_stmt) => (vec![use_stmt], false),
+                Statement::ConditionalUse {
+                    then, otherwise, ..
+                } => (std::iter::once(then).chain(otherwise).collect(), true),
+                _ => continue,
+            };
+            for use_stmt in uses {
+                let module_path = use_stmt.module_path();
+                if !seen.insert(module_path.to_string()) {
+                    continue;
+                }
+                match loader.load(base_dir, module_path) {
+                    Ok(module) => pending.push_back(module),
+                    Err(err) if !optional => errors.push(err),
+                    Err(_) => {}
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            diagnostics.push(FileDiagnostics { file, errors });
+        }
+    }
+
+    diagnostics
+}
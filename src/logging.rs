@@ -0,0 +1,148 @@
+//! Log lines written by `log-debug!`, `log-info!`, `log-warn!`, and
+//! `log-error!`. A line carries the time, the level, the message, and the
+//! other fields of the record the program logged, if it logged one, either
+//! as text for people to read or as one JSON object per line for log
+//! collectors. The interpreter drops lines below its [`LogLevel`].
+
+use crate::{interpreter::Value, json, time};
+use std::collections::BTreeMap;
+
+/// How severe a log line is, from least to most.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 4] = [
+        LogLevel::Debug,
+        LogLevel::Info,
+        LogLevel::Warn,
+        LogLevel::Error,
+    ];
+
+    /// The name used by `fip run --log-level`, `set-log-level!`, and JSON
+    /// lines.
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        LogLevel::ALL.into_iter().find(|level| level.name() == name)
+    }
+}
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `2024-05-01T12:30:00Z INFO  started port=8080`
+    #[default]
+    Text,
+    /// `{"time":"2024-05-01T12:30:00Z","level":"info","message":"started","port":8080}`
+    Json,
+}
+
+impl LogFormat {
+    /// The name used by `fip run --log-format`.
+    pub fn name(self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [LogFormat::Text, LogFormat::Json]
+            .into_iter()
+            .find(|format| format.name() == name)
+    }
+}
+
+/// Keys of every JSON line besides the message, which fields cannot reuse.
+const RESERVED: [&str; 2] = ["time", "level"];
+
+/// One log line without its newline. `time` is in milliseconds since the
+/// epoch and is left out when `None`, as when the clock may not be read.
+/// Field values are written as JSON in both formats, so strings are quoted.
+pub fn format_line(
+    format: LogFormat,
+    level: LogLevel,
+    time: Option<i64>,
+    message: &str,
+    fields: &BTreeMap<String, Value>,
+) -> Result<String, String> {
+    if let Some(key) = fields.keys().find(|key| RESERVED.contains(&key.as_str())) {
+        return Err(format!("Log field '{}' is reserved", key));
+    }
+    let mut line = String::new();
+    match format {
+        LogFormat::Text => {
+            if let Some(time) = time {
+                line.push_str(&time::format_iso(time));
+                line.push(' ');
+            }
+            line.push_str(&format!("{:<5} {}", level.name().to_uppercase(), message));
+            for (key, value) in fields {
+                line.push_str(&format!(" {}={}", key, json::encode(value)?));
+            }
+        }
+        LogFormat::Json => {
+            let mut entries = Vec::new();
+            if let Some(time) = time {
+                entries.push(("time", Value::String(time::format_iso(time))));
+            }
+            entries.push(("level", Value::String(level.name().to_string())));
+            entries.push(("message", Value::String(message.to_string())));
+            line.push('{');
+            let fields = fields
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.clone()));
+            for (index, (key, value)) in entries.into_iter().chain(fields).enumerate() {
+                if index > 0 {
+                    line.push(',');
+                }
+                line.push_str(&json::encode(&Value::String(key.to_string()))?);
+                line.push(':');
+                line.push_str(&json::encode(&value)?);
+            }
+            line.push('}');
+        }
+    }
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_text_or_json_objects() -> Result<(), String> {
+        let fields = BTreeMap::from([
+            ("port".to_string(), Value::Number(8080)),
+            ("host".to_string(), Value::String("local".to_string())),
+        ]);
+        assert_eq!(
+            format_line(LogFormat::Text, LogLevel::Info, Some(0), "started", &fields)?,
+            "1970-01-01T00:00:00Z INFO  started host=\"local\" port=8080"
+        );
+        assert_eq!(
+            format_line(LogFormat::Json, LogLevel::Warn, None, "say \"hi\"", &fields)?,
+            "{\"level\":\"warn\",\"message\":\"say \\\"hi\\\"\",\"host\":\"local\",\"port\":8080}"
+        );
+        let clash = BTreeMap::from([("level".to_string(), Value::Null)]);
+        assert!(format_line(LogFormat::Json, LogLevel::Error, None, "x", &clash).is_err());
+        assert!(LogLevel::Debug < LogLevel::Info);
+        assert_eq!(LogLevel::from_name("warn"), Some(LogLevel::Warn));
+        assert_eq!(LogFormat::from_name("yaml"), None);
+        Ok(())
+    }
+}
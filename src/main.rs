@@ -1,15 +1,9 @@
-mod ast;
-mod error;
-mod interpreter;
-mod lexer;
-mod parser;
-
 use std::{env, fs, path::Path};
 
-use error::LangError;
-use interpreter::Interpreter;
-use lexer::Lexer;
-use parser::Parser;
+use fippli_lang::error::LangError;
+use fippli_lang::interpreter::Interpreter;
+use fippli_lang::lexer::Lexer;
+use fippli_lang::parser::Parser;
 
 fn main() {
     if let Err(err) = run() {
@@ -53,6 +47,6 @@ fn run() -> Result<(), LangError> {
         .to_path_buf();
 
     let mut interpreter = Interpreter::with_entry_point_dir(entry_point_dir);
-    interpreter.eval_program(&program)?;
+    interpreter.eval_owned_program(program)?;
     Ok(())
 }
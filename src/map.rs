@@ -0,0 +1,58 @@
+//! Maps behind `to-map` and the `map-*` builtins. Records only take string
+//! keys; a map takes any value a set can hold, so numbers and lists can key
+//! memo tables and graph edges. Entries are kept sorted by key with the set
+//! order, so lookups are binary searches.
+
+use crate::interpreter::Value;
+use crate::set;
+
+pub type Entries = Vec<(Value, Value)>;
+
+fn position(entries: &[(Value, Value)], key: &Value) -> Result<Result<usize, usize>, String> {
+    set::check(key, "Map keys")?;
+    Ok(entries.binary_search_by(|(existing, _)| set::order(existing, key)))
+}
+
+pub fn get<'m>(entries: &'m [(Value, Value)], key: &Value) -> Result<Option<&'m Value>, String> {
+    Ok(position(entries, key)?.ok().map(|index| &entries[index].1))
+}
+
+/// Adds or replaces the entry for `key`.
+pub fn insert(entries: &mut Entries, key: Value, value: Value) -> Result<(), String> {
+    match position(entries, &key)? {
+        Ok(index) => entries[index].1 = value,
+        Err(index) => entries.insert(index, (key, value)),
+    }
+    Ok(())
+}
+
+pub fn remove(entries: &mut Entries, key: &Value) -> Result<(), String> {
+    if let Ok(index) = position(entries, key)? {
+        entries.remove(index);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_entries_sorted_and_replaces_existing_keys() {
+        let pair = |a, b| Value::List(vec![Value::Number(a), Value::Number(b)]);
+        let mut entries = Entries::new();
+        insert(&mut entries, pair(2, 1), Value::Number(21)).expect("lists are keys");
+        insert(&mut entries, pair(1, 2), Value::Number(12)).expect("lists are keys");
+        insert(&mut entries, pair(2, 1), Value::Number(0)).expect("lists are keys");
+        assert_eq!(format!("{:?}", entries), "[([1, 2], 12), ([2, 1], 0)]");
+        assert!(matches!(
+            get(&entries, &pair(2, 1)),
+            Ok(Some(Value::Number(0)))
+        ));
+        assert!(matches!(get(&entries, &pair(3, 3)), Ok(None)));
+
+        remove(&mut entries, &pair(1, 2)).expect("lists are keys");
+        assert_eq!(entries.len(), 1);
+        assert!(get(&entries, &Value::Object(Default::default())).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+//! The import graph of a program, behind `fip graph`. Building it reads
+//! and parses the entry file and every module it reaches through `use`,
+//! like [`check_module_tree`](crate::loader::check_module_tree), but runs
+//! nothing. Modules are visited in the order their imports appear, so the
+//! report is the same on every run.
+
+use crate::{ast::Statement, lexer::Lexer, loader::ModuleLoader, parser::Parser};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub struct ModuleGraph {
+    /// Every module reached, the entry file first, then in the order they
+    /// were first imported.
+    pub modules: Vec<ModuleNode>,
+}
+
+#[derive(Debug)]
+pub struct ModuleNode {
+    /// The import path, or the file name for the entry file.
+    pub name: String,
+    /// Why the module could not be loaded or lexed, if it could not.
+    pub error: Option<String>,
+    pub imports: Vec<Import>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Import {
+    /// The imported module's index in [`ModuleGraph::modules`].
+    pub module: usize,
+    /// Whether the import is a branch of a conditional `use`, which is only
+    /// loaded when the program picks it.
+    pub conditional: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    Open,
+    Done,
+}
+
+impl ModuleGraph {
+    pub fn build(
+        loader: &dyn ModuleLoader,
+        base_dir: Option<&Path>,
+        entry_file: &Path,
+        entry_source: &str,
+    ) -> Self {
+        let entry_name = entry_file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| entry_file.display().to_string());
+        let mut graph = ModuleGraph {
+            modules: vec![ModuleNode {
+                name: entry_name,
+                error: None,
+                imports: Vec::new(),
+            }],
+        };
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut sources: Vec<Option<(PathBuf, String)>> =
+            vec![Some((entry_file.to_path_buf(), entry_source.to_string()))];
+
+        let mut current = 0;
+        while current < graph.modules.len() {
+            let Some((file, source)) = sources[current].take() else {
+                current += 1;
+                continue;
+            };
+            let statements =
+                match Lexer::with_source_and_file(&source, source.clone(), file.clone()).lex() {
+                    // Imports that parsed still count when other statements do not
+                    Ok(tokens) => {
+                        Parser::with_source_and_file(tokens, source.clone(), file)
+                            .parse_program_recovering()
+                            .0
+                            .statements
+                    }
+                    Err(err) => {
+                        graph.modules[current].error = Some(err.to_string());
+                        Vec::new()
+                    }
+                };
+            for statement in &statements {
+                let (uses, conditional) = match statement {
+                    Statement::Use(use_stmt) => (vec![use_stmt], false),
+                    Statement::ConditionalUse {
+                        then, otherwise, ..
+                    } => (std::iter::once(then).chain(otherwise).collect(), true),
+                    _ => continue,
+                };
+                for use_stmt in uses {
+                    let module_path = use_stmt.module_path();
+                    let module = match indices.get(module_path) {
+                        Some(&module) => module,
+                        None => {
+                            let (error, source) = match loader.load(base_dir, module_path) {
+                                Ok(loaded) => (None, Some(loaded)),
+                                Err(err) => (Some(err.to_string()), None),
+                            };
+                            graph.modules.push(ModuleNode {
+                                name: module_path.to_string(),
+                                error,
+                                imports: Vec::new(),
+                            });
+                            sources.push(source);
+                            indices.insert(module_path.to_string(), graph.modules.len() - 1);
+                            graph.modules.len() - 1
+                        }
+                    };
+                    graph.modules[current].imports.push(Import {
+                        module,
+                        conditional,
+                    });
+                }
+            }
+            current += 1;
+        }
+        graph
+    }
+
+    /// The order modules finish loading in when the program runs: each
+    /// after the modules it imports, the entry file last. Modules that
+    /// could not be loaded are left out, and so is the import that closes
+    /// a cycle.
+    pub fn initialization_order(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        self.walk(
+            0,
+            &mut Vec::new(),
+            &mut vec![None; self.modules.len()],
+            &mut |event| {
+                if let Event::Finished(module) = event {
+                    order.push(module);
+                }
+            },
+        );
+        order
+    }
+
+    /// Every import cycle, as the modules along it from the first one
+    /// reached back to that module again.
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        let mut cycles = Vec::new();
+        self.walk(
+            0,
+            &mut Vec::new(),
+            &mut vec![None; self.modules.len()],
+            &mut |event| {
+                if let Event::Cycle(cycle) = event {
+                    cycles.push(cycle);
+                }
+            },
+        );
+        cycles
+    }
+
+    /// Modules that an unconditional import names but that could not be
+    /// loaded.
+    pub fn missing(&self) -> Vec<usize> {
+        (0..self.modules.len())
+            .filter(|&index| self.modules[index].error.is_some())
+            .filter(|&index| {
+                self.modules.iter().any(|module| {
+                    module
+                        .imports
+                        .iter()
+                        .any(|import| import.module == index && !import.conditional)
+                })
+            })
+            .collect()
+    }
+
+    /// Depth first from `module`, in import order; `path` holds the modules
+    /// being visited.
+    fn walk(
+        &self,
+        module: usize,
+        path: &mut Vec<usize>,
+        visits: &mut Vec<Option<Visit>>,
+        report: &mut dyn FnMut(Event),
+    ) {
+        visits[module] = Some(Visit::Open);
+        path.push(module);
+        for import in &self.modules[module].imports {
+            match visits[import.module] {
+                None if self.modules[import.module].error.is_none() => {
+                    self.walk(import.module, path, visits, report)
+                }
+                None => visits[import.module] = Some(Visit::Done),
+                Some(Visit::Open) => {
+                    let start = path
+                        .iter()
+                        .position(|&open| open == import.module)
+                        .expect("open modules are on the path");
+                    let mut cycle = path[start..].to_vec();
+                    cycle.push(import.module);
+                    report(Event::Cycle(cycle));
+                }
+                Some(Visit::Done) => {}
+            }
+        }
+        path.pop();
+        visits[module] = Some(Visit::Done);
+        report(Event::Finished(module));
+    }
+
+    /// The imports as an indented tree under the entry file. A module is
+    /// expanded where it is first imported and marked `see above` after
+    /// that.
+    pub fn tree(&self) -> String {
+        let mut out = format!("{}\n", self.modules[0].name);
+        let mut shown = vec![false; self.modules.len()];
+        shown[0] = true;
+        let mut path = vec![0];
+        self.tree_children(0, "", &mut shown, &mut path, &mut out);
+        out
+    }
+
+    fn tree_children(
+        &self,
+        module: usize,
+        indent: &str,
+        shown: &mut [bool],
+        path: &mut Vec<usize>,
+        out: &mut String,
+    ) {
+        let imports = &self.modules[module].imports;
+        for (position, import) in imports.iter().enumerate() {
+            let last = position + 1 == imports.len();
+            let child = &self.modules[import.module];
+            let mut notes = Vec::new();
+            if import.conditional {
+                notes.push("conditional");
+            }
+            if child.error.is_some() {
+                notes.push("not loaded");
+            }
+            let expand = if path.contains(&import.module) {
+                notes.push("cycle");
+                false
+            } else if shown[import.module] {
+                notes.push("see above");
+                false
+            } else {
+                true
+            };
+            let notes = if notes.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", notes.join(", "))
+            };
+            let branch = if last { "└── " } else { "├── " };
+            out.push_str(&format!("{}{}{}{}\n", indent, branch, child.name, notes));
+            if expand {
+                shown[import.module] = true;
+                path.push(import.module);
+                let indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+                self.tree_children(import.module, &indent, shown, path, out);
+                path.pop();
+            }
+        }
+    }
+
+    /// The graph in Graphviz DOT. Conditional imports are dashed, and
+    /// modules that could not be loaded and imports that close a cycle are
+    /// red.
+    pub fn dot(&self) -> String {
+        let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+        let closing: Vec<(usize, usize)> = self
+            .cycles()
+            .iter()
+            .map(|cycle| (cycle[cycle.len() - 2], cycle[cycle.len() - 1]))
+            .collect();
+        let mut out = String::from("digraph modules {\n");
+        for module in &self.modules {
+            let attributes = if module.error.is_some() {
+                " [color=red]"
+            } else {
+                ""
+            };
+            out.push_str(&format!("  {}{};\n", quote(&module.name), attributes));
+        }
+        for (index, module) in self.modules.iter().enumerate() {
+            for import in &module.imports {
+                let mut attributes = Vec::new();
+                if import.conditional {
+                    attributes.push("style=dashed");
+                }
+                if closing.contains(&(index, import.module)) {
+                    attributes.push("color=red");
+                }
+                let attributes = if attributes.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", attributes.join(", "))
+                };
+                out.push_str(&format!(
+                    "  {} -> {}{};\n",
+                    quote(&module.name),
+                    quote(&self.modules[import.module].name),
+                    attributes
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+enum Event {
+    Finished(usize),
+    Cycle(Vec<usize>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::MemoryLoader;
+
+    #[test]
+    fn imports_form_a_tree_an_order_and_cycles() {
+        let loader = MemoryLoader::new()
+            .with_module("app/users", "use * from \"db\"\nuse * from \"app/audit\"\n")
+            .with_module("db", "connect: () { null }\n")
+            .with_module("app/audit", "use * from \"app/users\"\n");
+        let entry =
+            "use * from \"app/users\"\nuse { connect } from \"db\"\nuse * from \"missing\"\n";
+        let graph = ModuleGraph::build(&loader, None, Path::new("src/main.fip"), entry);
+        let names = |indices: Vec<usize>| -> Vec<&str> {
+            indices
+                .into_iter()
+                .map(|index| graph.modules[index].name.as_str())
+                .collect()
+        };
+
+        assert_eq!(
+            graph.tree(),
+            "main.fip\n\
+             ├── app/users\n\
+             │   ├── db\n\
+             │   └── app/audit\n\
+             │       └── app/users (cycle)\n\
+             ├── db (see above)\n\
+             └── missing (not loaded)\n"
+        );
+        assert_eq!(
+            names(graph.initialization_order()),
+            ["db", "app/audit", "app/users", "main.fip"]
+        );
+        assert_eq!(
+            graph.cycles().into_iter().map(names).collect::<Vec<_>>(),
+            [["app/users", "app/audit", "app/users"]]
+        );
+        assert_eq!(names(graph.missing()), ["missing"]);
+        assert!(graph
+            .dot()
+            .contains("  \"app/audit\" -> \"app/users\" [color=red];\n"));
+        assert!(graph.dot().contains("  \"missing\" [color=red];\n"));
+    }
+}
@@ -0,0 +1,127 @@
+//! The naming convention for bindings, functions, parameters, imports, and
+//! exports: lower-case kebab-case, with an optional `!` or `?` suffix. The
+//! parser accepts any name the lexer reads; `fip-lint` checks names against
+//! these rules, which teams can relax with [`NamingRules`].
+
+/// Which departures from plain kebab-case are accepted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NamingRules {
+    /// Segments written entirely in capitals, such as `parse-HTML` or
+    /// `HTTP-status`.
+    pub allow_acronyms: bool,
+    /// Underscores between words, such as `user_name`.
+    pub allow_underscores: bool,
+}
+
+/// Why `name` breaks the convention, or `Ok` when it follows it.
+pub fn check_kebab_case(name: &str, rules: NamingRules) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Identifier name cannot be empty".to_string());
+    }
+
+    // The purity and predicate suffixes are not part of the words
+    let base_name = name
+        .strip_suffix('!')
+        .or_else(|| name.strip_suffix('?'))
+        .unwrap_or(name);
+    if base_name.is_empty() {
+        return Err(format!(
+            "Identifier '{}' must have a name before the suffix",
+            name
+        ));
+    }
+
+    let separators: &[char] = if rules.allow_underscores {
+        &['-', '_']
+    } else {
+        &['-']
+    };
+    if base_name.starts_with(separators) || base_name.ends_with(separators) {
+        return Err(format!(
+            "Identifier '{}' cannot start or end with a hyphen",
+            name
+        ));
+    }
+
+    let mut has_letter = false;
+    for segment in base_name.split(separators) {
+        if segment.is_empty() {
+            return Err(format!(
+                "Identifier '{}' cannot contain consecutive hyphens",
+                name
+            ));
+        }
+        let acronym = rules.allow_acronyms
+            && segment.chars().any(|ch| ch.is_ascii_uppercase())
+            && !segment.chars().any(|ch| ch.is_ascii_lowercase());
+        for ch in segment.chars() {
+            match ch {
+                'a'..='z' => has_letter = true,
+                'A'..='Z' if acronym => has_letter = true,
+                '0'..='9' if !has_letter => {
+                    return Err(format!(
+                        "Identifier '{}' must start with a lowercase letter",
+                        name
+                    ));
+                }
+                '0'..='9' => {}
+                '_' => {
+                    return Err(format!(
+                        "Identifier '{}' contains underscore. Identifiers must use kebab-case (lowercase letters, digits, and hyphens, not underscores)",
+                        name
+                    ));
+                }
+                'A'..='Z' if rules.allow_acronyms => {
+                    return Err(format!(
+                        "Identifier '{}' mixes cases in one word. Acronyms must be all capitals, as in 'parse-HTML'",
+                        name
+                    ));
+                }
+                _ => {
+                    return Err(format!(
+                        "Identifier '{}' contains invalid character '{}'. Identifiers must use kebab-case (lowercase letters, digits, and hyphens)",
+                        name, ch
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_follow_kebab_case_unless_the_rules_relax_it() {
+        let strict = NamingRules::default();
+        for name in ["user-name", "is-active?", "log-action!", "vec-2d", "x"] {
+            assert_eq!(check_kebab_case(name, strict), Ok(()), "{}", name);
+        }
+        for (name, problem) in [
+            ("user_name", "contains underscore"),
+            ("userName", "invalid character 'N'"),
+            ("parse-HTML", "invalid character 'H'"),
+            ("-name", "cannot start or end with a hyphen"),
+            ("a--b", "consecutive hyphens"),
+            ("!", "must have a name before the suffix"),
+        ] {
+            let message = check_kebab_case(name, strict).expect_err(name);
+            assert!(message.contains(problem), "{}: {}", name, message);
+        }
+
+        let relaxed = NamingRules {
+            allow_acronyms: true,
+            allow_underscores: true,
+        };
+        for name in ["parse-HTML", "HTTP-status?", "user_name", "to_JSON"] {
+            assert_eq!(check_kebab_case(name, relaxed), Ok(()), "{}", name);
+        }
+        let message = check_kebab_case("userName", relaxed).expect_err("mixed case");
+        assert!(message.contains("mixes cases"), "{}", message);
+        let message = check_kebab_case("a__b", relaxed).expect_err("doubled separator");
+        assert!(message.contains("consecutive"), "{}", message);
+    }
+}
@@ -1,18 +1,22 @@
 use crate::{
     ast::{
         BinaryOperator, ExportStatement, Expression, Function, ObjectField, ObjectPatternField,
-        Pattern, Program, Statement, StringSegment, StringTemplate, UseStatement,
+        Pattern, Program, Signature, Statement, StringSegment, StringTemplate, TypeExpr,
+        UseStatement,
     },
     error::{byte_offset_to_line, LangError, LangResult, Location},
     lexer::{Lexer, Token, TokenKind},
+    semantic::{self, PurityViolationKind},
 };
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf, rc::Rc};
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     source: String,
     file_path: PathBuf,
+    statement_starts: Vec<usize>,
+    block_steps: BTreeMap<usize, Vec<usize>>,
 }
 
 impl Parser {
@@ -22,6 +26,8 @@ impl Parser {
             current: 0,
             source: String::new(),
             file_path: PathBuf::from("<unknown>"),
+            statement_starts: Vec::new(),
+            block_steps: BTreeMap::new(),
         }
     }
 
@@ -31,9 +37,70 @@ impl Parser {
             current: 0,
             source,
             file_path,
+            statement_starts: Vec::new(),
+            block_steps: BTreeMap::new(),
         }
     }
 
+    /// Parses a standalone type such as `((a) -> b, [a]) -> [b]`.
+    pub fn parse_type_source(source: &str) -> LangResult<TypeExpr> {
+        let mut parser = Parser::new(Lexer::new(source).lex()?);
+        let ty = parser.parse_type()?;
+        parser.skip_newlines();
+        if !parser.is_at_end() {
+            return Err(parser.error_with_location(format!(
+                "Unexpected {:?} after type",
+                parser.current_kind()
+            )));
+        }
+        Ok(ty)
+    }
+
+    /// Byte offsets where each top-level statement of the last parsed
+    /// program starts, in statement order.
+    pub fn statement_starts(&self) -> &[usize] {
+        &self.statement_starts
+    }
+
+    /// Byte offsets where each step of each block in the last parsed
+    /// program starts, function bodies included, keyed by where the
+    /// block's `{` is. Blocks inside string interpolations are left out.
+    pub fn block_steps(&self) -> &BTreeMap<usize, Vec<usize>> {
+        &self.block_steps
+    }
+
+    /// The `//!` lines that open the source, with the markers removed.
+    fn module_doc(&self) -> Option<String> {
+        let lines = self
+            .source
+            .lines()
+            .map(str::trim_start)
+            .skip_while(|line| line.is_empty())
+            .map_while(|line| line.strip_prefix("//!"));
+        doc_text(lines)
+    }
+
+    /// Stores the `///` lines directly above the statement starting at
+    /// `start` on functions and bindings. Comments are not tokens, so this
+    /// reads the source and finds nothing for parsers made with `new`.
+    fn attach_doc(&self, statement: &mut Statement, start: usize) {
+        let doc = match statement {
+            Statement::Function(Function { doc, .. }) | Statement::Assignment { doc, .. } => doc,
+            _ => return,
+        };
+        let before = self.source.get(..start).unwrap_or_default();
+        // Drop the partial line the statement starts on
+        let before = &before[..before.rfind('\n').map_or(0, |end| end + 1)];
+        let mut lines: Vec<&str> = before
+            .lines()
+            .rev()
+            .map(str::trim_start)
+            .map_while(|line| line.strip_prefix("///"))
+            .collect();
+        lines.reverse();
+        *doc = doc_text(lines);
+    }
+
     fn error_with_location(&self, msg: String) -> LangError {
         let location = if self.current < self.tokens.len() {
             let token = &self.tokens[self.current];
@@ -52,24 +119,121 @@ impl Parser {
     pub fn parse_program(&mut self) -> LangResult<Program> {
         let mut statements = Vec::new();
         let mut statement_starts = Vec::new();
+        self.block_steps.clear();
 
         self.skip_newlines();
 
         while !self.is_at_end() {
             let start_pos = self.current_token().span.start;
             statement_starts.push(start_pos);
-            statements.push(self.parse_statement()?);
+            let mut statement = self.parse_statement()?;
+            self.attach_doc(&mut statement, start_pos);
+            statements.push(statement);
             self.skip_newlines();
         }
 
-        let program = Program { statements };
+        let program = Program {
+            statements,
+            doc: self.module_doc(),
+        };
 
         // Validate variable restrictions with statement start positions
         self.validate_program(&program, &statement_starts)?;
+        self.statement_starts = statement_starts;
 
         Ok(program)
     }
 
+    /// Parses the whole program, recording each failing statement instead of
+    /// stopping at the first one. After an error the parser skips ahead to
+    /// the next line that starts at column zero and resumes there. Returns
+    /// the statements that parsed successfully together with every error.
+    pub fn parse_program_recovering(&mut self) -> (Program, Vec<LangError>) {
+        let mut statements = Vec::new();
+        let mut statement_starts = Vec::new();
+        let mut errors = Vec::new();
+        self.block_steps.clear();
+
+        self.skip_newlines();
+
+        while !self.is_at_end() {
+            let start_pos = self.current_token().span.start;
+            let start_index = self.current;
+            match self.parse_statement() {
+                Ok(mut statement) => {
+                    self.attach_doc(&mut statement, start_pos);
+                    statement_starts.push(start_pos);
+                    statements.push(statement);
+                }
+                Err(err) => {
+                    errors.push(err);
+                    // Resume after the failing token, which may itself start
+                    // a line (a stray closing bracket, say).
+                    self.synchronize(self.current.max(start_index));
+                }
+            }
+            self.skip_newlines();
+        }
+
+        let program = Program {
+            statements,
+            doc: self.module_doc(),
+        };
+
+        if errors.is_empty() {
+            if let Err(err) = self.validate_program(&program, &statement_starts) {
+                errors.push(err);
+            }
+        }
+        self.statement_starts = statement_starts;
+
+        (program, errors)
+    }
+
+    /// Completes a program from top-level statements parsed separately,
+    /// checking them together as `parse_program` does and reading the module
+    /// doc. The parser's tokens and source must cover the whole program.
+    pub(crate) fn finish_program(
+        &mut self,
+        statements: Vec<Statement>,
+        statement_starts: Vec<usize>,
+    ) -> (Program, Vec<LangError>) {
+        let program = Program {
+            statements,
+            doc: self.module_doc(),
+        };
+        let errors = self
+            .validate_program(&program, &statement_starts)
+            .err()
+            .into_iter()
+            .collect();
+        self.statement_starts = statement_starts;
+        (program, errors)
+    }
+
+    /// Skips to the start of the next top-level statement after `failed_at`:
+    /// the first token after a newline that begins at column zero.
+    fn synchronize(&mut self, failed_at: usize) {
+        while !self.is_at_end() {
+            if self.current > failed_at && self.at_line_start() {
+                return;
+            }
+            self.current += 1;
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        let token = self.current_token();
+        let after_newline = self.current > 0
+            && matches!(self.tokens[self.current - 1].kind, TokenKind::Newline)
+            && !matches!(token.kind, TokenKind::Newline);
+        // Without source text every line break counts as a statement boundary.
+        let at_column_zero = self.source.is_empty()
+            || token.span.start == 0
+            || self.source.as_bytes().get(token.span.start - 1) == Some(&b'\n');
+        after_newline && at_column_zero
+    }
+
     fn validate_program(&self, program: &Program, statement_starts: &[usize]) -> LangResult<()> {
         use std::collections::HashSet;
 
@@ -82,9 +246,6 @@ impl Parser {
                     // Validate pattern and collect all identifiers
                     let identifiers = self.collect_pattern_identifiers(pattern)?;
                     for name in &identifiers {
-                        // Validate kebab-case
-                        self.validate_kebab_case(name)?;
-
                         // Check for duplicate binding
                         if defined_names.contains(name) {
                             // Find the identifier in this statement
@@ -99,9 +260,6 @@ impl Parser {
                     }
                 }
                 Statement::Function(func) => {
-                    // Validate kebab-case for function name
-                    self.validate_kebab_case(&func.name)?;
-
                     // Check for duplicate binding
                     if defined_names.contains(&func.name) {
                         let error_location =
@@ -112,15 +270,9 @@ impl Parser {
                         ));
                     }
                     defined_names.insert(func.name.clone());
-
-                    // Validate parameter names (they should also be kebab-case)
-                    for param in &func.params {
-                        self.validate_kebab_case(param)?;
-                    }
                 }
                 Statement::Use(use_stmt) => match use_stmt {
                     UseStatement::Single { name, .. } => {
-                        self.validate_kebab_case(name)?;
                         if defined_names.contains(name) {
                             let error_location =
                                 self.find_identifier_in_statement(statement_start, name);
@@ -132,7 +284,6 @@ impl Parser {
                         defined_names.insert(name.clone());
                     }
                     UseStatement::Namespace { alias, .. } => {
-                        self.validate_kebab_case(alias)?;
                         if defined_names.contains(alias) {
                             let error_location =
                                 self.find_identifier_in_statement(statement_start, alias);
@@ -145,7 +296,6 @@ impl Parser {
                     }
                     UseStatement::Selective { names, .. } => {
                         for name in names {
-                            self.validate_kebab_case(name)?;
                             if defined_names.contains(name) {
                                 let error_location =
                                     self.find_identifier_in_statement(statement_start, name);
@@ -157,20 +307,64 @@ impl Parser {
                             defined_names.insert(name.clone());
                         }
                     }
+                    UseStatement::Glob { .. } => {
+                        // Glob imports bind names only once the module is loaded
+                    }
                 },
-                Statement::Export(export) => {
-                    // Exports don't create bindings, but validate the name format
-                    self.validate_kebab_case(&export.name)?;
+                Statement::ConditionalUse { .. } => {
+                    // Which names are bound depends on the condition at runtime
                 }
-                Statement::Expression(_) => {
-                    // Expressions don't create bindings
+                Statement::Export(_) | Statement::Expression(_) => {
+                    // Neither creates a binding
                 }
             }
         }
 
+        if let Some(violation) = semantic::check_purity(program).into_iter().next() {
+            let statement_start = statement_starts
+                .get(violation.statement)
+                .copied()
+                .unwrap_or(0);
+            let statement_end = statement_starts.get(violation.statement + 1).copied();
+            let error_location = match &violation.kind {
+                PurityViolationKind::MissingImpureMarker(call) => call
+                    .occurrence
+                    .and_then(|occurrence| {
+                        self.find_nth_identifier(
+                            statement_start,
+                            statement_end,
+                            &call.name,
+                            occurrence,
+                        )
+                    })
+                    .unwrap_or(statement_start),
+                PurityViolationKind::NeedlessImpureMarker => statement_start,
+            };
+            return Err(self.error_at_location(error_location, violation.message()));
+        }
+
         Ok(())
     }
 
+    /// Finds the `occurrence`-th token (counting from zero) naming `name`
+    /// between two byte offsets.
+    fn find_nth_identifier(
+        &self,
+        start: usize,
+        end: Option<usize>,
+        name: &str,
+        occurrence: usize,
+    ) -> Option<usize> {
+        self.tokens
+            .iter()
+            .filter(|token| {
+                token.span.start >= start && end.is_none_or(|end| token.span.start < end)
+            })
+            .filter(|token| matches!(&token.kind, TokenKind::Identifier(id) if id == name))
+            .nth(occurrence)
+            .map(|token| token.span.start)
+    }
+
     fn find_identifier_in_statement(&self, statement_start: usize, name: &str) -> usize {
         // Find the token that starts at or after statement_start
         let mut token_index = 0;
@@ -243,103 +437,6 @@ impl Parser {
         Ok(identifiers)
     }
 
-    fn validate_kebab_case(&self, name: &str) -> LangResult<()> {
-        // Check if name is empty
-        if name.is_empty() {
-            return Err(self.error_with_location("Identifier name cannot be empty".to_string()));
-        }
-
-        // Handle function suffixes (! and ?) - strip them for validation
-        let base_name = if name.ends_with('!') || name.ends_with('?') {
-            &name[..name.len() - 1]
-        } else {
-            name
-        };
-
-        // After stripping suffix, base name cannot be empty
-        if base_name.is_empty() {
-            return Err(self.error_with_location(format!(
-                "Identifier '{}' must have a name before the suffix",
-                name
-            )));
-        }
-
-        // Check if base name starts or ends with hyphen
-        if base_name.starts_with('-') || base_name.ends_with('-') {
-            return Err(self.error_with_location(format!(
-                "Identifier '{}' cannot start or end with a hyphen",
-                name
-            )));
-        }
-
-        // Check for consecutive hyphens
-        if base_name.contains("--") {
-            return Err(self.error_with_location(format!(
-                "Identifier '{}' cannot contain consecutive hyphens",
-                name
-            )));
-        }
-
-        // Check that all characters are lowercase letters, digits, or hyphens
-        // and that it follows kebab-case pattern
-        let mut chars = base_name.chars().peekable();
-        let mut has_letter = false;
-
-        while let Some(ch) = chars.next() {
-            match ch {
-                'a'..='z' => {
-                    has_letter = true;
-                }
-                '0'..='9' => {
-                    // Digits are allowed but name must start with a letter
-                    if !has_letter {
-                        return Err(self.error_with_location(format!(
-                            "Identifier '{}' must start with a lowercase letter",
-                            name
-                        )));
-                    }
-                }
-                '-' => {
-                    // Hyphens are allowed but must be followed by a letter or digit
-                    if let Some(&next) = chars.peek() {
-                        if !matches!(next, 'a'..='z' | '0'..='9') {
-                            return Err(self.error_with_location(
-                                format!("Identifier '{}' must have a lowercase letter or digit after each hyphen", name)
-                            ));
-                        }
-                    } else {
-                        // Hyphen at end is already caught above
-                        return Err(self.error_with_location(format!(
-                            "Identifier '{}' cannot end with a hyphen",
-                            name
-                        )));
-                    }
-                }
-                '_' => {
-                    // Underscores are not allowed in kebab-case
-                    return Err(self.error_with_location(
-                        format!("Identifier '{}' contains underscore. Identifiers must use kebab-case (lowercase letters, digits, and hyphens, not underscores)", name)
-                    ));
-                }
-                _ => {
-                    return Err(self.error_with_location(
-                        format!("Identifier '{}' contains invalid character '{}'. Identifiers must use kebab-case (lowercase letters, digits, and hyphens)", name, ch)
-                    ));
-                }
-            }
-        }
-
-        // Name must contain at least one letter
-        if !has_letter {
-            return Err(self.error_with_location(format!(
-                "Identifier '{}' must contain at least one letter",
-                name
-            )));
-        }
-
-        Ok(())
-    }
-
     fn parse_statement(&mut self) -> LangResult<Statement> {
         self.skip_newlines();
         let start_index = self.current;
@@ -384,11 +481,14 @@ impl Parser {
                     let params_result = self.parse_parameter_list();
 
                     match params_result {
-                        Ok(params) => {
+                        Ok((params, param_types)) => {
                             self.skip_newlines();
+                            // `(a + b)` starts like a parameter list; anything
+                            // but ')' after the names means it is an expression
                             match self.expect(TokenKind::RParen, "Expected ')' after parameters") {
                                 Ok(()) => {
                                     self.skip_newlines();
+                                    let result = self.parse_result_annotation()?;
                                     if matches!(self.current_kind(), TokenKind::LBrace) {
                                         self.advance();
                                         let body_expressions = self.parse_block_contents()?;
@@ -400,14 +500,19 @@ impl Parser {
                                         return Ok(Statement::Function(Function {
                                             name: name.clone(),
                                             params,
-                                            body: Expression::Block(body_expressions),
+                                            signature: Signature {
+                                                params: param_types,
+                                                result,
+                                            },
+                                            body: Rc::new(Expression::Block(body_expressions)),
                                             impure,
+                                            doc: None,
                                         }));
                                     } else {
                                         self.current = expr_start;
                                     }
                                 }
-                                Err(err) => return Err(err),
+                                Err(_) => self.current = expr_start,
                             }
                         }
                         Err(err) => {
@@ -423,7 +528,11 @@ impl Parser {
             self.current = expr_start;
             self.skip_newlines();
             let expr = self.parse_expression()?;
-            return Ok(Statement::Assignment { pattern, expr });
+            return Ok(Statement::Assignment {
+                pattern,
+                expr,
+                doc: None,
+            });
         }
 
         self.current = start_index;
@@ -481,7 +590,7 @@ impl Parser {
                             if matches!(
                                 self.current_kind(),
                                 TokenKind::StringLiteral(_)
-                                    | TokenKind::Number(_)
+                                    | TokenKind::Number { .. }
                                     | TokenKind::Boolean(_)
                                     | TokenKind::Null
                                     | TokenKind::LParen
@@ -574,11 +683,12 @@ impl Parser {
         None
     }
 
-    fn parse_parameter_list(&mut self) -> LangResult<Vec<String>> {
+    fn parse_parameter_list(&mut self) -> LangResult<(Vec<String>, Vec<Option<TypeExpr>>)> {
         let mut params = Vec::new();
+        let mut param_types = Vec::new();
         self.skip_newlines();
         if matches!(self.current_kind(), TokenKind::RParen) {
-            return Ok(params);
+            return Ok((params, param_types));
         }
 
         loop {
@@ -588,9 +698,8 @@ impl Parser {
                     self.error_with_location("Parameter names cannot end with '!'".to_string())
                 );
             }
-            // Validate kebab-case for parameter names
-            self.validate_kebab_case(&name)?;
             params.push(name);
+            param_types.push(self.parse_param_annotation()?);
 
             self.skip_newlines();
             if matches!(self.current_kind(), TokenKind::Comma) {
@@ -600,7 +709,112 @@ impl Parser {
                 break;
             }
         }
-        Ok(params)
+        Ok((params, param_types))
+    }
+
+    /// Parses the optional `: Type` after a parameter name.
+    fn parse_param_annotation(&mut self) -> LangResult<Option<TypeExpr>> {
+        if !matches!(self.current_kind(), TokenKind::Colon) {
+            return Ok(None);
+        }
+        self.advance();
+        self.skip_newlines();
+        self.parse_type().map(Some)
+    }
+
+    /// Parses the optional `: Type` between a parameter list and the body.
+    fn parse_result_annotation(&mut self) -> LangResult<Option<TypeExpr>> {
+        if !matches!(self.current_kind(), TokenKind::Colon) {
+            return Ok(None);
+        }
+        self.advance();
+        self.skip_newlines();
+        let result = self.parse_type()?;
+        self.skip_newlines();
+        if !matches!(self.current_kind(), TokenKind::LBrace) {
+            return Err(self
+                .error_with_location("Expected '{' after the result type annotation".to_string()));
+        }
+        Ok(Some(result))
+    }
+
+    fn parse_type(&mut self) -> LangResult<TypeExpr> {
+        match self.current_kind().clone() {
+            TokenKind::Identifier(name) => {
+                self.advance();
+                if name.starts_with(|c: char| c.is_ascii_uppercase()) {
+                    if !TypeExpr::NAMES.contains(&name.as_str()) {
+                        return Err(self.error_with_location(format!(
+                            "Unknown type '{}'; expected one of {}",
+                            name,
+                            TypeExpr::NAMES.join(", ")
+                        )));
+                    }
+                    Ok(TypeExpr::Named(name))
+                } else {
+                    Ok(TypeExpr::Variable(name))
+                }
+            }
+            TokenKind::LBracket => {
+                self.advance();
+                self.skip_newlines();
+                let element = self.parse_type()?;
+                self.skip_newlines();
+                self.expect(TokenKind::RBracket, "Expected ']' after list element type")?;
+                Ok(TypeExpr::List(Box::new(element)))
+            }
+            TokenKind::LBrace => {
+                self.advance();
+                let mut fields = Vec::new();
+                loop {
+                    self.skip_newlines();
+                    if matches!(self.current_kind(), TokenKind::RBrace) {
+                        break;
+                    }
+                    let name = self.consume_identifier("Expected field name in object type")?;
+                    self.expect(TokenKind::Colon, "Expected ':' after field name")?;
+                    self.skip_newlines();
+                    fields.push((name, self.parse_type()?));
+                    self.skip_newlines();
+                    if matches!(self.current_kind(), TokenKind::Comma) {
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+                self.skip_newlines();
+                self.expect(TokenKind::RBrace, "Expected '}' after object type")?;
+                Ok(TypeExpr::Object(fields))
+            }
+            TokenKind::LParen => {
+                self.advance();
+                let mut params = Vec::new();
+                loop {
+                    self.skip_newlines();
+                    if matches!(self.current_kind(), TokenKind::RParen) {
+                        break;
+                    }
+                    params.push(self.parse_type()?);
+                    self.skip_newlines();
+                    if matches!(self.current_kind(), TokenKind::Comma) {
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+                self.skip_newlines();
+                self.expect(TokenKind::RParen, "Expected ')' after parameter types")?;
+                self.expect(TokenKind::Minus, "Expected '->' after parameter types")?;
+                self.expect(
+                    TokenKind::GreaterThan,
+                    "Expected '->' after parameter types",
+                )?;
+                self.skip_newlines();
+                let result = self.parse_type()?;
+                Ok(TypeExpr::Function(params, Box::new(result)))
+            }
+            other => Err(self.error_with_location(format!("Expected a type, found {:?}", other))),
+        }
     }
 
     fn parse_expression(&mut self) -> LangResult<Expression> {
@@ -642,10 +856,18 @@ impl Parser {
             self.advance();
             let expr = self.parse_unary_expression()?;
             Ok(Expression::Binary {
-                left: Box::new(Expression::Number(0)),
+                left: Box::new(Expression::Number {
+                    value: 0,
+                    spelling: None,
+                }),
                 op: BinaryOperator::Sub,
                 right: Box::new(expr),
             })
+        } else if matches!(self.current_kind(), TokenKind::Exclamation) {
+            // A `!` that is not an identifier's impure suffix negates
+            self.advance();
+            let expr = self.parse_unary_expression()?;
+            Ok(Expression::Not(Box::new(expr)))
         } else {
             self.parse_call_expression()
         }
@@ -667,7 +889,8 @@ impl Parser {
                     callee: Box::new(expr),
                     args,
                 };
-            } else if matches!(self.current_kind(), TokenKind::Dot) {
+            } else if matches!(self.current_kind(), TokenKind::Dot | TokenKind::SafeDot) {
+                let optional = matches!(self.current_kind(), TokenKind::SafeDot);
                 self.advance();
                 self.skip_newlines();
                 let property = match self.current_kind().clone() {
@@ -675,7 +898,7 @@ impl Parser {
                         self.advance();
                         name
                     }
-                    TokenKind::Number(value) => {
+                    TokenKind::Number { value, .. } => {
                         if value < 0 {
                             return Err(self.error_with_location(
                                 "List indices must be non-negative".to_string(),
@@ -693,7 +916,26 @@ impl Parser {
                 expr = Expression::PropertyAccess {
                     object: Box::new(expr),
                     property,
+                    optional,
+                };
+            } else if matches!(self.current_kind(), TokenKind::Identifier(name) if name == "with")
+                && matches!(
+                    self.peek_non_newline_kind(self.current + 1),
+                    Some(TokenKind::LBrace)
+                )
+            {
+                // `record with { field: value }` copies the record with
+                // the listed fields replaced
+                self.advance();
+                self.expect(TokenKind::LBrace, "Expected '{' after 'with'")?;
+                let Some(Expression::Object(fields)) = self.try_parse_object()? else {
+                    return Err(self.error_with_location(
+                        "Expected fields such as { name: value } after 'with'".to_string(),
+                    ));
                 };
+                let mut copy = vec![ObjectField::Base(expr)];
+                copy.extend(fields);
+                expr = Expression::Object(copy);
             } else {
                 break;
             }
@@ -704,9 +946,9 @@ impl Parser {
 
     fn parse_primary_expression(&mut self) -> LangResult<Expression> {
         match self.current_kind().clone() {
-            TokenKind::Number(value) => {
+            TokenKind::Number { value, spelling } => {
                 self.advance();
-                Ok(Expression::Number(value))
+                Ok(Expression::Number { value, spelling })
             }
             TokenKind::Boolean(value) => {
                 self.advance();
@@ -768,6 +1010,10 @@ impl Parser {
             if matches!(self.current_kind(), TokenKind::Comma) {
                 self.advance();
                 self.skip_newlines();
+                // Allow a trailing comma before the closing parenthesis
+                if matches!(self.current_kind(), TokenKind::RParen) {
+                    break;
+                }
             } else {
                 break;
             }
@@ -796,6 +1042,10 @@ impl Parser {
             if matches!(self.current_kind(), TokenKind::Comma) {
                 self.advance();
                 self.skip_newlines();
+                // Allow a trailing comma before the closing bracket
+                if matches!(self.current_kind(), TokenKind::RBracket) {
+                    break;
+                }
             } else {
                 break;
             }
@@ -818,6 +1068,8 @@ impl Parser {
             TokenKind::GreaterThanEq => BinaryOperator::GreaterThanEq,
             TokenKind::Ampersand => BinaryOperator::And,
             TokenKind::Pipe => BinaryOperator::Or,
+            TokenKind::Coalesce => BinaryOperator::Coalesce,
+            TokenKind::PipeForward => BinaryOperator::PipeForward,
             other => {
                 return Err(
                     self.error_with_location(format!("Expected operator but found {:?}", other))
@@ -830,16 +1082,18 @@ impl Parser {
 
     fn current_precedence(&self) -> Option<u8> {
         match self.current_kind() {
-            TokenKind::Pipe => Some(0),
-            TokenKind::Ampersand => Some(1),
+            TokenKind::PipeForward => Some(0),
+            TokenKind::Coalesce => Some(1),
+            TokenKind::Pipe => Some(2),
+            TokenKind::Ampersand => Some(3),
             TokenKind::Equal
             | TokenKind::NotEqual
             | TokenKind::LessThan
             | TokenKind::LessThanEq
             | TokenKind::GreaterThan
-            | TokenKind::GreaterThanEq => Some(2),
-            TokenKind::Plus | TokenKind::Minus => Some(3),
-            TokenKind::Star | TokenKind::Slash => Some(4),
+            | TokenKind::GreaterThanEq => Some(4),
+            TokenKind::Plus | TokenKind::Minus => Some(5),
+            TokenKind::Star | TokenKind::Slash => Some(6),
             _ => None,
         }
     }
@@ -857,7 +1111,7 @@ impl Parser {
                 }
                 let mut expr_content = String::new();
                 let mut found_end = false;
-                while let Some(inner) = chars.next() {
+                for inner in chars.by_ref() {
                     if inner == '>' {
                         found_end = true;
                         break;
@@ -966,6 +1220,7 @@ impl Parser {
         self.skip_newlines();
 
         let mut params = Vec::new();
+        let mut param_types = Vec::new();
         if matches!(self.current_kind(), TokenKind::RParen) {
             self.advance();
         } else {
@@ -977,10 +1232,10 @@ impl Parser {
                                 "Parameter names cannot end with '!'".to_string(),
                             ));
                         }
-                        // Validate kebab-case for parameter names
-                        self.validate_kebab_case(&name)?;
                         params.push(name);
                         self.advance();
+                        // `(x: Number)` can only be a lambda
+                        param_types.push(self.parse_param_annotation()?);
                     }
                     _ => {
                         self.current = start;
@@ -1018,6 +1273,8 @@ impl Parser {
             self.skip_newlines();
         }
 
+        let result = self.parse_result_annotation()?;
+
         if !matches!(self.current_kind(), TokenKind::LBrace) {
             self.current = start;
             return Ok(None);
@@ -1028,7 +1285,11 @@ impl Parser {
 
         Ok(Some(Expression::Lambda {
             params,
-            body: Box::new(Expression::Block(body_expressions)),
+            signature: Signature {
+                params: param_types,
+                result,
+            },
+            body: Rc::new(Expression::Block(body_expressions)),
             impure,
         }))
     }
@@ -1105,19 +1366,25 @@ impl Parser {
         Ok(Some(Expression::Object(fields)))
     }
 
+    /// Parses the steps of a block whose `{` was just consumed.
     fn parse_block_contents(&mut self) -> LangResult<Vec<Expression>> {
+        let open = self.tokens[self.current - 1].span.start;
         let mut expressions = Vec::new();
+        let mut starts = Vec::new();
         self.skip_newlines();
 
         while !matches!(self.current_kind(), TokenKind::RBrace) {
             if self.is_at_end() {
                 return Err(self.error_with_location("Unterminated block expression".to_string()));
             }
+            starts.push(self.current_token().span.start);
             let expr = self.parse_expression()?;
             expressions.push(expr);
             self.skip_newlines();
         }
 
+        // A block parsed again after backtracking replaces its first parse
+        self.block_steps.insert(open, starts);
         Ok(expressions)
     }
 
@@ -1162,11 +1429,24 @@ impl Parser {
                 );
             }
             self.skip_newlines();
-            let module_path = self.parse_module_path()?;
-            return Ok(Statement::Use(UseStatement::Selective {
-                names,
+            return self.finish_use_statement(|module_path| UseStatement::Selective {
+                names: names.clone(),
                 module_path,
-            }));
+            });
+        }
+
+        // Check for glob import: use * from "..."
+        if matches!(self.current_kind(), TokenKind::Star) {
+            self.advance(); // consume '*'
+            self.skip_newlines();
+            match self.current_kind() {
+                TokenKind::Identifier(name) if name == "from" => {
+                    self.advance();
+                }
+                _ => return Err(self.error_with_location("Expected 'from' after '*'".to_string())),
+            }
+            self.skip_newlines();
+            return self.finish_use_statement(|module_path| UseStatement::Glob { module_path });
         }
 
         // Check for namespace import: use name as alias from "..."
@@ -1190,11 +1470,10 @@ impl Parser {
                     return Err(self.error_with_location("Expected 'from' after alias".to_string()));
                 }
                 self.skip_newlines();
-                let module_path = self.parse_module_path()?;
-                return Ok(Statement::Use(UseStatement::Namespace {
-                    alias,
+                return self.finish_use_statement(|module_path| UseStatement::Namespace {
+                    alias: alias.clone(),
                     module_path,
-                }));
+                });
             }
         }
 
@@ -1203,17 +1482,59 @@ impl Parser {
             if name == "from" {
                 self.advance(); // consume 'from'
                 self.skip_newlines();
-                let module_path = self.parse_module_path()?;
-                return Ok(Statement::Use(UseStatement::Single {
-                    name: first_name,
+                return self.finish_use_statement(|module_path| UseStatement::Single {
+                    name: first_name.clone(),
                     module_path,
-                }));
+                });
             }
         }
 
         Err(self.error_with_location("Expected 'from' after import name".to_string()))
     }
 
+    /// Parses the module source after `from`: either a path or
+    /// `when(condition, "path")` with an optional fallback path as a third
+    /// argument.
+    fn finish_use_statement(
+        &mut self,
+        build: impl Fn(String) -> UseStatement,
+    ) -> LangResult<Statement> {
+        let is_when = matches!(self.current_kind(), TokenKind::Identifier(name) if name == "when")
+            && matches!(
+                self.tokens.get(self.current + 1).map(|token| &token.kind),
+                Some(TokenKind::LParen)
+            );
+        if !is_when {
+            let module_path = self.parse_module_path()?;
+            return Ok(Statement::Use(build(module_path)));
+        }
+
+        self.advance(); // consume 'when'
+        self.advance(); // consume '('
+        self.skip_newlines();
+        let condition = self.parse_expression()?;
+        self.skip_newlines();
+        self.expect(TokenKind::Comma, "Expected ',' after condition in 'when'")?;
+        self.skip_newlines();
+        let then_path = self.parse_module_path()?;
+        self.skip_newlines();
+        let otherwise_path = if matches!(self.current_kind(), TokenKind::Comma) {
+            self.advance();
+            self.skip_newlines();
+            Some(self.parse_module_path()?)
+        } else {
+            None
+        };
+        self.skip_newlines();
+        self.expect(TokenKind::RParen, "Expected ')' to close 'when'")?;
+
+        Ok(Statement::ConditionalUse {
+            condition,
+            then: build(then_path),
+            otherwise: otherwise_path.map(build),
+        })
+    }
+
     fn parse_export_statement(&mut self) -> LangResult<Statement> {
         self.advance(); // consume 'export'
         self.skip_newlines();
@@ -1234,6 +1555,17 @@ impl Parser {
     }
 }
 
+/// Joins doc comment lines, dropping the space after the marker, or
+/// `None` when there are none.
+fn doc_text<'a>(lines: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let lines: Vec<&str> = lines
+        .into_iter()
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect();
+    let text = lines.join("\n").trim_end().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1253,4 +1585,185 @@ mod tests {
             other => panic!("expected lambda, got {:?}", other),
         }
     }
+
+    #[test]
+    fn recovering_parse_reports_every_broken_statement() {
+        let source = "a: (1 +\nb: 2\nc: )\nd: 4\n";
+        let tokens =
+            Lexer::with_source_and_file(source, source.to_string(), PathBuf::from("t.fip"))
+                .lex()
+                .expect("lexing should succeed");
+        let mut parser =
+            Parser::with_source_and_file(tokens, source.to_string(), PathBuf::from("t.fip"));
+        let (program, errors) = parser.parse_program_recovering();
+        assert_eq!(errors.len(), 2);
+        let lines: Vec<usize> = errors
+            .iter()
+            .filter_map(|err| match err {
+                LangError::Parser(_, Some(location)) => Some(location.line),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(lines, vec![2, 3]);
+        assert_eq!(program.statements.len(), 1);
+    }
+
+    #[test]
+    fn accepts_trailing_commas_in_lists_and_calls() {
+        let source = "xs: [\n  1,\n  2,\n]\ny: add(\n  1,\n  2,\n)\n";
+        let tokens = Lexer::new(source).lex().expect("lexing should succeed");
+        let program = Parser::new(tokens)
+            .parse_program()
+            .expect("trailing commas should parse");
+        assert_eq!(program.statements.len(), 2);
+    }
+
+    #[test]
+    fn recovering_parse_reports_a_stray_closing_bracket_once() {
+        let source = "xs: [\n  1 +\n]\ny: 2\n";
+        let tokens =
+            Lexer::with_source_and_file(source, source.to_string(), PathBuf::from("t.fip"))
+                .lex()
+                .expect("lexing should succeed");
+        let mut parser =
+            Parser::with_source_and_file(tokens, source.to_string(), PathBuf::from("t.fip"));
+        let (program, errors) = parser.parse_program_recovering();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(program.statements.len(), 1);
+    }
+
+    #[test]
+    fn purity_errors_point_at_the_impure_call() {
+        let source = "show!: (x) { log!(x) }\nf: (x) {\n  x + 1\n  log!(x)\n}\n";
+        let tokens = Lexer::new(source).lex().expect("lexing should succeed");
+        let mut parser =
+            Parser::with_source_and_file(tokens, source.to_string(), PathBuf::from("t.fip"));
+        match parser.parse_program() {
+            Err(LangError::Parser(message, Some(location))) => {
+                assert_eq!(
+                    message,
+                    "Function 'f' must be declared impure (end the name with '!') to call 'log!'"
+                );
+                assert_eq!(location.line, 4);
+            }
+            other => panic!("expected purity error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pipe_forward_binds_looser_than_every_other_operator() {
+        let tokens = Lexer::new("x + 1 |> f ?? g |> h")
+            .lex()
+            .expect("lexing should succeed");
+        let expr = Parser::new(tokens)
+            .parse_expression()
+            .expect("parsing should succeed");
+        let Expression::Binary {
+            left,
+            op: BinaryOperator::PipeForward,
+            right,
+        } = expr
+        else {
+            panic!("expected a pipe, got {:?}", expr);
+        };
+        assert_eq!(*right, Expression::Identifier("h".to_string()));
+        assert!(matches!(
+            *left,
+            Expression::Binary {
+                op: BinaryOperator::PipeForward,
+                ref left,
+                ref right,
+            } if matches!(**left, Expression::Binary { op: BinaryOperator::Add, .. })
+                && matches!(**right, Expression::Binary { op: BinaryOperator::Coalesce, .. })
+        ));
+    }
+
+    #[test]
+    fn doc_comments_attach_to_the_definition_directly_below() {
+        let source = "//! Module notes.\n\n///   Adds one.\n/// Twice.\ninc: (n) { n + 1 }\n\n/// Detached.\n\nx: 1\n  /// Indented.\ny: 2\n";
+        let tokens = Lexer::new(source).lex().expect("lexing should succeed");
+        let program = Parser::with_source_and_file(tokens, source.to_string(), "m.fip".into())
+            .parse_program()
+            .expect("parsing should succeed");
+        assert_eq!(program.doc.as_deref(), Some("Module notes."));
+        let docs: Vec<Option<&str>> = program
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::Function(Function { doc, .. }) | Statement::Assignment { doc, .. } => {
+                    doc.as_deref()
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(docs, [Some("  Adds one.\nTwice."), None, Some("Indented.")]);
+    }
+
+    #[test]
+    fn with_update_desugars_to_a_copy_of_the_base_record() {
+        let tokens = Lexer::new("person.home with { city: \"Oslo\" }")
+            .lex()
+            .expect("lexing should succeed");
+        let expr = Parser::new(tokens)
+            .parse_expression()
+            .expect("parsing should succeed");
+        let Expression::Object(fields) = expr else {
+            panic!("expected an object, got {:?}", expr);
+        };
+        assert!(matches!(
+            &fields[..],
+            [
+                ObjectField::Base(Expression::PropertyAccess { property, .. }),
+                ObjectField::Field { name, .. },
+            ] if property == "home" && name == "city"
+        ));
+
+        let tokens = Lexer::new("with-age: 1\nwith: 2\nx: with")
+            .lex()
+            .expect("lexing should succeed");
+        Parser::new(tokens)
+            .parse_program()
+            .expect("'with' is still an ordinary name elsewhere");
+    }
+
+    #[test]
+    fn reads_hex_binary_and_separated_number_literals() {
+        let source = "[1_000_000, 0xff, 0B1010, 42]";
+        let tokens = Lexer::new(source).lex().expect("lexing should succeed");
+        let expr = Parser::new(tokens)
+            .parse_expression()
+            .expect("parsing should succeed");
+        let Expression::List(items) = expr else {
+            panic!("expected list, got {:?}", expr);
+        };
+        let numbers: Vec<(i64, Option<String>)> = items
+            .into_iter()
+            .map(|item| match item {
+                Expression::Number { value, spelling } => (value, spelling),
+                other => panic!("expected number, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            numbers,
+            vec![
+                (1_000_000, Some("1_000_000".to_string())),
+                (255, Some("0xff".to_string())),
+                (10, Some("0B1010".to_string())),
+                (42, None),
+            ]
+        );
+
+        for (source, reason) in [
+            ("1__0", "underscores must sit between digits"),
+            ("0x", "expected digits after the prefix"),
+            ("0b12", "'2' is not a base 2 digit"),
+        ] {
+            match Lexer::new(source).lex() {
+                Err(LangError::Lexer(message, _)) => {
+                    assert!(message.ends_with(reason), "{} gave {}", source, message)
+                }
+                other => panic!("expected lex error for {}, got {:?}", source, other),
+            }
+        }
+    }
 }
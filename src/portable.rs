@@ -0,0 +1,213 @@
+//! Copies of values that can move between threads. Values share their
+//! parts through `Rc`, so they stay on the thread that made them;
+//! `parallel-map!` and `spawn!` copy what they hand to a worker into a
+//! [`Portable`], the worker rebuilds it in an interpreter of its own, and
+//! results come back the same way. A function travels as the source text
+//! of its lambda together with the bindings its body reads, so the copy
+//! behaves like the original as long as those bindings are plain data or
+//! functions themselves.
+
+use crate::{
+    ast::{Expression, ObjectField, Signature, Slot, Statement, StringSegment},
+    error::{LangError, LangResult},
+    formatter::Formatter,
+    lexer::Lexer,
+    parser::Parser,
+    resolve,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+/// A value without shared parts. Functions are numbered entries of the
+/// [`Package`] they travel in, so functions that refer to each other, or
+/// to themselves, are copied once.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Portable {
+    Number(i64),
+    String(String),
+    Bytes(Vec<u8>),
+    Boolean(bool),
+    List(Vec<Portable>),
+    Set(Vec<Portable>),
+    Map(Vec<(Portable, Portable)>),
+    Object(BTreeMap<String, Portable>),
+    Function(usize),
+    /// Builtins are looked up by name in the receiving interpreter.
+    Builtin(String),
+    Partial {
+        callee: Box<Portable>,
+        applied: Vec<Portable>,
+    },
+    /// Channels are numbered in the table the interpreters share.
+    Channel(usize),
+    Null,
+    Unit,
+}
+
+/// A function copied out of its interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortableFunction {
+    pub name: String,
+    pub impure: bool,
+    /// The function written as a lambda, `(params) { body }`.
+    pub source: String,
+    /// The captures and top-level bindings the body reads, by name.
+    pub bindings: Vec<(String, Portable)>,
+}
+
+/// Values copied together, with the functions they refer to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Package {
+    pub functions: Vec<PortableFunction>,
+    pub values: Vec<Portable>,
+}
+
+/// What a function body reads from outside its parameters.
+#[derive(Debug, Default, PartialEq)]
+pub struct FreeVariables {
+    /// Bindings the closure captured, by name and position among its
+    /// captures.
+    pub captures: BTreeMap<String, usize>,
+    /// Names looked up at the top level when the body runs.
+    pub globals: BTreeSet<String>,
+}
+
+/// The captures and top-level names `body` reads, including those read by
+/// the closures it creates.
+pub fn free_variables(body: &Expression) -> FreeVariables {
+    let mut free = FreeVariables::default();
+    collect(body, true, &mut free);
+    free
+}
+
+/// Walks `expr`; `own` is whether it belongs to the function being copied
+/// rather than to a closure nested inside it, whose slots count from its
+/// own scope.
+fn collect(expr: &Expression, own: bool, free: &mut FreeVariables) {
+    match expr {
+        Expression::Identifier(name) => {
+            free.globals.insert(name.clone());
+        }
+        Expression::Local(slot) => {
+            if own {
+                capture(slot, free);
+            }
+        }
+        Expression::Closure { lambda, captures } => {
+            if own {
+                captures.iter().for_each(|slot| capture(slot, free));
+            }
+            collect(lambda, false, free);
+        }
+        Expression::Lambda { body, .. } => collect(body, false, free),
+        Expression::Call { callee, args } => {
+            collect(callee, own, free);
+            args.iter().for_each(|arg| collect(arg, own, free));
+        }
+        Expression::Binary { left, right, .. } => {
+            collect(left, own, free);
+            collect(right, own, free);
+        }
+        Expression::Block(expressions) | Expression::List(expressions) => {
+            expressions.iter().for_each(|expr| collect(expr, own, free));
+        }
+        Expression::Object(fields) => {
+            for field in fields {
+                match field {
+                    ObjectField::Field { value, .. } => collect(value, own, free),
+                    ObjectField::Spread(expr) | ObjectField::Base(expr) => collect(expr, own, free),
+                }
+            }
+        }
+        Expression::Spread(expr) | Expression::Not(expr) => collect(expr, own, free),
+        Expression::PropertyAccess { object, .. } => collect(object, own, free),
+        Expression::String(template) => {
+            for segment in &template.segments {
+                if let StringSegment::Expr(expr) = segment {
+                    collect(expr, own, free);
+                }
+            }
+        }
+        Expression::Boolean(_) | Expression::Number { .. } | Expression::Null => {}
+    }
+}
+
+/// Records a slot that reads one of the closure's captures; parameters
+/// arrive with each call and are not copied.
+fn capture(slot: &Slot, free: &mut FreeVariables) {
+    if slot.depth == 1 {
+        free.captures.insert(slot.name.clone(), slot.index);
+    }
+}
+
+/// A function with these parameters and body, written as a lambda.
+pub fn lambda_source(params: &[String], body: &Expression, impure: bool) -> String {
+    let lambda = Expression::Lambda {
+        params: params.to_vec(),
+        signature: Signature::default(),
+        body: Rc::new(body.clone()),
+        impure,
+    };
+    Formatter::new().format_expression(&lambda)
+}
+
+/// Parses and resolves a lambda written by [`lambda_source`], returning its
+/// parameters and body. Everything the body reads besides its parameters
+/// is left to be looked up by name.
+pub fn parse_lambda(source: &str) -> LangResult<(Vec<String>, Rc<Expression>)> {
+    let mut program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+    resolve::resolve_program(&mut program);
+    if let [Statement::Expression(Expression::Closure { lambda, .. })] =
+        program.statements.as_slice()
+    {
+        if let Expression::Lambda { params, body, .. } = lambda.as_ref() {
+            return Ok((params.clone(), Rc::clone(body)));
+        }
+    }
+    Err(LangError::Runtime(
+        format!("Copied function did not parse as a lambda: {}", source),
+        None,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functions_round_trip_through_their_source() -> LangResult<()> {
+        let source = "scale: (k) { (n) { [n * k, offset, (m) { m + k }] } }";
+        let mut program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        resolve::resolve_program(&mut program);
+        let Statement::Function(scale) = &program.statements[0] else {
+            panic!("expected a function");
+        };
+        let Expression::Block(steps) = scale.body.as_ref() else {
+            panic!("expected a block");
+        };
+        let Expression::Closure { lambda, .. } = &steps[0] else {
+            panic!("expected a closure");
+        };
+        let Expression::Lambda { params, body, .. } = lambda.as_ref() else {
+            panic!("expected a lambda");
+        };
+
+        let free = free_variables(body);
+        assert_eq!(free.captures, BTreeMap::from([("k".to_string(), 0)]));
+        assert_eq!(free.globals, BTreeSet::from(["offset".to_string()]));
+
+        let source = lambda_source(params, body, false);
+        let (params, body) = parse_lambda(&source)?;
+        assert_eq!(params, ["n"]);
+        assert_eq!(lambda_source(&params, &body, false), source);
+        let free = free_variables(&body);
+        assert!(free.captures.is_empty());
+        assert_eq!(
+            free.globals,
+            BTreeSet::from(["k".to_string(), "offset".to_string()])
+        );
+        Ok(())
+    }
+}
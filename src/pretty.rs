@@ -0,0 +1,112 @@
+//! Renders values the way they would be written in source, for assertion
+//! failures. Unlike `log!`, strings keep their quotes so `"1"` and `1` read
+//! differently, and lists and records too long for one line are broken up
+//! with one element per line.
+
+use crate::interpreter::Value;
+
+const WIDTH: usize = 60;
+
+pub fn pretty(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, 0, &mut out);
+    out
+}
+
+fn write_value(value: &Value, indent: usize, out: &mut String) {
+    let flat = inline(value);
+    if flat.len() + indent <= WIDTH {
+        out.push_str(&flat);
+        return;
+    }
+    let pad = " ".repeat(indent + 2);
+    match value {
+        Value::List(items) => {
+            out.push_str("[\n");
+            for item in items {
+                out.push_str(&pad);
+                write_value(item, indent + 2, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&" ".repeat(indent));
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            out.push_str("{\n");
+            for (key, field) in fields {
+                out.push_str(&pad);
+                out.push_str(key);
+                out.push_str(": ");
+                write_value(field, indent + 2, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&" ".repeat(indent));
+            out.push('}');
+        }
+        _ => out.push_str(&flat),
+    }
+}
+
+fn inline(value: &Value) -> String {
+    match value {
+        Value::String(text) => quote(text),
+        Value::List(items) => format!("[{}]", join(items.iter().map(inline))),
+        Value::Set(items) => format!("<set [{}]>", join(items.iter().map(inline))),
+        Value::Map(entries) => format!(
+            "<map {{{}}}>",
+            join(
+                entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", inline(key), inline(value)))
+            )
+        ),
+        Value::Object(fields) if fields.is_empty() => "{}".to_string(),
+        Value::Object(fields) => format!(
+            "{{ {} }}",
+            join(
+                fields
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, inline(value)))
+            )
+        ),
+        other => format!("{:?}", other),
+    }
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(", ")
+}
+
+fn quote(text: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn quotes_strings_and_breaks_long_collections() {
+        let record = Value::Object(BTreeMap::from([
+            ("name".to_string(), Value::String("say \"hi\"".to_string())),
+            ("tags".to_string(), Value::List(vec![Value::Null])),
+        ]));
+        assert_eq!(pretty(&record), r#"{ name: "say \"hi\"", tags: [null] }"#);
+
+        let long = Value::List(vec![Value::String("x".repeat(30)); 2]);
+        let expected = format!("[\n  \"{0}\",\n  \"{0}\",\n]", "x".repeat(30));
+        assert_eq!(pretty(&long), expected);
+    }
+}
@@ -0,0 +1,183 @@
+//! Instrumenting profiler behind `fip run --profile`. The interpreter reports
+//! every function and builtin call as it starts and returns; the profiler
+//! keeps a stack of open calls so each one's time can be split into time spent
+//! in its own body and time spent in the calls it made.
+
+use crate::{error::LangError, hooks::Hooks, interpreter::Value};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Aggregated timings for every call to one function name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub calls: u64,
+    /// Time from entry to return. Recursive calls are only counted once,
+    /// at the outermost call, so this never exceeds the run time.
+    pub total: Duration,
+    /// `total` minus the time spent in calls made from this function.
+    pub self_time: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Profiler {
+    stack: Vec<Frame>,
+    functions: HashMap<String, FunctionStats>,
+    /// Self time per call path, such as `main;parse;split`.
+    stacks: HashMap<String, Duration>,
+}
+
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    path: String,
+    started: Instant,
+    children: Duration,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enter(&mut self, name: &str, now: Instant) {
+        let path = match self.stack.last() {
+            Some(parent) => format!("{};{}", parent.path, name),
+            None => name.to_string(),
+        };
+        self.stack.push(Frame {
+            name: name.to_string(),
+            path,
+            started: now,
+            children: Duration::ZERO,
+        });
+    }
+
+    /// Closes the most recent call. Calls that fail are closed the same way.
+    pub fn exit(&mut self, now: Instant) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = now.saturating_duration_since(frame.started);
+        let self_time = elapsed.saturating_sub(frame.children);
+        let recursive = self.stack.iter().any(|open| open.name == frame.name);
+        let stats = self.functions.entry(frame.name).or_default();
+        stats.calls += 1;
+        stats.self_time += self_time;
+        if !recursive {
+            stats.total += elapsed;
+        }
+        *self.stacks.entry(frame.path).or_default() += self_time;
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+    }
+
+    /// Per-function statistics, by descending self time and then by name.
+    pub fn functions(&self) -> Vec<(&str, FunctionStats)> {
+        let mut functions: Vec<_> = self
+            .functions
+            .iter()
+            .map(|(name, stats)| (name.as_str(), *stats))
+            .collect();
+        functions.sort_by(|(a_name, a), (b_name, b)| {
+            b.self_time.cmp(&a.self_time).then(a_name.cmp(b_name))
+        });
+        functions
+    }
+
+    /// A table of the per-function statistics, hottest first.
+    pub fn report(&self) -> String {
+        let functions = self.functions();
+        let width = functions
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0)
+            .max("function".len());
+        let mut report = format!(
+            "{:<width$}  {:>8}  {:>12}  {:>12}\n",
+            "function",
+            "calls",
+            "total ms",
+            "self ms",
+            width = width
+        );
+        for (name, stats) in functions {
+            report.push_str(&format!(
+                "{:<width$}  {:>8}  {:>12.3}  {:>12.3}\n",
+                name,
+                stats.calls,
+                stats.total.as_secs_f64() * 1000.0,
+                stats.self_time.as_secs_f64() * 1000.0,
+                width = width
+            ));
+        }
+        report
+    }
+
+    /// Self time per call path in the folded-stacks format read by
+    /// flamegraph tools: one `a;b;c <microseconds>` line per path.
+    pub fn folded_stacks(&self) -> String {
+        let mut paths: Vec<_> = self.stacks.iter().collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|(path, time)| format!("{} {}\n", path, time.as_micros()))
+            .collect()
+    }
+}
+
+impl Hooks for Profiler {
+    fn on_call(&mut self, name: &str, _args: &[Value], _depth: usize) {
+        self.enter(name, Instant::now());
+    }
+
+    fn on_return(&mut self, _name: &str, _result: Result<&Value, &LangError>, _depth: usize) {
+        self.exit(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_time_between_callers_and_callees() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let mut profiler = Profiler::new();
+        profiler.enter("main", at(0));
+        profiler.enter("fib", at(1));
+        profiler.enter("fib", at(2));
+        profiler.exit(at(5));
+        profiler.enter("add", at(6));
+        profiler.exit(at(7));
+        profiler.exit(at(9));
+        profiler.exit(at(10));
+
+        let stats = |name: &str| {
+            profiler
+                .functions()
+                .into_iter()
+                .find(|(found, _)| *found == name)
+                .map(|(_, stats)| stats)
+                .expect("function was profiled")
+        };
+        let ms = Duration::from_millis;
+        assert_eq!(
+            stats("fib"),
+            FunctionStats {
+                calls: 2,
+                total: ms(8),
+                self_time: ms(7),
+            }
+        );
+        assert_eq!(stats("main").self_time, ms(2));
+        assert_eq!(profiler.functions()[0].0, "fib");
+        assert_eq!(
+            profiler.folded_stacks(),
+            "main 2000\nmain;fib 4000\nmain;fib;add 1000\nmain;fib;fib 3000\n"
+        );
+        assert!(profiler.report().starts_with("function     calls"));
+    }
+}
@@ -0,0 +1,136 @@
+//! Choice sequences behind `for-all` and the `gen-*` builtins. A generator
+//! never sees randomness directly: it asks for a number up to some bound
+//! and builds its value from the answers. `for-all` records the answers of
+//! each case, so a failing case can be replayed from a smaller or shorter
+//! sequence. Generators map small answers to simple values (0, empty lists,
+//! the first alternative), so shrinking the sequence shrinks the value, even
+//! through `gen-map` and hand-written generators.
+
+/// Cases tried by one `for-all`.
+pub const CASES: u64 = 100;
+
+/// The largest size, which bounds list and string lengths; cases start at
+/// size 0 and grow towards it.
+const MAX_SIZE: u64 = 20;
+
+/// Replays allowed while shrinking one failure.
+pub const SHRINK_BUDGET: usize = 500;
+
+/// The answers for one generated case.
+pub struct Choices {
+    /// Answers to give back before drawing new ones; `None` when drawing
+    /// fresh answers.
+    replay: Option<Vec<u64>>,
+    rng: u64,
+    /// Every answer given so far, in order.
+    pub used: Vec<u64>,
+    pub size: u64,
+}
+
+impl Choices {
+    /// Fresh answers for case number `case`.
+    pub fn random(seed: u64, case: u64) -> Self {
+        Self {
+            replay: None,
+            rng: seed ^ case.wrapping_mul(0x9e37_79b9_7f4a_7c15),
+            used: Vec::new(),
+            size: case * MAX_SIZE / CASES,
+        }
+    }
+
+    /// Gives back `answers` in order, then zeros once they run out.
+    pub fn replay(answers: Vec<u64>, size: u64) -> Self {
+        Self {
+            replay: Some(answers),
+            rng: 0,
+            used: Vec::new(),
+            size,
+        }
+    }
+
+    /// An answer between 0 and `bound`, inclusive.
+    pub fn draw(&mut self, bound: u64) -> u64 {
+        let answer = match &self.replay {
+            Some(answers) => answers
+                .get(self.used.len())
+                .copied()
+                .unwrap_or(0)
+                .min(bound),
+            None => {
+                let random = splitmix64(&mut self.rng);
+                match bound.checked_add(1) {
+                    Some(range) => random % range,
+                    None => random,
+                }
+            }
+        };
+        self.used.push(answer);
+        answer
+    }
+}
+
+/// Smaller variants of a failing sequence, most promising first: chunks
+/// removed, then single answers zeroed, halved, or decremented.
+pub fn candidates(answers: &[u64]) -> Vec<Vec<u64>> {
+    let mut result = Vec::new();
+    for chunk in [8, 4, 2, 1] {
+        for start in (0..answers.len().saturating_sub(chunk - 1)).rev() {
+            let mut shorter = answers[..start].to_vec();
+            shorter.extend_from_slice(&answers[start + chunk..]);
+            result.push(shorter);
+        }
+    }
+    for (index, &answer) in answers.iter().enumerate() {
+        let mut smaller = vec![0, answer / 2, answer.saturating_sub(1)];
+        smaller.dedup();
+        for value in smaller.into_iter().filter(|&value| value < answer) {
+            let mut changed = answers.to_vec();
+            changed[index] = value;
+            result.push(changed);
+        }
+    }
+    result
+}
+
+/// Whether `left` is a simpler sequence than `right`: shorter, or as long
+/// and smaller at the first difference.
+pub fn simpler(left: &[u64], right: &[u64]) -> bool {
+    (left.len(), left) < (right.len(), right)
+}
+
+/// SplitMix64, a small generator that is good enough for test cases and
+/// reproducible from its seed.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_answers_and_offers_simpler_candidates() {
+        let mut fresh = Choices::random(1, 50);
+        let drawn: Vec<u64> = (0..5).map(|_| fresh.draw(9)).collect();
+        assert!(drawn.iter().all(|&answer| answer <= 9));
+        assert_eq!(fresh.used, drawn);
+
+        let mut replayed = Choices::replay(vec![7, 30], 10);
+        assert_eq!(
+            [replayed.draw(9), replayed.draw(9), replayed.draw(9)],
+            [7, 9, 0]
+        );
+
+        let candidates = candidates(&[3, 5]);
+        assert!(candidates.contains(&vec![5]));
+        assert!(candidates.contains(&vec![0, 5]));
+        assert!(candidates.contains(&vec![3, 2]));
+        assert!(candidates
+            .iter()
+            .all(|candidate| simpler(candidate, &[3, 5])));
+    }
+}
@@ -0,0 +1,327 @@
+//! Refactorings that rewrite a file through its syntax tree, behind
+//! `fip extract` and `fip inline`. Each returns the edits to make, like
+//! [`rename_binding`](crate::rename::rename_binding), and checks that the
+//! file still parses afterwards.
+
+use crate::{
+    ast::{Expression, ObjectField, Pattern, Statement, StringSegment},
+    error::byte_offset_to_line,
+    lexer::TokenKind,
+    rename::{self, Kind},
+    semantic,
+    source_file::TextEdit,
+};
+use std::ops::Range;
+
+/// Moves the expression at `range` into a new top-level function `name`
+/// and calls it there instead. Parameters of the functions around the
+/// expression that it reads become the new function's parameters, in the
+/// order the expression first reads them. The function goes above the
+/// statement the expression was in, before its doc comment.
+pub fn extract_function(
+    source: &str,
+    range: Range<usize>,
+    name: &str,
+) -> Result<Vec<TextEdit>, String> {
+    rename::check_name(name)?;
+    let parsed = rename::parse(source)?;
+    let selected = source
+        .get(range.clone())
+        .ok_or("The selection is outside the file")?;
+    let start = range.start + (selected.len() - selected.trim_start().len());
+    let end = range.start + selected.trim_end().len();
+    let text = &source[start..end];
+    let whole_tokens = parsed.tokens.iter().any(|token| token.span.start == start)
+        && parsed.tokens.iter().any(|token| token.span.end == end);
+    if text.is_empty() || !whole_tokens {
+        return Err("Select a whole expression".to_string());
+    }
+
+    // The selection is one expression of the tree exactly when wrapping it
+    // in parentheses leaves the tree as it was
+    let wrapped = format!("{}({}){}", &source[..start], text, &source[end..]);
+    let unchanged =
+        rename::parse(&wrapped).is_ok_and(|wrapped| wrapped.statements == parsed.statements);
+    let expression = match rename::parse(text).map(|parsed| parsed.statements) {
+        Ok(statements) if unchanged => match <[Statement; 1]>::try_from(statements) {
+            Ok([Statement::Expression(expression)]) => expression,
+            _ => return Err("Select a whole expression".to_string()),
+        },
+        _ => return Err("Select a whole expression".to_string()),
+    };
+
+    let impure = semantic::find_impure_call(&expression);
+    match (&impure, name.ends_with('!')) {
+        (Some(call), false) => {
+            return Err(format!(
+                "The selection uses '{}', so the new function's name must end in '!'",
+                call.name
+            ))
+        }
+        (None, true) => {
+            return Err(format!(
+                "The selection performs no impure operations, so '{}' must not end in '!'",
+                name
+            ))
+        }
+        _ => {}
+    }
+    let statement = parsed
+        .starts
+        .iter()
+        .rposition(|&statement_start| statement_start <= start)
+        .ok_or("Select a whole expression")?;
+    rename::check_unused(&rename::mentions(source, &parsed, &[name]), name)?;
+
+    let around = rename::params_at(&parsed.statement_tokens(statement), start);
+    let mut params = Vec::new();
+    free_names(&expression, &mut Vec::new(), &mut params);
+    params.retain(|param| around.contains(param));
+    let params = params.join(", ");
+
+    let insert_at = line_start_with_docs(source, parsed.starts[statement]);
+    let edits = vec![
+        TextEdit {
+            range: insert_at..insert_at,
+            text: format!("{}: ({}) {{\n  {}\n}}\n\n", name, params, text),
+        },
+        TextEdit {
+            range: start..end,
+            text: format!("{}({})", name, params),
+        },
+    ];
+    rename::check_result(source, &edits, &format!("Extracting '{}'", name))?;
+    Ok(edits)
+}
+
+/// Replaces every use of the top-level binding `name` with its value and
+/// removes the binding. Only a pure value bound to a plain name can be
+/// inlined, and not when it is exported or when a parameter at one of its
+/// uses hides a name the value reads.
+pub fn inline_binding(source: &str, name: &str) -> Result<Vec<TextEdit>, String> {
+    let parsed = rename::parse(source)?;
+    let (index, expression) = parsed
+        .statements
+        .iter()
+        .enumerate()
+        .find_map(|(index, statement)| match statement {
+            Statement::Assignment {
+                pattern: Pattern::Identifier(bound),
+                expr,
+                ..
+            } if bound == name => Some((index, expr)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No top-level binding named '{}' with a value", name))?;
+    let exported = parsed
+        .statements
+        .iter()
+        .any(|statement| matches!(statement, Statement::Export(export) if export.name == name));
+    if exported {
+        return Err(format!(
+            "'{}' is exported, so other modules may read it",
+            name
+        ));
+    }
+    if let Some(call) = semantic::find_impure_call(expression) {
+        return Err(format!(
+            "'{}' uses '{}', which would run at every use instead of once",
+            name, call.name
+        ));
+    }
+
+    // The value is everything after the name and its `:`
+    let tokens = parsed.statement_tokens(index);
+    let value_start = tokens
+        .iter()
+        .skip(1)
+        .find(|token| !matches!(token.kind, TokenKind::Colon | TokenKind::Newline))
+        .map(|token| token.span.start)
+        .ok_or("The binding has no value")?;
+    let value_end = tokens.last().map_or(value_start, |token| token.span.end);
+    let value = &source[value_start..value_end];
+    let atomic = matches!(
+        expression,
+        Expression::Identifier(_)
+            | Expression::Number { .. }
+            | Expression::String(_)
+            | Expression::Boolean(_)
+            | Expression::Null
+            | Expression::List(_)
+            | Expression::Object(_)
+            | Expression::Call { .. }
+            | Expression::PropertyAccess { .. }
+    );
+    let replacement = if atomic {
+        value.to_string()
+    } else {
+        format!("({})", value)
+    };
+    let mut reads = Vec::new();
+    free_names(expression, &mut Vec::new(), &mut reads);
+
+    let definition =
+        line_start_with_docs(source, parsed.starts[index])..line_end(source, value_end);
+    let mut edits = vec![TextEdit {
+        range: definition.clone(),
+        text: String::new(),
+    }];
+    for mention in rename::mentions(source, &parsed, &[name]) {
+        if mention.kind != Kind::Free
+            || mention.params.iter().any(|param| param == name)
+            || definition.contains(&mention.span.start)
+        {
+            continue;
+        }
+        let line = byte_offset_to_line(source, mention.span.start);
+        if let Some(hidden) = reads.iter().find(|read| mention.params.contains(read)) {
+            return Err(format!(
+                "A parameter on line {} hides '{}', which the value reads",
+                line, hidden
+            ));
+        }
+        let in_string = parsed.tokens.iter().any(|token| {
+            token.span.start < mention.span.start && mention.span.end < token.span.end
+        });
+        if in_string && replacement.contains(['<', '>', '"']) {
+            return Err(format!(
+                "The value cannot be written inside the string on line {}",
+                line
+            ));
+        }
+        edits.push(TextEdit {
+            range: mention.span,
+            text: replacement.clone(),
+        });
+    }
+    rename::check_result(source, &edits, &format!("Inlining '{}'", name))?;
+    Ok(edits)
+}
+
+/// Where the line holding `offset` starts, moved up over the `///` lines
+/// directly above it.
+fn line_start_with_docs(source: &str, offset: usize) -> usize {
+    let mut start = source[..offset]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+    while start > 0 {
+        let above = source[..start - 1]
+            .rfind('\n')
+            .map_or(0, |newline| newline + 1);
+        if !source[above..start].trim_start().starts_with("///") {
+            break;
+        }
+        start = above;
+    }
+    start
+}
+
+/// Where the line after the one holding `offset` starts.
+fn line_end(source: &str, offset: usize) -> usize {
+    source[offset..]
+        .find('\n')
+        .map_or(source.len(), |newline| offset + newline + 1)
+}
+
+/// The names `expr` reads that it does not bind itself, in the order it
+/// first reads them.
+fn free_names(expr: &Expression, bound: &mut Vec<String>, names: &mut Vec<String>) {
+    match expr {
+        Expression::Identifier(name) => {
+            if !bound.contains(name) && !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        Expression::Local(slot) => {
+            if !bound.contains(&slot.name) && !names.contains(&slot.name) {
+                names.push(slot.name.clone());
+            }
+        }
+        Expression::Lambda { params, body, .. } => {
+            let outer = bound.len();
+            bound.extend(params.iter().cloned());
+            free_names(body, bound, names);
+            bound.truncate(outer);
+        }
+        Expression::Closure { lambda, .. } => free_names(lambda, bound, names),
+        Expression::Call { callee, args } => {
+            free_names(callee, bound, names);
+            for arg in args {
+                free_names(arg, bound, names);
+            }
+        }
+        Expression::Binary { left, right, .. } => {
+            free_names(left, bound, names);
+            free_names(right, bound, names);
+        }
+        Expression::Block(expressions) | Expression::List(expressions) => {
+            for expr in expressions {
+                free_names(expr, bound, names);
+            }
+        }
+        Expression::Object(fields) => {
+            for field in fields {
+                match field {
+                    ObjectField::Field { value, .. } => free_names(value, bound, names),
+                    ObjectField::Spread(expr) | ObjectField::Base(expr) => {
+                        free_names(expr, bound, names)
+                    }
+                }
+            }
+        }
+        Expression::PropertyAccess { object: expr, .. }
+        | Expression::Spread(expr)
+        | Expression::Not(expr) => free_names(expr, bound, names),
+        Expression::String(template) => {
+            for segment in &template.segments {
+                if let StringSegment::Expr(expr) = segment {
+                    free_names(expr, bound, names);
+                }
+            }
+        }
+        Expression::Number { .. } | Expression::Boolean(_) | Expression::Null => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rename::apply;
+
+    #[test]
+    fn extracts_an_expression_and_inlines_a_binding() -> Result<(), String> {
+        let source = "rate: 2\n\
+                      /// Prices with tax.\n\
+                      prices: map((p) { p * rate + 1 }, [1, 2])\n";
+        let selection = |text: &str| {
+            let start = source.find(text).expect("selected text");
+            start..start + text.len()
+        };
+        let edits = extract_function(source, selection("p * rate"), "taxed")?;
+        assert_eq!(
+            apply(source, &edits),
+            "rate: 2\n\
+             taxed: (p) {\n  p * rate\n}\n\n\
+             /// Prices with tax.\n\
+             prices: map((p) { taxed(p) + 1 }, [1, 2])\n"
+        );
+        // `rate + 1` is not one expression of `p * rate + 1`
+        assert!(extract_function(source, selection("rate + 1"), "bumped").is_err());
+        assert!(extract_function(source, selection("p * rate"), "log!").is_err());
+
+        let edits = inline_binding(source, "rate")?;
+        assert_eq!(
+            apply(source, &edits),
+            "/// Prices with tax.\n\
+             prices: map((p) { p * 2 + 1 }, [1, 2])\n"
+        );
+        let source = "sum: 1 + 2\ntotal: sum * 3\nsay: \"<sum>\"\n";
+        assert_eq!(
+            apply(source, &inline_binding(source, "sum")?),
+            "total: (1 + 2) * 3\nsay: \"<(1 + 2)>\"\n"
+        );
+        assert!(inline_binding("a: 1\nf: (a) { a }\nb: a\nexport a\n", "a").is_err());
+        assert!(inline_binding("a: b\nf: (b) { a }\nb: 1\n", "a").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,705 @@
+//! Renaming a top-level binding, behind `fip rename`. The edits come from
+//! the tokens of each statement, read with the scoping rules the parser
+//! applies: a name after `.` is a field, a name before `:` is a key unless it
+//! starts the statement, and a function's parameters hide the bindings they
+//! share a name with in its body. Names inside string interpolations are
+//! renamed too. Comments are left as they are.
+
+use crate::{
+    ast::{Pattern, Statement, UseStatement},
+    error::byte_offset_to_line,
+    interpreter::Interpreter,
+    lexer::{Lexer, Token, TokenKind},
+    parser::Parser,
+    source_file::TextEdit,
+};
+use std::ops::Range;
+
+/// The edits that rename a binding in the file that defines it.
+#[derive(Debug)]
+pub struct Renamed {
+    pub edits: Vec<TextEdit>,
+    /// Whether the file exports the binding, so modules that import it
+    /// need [`rename_import`] as well.
+    pub exported: bool,
+}
+
+/// Renames the top-level binding `old` to `new` in `source` and everything
+/// that refers to it there. Fails when the file does not parse, does not
+/// define `old`, or already uses `new`, and when the new name would be
+/// hidden by a parameter where the old one is read.
+pub fn rename_binding(source: &str, old: &str, new: &str) -> Result<Renamed, String> {
+    check_purity_marker(old, new)?;
+    check_name(new)?;
+    let parsed = parse(source)?;
+    let statements = &parsed.statements;
+    let defined = statements.iter().any(|statement| match statement {
+        Statement::Function(function) => function.name == old,
+        Statement::Assignment { pattern, .. } => pattern.names().contains(&old),
+        _ => false,
+    });
+    if !defined {
+        return Err(format!("No top-level binding named '{}'", old));
+    }
+    let exported = statements
+        .iter()
+        .any(|statement| matches!(statement, Statement::Export(export) if export.name == old));
+
+    let mentions = mentions(source, &parsed, &[old, new]);
+    check_unused(&mentions, new)?;
+    let edits = rename_free(source, &mentions, old, new)?;
+    check_result(source, &edits, &format!("Renaming to '{}'", new))?;
+    Ok(Renamed { edits, exported })
+}
+
+/// Renames `old` to `new` in a file that may import it from the renamed
+/// module. `imports_module` tells whether a `use` path names that module.
+/// Names listed in its `use` statements and read through them are renamed,
+/// and so are fields read as `alias.old` from a `use ... as alias` import.
+pub fn rename_import(
+    source: &str,
+    old: &str,
+    new: &str,
+    imports_module: &dyn Fn(&str) -> bool,
+) -> Result<Vec<TextEdit>, String> {
+    if !source.contains(old) {
+        return Ok(Vec::new());
+    }
+    check_purity_marker(old, new)?;
+    check_name(new)?;
+    let parsed = parse(source)?;
+    let statements = &parsed.statements;
+    let mut by_name = false;
+    let mut aliases = Vec::new();
+    for statement in statements {
+        let uses = match statement {
+            Statement::Use(use_stmt) => vec![use_stmt],
+            Statement::ConditionalUse {
+                then, otherwise, ..
+            } => std::iter::once(then).chain(otherwise).collect(),
+            _ => continue,
+        };
+        for use_stmt in uses {
+            if !imports_module(use_stmt.module_path()) {
+                continue;
+            }
+            match use_stmt {
+                UseStatement::Single { name, .. } => by_name |= name == old,
+                UseStatement::Selective { names, .. } => by_name |= names.iter().any(|n| n == old),
+                UseStatement::Glob { .. } => by_name = true,
+                UseStatement::Namespace { alias, .. } => aliases.push(alias.as_str()),
+            }
+        }
+    }
+
+    let mentions = mentions(source, &parsed, &[old, new]);
+    let mut edits = Vec::new();
+    if by_name {
+        check_unused(&mentions, new)?;
+        edits = rename_free(source, &mentions, old, new)?;
+    }
+    for mention in &mentions {
+        if let Kind::Member(Some(object)) = &mention.kind {
+            if mention.name == old
+                && aliases.contains(&object.as_str())
+                && !mention.params.contains(object)
+            {
+                edits.push(TextEdit {
+                    range: mention.span.clone(),
+                    text: new.to_string(),
+                });
+            }
+        }
+    }
+    edits.sort_by_key(|edit| edit.range.start);
+    check_result(source, &edits, &format!("Renaming to '{}'", new))?;
+    Ok(edits)
+}
+
+/// Applies edits that do not overlap, in any order.
+pub fn apply(source: &str, edits: &[TextEdit]) -> String {
+    let mut edits: Vec<&TextEdit> = edits.iter().collect();
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start));
+    let mut text = source.to_string();
+    for edit in edits {
+        text.replace_range(edit.range.clone(), &edit.text);
+    }
+    text
+}
+
+fn check_purity_marker(old: &str, new: &str) -> Result<(), String> {
+    if old.ends_with('!') != new.ends_with('!') {
+        return Err(format!(
+            "'{}' and '{}' must both end in '!' or neither, since it marks impure functions",
+            old, new
+        ));
+    }
+    Ok(())
+}
+
+/// Fails when `new` cannot name a top-level binding.
+pub(crate) fn check_name(new: &str) -> Result<(), String> {
+    let is_name = |name: &str| {
+        matches!(
+            Lexer::new(name).lex().as_deref(),
+            Ok([Token { kind: TokenKind::Identifier(lexed), .. }, Token { kind: TokenKind::Eof, .. }])
+                if lexed == name
+        )
+    };
+    if !is_name(new) {
+        return Err(format!("'{}' is not a valid name", new));
+    }
+    if Interpreter::new()
+        .builtins()
+        .iter()
+        .any(|builtin| builtin.name == new)
+    {
+        return Err(format!("'{}' is a builtin", new));
+    }
+    Ok(())
+}
+
+pub(crate) struct Parsed {
+    pub tokens: Vec<Token>,
+    /// Where each statement starts.
+    pub starts: Vec<usize>,
+    pub statements: Vec<Statement>,
+}
+
+impl Parsed {
+    /// The tokens of statement `index`, without line breaks at either end.
+    pub fn statement_tokens(&self, index: usize) -> Vec<&Token> {
+        let start = self.starts[index];
+        let end = self.starts.get(index + 1).copied();
+        let mut tokens: Vec<&Token> = self
+            .tokens
+            .iter()
+            .filter(|token| {
+                token.span.start >= start && end.is_none_or(|end| token.span.start < end)
+            })
+            .filter(|token| token.kind != TokenKind::Eof)
+            .collect();
+        while tokens
+            .last()
+            .is_some_and(|token| token.kind == TokenKind::Newline)
+        {
+            tokens.pop();
+        }
+        tokens
+    }
+}
+
+pub(crate) fn parse(source: &str) -> Result<Parsed, String> {
+    let tokens = Lexer::new(source).lex().map_err(|err| err.to_string())?;
+    let mut parser = Parser::with_source_and_file(
+        tokens.clone(),
+        source.to_string(),
+        std::path::PathBuf::from("<rename>"),
+    );
+    let (program, errors) = parser.parse_program_recovering();
+    if let Some(error) = errors.into_iter().next() {
+        return Err(error.to_string());
+    }
+    Ok(Parsed {
+        tokens,
+        starts: parser.statement_starts().to_vec(),
+        statements: program.statements,
+    })
+}
+
+/// Fails when `new` already names something the renamed binding would
+/// clash with.
+pub(crate) fn check_unused(mentions: &[Mention], new: &str) -> Result<(), String> {
+    let used = mentions.iter().any(|mention| {
+        mention.name == new
+            && !matches!(mention.kind, Kind::Member(_))
+            && !mention.params.iter().any(|param| param == new)
+    });
+    if used {
+        return Err(format!("'{}' is already used in this file", new));
+    }
+    Ok(())
+}
+
+/// Edits every mention of `old` that refers to the top-level binding.
+fn rename_free(
+    source: &str,
+    mentions: &[Mention],
+    old: &str,
+    new: &str,
+) -> Result<Vec<TextEdit>, String> {
+    let mut edits = Vec::new();
+    for mention in mentions {
+        if mention.name != old || mention.params.iter().any(|param| param == old) {
+            continue;
+        }
+        let text = match mention.kind {
+            Kind::Free => new.to_string(),
+            // `{ old }: record` reads the field `old`, which keeps its name
+            Kind::Shorthand => format!("{}: {}", old, new),
+            Kind::Member(_) => continue,
+        };
+        if mention.params.iter().any(|param| param == new) {
+            return Err(format!(
+                "A parameter named '{}' would hide the binding on line {}",
+                new,
+                byte_offset_to_line(source, mention.span.start)
+            ));
+        }
+        edits.push(TextEdit {
+            range: mention.span.clone(),
+            text,
+        });
+    }
+    Ok(edits)
+}
+
+/// Fails when the edited source would not parse, such as after renaming
+/// to `use`. `change` names the edits in the message.
+pub(crate) fn check_result(source: &str, edits: &[TextEdit], change: &str) -> Result<(), String> {
+    parse(&apply(source, edits))
+        .map(|_| ())
+        .map_err(|err| format!("{} breaks the file: {}", change, err))
+}
+
+#[derive(Debug)]
+pub(crate) struct Mention {
+    pub name: String,
+    pub span: Range<usize>,
+    pub kind: Kind,
+    /// The parameters of the functions around the mention.
+    pub params: Vec<String>,
+    /// Whether the mention binds the name, as a binding, an import, or a
+    /// parameter, rather than reading it.
+    pub binds: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Kind {
+    /// The name of a binding, where it is bound or read.
+    Free,
+    /// A `{ name }` pattern, which binds the field of the same name.
+    Shorthand,
+    /// `object.name`, with `object` when it is a plain name.
+    Member(Option<String>),
+}
+
+/// Every mention of one of `names` in the program, statement by
+/// statement, or of every name when `names` is empty.
+pub(crate) fn mentions(source: &str, parsed: &Parsed, names: &[&str]) -> Vec<Mention> {
+    let mut scan = Scan {
+        source,
+        names,
+        mentions: Vec::new(),
+    };
+    for (index, statement) in parsed.statements.iter().enumerate() {
+        let tokens = parsed.statement_tokens(index);
+        let body = match statement {
+            Statement::Use(_) | Statement::ConditionalUse { .. } => scan.use_names(&tokens),
+            Statement::Assignment {
+                pattern: Pattern::Identifier(_),
+                ..
+            }
+            | Statement::Function(_) => {
+                scan.mention(&tokens, 0, Kind::Free, &[]);
+                1
+            }
+            Statement::Assignment { .. } => scan.pattern(&tokens),
+            Statement::Export(_) | Statement::Expression(_) => 0,
+        };
+        scan.tokens(&tokens[body..], 0, &[]);
+    }
+    scan.mentions
+}
+
+struct Scan<'a> {
+    source: &'a str,
+    names: &'a [&'a str],
+    mentions: Vec<Mention>,
+}
+
+impl Scan<'_> {
+    /// Records a name the statement binds.
+    fn mention(&mut self, tokens: &[&Token], at: usize, kind: Kind, params: &[String]) {
+        self.mention_at(tokens[at], 0, kind, params, true);
+    }
+
+    fn mention_at(
+        &mut self,
+        token: &Token,
+        offset: usize,
+        kind: Kind,
+        params: &[String],
+        binds: bool,
+    ) {
+        if let TokenKind::Identifier(name) = &token.kind {
+            if self.names.is_empty() || self.names.contains(&name.as_str()) {
+                self.mentions.push(Mention {
+                    name: name.clone(),
+                    span: token.span.start + offset..token.span.end + offset,
+                    kind,
+                    params: params.to_vec(),
+                    binds,
+                });
+            }
+        }
+    }
+
+    /// Records the names a `use` statement binds and returns where the
+    /// module source starts, since a `when` condition reads bindings.
+    fn use_names(&mut self, tokens: &[&Token]) -> usize {
+        let from = tokens
+            .iter()
+            .position(|token| matches!(&token.kind, TokenKind::Identifier(name) if name == "from"))
+            .unwrap_or(tokens.len());
+        let alias = tokens[..from]
+            .iter()
+            .position(|token| matches!(&token.kind, TokenKind::Identifier(name) if name == "as"));
+        match alias {
+            Some(alias) if alias + 1 < from => self.mention(tokens, alias + 1, Kind::Free, &[]),
+            Some(_) => {}
+            None => {
+                for at in 1..from {
+                    self.mention(tokens, at, Kind::Free, &[]);
+                }
+            }
+        }
+        // Skip `when` itself
+        (from + 2).min(tokens.len())
+    }
+
+    /// Records the names a destructuring pattern binds and returns where
+    /// the value after its `:` starts.
+    fn pattern(&mut self, tokens: &[&Token]) -> usize {
+        let mut open = Vec::new();
+        for at in 0..tokens.len() {
+            match tokens[at].kind {
+                TokenKind::LBrace | TokenKind::LBracket => open.push(&tokens[at].kind),
+                TokenKind::RBrace | TokenKind::RBracket => {
+                    open.pop();
+                }
+                TokenKind::Colon if open.is_empty() => return at + 1,
+                TokenKind::Identifier(_) if next_kind(tokens, at) != Some(&TokenKind::Colon) => {
+                    let kind = if open.last() == Some(&&TokenKind::LBrace) {
+                        Kind::Shorthand
+                    } else {
+                        Kind::Free
+                    };
+                    self.mention(tokens, at, kind, &[]);
+                }
+                _ => {}
+            }
+        }
+        tokens.len()
+    }
+
+    /// Records the mentions in an expression. `offset` moves the spans of
+    /// tokens lexed from inside a string, and `outer` holds the parameters
+    /// of the functions around them.
+    fn tokens(&mut self, tokens: &[&Token], offset: usize, outer: &[String]) {
+        // The parameters of each function whose body is open, with the
+        // brace depth inside that body
+        let mut functions: Vec<(Vec<String>, usize)> = Vec::new();
+        let mut depth = 0;
+        let mut at = 0;
+        while at < tokens.len() {
+            let params = || -> Vec<String> {
+                outer
+                    .iter()
+                    .chain(functions.iter().flat_map(|(params, _)| params))
+                    .cloned()
+                    .collect()
+            };
+            match &tokens[at].kind {
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace => {
+                    if functions.last().is_some_and(|(_, body)| *body == depth) {
+                        functions.pop();
+                    }
+                    depth -= 1;
+                }
+                TokenKind::LParen if !(at > 0 && ends_operand(&tokens[at - 1].kind)) => {
+                    if let Some((names, body)) = lambda(tokens, at) {
+                        let mut params = params();
+                        params.extend(names.iter().cloned());
+                        for param in at + 1..body {
+                            let declared = matches!(&tokens[param].kind, TokenKind::Identifier(name) if names.contains(name))
+                                && tokens[..param]
+                                    .iter()
+                                    .rev()
+                                    .find(|token| token.kind != TokenKind::Newline)
+                                    .is_some_and(|token| {
+                                        matches!(token.kind, TokenKind::LParen | TokenKind::Comma)
+                                    });
+                            if declared {
+                                self.mention_at(tokens[param], offset, Kind::Free, &params, true);
+                            }
+                        }
+                        depth += 1;
+                        functions.push((names, depth));
+                        at = body + 1;
+                        continue;
+                    }
+                }
+                TokenKind::StringLiteral(_) => {
+                    let params = params();
+                    let span = &tokens[at].span;
+                    self.interpolations(span.start + offset..span.end + offset, &params)
+                }
+                TokenKind::Identifier(_) => {
+                    let before = at.checked_sub(1).map(|before| &tokens[before].kind);
+                    if matches!(before, Some(TokenKind::Dot | TokenKind::SafeDot)) {
+                        // `a.b.name` is a field of a field, not of a binding
+                        let object = match at.checked_sub(2).map(|object| &tokens[object].kind) {
+                            Some(TokenKind::Identifier(object))
+                                if !(at >= 3
+                                    && matches!(
+                                        tokens[at - 3].kind,
+                                        TokenKind::Dot | TokenKind::SafeDot
+                                    )) =>
+                            {
+                                Some(object.clone())
+                            }
+                            _ => None,
+                        };
+                        let params = params();
+                        self.mention_at(tokens[at], offset, Kind::Member(object), &params, false);
+                    } else if next_kind(tokens, at) != Some(&TokenKind::Colon) {
+                        let params = params();
+                        self.mention_at(tokens[at], offset, Kind::Free, &params, false);
+                    }
+                }
+                _ => {}
+            }
+            at += 1;
+        }
+    }
+
+    /// Records the mentions inside the `<...>` parts of the string literal
+    /// at `span`.
+    fn interpolations(&mut self, span: Range<usize>, params: &[String]) {
+        let mut rest = &self.source[span.clone()];
+        let mut base = span.start;
+        while let Some(open) = rest.find('<') {
+            let Some(close) = rest[open..].find('>') else {
+                return;
+            };
+            let inner = &rest[open + 1..open + close];
+            if let Ok(tokens) = Lexer::new(inner).lex() {
+                let tokens: Vec<&Token> = tokens
+                    .iter()
+                    .filter(|token| token.kind != TokenKind::Eof)
+                    .collect();
+                self.tokens(&tokens, base + open + 1, params);
+            }
+            base += open + close + 1;
+            rest = &rest[open + close + 1..];
+        }
+    }
+}
+
+/// The parameters of the functions whose bodies hold `offset`, among the
+/// tokens of a statement.
+pub(crate) fn params_at(tokens: &[&Token], offset: usize) -> Vec<String> {
+    let mut functions: Vec<(Vec<String>, usize)> = Vec::new();
+    let mut depth = 0;
+    let mut at = 0;
+    while at < tokens.len() && tokens[at].span.start < offset {
+        match tokens[at].kind {
+            TokenKind::LBrace => depth += 1,
+            TokenKind::RBrace => {
+                if functions.last().is_some_and(|(_, body)| *body == depth) {
+                    functions.pop();
+                }
+                depth -= 1;
+            }
+            TokenKind::LParen if !(at > 0 && ends_operand(&tokens[at - 1].kind)) => {
+                if let Some((names, body)) = lambda(tokens, at) {
+                    if tokens[body].span.start >= offset {
+                        break;
+                    }
+                    depth += 1;
+                    functions.push((names, depth));
+                    at = body + 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        at += 1;
+    }
+    functions
+        .into_iter()
+        .flat_map(|(params, _)| params)
+        .collect()
+}
+
+/// The kind of the next token that is not a line break.
+fn next_kind<'a>(tokens: &[&'a Token], at: usize) -> Option<&'a TokenKind> {
+    tokens[at + 1..]
+        .iter()
+        .map(|token| &token.kind)
+        .find(|kind| **kind != TokenKind::Newline)
+}
+
+/// Whether a `(` after this token opens call arguments.
+fn ends_operand(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Identifier(_)
+            | TokenKind::Number { .. }
+            | TokenKind::StringLiteral(_)
+            | TokenKind::Boolean(_)
+            | TokenKind::Null
+            | TokenKind::RParen
+            | TokenKind::RBracket
+            | TokenKind::RBrace
+    )
+}
+
+/// The parameters of the function whose parameter list opens at `open`
+/// and where its body's `{` is, or `None` when the parenthesis starts an
+/// ordinary expression.
+fn lambda(tokens: &[&Token], open: usize) -> Option<(Vec<String>, usize)> {
+    enum Expect {
+        Name,
+        AfterName,
+        Type,
+    }
+    let mut params = Vec::new();
+    let mut expect = Expect::Name;
+    let mut depth = 0;
+    let mut at = open + 1;
+    loop {
+        let kind = &tokens.get(at)?.kind;
+        match (&expect, kind) {
+            (_, TokenKind::Newline) => {}
+            (Expect::Name, TokenKind::Identifier(name)) => {
+                params.push(name.clone());
+                expect = Expect::AfterName;
+            }
+            (Expect::Name, TokenKind::RParen) if params.is_empty() => break,
+            (Expect::AfterName, TokenKind::Colon) => expect = Expect::Type,
+            (Expect::AfterName, TokenKind::Comma) => expect = Expect::Name,
+            (Expect::AfterName, TokenKind::RParen) => break,
+            (Expect::Type, TokenKind::Comma) if depth == 0 => expect = Expect::Name,
+            (Expect::Type, TokenKind::RParen) if depth == 0 => break,
+            (Expect::Type, TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace) => {
+                depth += 1
+            }
+            (Expect::Type, TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace) => {
+                depth -= 1
+            }
+            (Expect::Type, _) => {}
+            _ => return None,
+        }
+        at += 1;
+    }
+    let mut at = skip_newlines(tokens, at + 1);
+    if matches!(
+        tokens.get(at)?.kind,
+        TokenKind::Exclamation | TokenKind::Question
+    ) {
+        at = skip_newlines(tokens, at + 1);
+    }
+    if tokens.get(at)?.kind == TokenKind::Colon {
+        at = skip_newlines(tokens, skip_type(tokens, skip_newlines(tokens, at + 1))?);
+    }
+    (tokens.get(at)?.kind == TokenKind::LBrace).then_some((params, at))
+}
+
+fn skip_newlines(tokens: &[&Token], mut at: usize) -> usize {
+    while tokens
+        .get(at)
+        .is_some_and(|token| token.kind == TokenKind::Newline)
+    {
+        at += 1;
+    }
+    at
+}
+
+/// Where the type annotation starting at `at` ends.
+fn skip_type(tokens: &[&Token], at: usize) -> Option<usize> {
+    match tokens.get(at)?.kind {
+        TokenKind::Identifier(_) => Some(at + 1),
+        TokenKind::LBracket | TokenKind::LBrace | TokenKind::LParen => {
+            let mut depth = 0;
+            let mut end = at;
+            loop {
+                match tokens.get(end)?.kind {
+                    TokenKind::LBracket | TokenKind::LBrace | TokenKind::LParen => depth += 1,
+                    TokenKind::RBracket | TokenKind::RBrace | TokenKind::RParen => depth -= 1,
+                    _ => {}
+                }
+                end += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            if tokens[at].kind != TokenKind::LParen {
+                return Some(end);
+            }
+            // `(A, B) -> C`
+            let arrow = skip_newlines(tokens, end);
+            if tokens.get(arrow)?.kind != TokenKind::Minus
+                || tokens.get(arrow + 1)?.kind != TokenKind::GreaterThan
+            {
+                return None;
+            }
+            skip_type(tokens, skip_newlines(tokens, arrow + 2))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_the_binding_and_what_reads_it() -> Result<(), String> {
+        let source = "/// Counts items.\n\
+                      count: (items) { length(items) }\n\
+                      export count\n\
+                      total: count([1, 2]) + { count: 1 }.count\n\
+                      shadowed: (count) { count + 1 }\n\
+                      { count: n, total: t }: { count: 1, total: 2 }\n\
+                      message: \"<count([total])> items\"\n";
+        let renamed = rename_binding(source, "count", "size")?;
+        assert!(renamed.exported);
+        assert_eq!(
+            apply(source, &renamed.edits),
+            "/// Counts items.\n\
+             size: (items) { length(items) }\n\
+             export size\n\
+             total: size([1, 2]) + { count: 1 }.count\n\
+             shadowed: (count) { count + 1 }\n\
+             { count: n, total: t }: { count: 1, total: 2 }\n\
+             message: \"<size([total])> items\"\n"
+        );
+
+        let source = "{ name }: { name: \"Ada\" }\nhi: (x) { \"<name>\" }\n";
+        let renamed = rename_binding(source, "name", "first")?;
+        assert_eq!(
+            apply(source, &renamed.edits),
+            "{ name: first }: { name: \"Ada\" }\nhi: (x) { \"<first>\" }\n"
+        );
+        assert!(rename_binding(source, "name", "hi").is_err());
+        assert!(rename_binding(source, "name", "map").is_err());
+        assert!(rename_binding("a: 1\nf: (b) { a + b }\n", "a", "b").is_err());
+        assert!(rename_binding("f!: () { log!(1) }\n", "f!", "g").is_err());
+
+        let importer = "use { count } from \"lib\"\n\
+                        use lib as l from \"lib\"\n\
+                        a: count(l.count([]))\n";
+        let edits = rename_import(importer, "count", "size", &|path| path == "lib")?;
+        assert_eq!(
+            apply(importer, &edits),
+            "use { size } from \"lib\"\n\
+             use lib as l from \"lib\"\n\
+             a: size(l.size([]))\n"
+        );
+        assert!(rename_import(importer, "count", "size", &|_| false)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+//! Terminal output shared by `fip` and `fip-lint`: severity badges,
+//! colours, and wrapping long messages to the terminal width. Colour and
+//! wrapping only apply when writing to a terminal, so piped output stays
+//! plain for editors and scripts, and `NO_COLOR` turns colour off.
+
+use std::io::IsTerminal;
+
+/// Width to wrap at on a terminal when `COLUMNS` is not set.
+const DEFAULT_WIDTH: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+            Severity::Info => "1;36",
+        }
+    }
+}
+
+/// How to render reports for one output stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reporter {
+    color: bool,
+    /// Column to wrap messages at; `None` leaves lines as they are.
+    width: Option<usize>,
+}
+
+impl Reporter {
+    pub fn new(color: bool, width: Option<usize>) -> Self {
+        Self { color, width }
+    }
+
+    /// No colour and no wrapping.
+    pub fn plain() -> Self {
+        Self::new(false, None)
+    }
+
+    pub fn stdout() -> Self {
+        Self::detect(std::io::stdout().is_terminal())
+    }
+
+    pub fn stderr() -> Self {
+        Self::detect(std::io::stderr().is_terminal())
+    }
+
+    fn detect(is_terminal: bool) -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let columns = std::env::var("COLUMNS").ok();
+        Self::for_terminal(is_terminal, no_color, columns.as_deref())
+    }
+
+    fn for_terminal(is_terminal: bool, no_color: bool, columns: Option<&str>) -> Self {
+        if !is_terminal {
+            return Self::plain();
+        }
+        let width = columns
+            .and_then(|columns| columns.parse().ok())
+            .filter(|&width| width > 0)
+            .unwrap_or(DEFAULT_WIDTH);
+        Self::new(!no_color, Some(width))
+    }
+
+    /// `error`, `warning`, or `info`, coloured by severity.
+    pub fn badge(&self, severity: Severity) -> String {
+        self.paint(severity.label(), severity.color())
+    }
+
+    /// `ok` in green, for passing checks.
+    pub fn ok(&self, text: &str) -> String {
+        self.paint(text, "32")
+    }
+
+    /// `text` in red, for failing checks.
+    pub fn failed(&self, text: &str) -> String {
+        self.paint(text, "31")
+    }
+
+    /// One problem as `location: severity: message`, with the location in
+    /// bold. Lines too long for the terminal are wrapped at spaces and
+    /// continue two columns further in than they started.
+    pub fn diagnostic(&self, severity: Severity, location: Option<&str>, message: &str) -> String {
+        let mut prefix = String::new();
+        let mut prefix_width = 0;
+        if let Some(location) = location {
+            prefix.push_str(&self.paint(location, "1"));
+            prefix.push_str(": ");
+            prefix_width += location.chars().count() + 2;
+        }
+        prefix.push_str(&self.badge(severity));
+        prefix.push_str(": ");
+        prefix_width += severity.label().len() + 2;
+
+        let mut lines = Vec::new();
+        for (index, line) in message.lines().enumerate() {
+            let used = if index == 0 { prefix_width } else { 0 };
+            lines.extend(self.wrap(line, used));
+        }
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        format!("{}{}", prefix, lines.join("\n"))
+    }
+
+    /// Splits `line` at spaces so each piece fits the width, counting the
+    /// `used` columns before the first piece. Words longer than a line are
+    /// left whole.
+    fn wrap(&self, line: &str, used: usize) -> Vec<String> {
+        let Some(width) = self.width else {
+            return vec![line.to_string()];
+        };
+        let indent = line.len() - line.trim_start().len();
+        let continuation = " ".repeat(indent + 2);
+        let mut pieces = Vec::new();
+        let mut current = line[..indent].to_string();
+        let mut current_width = used + indent;
+        let mut empty = true;
+        for word in line[indent..].split(' ') {
+            let word_width = word.chars().count();
+            if !empty && current_width + 1 + word_width > width {
+                pieces.push(std::mem::replace(&mut current, continuation.clone()));
+                current_width = continuation.len();
+                empty = true;
+            }
+            if !empty {
+                current.push(' ');
+                current_width += 1;
+            }
+            current.push_str(word);
+            current_width += word_width;
+            empty = false;
+        }
+        pieces.push(current);
+        pieces
+    }
+
+    fn paint(&self, text: &str, code: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_are_coloured_and_wrapped_only_on_terminals() {
+        assert_eq!(
+            Reporter::for_terminal(false, false, Some("40")),
+            Reporter::plain()
+        );
+        assert_eq!(
+            Reporter::for_terminal(true, true, None),
+            Reporter::new(false, Some(DEFAULT_WIDTH))
+        );
+
+        let plain = Reporter::plain();
+        assert_eq!(
+            plain.diagnostic(Severity::Warning, Some("a.fip:3:1"), "unused binding 'x'"),
+            "a.fip:3:1: warning: unused binding 'x'"
+        );
+
+        let colored = Reporter::new(true, None);
+        assert_eq!(
+            colored.diagnostic(Severity::Error, None, "boom"),
+            "\x1b[1;31merror\x1b[0m: boom"
+        );
+
+        let narrow = Reporter::new(false, Some(24));
+        assert_eq!(
+            narrow.diagnostic(
+                Severity::Error,
+                None,
+                "the value does not match\nexpected:\n  a rather long expected value"
+            ),
+            "error: the value does\n  not match\nexpected:\n  a rather long expected\n    value"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Locates variables before the interpreter runs a program, so reading a
+//! parameter or a captured binding is an index instead of a walk up the
+//! scope chain with a hash lookup per scope. Every name a function reads
+//! from an enclosing function becomes one of its captures, copied into the
+//! closure when it is created. Names that are not parameters of an
+//! enclosing function are left as identifiers and looked up by name in the
+//! top-level scope, which fills in as the program runs.
+
+use crate::ast::{Expression, ObjectField, Program, Slot, Statement, StringSegment};
+use std::rc::Rc;
+
+/// Rewrites the variables of every function in `program` to slots and every
+/// lambda to a closure listing its captures.
+pub fn resolve_program(program: &mut Program) {
+    let mut resolver = Resolver::default();
+    for statement in &mut program.statements {
+        match statement {
+            Statement::Function(function) => {
+                resolver.frames.push(Frame::new(function.params.clone()));
+                resolver.expression(Rc::make_mut(&mut function.body));
+                resolver.frames.pop();
+            }
+            Statement::Assignment { expr, .. }
+            | Statement::Expression(expr)
+            | Statement::ConditionalUse {
+                condition: expr, ..
+            } => resolver.expression(expr),
+            Statement::Use(_) | Statement::Export(_) => {}
+        }
+    }
+}
+
+/// The scope of one function being resolved.
+struct Frame {
+    params: Vec<String>,
+    /// The bindings copied from the enclosing function when the closure is
+    /// created, as slots of that function.
+    captures: Vec<Slot>,
+}
+
+impl Frame {
+    fn new(params: Vec<String>) -> Self {
+        Self {
+            params,
+            captures: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Resolver {
+    /// The functions enclosing the current expression, innermost last.
+    frames: Vec<Frame>,
+}
+
+impl Resolver {
+    fn expression(&mut self, expr: &mut Expression) {
+        match expr {
+            Expression::Identifier(name) => {
+                if let Some(level) = self.frames.len().checked_sub(1) {
+                    if let Some(slot) = self.lookup(level, name) {
+                        *expr = Expression::Local(slot);
+                    }
+                }
+            }
+            Expression::Lambda { params, body, .. } => {
+                self.frames.push(Frame::new(params.clone()));
+                self.expression(Rc::make_mut(body));
+                let frame = self.frames.pop().expect("pushed above");
+                let lambda = std::mem::replace(expr, Expression::Null);
+                *expr = Expression::Closure {
+                    lambda: Box::new(lambda),
+                    captures: frame.captures,
+                };
+            }
+            Expression::Call { callee, args } => {
+                self.expression(callee);
+                args.iter_mut().for_each(|arg| self.expression(arg));
+            }
+            Expression::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Block(expressions) | Expression::List(expressions) => {
+                expressions
+                    .iter_mut()
+                    .for_each(|expr| self.expression(expr));
+            }
+            Expression::Object(fields) => {
+                for field in fields {
+                    match field {
+                        ObjectField::Field { value, .. } => self.expression(value),
+                        ObjectField::Spread(expr) | ObjectField::Base(expr) => {
+                            self.expression(expr)
+                        }
+                    }
+                }
+            }
+            Expression::Spread(expr) | Expression::Not(expr) => self.expression(expr),
+            Expression::PropertyAccess { object, .. } => self.expression(object),
+            Expression::String(template) => {
+                for segment in &mut template.segments {
+                    if let StringSegment::Expr(expr) = segment {
+                        self.expression(expr);
+                    }
+                }
+            }
+            Expression::Boolean(_)
+            | Expression::Number { .. }
+            | Expression::Null
+            | Expression::Local(_)
+            | Expression::Closure { .. } => {}
+        }
+    }
+
+    /// Where `name` lives as seen from the function at `level`, capturing it
+    /// into every function between there and the one that binds it.
+    fn lookup(&mut self, level: usize, name: &str) -> Option<Slot> {
+        let frame = &self.frames[level];
+        // A later parameter of the same name shadows an earlier one
+        if let Some(index) = frame.params.iter().rposition(|param| param == name) {
+            return Some(Slot {
+                name: name.to_string(),
+                depth: 0,
+                index,
+            });
+        }
+        if let Some(index) = frame.captures.iter().position(|slot| slot.name == name) {
+            return Some(Slot {
+                name: name.to_string(),
+                depth: 1,
+                index,
+            });
+        }
+        let outer = self.lookup(level.checked_sub(1)?, name)?;
+        let captures = &mut self.frames[level].captures;
+        captures.push(outer);
+        Some(Slot {
+            name: name.to_string(),
+            depth: 1,
+            index: captures.len() - 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::LangResult, lexer::Lexer, parser::Parser};
+
+    fn slot(name: &str, depth: usize, index: usize) -> Slot {
+        Slot {
+            name: name.to_string(),
+            depth,
+            index,
+        }
+    }
+
+    /// The captures and body of the closure that `body` consists of.
+    fn only_closure(body: &Expression) -> (&[Slot], &Expression) {
+        if let Expression::Block(steps) = body {
+            if let [Expression::Closure { lambda, captures }] = steps.as_slice() {
+                if let Expression::Lambda { body, .. } = lambda.as_ref() {
+                    return (captures, body);
+                }
+            }
+        }
+        panic!("expected a single closure, found {:?}", body)
+    }
+
+    #[test]
+    fn variables_resolve_to_parameters_captures_or_globals() -> LangResult<()> {
+        let source = "adder: (a) { (b) { (c) { a + c + total } } }";
+        let mut program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        resolve_program(&mut program);
+        let Statement::Function(adder) = &program.statements[0] else {
+            panic!("expected a function");
+        };
+
+        // (b) captures a from adder, and (c) captures it again from (b)
+        let (captures, body) = only_closure(&adder.body);
+        assert_eq!(captures, [slot("a", 0, 0)]);
+        let (captures, body) = only_closure(body);
+        assert_eq!(captures, [slot("a", 1, 0)]);
+
+        let resolved = format!("{:?}", body);
+        assert!(resolved.contains(&format!("Local({:?})", slot("a", 1, 0))));
+        assert!(resolved.contains(&format!("Local({:?})", slot("c", 0, 0))));
+        assert!(resolved.contains("Identifier(\"total\")"));
+        Ok(())
+    }
+}
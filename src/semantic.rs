@@ -0,0 +1,313 @@
+//! Static analysis over a parsed program.
+//!
+//! The purity check lives here so the parser, the interpreter, and the linter
+//! all apply the same rules: a function that uses an impure name (one ending
+//! in `!`) must be marked impure itself, and a function marked impure must use
+//! at least one impure name.
+
+use crate::ast::{Expression, ObjectField, Program, Slot, Statement, StringSegment};
+use std::collections::{HashMap, HashSet};
+
+/// An impure name used inside a function body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImpureCall {
+    pub name: String,
+    /// True for `log!(x)`, false when the name is only passed around.
+    pub called: bool,
+    /// How many times the same identifier appears before this one in the
+    /// enclosing statement, counting in source order. `None` when the name
+    /// sits inside a string interpolation and has no token of its own.
+    pub occurrence: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PurityViolationKind {
+    /// The body uses an impure name but the function is not marked impure.
+    MissingImpureMarker(ImpureCall),
+    /// The function is marked impure but its body uses no impure names.
+    NeedlessImpureMarker,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurityViolation {
+    /// Index of the top-level statement containing the function.
+    pub statement: usize,
+    /// The function's name, or `None` for an anonymous function.
+    pub function: Option<String>,
+    pub kind: PurityViolationKind,
+}
+
+impl PurityViolation {
+    pub fn message(&self) -> String {
+        match (&self.function, &self.kind) {
+            (Some(name), PurityViolationKind::MissingImpureMarker(call)) => format!(
+                "Function '{}' must be declared impure (end the name with '!') to {} '{}'",
+                name,
+                call.verb(),
+                call.name
+            ),
+            (None, PurityViolationKind::MissingImpureMarker(call)) => format!(
+                "Anonymous function must be declared impure (use '!') to {} '{}'",
+                call.verb(),
+                call.name
+            ),
+            (Some(name), PurityViolationKind::NeedlessImpureMarker) => format!(
+                "Function '{}' is marked impure but performs no impure operations",
+                name
+            ),
+            (None, PurityViolationKind::NeedlessImpureMarker) => {
+                "Anonymous function is marked impure but performs no impure operations".to_string()
+            }
+        }
+    }
+}
+
+impl ImpureCall {
+    fn verb(&self) -> &'static str {
+        if self.called {
+            "call"
+        } else {
+            "use"
+        }
+    }
+}
+
+/// Checks every named and anonymous function in the program, returning the
+/// violations in source order.
+pub fn check_purity(program: &Program) -> Vec<PurityViolation> {
+    let mut violations = Vec::new();
+    for (index, statement) in program.statements.iter().enumerate() {
+        let mut seen = HashMap::new();
+        let mut check = |node: Node, seen: &mut HashMap<String, usize>| match node {
+            Node::Name {
+                name, in_template, ..
+            } => {
+                if !in_template {
+                    *seen.entry(name.to_string()).or_insert(0) += 1;
+                }
+            }
+            Node::Function { name, body, impure } => {
+                if let Some(violation) = check_function(index, name, body, impure, seen) {
+                    violations.push(violation);
+                }
+            }
+        };
+        match statement {
+            Statement::Function(func) => {
+                check(
+                    Node::Name {
+                        name: &func.name,
+                        called: false,
+                        in_template: false,
+                    },
+                    &mut seen,
+                );
+                check(
+                    Node::Function {
+                        name: Some(&func.name),
+                        body: &func.body,
+                        impure: func.impure,
+                    },
+                    &mut seen,
+                );
+                walk(&func.body, false, &mut |node| check(node, &mut seen));
+            }
+            Statement::Assignment { expr, .. } | Statement::Expression(expr) => {
+                walk(expr, false, &mut |node| check(node, &mut seen));
+            }
+            Statement::ConditionalUse { condition, .. } => {
+                walk(condition, false, &mut |node| check(node, &mut seen));
+            }
+            Statement::Use(_) | Statement::Export(_) => {}
+        }
+    }
+    violations
+}
+
+/// Returns the first impure name used in the expression, including inside
+/// nested anonymous functions. Occurrences count from the start of `expr`.
+pub fn find_impure_call(expr: &Expression) -> Option<ImpureCall> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let mut found = None;
+    walk(expr, false, &mut |node| {
+        if let Node::Name {
+            name,
+            called,
+            in_template,
+        } = node
+        {
+            let count = seen.entry(name).or_insert(0);
+            if found.is_none() && name.ends_with('!') {
+                found = Some(ImpureCall {
+                    name: name.to_string(),
+                    called,
+                    occurrence: (!in_template).then_some(*count),
+                });
+            }
+            if !in_template {
+                *count += 1;
+            }
+        }
+    });
+    found
+}
+
+/// Every identifier the expression mentions, including inside nested
+/// functions and string interpolations. Parameters are included too, so this
+/// over-approximates the names the expression reads from enclosing scopes.
+pub fn referenced_names(expr: &Expression) -> HashSet<String> {
+    let mut names = HashSet::new();
+    walk(expr, false, &mut |node| {
+        if let Node::Name { name, .. } = node {
+            names.insert(name.to_string());
+        }
+    });
+    names
+}
+
+fn check_function(
+    statement: usize,
+    name: Option<&str>,
+    body: &Expression,
+    impure: bool,
+    seen: &HashMap<String, usize>,
+) -> Option<PurityViolation> {
+    let kind = match (find_impure_call(body), impure) {
+        (Some(mut call), false) => {
+            // Make the occurrence relative to the statement, not the body
+            let before = seen.get(&call.name).copied().unwrap_or(0);
+            call.occurrence = call.occurrence.map(|count| count + before);
+            PurityViolationKind::MissingImpureMarker(call)
+        }
+        (None, true) => PurityViolationKind::NeedlessImpureMarker,
+        _ => return None,
+    };
+    Some(PurityViolation {
+        statement,
+        function: name.map(str::to_string),
+        kind,
+    })
+}
+
+enum Node<'a> {
+    Name {
+        name: &'a str,
+        called: bool,
+        in_template: bool,
+    },
+    /// Reported before the function's body is walked.
+    Function {
+        name: Option<&'a str>,
+        body: &'a Expression,
+        impure: bool,
+    },
+}
+
+/// Visits identifiers and anonymous functions in source order.
+fn walk<'a>(expr: &'a Expression, in_template: bool, visit: &mut dyn FnMut(Node<'a>)) {
+    match expr {
+        Expression::Identifier(name) | Expression::Local(Slot { name, .. }) => visit(Node::Name {
+            name,
+            called: false,
+            in_template,
+        }),
+        Expression::Call { callee, args } => {
+            match callee.as_ref() {
+                Expression::Identifier(name) | Expression::Local(Slot { name, .. }) => {
+                    visit(Node::Name {
+                        name,
+                        called: true,
+                        in_template,
+                    })
+                }
+                other => walk(other, in_template, visit),
+            }
+            for arg in args {
+                walk(arg, in_template, visit);
+            }
+        }
+        Expression::Lambda { body, impure, .. } => {
+            visit(Node::Function {
+                name: None,
+                body,
+                impure: *impure,
+            });
+            walk(body, in_template, visit);
+        }
+        Expression::Binary { left, right, .. } => {
+            walk(left, in_template, visit);
+            walk(right, in_template, visit);
+        }
+        Expression::Block(expressions) | Expression::List(expressions) => {
+            for expr in expressions {
+                walk(expr, in_template, visit);
+            }
+        }
+        Expression::Object(fields) => {
+            for field in fields {
+                match field {
+                    ObjectField::Field { value, .. } => walk(value, in_template, visit),
+                    ObjectField::Spread(expr) | ObjectField::Base(expr) => {
+                        walk(expr, in_template, visit)
+                    }
+                }
+            }
+        }
+        Expression::Spread(expr) | Expression::Not(expr) => walk(expr, in_template, visit),
+        Expression::Closure { lambda, .. } => walk(lambda, in_template, visit),
+        Expression::PropertyAccess { object, .. } => walk(object, in_template, visit),
+        Expression::String(template) => {
+            for segment in &template.segments {
+                if let StringSegment::Expr(expr) = segment {
+                    walk(expr, true, visit);
+                }
+            }
+        }
+        Expression::Boolean(_) | Expression::Number { .. } | Expression::Null => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn violations(source: &str) -> Vec<PurityViolation> {
+        let tokens = Lexer::new(source).lex().expect("lexes");
+        let mut parser = Parser::new(tokens);
+        let (program, _) = parser.parse_program_recovering();
+        check_purity(&program)
+    }
+
+    #[test]
+    fn reports_which_call_and_which_occurrence() {
+        let found = violations(
+            "ok!: (x) { log!(x) }\nf: (x) {\n  log!\n  map((y)! { log!(y) }, [x])\n}\nquiet!: (x) { x }\n",
+        );
+        assert_eq!(
+            found,
+            vec![
+                PurityViolation {
+                    statement: 1,
+                    function: Some("f".to_string()),
+                    kind: PurityViolationKind::MissingImpureMarker(ImpureCall {
+                        name: "log!".to_string(),
+                        called: false,
+                        occurrence: Some(0),
+                    }),
+                },
+                PurityViolation {
+                    statement: 2,
+                    function: Some("quiet!".to_string()),
+                    kind: PurityViolationKind::NeedlessImpureMarker,
+                },
+            ]
+        );
+
+        let found = violations("xs: map((n) { \"<n>\" + log!(n) }, [1])\n");
+        assert_eq!(
+            found[0].message(),
+            "Anonymous function must be declared impure (use '!') to call 'log!'"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Sets behind `to-set` and the `set-*` builtins. A set is a sorted list
+//! without repeats, so membership is a binary search and union,
+//! intersection, and difference are single merges over both sides.
+//!
+//! Elements must be orderable: null, booleans, numbers, strings, bytes, and
+//! lists of those. Values of different kinds are ordered by kind, in that
+//! order, so one set can mix them. Map keys use the same order.
+
+use crate::interpreter::Value;
+use std::cmp::Ordering;
+
+/// Builds a set from arbitrary values, dropping repeats.
+pub fn from_values(mut values: Vec<Value>) -> Result<Vec<Value>, String> {
+    values
+        .iter()
+        .try_for_each(|value| check(value, "Set elements"))?;
+    values.sort_by(order);
+    values.dedup_by(|a, b| order(a, b) == Ordering::Equal);
+    Ok(values)
+}
+
+pub fn contains(set: &[Value], value: &Value) -> Result<bool, String> {
+    check(value, "Set elements")?;
+    Ok(set.binary_search_by(|item| order(item, value)).is_ok())
+}
+
+pub fn union(left: &[Value], right: &[Value]) -> Vec<Value> {
+    merge(left, right, true, true, true)
+}
+
+pub fn intersection(left: &[Value], right: &[Value]) -> Vec<Value> {
+    merge(left, right, false, true, false)
+}
+
+pub fn difference(left: &[Value], right: &[Value]) -> Vec<Value> {
+    merge(left, right, true, false, false)
+}
+
+/// Walks both sorted sets at once, keeping elements found only on the left,
+/// on both sides, or only on the right as asked.
+fn merge(
+    left: &[Value],
+    right: &[Value],
+    keep_left: bool,
+    keep_both: bool,
+    keep_right: bool,
+) -> Vec<Value> {
+    let mut result = Vec::new();
+    let (mut l, mut r) = (0, 0);
+    while l < left.len() && r < right.len() {
+        match order(&left[l], &right[r]) {
+            Ordering::Less => {
+                if keep_left {
+                    result.push(left[l].clone());
+                }
+                l += 1;
+            }
+            Ordering::Greater => {
+                if keep_right {
+                    result.push(right[r].clone());
+                }
+                r += 1;
+            }
+            Ordering::Equal => {
+                if keep_both {
+                    result.push(left[l].clone());
+                }
+                l += 1;
+                r += 1;
+            }
+        }
+    }
+    if keep_left {
+        result.extend_from_slice(&left[l..]);
+    }
+    if keep_right {
+        result.extend_from_slice(&right[r..]);
+    }
+    result
+}
+
+/// Fails unless `value` can be ordered; `what` names the values being
+/// checked, such as "Map keys".
+pub(crate) fn check(value: &Value, what: &str) -> Result<(), String> {
+    match value {
+        Value::List(items) => items.iter().try_for_each(|item| check(item, what)),
+        value if rank(value).is_some() => Ok(()),
+        other => Err(format!(
+            "{} can only be null, booleans, numbers, strings, bytes, or lists of those, found {:?}",
+            what, other
+        )),
+    }
+}
+
+fn rank(value: &Value) -> Option<u8> {
+    match value {
+        Value::Null => Some(0),
+        Value::Boolean(_) => Some(1),
+        Value::Number(_) => Some(2),
+        Value::String(_) => Some(3),
+        Value::Bytes(_) => Some(4),
+        Value::List(_) => Some(5),
+        _ => None,
+    }
+}
+
+/// A total order over values that passed [`check`].
+pub(crate) fn order(left: &Value, right: &Value) -> Ordering {
+    match (left, right) {
+        (Value::Boolean(l), Value::Boolean(r)) => l.cmp(r),
+        (Value::Number(l), Value::Number(r)) => l.cmp(r),
+        (Value::String(l), Value::String(r)) => l.cmp(r),
+        (Value::Bytes(l), Value::Bytes(r)) => l.cmp(r),
+        (Value::List(l), Value::List(r)) => l
+            .iter()
+            .zip(r)
+            .map(|(a, b)| order(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| l.len().cmp(&r.len())),
+        _ => rank(left).cmp(&rank(right)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(values: &[i64]) -> Vec<Value> {
+        values.iter().map(|n| Value::Number(*n)).collect()
+    }
+
+    fn as_numbers(set: &[Value]) -> Vec<i64> {
+        set.iter()
+            .map(|value| match value {
+                Value::Number(n) => *n,
+                other => panic!("expected number, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sorts_dedups_and_merges() {
+        let left = from_values(numbers(&[3, 1, 2, 3, 1])).expect("numbers form a set");
+        let right = from_values(numbers(&[4, 2])).expect("numbers form a set");
+        assert_eq!(as_numbers(&left), [1, 2, 3]);
+        assert_eq!(as_numbers(&union(&left, &right)), [1, 2, 3, 4]);
+        assert_eq!(as_numbers(&intersection(&left, &right)), [2]);
+        assert_eq!(as_numbers(&difference(&left, &right)), [1, 3]);
+        assert_eq!(contains(&left, &Value::Number(2)), Ok(true));
+        assert_eq!(contains(&left, &Value::String("2".to_string())), Ok(false));
+
+        let mixed = from_values(vec![
+            Value::String("a".to_string()),
+            Value::Number(1),
+            Value::Null,
+            Value::List(numbers(&[1])),
+            Value::Boolean(true),
+        ])
+        .expect("mixed kinds form a set");
+        assert_eq!(
+            format!("{:?}", mixed),
+            "[null, true, 1, \"a\", [1]]".to_string()
+        );
+        assert!(from_values(vec![Value::List(vec![Value::Unit])]).is_err());
+    }
+}
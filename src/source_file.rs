@@ -0,0 +1,302 @@
+//! A source file kept parsed while an editor changes it. [`SourceFile`]
+//! holds the text with its tokens, syntax tree, and diagnostics, and
+//! [`SourceFile::update`] applies an edit by lexing and parsing again only
+//! the top-level statements around it, reusing the tokens and statements of
+//! the rest of the file. Checks that span the whole program, such as
+//! duplicate bindings and purity, still run over every statement, but they
+//! only walk the tree.
+
+use crate::{
+    ast::Program,
+    error::LangError,
+    lexer::{Lexer, Token, TokenKind},
+    parser::Parser,
+};
+use std::{collections::BTreeMap, ops::Range, path::PathBuf};
+
+/// Replaces the bytes in `range` with `text`, as an editor reports a change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    path: PathBuf,
+    text: String,
+    /// Every token of `text`, ending with `Eof`; empty when lexing failed.
+    tokens: Vec<Token>,
+    program: Program,
+    /// Where each top-level statement of `program` starts.
+    statement_starts: Vec<usize>,
+    /// Where the steps of each block start, keyed by where its `{` is.
+    block_steps: BTreeMap<usize, Vec<usize>>,
+    diagnostics: Vec<LangError>,
+    /// The statements the last parse produced anew, by index.
+    reparsed: Range<usize>,
+}
+
+impl SourceFile {
+    pub fn new(path: impl Into<PathBuf>, text: impl Into<String>) -> Self {
+        let mut file = Self {
+            path: path.into(),
+            text: text.into(),
+            tokens: Vec::new(),
+            program: Program {
+                statements: Vec::new(),
+                doc: None,
+            },
+            statement_starts: Vec::new(),
+            block_steps: BTreeMap::new(),
+            diagnostics: Vec::new(),
+            reparsed: 0..0,
+        };
+        file.parse_all();
+        file
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// The statements that parsed. When there are diagnostics, statements
+    /// that failed to parse are missing.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Byte offsets where each top-level statement starts.
+    pub fn statement_starts(&self) -> &[usize] {
+        &self.statement_starts
+    }
+
+    /// Byte offsets where each step of each block starts, keyed by where
+    /// the block's `{` is, as [`Parser::block_steps`] reports them.
+    pub fn block_steps(&self) -> &BTreeMap<usize, Vec<usize>> {
+        &self.block_steps
+    }
+
+    /// The lexer and parser errors of the current text, in source order.
+    pub fn diagnostics(&self) -> &[LangError] {
+        &self.diagnostics
+    }
+
+    /// Applies `edit` and brings the tokens, tree, and diagnostics up to
+    /// date.
+    ///
+    /// # Panics
+    ///
+    /// When the range is out of bounds or does not fall on character
+    /// boundaries, like [`String::replace_range`].
+    pub fn update(&mut self, edit: TextEdit) {
+        let old_len = self.text.len();
+        self.text.replace_range(edit.range.clone(), &edit.text);
+        if !self.parse_around(&edit, old_len) {
+            self.parse_all();
+        }
+    }
+
+    fn parse_all(&mut self) {
+        let lexer = Lexer::with_source_and_file(&self.text, self.text.clone(), self.path.clone());
+        let tokens = match lexer.lex() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                self.tokens.clear();
+                self.program.statements.clear();
+                self.program.doc = None;
+                self.statement_starts.clear();
+                self.block_steps.clear();
+                self.diagnostics = vec![error];
+                self.reparsed = 0..0;
+                return;
+            }
+        };
+        let mut parser =
+            Parser::with_source_and_file(tokens.clone(), self.text.clone(), self.path.clone());
+        let (program, diagnostics) = parser.parse_program_recovering();
+        self.tokens = tokens;
+        self.statement_starts = parser.statement_starts().to_vec();
+        self.block_steps = parser.block_steps().clone();
+        self.reparsed = 0..program.statements.len();
+        self.program = program;
+        self.diagnostics = diagnostics;
+    }
+
+    /// Lexes and parses again the statements `edit` touched, together with
+    /// the unchanged statement on either side of them. Those neighbours must
+    /// come out where they were: a line that starts with an operator carries
+    /// on the statement above it, so an edit can join statements or split
+    /// them. Returns false, leaving the rest to a full parse, when they do
+    /// not, when the new text does not parse, or when the file had errors,
+    /// since statements that failed to parse leave no boundaries to trust.
+    fn parse_around(&mut self, edit: &TextEdit, old_len: usize) -> bool {
+        let count = self.statement_starts.len();
+        if count == 0 || !self.diagnostics.is_empty() {
+            return false;
+        }
+        // Statement `i` owns the text up to the next one, and the first one
+        // also owns any comments above it
+        let starts = &self.statement_starts;
+        let owned_from = |i: usize| if i == 0 { 0 } else { starts[i] };
+        let owned_to = |i: usize| starts.get(i + 1).copied().unwrap_or(old_len);
+
+        let first_touched = (0..count)
+            .find(|&i| owned_to(i) >= edit.range.start)
+            .unwrap_or(count - 1);
+        let last_touched = (first_touched..count)
+            .take_while(|&i| owned_from(i) <= edit.range.end)
+            .last()
+            .unwrap_or(first_touched);
+        let first = first_touched.saturating_sub(1);
+        let last = (last_touched + 1).min(count - 1);
+
+        let removed = edit.range.end - edit.range.start;
+        let shift = |offset: usize| offset + edit.text.len() - removed;
+        let old_region = owned_from(first)..owned_to(last);
+        let region = old_region.start..shift(old_region.end);
+
+        let Ok(mut tokens) = Lexer::new(&self.text[region.clone()]).lex() else {
+            return false;
+        };
+        for token in &mut tokens {
+            token.span = token.span.start + region.start..token.span.end + region.start;
+        }
+        if let Some(eof) = tokens.last_mut() {
+            eof.span = region.end..region.end;
+        }
+        let mut parser =
+            Parser::with_source_and_file(tokens.clone(), self.text.clone(), self.path.clone());
+        let (program, errors) = parser.parse_program_recovering();
+        let new_starts = parser.statement_starts();
+        let kept_first = first == first_touched || new_starts.first() == Some(&starts[first]);
+        let kept_last = last == last_touched || new_starts.last() == Some(&shift(starts[last]));
+        if !errors.is_empty() || !kept_first || !kept_last {
+            return false;
+        }
+
+        // Splice the new tokens and statements in, moving what follows
+        tokens.pop();
+        let before = self
+            .tokens
+            .iter()
+            .take_while(|token| token.span.start < old_region.start)
+            .count();
+        let after: Vec<Token> = self
+            .tokens
+            .iter()
+            .skip_while(|token| token.span.start < old_region.end)
+            .map(|token| Token {
+                kind: token.kind.clone(),
+                span: shift(token.span.start)..shift(token.span.end),
+            })
+            .collect();
+        self.tokens.truncate(before);
+        self.tokens.extend(tokens);
+        self.tokens.extend(after);
+
+        let new_count = program.statements.len();
+        let mut statement_starts = self.statement_starts.clone();
+        let later = statement_starts.split_off(last + 1);
+        statement_starts.truncate(first);
+        statement_starts.extend_from_slice(new_starts);
+        statement_starts.extend(later.into_iter().map(shift));
+
+        let later = self.block_steps.split_off(&old_region.end);
+        self.block_steps.split_off(&old_region.start);
+        self.block_steps.extend(parser.block_steps().clone());
+        self.block_steps.extend(
+            later
+                .into_iter()
+                .map(|(open, steps)| (shift(open), steps.into_iter().map(shift).collect())),
+        );
+
+        let mut statements = std::mem::take(&mut self.program.statements);
+        statements.splice(first..=last, program.statements);
+
+        let mut parser =
+            Parser::with_source_and_file(self.tokens.clone(), self.text.clone(), self.path.clone());
+        let (program, diagnostics) = parser.finish_program(statements, statement_starts);
+        self.program = program;
+        self.statement_starts = parser.statement_starts().to_vec();
+        self.diagnostics = diagnostics;
+        self.reparsed = first..first + new_count;
+        debug_assert!(matches!(
+            self.tokens.last().map(|token| &token.kind),
+            Some(TokenKind::Eof)
+        ));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(file: &mut SourceFile, old: &str, new: &str) {
+        let start = file.text().find(old).expect("text to replace");
+        file.update(TextEdit {
+            range: start..start + old.len(),
+            text: new.to_string(),
+        });
+    }
+
+    /// The file as a fresh parse of its current text would leave it.
+    fn assert_matches_fresh_parse(file: &SourceFile) {
+        let fresh = SourceFile::new("edited.fip", file.text());
+        assert_eq!(file.tokens(), fresh.tokens());
+        assert_eq!(file.program(), fresh.program());
+        assert_eq!(file.statement_starts(), fresh.statement_starts());
+        assert_eq!(file.block_steps(), fresh.block_steps());
+        let messages = |file: &SourceFile| -> Vec<String> {
+            file.diagnostics().iter().map(ToString::to_string).collect()
+        };
+        assert_eq!(messages(file), messages(&fresh));
+    }
+
+    #[test]
+    fn edits_parse_again_only_the_statements_around_them() {
+        let source = "//! Numbers.\n\
+                      one: \"één\" ?? 1\n\
+                      two: add(one, 1)\n\
+                      /// Doubles.\n\
+                      double: (n) { n * 2 }\n\
+                      four: double(two)\n\
+                      log!(four)\n\
+                      five: 5\n";
+        let mut file = SourceFile::new("edited.fip", source);
+        assert!(file.diagnostics().is_empty());
+        assert_eq!(file.reparsed, 0..6);
+
+        replace(&mut file, "n * 2", "n * 3");
+        assert_matches_fresh_parse(&file);
+        assert_eq!(file.reparsed, 1..4);
+
+        // A line that starts with an operator joins the statement above
+        replace(&mut file, "log!(four)", "+ 1");
+        assert_matches_fresh_parse(&file);
+        assert_eq!(file.program().statements.len(), 5);
+
+        // An error leaves the rest of the file parsed until it is fixed
+        replace(&mut file, "five: 5", "five: (");
+        assert_eq!(file.diagnostics().len(), 1);
+        assert_eq!(file.program().statements.len(), 4);
+        replace(&mut file, "five: (", "five: 5");
+        assert_matches_fresh_parse(&file);
+        assert!(file.diagnostics().is_empty());
+
+        // Checks across statements still see the whole program
+        replace(&mut file, "five: 5", "one: 5");
+        assert_matches_fresh_parse(&file);
+        assert_eq!(file.reparsed, 2..5);
+        assert_eq!(file.diagnostics().len(), 1);
+
+        replace(&mut file, "//! Numbers.", "//! Counting.");
+        assert_matches_fresh_parse(&file);
+        assert_eq!(file.program().doc.as_deref(), Some("Counting."));
+    }
+}
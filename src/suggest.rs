@@ -0,0 +1,71 @@
+//! "Did you mean" hints for misspelled names, shared by the interpreter's
+//! undefined identifier error and the linter's undefined name rule.
+
+/// The candidate closest to `name`, if one is close enough to be a likely
+/// typo: at most one edit away for short names and a third of the length
+/// for longer ones. Ties go to the alphabetically first candidate, so the
+/// hint does not depend on the order candidates come in.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// `". Did you mean 'x'?"` for the closest candidate, or nothing, to append
+/// to an error message.
+pub fn hint<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    match closest(name, candidates) {
+        Some(candidate) => format!(". Did you mean '{}'?", candidate),
+        None => String::new(),
+    }
+}
+
+/// How many single-character insertions, deletions, substitutions, or
+/// swaps of neighbours turn `a` into `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows[i][j] is the distance between the first i of a and first j of b
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_the_nearest_name_within_reach() {
+        let names = ["length", "log!", "map", "filter", "flatten"];
+        assert_eq!(closest("lenght", names), Some("length"));
+        assert_eq!(closest("fliter", names), Some("filter"));
+        assert_eq!(closest("mpa", names), Some("map"));
+        assert_eq!(closest("log", names), Some("log!"));
+        assert_eq!(closest("reduce", names), None);
+        assert_eq!(closest("map", names), None);
+        assert_eq!(hint("lenght", names), ". Did you mean 'length'?");
+        assert_eq!(hint("xyz", names), "");
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+}
@@ -0,0 +1,171 @@
+//! The tokens of a file with everything the lexer skips attached to them,
+//! so the file can be written back byte for byte. Whitespace and comments
+//! are trivia: the trivia after a token up to the end of its line trails
+//! it, and any other trivia leads the token after it. Line breaks are
+//! tokens of their own, so a comment on a line by itself leads the line
+//! break that ends it.
+
+use crate::{
+    error::LangResult,
+    lexer::{Lexer, Token, TokenKind},
+};
+use std::{fmt, ops::Range};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    /// A `//` comment, `///` and `//!` docs included, without the line
+    /// break after it.
+    Comment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    /// Byte offsets in the tree's source.
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub token: Token,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    source: String,
+    /// Every token of `source`, ending with `Eof`, which leads whatever
+    /// trivia ends the file.
+    tokens: Vec<SyntaxToken>,
+}
+
+impl SyntaxTree {
+    /// Lexes `source` and attaches its trivia.
+    pub fn parse(source: &str) -> LangResult<Self> {
+        let tokens = Lexer::new(source).lex()?;
+        Ok(Self::from_tokens(source, tokens))
+    }
+
+    /// Attaches the trivia of `source` to `tokens`, which the lexer made
+    /// from it.
+    pub fn from_tokens(source: &str, tokens: Vec<Token>) -> Self {
+        let mut tree = Self {
+            source: source.to_string(),
+            tokens: Vec::with_capacity(tokens.len()),
+        };
+        let mut gap_start = 0;
+        for token in tokens {
+            let trivia = tree.trivia(gap_start..token.span.start);
+            gap_start = token.span.end;
+            let mut leading = Vec::new();
+            match tree.tokens.last_mut() {
+                Some(previous) if previous.token.kind != TokenKind::Newline => {
+                    previous.trailing = trivia
+                }
+                _ => leading = trivia,
+            }
+            tree.tokens.push(SyntaxToken {
+                token,
+                leading,
+                trailing: Vec::new(),
+            });
+        }
+        tree
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn tokens(&self) -> &[SyntaxToken] {
+        &self.tokens
+    }
+
+    /// The source text of `trivia`.
+    pub fn text(&self, trivia: &Trivia) -> &str {
+        &self.source[trivia.span.clone()]
+    }
+
+    /// The comments in the trivia of tokens that start in `range`, in
+    /// source order.
+    pub fn comments(&self, range: Range<usize>) -> Vec<&str> {
+        self.tokens
+            .iter()
+            .filter(|token| range.contains(&token.token.span.start))
+            .flat_map(|token| token.leading.iter().chain(&token.trailing))
+            .filter(|trivia| trivia.kind == TriviaKind::Comment)
+            .map(|trivia| self.text(trivia))
+            .collect()
+    }
+
+    /// Splits a stretch of source between two tokens into whitespace runs
+    /// and comments.
+    fn trivia(&self, gap: Range<usize>) -> Vec<Trivia> {
+        let mut trivia = Vec::new();
+        let mut offset = gap.start;
+        while offset < gap.end {
+            let rest = &self.source[offset..gap.end];
+            let (kind, len) = match rest.find("//") {
+                Some(0) => {
+                    let line = rest.split('\n').next().unwrap_or(rest);
+                    (TriviaKind::Comment, line.trim_end_matches('\r').len())
+                }
+                Some(comment) => (TriviaKind::Whitespace, comment),
+                None => (TriviaKind::Whitespace, rest.len()),
+            };
+            trivia.push(Trivia {
+                kind,
+                span: offset..offset + len,
+            });
+            offset += len;
+        }
+        trivia
+    }
+}
+
+/// Writes the source back exactly as it was.
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            for trivia in &token.leading {
+                f.write_str(self.text(trivia))?;
+            }
+            f.write_str(&self.source[token.token.span.clone()])?;
+            for trivia in &token.trailing {
+                f.write_str(self.text(trivia))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trivia_is_attached_and_the_source_round_trips() {
+        let source = "//! Module.\r\n\r\n// lone\nsum: 1 +  2 // trailing\n\
+                      f: (x) {\n  \"<x>\"  }\n  // at the end";
+        let tree = SyntaxTree::parse(source).expect("lexes");
+        assert_eq!(tree.to_string(), source);
+
+        let two = tree
+            .tokens()
+            .iter()
+            .find(|token| &source[token.token.span.clone()] == "2")
+            .expect("the 2");
+        let trailing: Vec<&str> = two.trailing.iter().map(|t| tree.text(t)).collect();
+        assert_eq!(trailing, [" ", "// trailing"]);
+        assert_eq!(
+            tree.comments(0..source.len() + 1),
+            ["//! Module.", "// lone", "// trailing", "// at the end"]
+        );
+        let end = tree.tokens().last().expect("eof");
+        assert_eq!(end.token.kind, TokenKind::Eof);
+        let leading: Vec<&str> = end.leading.iter().map(|t| tree.text(t)).collect();
+        assert_eq!(leading, ["  ", "// at the end"]);
+    }
+}
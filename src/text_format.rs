@@ -0,0 +1,577 @@
+//! Number, template, and table formatting behind `format-number`,
+//! `format`, `format-table`, and `format-markdown-table`.
+//! Numbers are whole, so a precision is a count of implied decimals: with
+//! two decimals `123456` reads as `1234.56`, which suits amounts kept in
+//! cents. Thousands and decimal separators follow a locale when the
+//! interpreter is built with the `locale-data` feature, and English
+//! conventions otherwise.
+
+use crate::interpreter::Value;
+
+/// How `format_number` writes a number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Put between groups of three digits; empty for no grouping.
+    pub grouping: String,
+    pub decimal: String,
+    /// How many of the last digits come after the decimal separator.
+    pub decimals: usize,
+    /// The least number of characters to write, padded on the left.
+    pub width: usize,
+    /// Pads up to `width`. Zeros go after the sign; other fills before it.
+    pub fill: char,
+    /// Writes `+` before positive numbers too.
+    pub sign: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            grouping: String::new(),
+            decimal: ".".to_string(),
+            decimals: 0,
+            width: 0,
+            fill: ' ',
+            sign: false,
+        }
+    }
+}
+
+impl NumberFormat {
+    /// The separators `locale` (a tag such as `"en"` or `"de-CH"`) writes
+    /// numbers with, grouping thousands.
+    pub fn for_locale(locale: &str) -> Self {
+        let (grouping, decimal) = separators_for(locale);
+        Self {
+            grouping: grouping.to_string(),
+            decimal: decimal.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(feature = "locale-data")]
+fn separators_for(locale: &str) -> (&'static str, &'static str) {
+    let mut subtags = locale.split(['-', '_']).map(str::to_ascii_lowercase);
+    let language = subtags.next().unwrap_or_default();
+    let region = subtags.next().unwrap_or_default();
+    match (language.as_str(), region.as_str()) {
+        ("de", "ch") | ("de", "li") => ("\u{2019}", "."),
+        ("de" | "nl" | "es" | "it" | "pt" | "da" | "id" | "tr" | "el", _) => (".", ","),
+        ("fr", _) => ("\u{202f}", ","),
+        ("sv" | "fi" | "nb" | "nn" | "no" | "pl" | "cs" | "sk" | "ru" | "uk" | "et", _) => {
+            ("\u{a0}", ",")
+        }
+        _ => (",", "."),
+    }
+}
+
+#[cfg(not(feature = "locale-data"))]
+fn separators_for(_locale: &str) -> (&'static str, &'static str) {
+    (",", ".")
+}
+
+/// Writes `value` as `format` describes.
+pub fn format_number(value: i64, format: &NumberFormat) -> String {
+    let digits = value.unsigned_abs().to_string();
+    // Enough leading zeros that there is a digit before the separator
+    let digits = format!("{:0>width$}", digits, width = format.decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - format.decimals);
+
+    let mut body = String::new();
+    for (index, digit) in whole.chars().enumerate() {
+        if index > 0 && (whole.len() - index) % 3 == 0 {
+            body.push_str(&format.grouping);
+        }
+        body.push(digit);
+    }
+    if !fraction.is_empty() {
+        body.push_str(&format.decimal);
+        body.push_str(fraction);
+    }
+
+    let sign = if value < 0 {
+        "-"
+    } else if format.sign {
+        "+"
+    } else {
+        ""
+    };
+    let padding = format
+        .width
+        .saturating_sub(sign.chars().count() + body.chars().count());
+    let fill = format.fill.to_string().repeat(padding);
+    if format.fill == '0' {
+        format!("{}{}{}", sign, fill, body)
+    } else {
+        format!("{}{}{}", fill, sign, body)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// A parsed `{:...}` specifier: `[[fill]align][+][0][width][,][.precision]`,
+/// where `align` is `-` (or `<`) for left, `>` for right, and `^` to center.
+#[derive(Debug, Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<Align>,
+    sign: bool,
+    zero: bool,
+    width: usize,
+    grouping: bool,
+    precision: Option<usize>,
+}
+
+fn parse_spec(spec: &str) -> Result<Spec, String> {
+    // `-` as in printf, since `<` starts an interpolation in fip strings
+    let align_of = |ch| match ch {
+        '-' | '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    };
+    let mut parsed = Spec::default();
+    let chars: Vec<char> = spec.chars().collect();
+    let mut rest = chars.as_slice();
+    if let [fill, align, tail @ ..] = rest {
+        if let Some(align) = align_of(*align) {
+            parsed.fill = Some(*fill);
+            parsed.align = Some(align);
+            rest = tail;
+        }
+    }
+    if parsed.align.is_none() {
+        if let [align, tail @ ..] = rest {
+            if let Some(align) = align_of(*align) {
+                parsed.align = Some(align);
+                rest = tail;
+            }
+        }
+    }
+    if let ['+', tail @ ..] = rest {
+        parsed.sign = true;
+        rest = tail;
+    }
+    if let ['0', tail @ ..] = rest {
+        parsed.zero = true;
+        rest = tail;
+    }
+    let number = |rest: &mut &[char]| -> Option<usize> {
+        let length = rest.iter().take_while(|ch| ch.is_ascii_digit()).count();
+        let (digits, tail) = rest.split_at(length);
+        *rest = tail;
+        digits.iter().collect::<String>().parse().ok()
+    };
+    parsed.width = number(&mut rest).unwrap_or(0);
+    if let [',', tail @ ..] = rest {
+        parsed.grouping = true;
+        rest = tail;
+    }
+    if let ['.', tail @ ..] = rest {
+        rest = tail;
+        parsed.precision =
+            Some(number(&mut rest).ok_or_else(|| format!("Missing precision in '{{:{}}}'", spec))?);
+    }
+    if !rest.is_empty() {
+        return Err(format!("Invalid format specifier '{{:{}}}'", spec));
+    }
+    Ok(parsed)
+}
+
+/// Pads `text` to `width` characters.
+fn align(text: &str, width: usize, fill: char, align: Align) -> String {
+    let padding = width.saturating_sub(text.chars().count());
+    let (before, after) = match align {
+        Align::Left => (0, padding),
+        Align::Right => (padding, 0),
+        Align::Center => (padding / 2, padding - padding / 2),
+    };
+    let fill = |count| fill.to_string().repeat(count);
+    format!("{}{}{}", fill(before), text, fill(after))
+}
+
+/// Fills the placeholders of `template` from `values`. `{}` takes the next
+/// value of a list, `{1}` the value at that position, and `{name}` a field
+/// of an object; `{{` and `}}` are literal braces. Any placeholder may end
+/// in a specifier such as `{:-8}` or `{total:,.2}`. Numbers align right and
+/// everything else left; `display` writes the values that are not numbers.
+pub fn format_template(
+    template: &str,
+    values: &Value,
+    display: &dyn Fn(&Value) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut out = String::new();
+    let mut next = 0;
+    let mut chars = template.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                out.push('}');
+            }
+            '}' => return Err("Unmatched '}' in template; write '}}' for a brace".to_string()),
+            '{' => {
+                let rest = chars.as_str();
+                let close = rest.find('}').ok_or_else(|| {
+                    "Unclosed '{' in template; write '{{' for a brace".to_string()
+                })?;
+                let placeholder = &rest[..close];
+                chars = rest[close + 1..].chars();
+                let (key, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+                let value = lookup(values, key.trim(), &mut next)?;
+                out.push_str(&format_value(value, &parse_spec(spec)?, display)?);
+            }
+            _ => out.push(ch),
+        }
+    }
+    Ok(out)
+}
+
+fn lookup<'a>(values: &'a Value, key: &str, next: &mut usize) -> Result<&'a Value, String> {
+    match values {
+        Value::List(items) => {
+            let index = if key.is_empty() {
+                *next += 1;
+                *next - 1
+            } else {
+                key.parse()
+                    .map_err(|_| format!("Placeholder '{{{}}}' needs an object of values", key))?
+            };
+            items.get(index).ok_or_else(|| {
+                format!(
+                    "Template needs a value at position {}, but {} given",
+                    index,
+                    items.len()
+                )
+            })
+        }
+        Value::Object(fields) if !key.is_empty() => fields
+            .get(key)
+            .ok_or_else(|| format!("Template needs a value named '{}'", key)),
+        Value::Object(_) => Err("Placeholder '{}' needs a list of values".to_string()),
+        other => Err(format!(
+            "Values must be a list or an object, found {}",
+            other.type_name()
+        )),
+    }
+}
+
+fn format_value(
+    value: &Value,
+    spec: &Spec,
+    display: &dyn Fn(&Value) -> Result<String, String>,
+) -> Result<String, String> {
+    match value {
+        Value::Number(n) => {
+            let format = NumberFormat {
+                grouping: if spec.grouping {
+                    ",".to_string()
+                } else {
+                    String::new()
+                },
+                decimals: spec.precision.unwrap_or(0),
+                sign: spec.sign,
+                ..NumberFormat::default()
+            };
+            if spec.zero && spec.align.is_none() {
+                return Ok(format_number(
+                    *n,
+                    &NumberFormat {
+                        width: spec.width,
+                        fill: '0',
+                        ..format
+                    },
+                ));
+            }
+            let text = format_number(*n, &format);
+            let fill = spec.fill.unwrap_or(' ');
+            Ok(align(
+                &text,
+                spec.width,
+                fill,
+                spec.align.unwrap_or(Align::Right),
+            ))
+        }
+        other => {
+            if spec.sign || spec.zero || spec.grouping {
+                return Err(format!(
+                    "Only numbers take '+', '0', or ',' in a format specifier, found {}",
+                    other.type_name()
+                ));
+            }
+            let mut text = display(other)?;
+            // A precision cuts text short
+            if let Some(precision) = spec.precision {
+                text = text.chars().take(precision).collect();
+            }
+            let fill = spec.fill.unwrap_or(' ');
+            Ok(align(
+                &text,
+                spec.width,
+                fill,
+                spec.align.unwrap_or(Align::Left),
+            ))
+        }
+    }
+}
+
+/// How `format_table` lays out its rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableStyle {
+    /// Columns two spaces apart, with a rule of dashes under the header.
+    Text,
+    /// A GitHub-flavored markdown table, padded to line up in the source.
+    Markdown,
+}
+
+/// Lays out `rows`, a list of objects, as a table with a column per field.
+/// Columns follow the field order of the first row, then fields that only
+/// later rows have. Columns holding only numbers align right; a row
+/// without a field leaves its cell empty. Lines have no trailing newline.
+pub fn format_table(
+    rows: &Value,
+    style: TableStyle,
+    display: &dyn Fn(&Value) -> Result<String, String>,
+) -> Result<String, String> {
+    let Value::List(rows) = rows else {
+        return Err(format!(
+            "expected a list of objects, found {}",
+            rows.type_name()
+        ));
+    };
+    let mut records = Vec::with_capacity(rows.len());
+    let mut columns: Vec<&str> = Vec::new();
+    for row in rows {
+        let Value::Object(fields) = row else {
+            return Err(format!(
+                "expected a list of objects, found a {} in it",
+                row.type_name()
+            ));
+        };
+        for key in fields.keys() {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key);
+            }
+        }
+        records.push(fields);
+    }
+    if columns.is_empty() {
+        return Ok(String::new());
+    }
+
+    let numeric: Vec<bool> = columns
+        .iter()
+        .map(|column| {
+            records
+                .iter()
+                .filter_map(|fields| fields.get(*column))
+                .all(|value| matches!(value, Value::Number(_)))
+        })
+        .collect();
+    let cell = |text: String| match style {
+        TableStyle::Text => text,
+        TableStyle::Markdown => text.replace('|', "\\|").replace('\n', " "),
+    };
+    let header: Vec<String> = columns
+        .iter()
+        .map(|column| cell(column.to_string()))
+        .collect();
+    let mut body = Vec::with_capacity(records.len());
+    for fields in &records {
+        let line = columns
+            .iter()
+            .map(|column| match fields.get(*column) {
+                None => Ok(String::new()),
+                Some(Value::Number(n)) => Ok(n.to_string()),
+                Some(value) => display(value).map(cell),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        body.push(line);
+    }
+    let minimum = match style {
+        TableStyle::Text => 1,
+        // A markdown rule needs three dashes
+        TableStyle::Markdown => 3,
+    };
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|index| {
+            body.iter()
+                .map(|line| line[index].chars().count())
+                .chain([header[index].chars().count(), minimum])
+                .max()
+                .unwrap_or(minimum)
+        })
+        .collect();
+
+    let layout = |cells: &[String]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .enumerate()
+            .map(|(index, text)| {
+                let side = if numeric[index] {
+                    Align::Right
+                } else {
+                    Align::Left
+                };
+                align(text, widths[index], ' ', side)
+            })
+            .collect();
+        match style {
+            TableStyle::Text => padded.join("  ").trim_end().to_string(),
+            TableStyle::Markdown => format!("| {} |", padded.join(" | ")),
+        }
+    };
+    let rule: Vec<String> = widths
+        .iter()
+        .zip(&numeric)
+        .map(|(width, numeric)| match style {
+            TableStyle::Markdown if *numeric => format!("{}:", "-".repeat(width - 1)),
+            _ => "-".repeat(*width),
+        })
+        .collect();
+    let rule = match style {
+        TableStyle::Text => rule.join("  "),
+        TableStyle::Markdown => format!("| {} |", rule.join(" | ")),
+    };
+
+    let mut lines = vec![layout(&header), rule];
+    lines.extend(body.iter().map(|line| layout(line)));
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &Value) -> Result<String, String> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            other => Ok(format!("{:?}", other)),
+        }
+    }
+
+    #[test]
+    fn numbers_group_pad_and_take_implied_decimals() {
+        let en = NumberFormat::for_locale("en");
+        assert_eq!(format_number(1234567, &en), "1,234,567");
+        assert_eq!(format_number(-999, &en), "-999");
+        assert_eq!(format_number(i64::MIN, &en), "-9,223,372,036,854,775,808");
+        let cents = NumberFormat {
+            decimals: 2,
+            ..en.clone()
+        };
+        assert_eq!(format_number(123456, &cents), "1,234.56");
+        assert_eq!(format_number(-5, &cents), "-0.05");
+        let padded = NumberFormat {
+            width: 6,
+            fill: '0',
+            sign: true,
+            ..NumberFormat::default()
+        };
+        assert_eq!(format_number(42, &padded), "+00042");
+        assert_eq!(
+            format_number(
+                42,
+                &NumberFormat {
+                    width: 5,
+                    ..NumberFormat::default()
+                }
+            ),
+            "   42"
+        );
+    }
+
+    #[test]
+    fn tables_line_up_columns_of_objects() -> Result<(), String> {
+        let row = |fields: &[(&str, Value)]| {
+            Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.clone()))
+                    .collect(),
+            )
+        };
+        let name = |text: &str| Value::String(text.to_string());
+        let rows = Value::List(vec![
+            row(&[("name", name("apples")), ("qty", Value::Number(3))]),
+            row(&[("name", name("kiwi|lime")), ("qty", Value::Number(12))]),
+            row(&[("name", name("figs")), ("note", name("ripe"))]),
+        ]);
+        assert_eq!(
+            format_table(&rows, TableStyle::Text, &text)?,
+            "name       qty  note\n\
+             ---------  ---  ----\n\
+             apples       3\n\
+             kiwi|lime   12\n\
+             figs            ripe"
+        );
+        assert_eq!(
+            format_table(&rows, TableStyle::Markdown, &text)?,
+            "| name       | qty | note |\n\
+             | ---------- | --: | ---- |\n\
+             | apples     |   3 |      |\n\
+             | kiwi\\|lime |  12 |      |\n\
+             | figs       |     | ripe |"
+        );
+        assert_eq!(
+            format_table(&Value::List(vec![]), TableStyle::Text, &text)?,
+            ""
+        );
+        assert!(format_table(
+            &Value::List(vec![Value::Number(1)]),
+            TableStyle::Text,
+            &text
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn templates_fill_placeholders_by_position_or_name() -> Result<(), String> {
+        let list = Value::List(vec![
+            Value::String("apples".to_string()),
+            Value::Number(1234),
+            Value::Number(-7),
+        ]);
+        assert_eq!(
+            format_template("{:-8}|{:>8,}|{:+04}|{{}}", &list, &text)?,
+            "apples  |   1,234|-007|{}"
+        );
+        assert_eq!(
+            format_template("{1:.2} {0:.3} {2:*^5}", &list, &text)?,
+            "12.34 app *-7**"
+        );
+
+        let object = Value::Object(
+            [("total".to_string(), Value::Number(995))]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(
+            format_template("Total: {total:.2}", &object, &text)?,
+            "Total: 9.95"
+        );
+
+        for (template, values, problem) in [
+            ("{} {} {} {}", &list, "position 3"),
+            ("{missing}", &object, "named 'missing'"),
+            ("{:>}x}", &list, "Unmatched '}'"),
+            ("{:y}", &list, "Invalid format specifier '{:y}'"),
+            ("{:,}", &list, "Only numbers"),
+        ] {
+            let message = format_template(template, values, &text).expect_err(template);
+            assert!(message.contains(problem), "{}: {}", template, message);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,409 @@
+//! Calendar arithmetic for the time builtins. Programs see timestamps as UTC
+//! records of `year`, `month`, `day`, `hour`, `minute`, `second`, and
+//! `millisecond`; here they are converted to and from milliseconds since the
+//! Unix epoch, which makes duration arithmetic plain integer math.
+
+use crate::interpreter::Value;
+use std::collections::BTreeMap;
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// Years outside this range are rejected so conversions cannot overflow.
+const MAX_YEAR: i64 = 1_000_000;
+
+/// The fields of a timestamp record, in the order they are listed.
+pub const FIELDS: [&str; 7] = [
+    "year",
+    "month",
+    "day",
+    "hour",
+    "minute",
+    "second",
+    "millisecond",
+];
+
+/// The fields of a duration record with their length in milliseconds.
+pub const DURATION_UNITS: [(&str, i64); 6] = [
+    ("weeks", 7 * MILLIS_PER_DAY),
+    ("days", MILLIS_PER_DAY),
+    ("hours", 3_600_000),
+    ("minutes", 60_000),
+    ("seconds", 1_000),
+    ("milliseconds", 1),
+];
+
+/// A UTC date and time. Every field is a plain number so it maps directly
+/// onto a record.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+    pub hour: i64,
+    pub minute: i64,
+    pub second: i64,
+    pub millisecond: i64,
+}
+
+impl DateTime {
+    pub fn from_millis(millis: i64) -> Self {
+        let days = millis.div_euclid(MILLIS_PER_DAY);
+        let rest = millis.rem_euclid(MILLIS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: rest / 3_600_000,
+            minute: rest / 60_000 % 60,
+            second: rest / 1_000 % 60,
+            millisecond: rest % 1_000,
+        }
+    }
+
+    /// Checks every field against the calendar, naming the first bad one.
+    pub fn validate(&self) -> Result<(), String> {
+        let limits = [
+            ("year", self.year, -MAX_YEAR, MAX_YEAR),
+            ("month", self.month, 1, 12),
+            ("day", self.day, 1, days_in_month(self.year, self.month)),
+            ("hour", self.hour, 0, 23),
+            ("minute", self.minute, 0, 59),
+            ("second", self.second, 0, 59),
+            ("millisecond", self.millisecond, 0, 999),
+        ];
+        for (name, value, min, max) in limits {
+            if value < min || value > max {
+                return Err(format!(
+                    "timestamp field '{}' must be between {} and {}, found {}",
+                    name, min, max, value
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Milliseconds since the epoch. The timestamp must be valid.
+    pub fn to_millis(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) * MILLIS_PER_DAY
+            + self.hour * 3_600_000
+            + self.minute * 60_000
+            + self.second * 1_000
+            + self.millisecond
+    }
+
+    pub fn field(&self, name: &str) -> Option<i64> {
+        match name {
+            "year" => Some(self.year),
+            "month" => Some(self.month),
+            "day" => Some(self.day),
+            "hour" => Some(self.hour),
+            "minute" => Some(self.minute),
+            "second" => Some(self.second),
+            "millisecond" => Some(self.millisecond),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a timestamp record. `year`, `month`, and `day` are required; the
+/// time of day defaults to midnight.
+pub fn timestamp_from_value(value: &Value) -> Result<i64, String> {
+    let Value::Object(fields) = value else {
+        return Err(format!("expected a timestamp record, found {:?}", value));
+    };
+    let mut parts = [0; 7];
+    for (name, value) in fields {
+        let index = FIELDS
+            .iter()
+            .position(|field| field == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown timestamp field '{}'; expected {}",
+                    name,
+                    FIELDS.join(", ")
+                )
+            })?;
+        parts[index] = match value {
+            Value::Number(n) => *n,
+            other => {
+                return Err(format!(
+                    "timestamp field '{}' must be a number, found {:?}",
+                    name, other
+                ))
+            }
+        };
+    }
+    if let Some(missing) = FIELDS[..3]
+        .iter()
+        .find(|field| !fields.contains_key(**field))
+    {
+        return Err(format!("timestamp is missing the '{}' field", missing));
+    }
+    let [year, month, day, hour, minute, second, millisecond] = parts;
+    let time = DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        millisecond,
+    };
+    time.validate()?;
+    Ok(time.to_millis())
+}
+
+pub fn timestamp_to_value(millis: i64) -> Value {
+    let time = DateTime::from_millis(millis);
+    Value::Object(
+        FIELDS
+            .iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    Value::Number(time.field(name).unwrap_or(0)),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Reads a duration record into milliseconds. Every unit is optional and
+/// may be negative.
+pub fn duration_from_value(value: &Value) -> Result<i64, String> {
+    let Value::Object(fields) = value else {
+        return Err(format!("expected a duration record, found {:?}", value));
+    };
+    let mut total: i64 = 0;
+    for (name, value) in fields {
+        let unit = DURATION_UNITS
+            .iter()
+            .find(|(unit, _)| unit == name)
+            .map(|(_, millis)| *millis)
+            .ok_or_else(|| {
+                let units: Vec<&str> = DURATION_UNITS.iter().map(|(unit, _)| *unit).collect();
+                format!(
+                    "unknown duration field '{}'; expected {}",
+                    name,
+                    units.join(", ")
+                )
+            })?;
+        let amount = match value {
+            Value::Number(n) => *n,
+            other => {
+                return Err(format!(
+                    "duration field '{}' must be a number, found {:?}",
+                    name, other
+                ))
+            }
+        };
+        total = amount
+            .checked_mul(unit)
+            .and_then(|millis| total.checked_add(millis))
+            .ok_or_else(|| "duration is too large".to_string())?;
+    }
+    Ok(total)
+}
+
+/// Splits milliseconds into days down to milliseconds. Every field carries
+/// the sign of the whole duration.
+pub fn duration_to_value(millis: i64) -> Value {
+    let mut rest = millis;
+    let mut fields = BTreeMap::new();
+    for (name, unit) in &DURATION_UNITS[1..] {
+        fields.insert(name.to_string(), Value::Number(rest / unit));
+        rest %= unit;
+    }
+    Value::Object(fields)
+}
+
+/// Moves a timestamp by a duration, both in milliseconds.
+pub fn add_duration(millis: i64, duration: i64) -> Result<i64, String> {
+    let moved = millis
+        .checked_add(duration)
+        .ok_or_else(|| "the result is out of range".to_string())?;
+    DateTime::from_millis(moved)
+        .validate()
+        .map_err(|_| "the result is out of range".to_string())?;
+    Ok(moved)
+}
+
+/// The current time in milliseconds since the epoch.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_millis() -> Result<i64, String> {
+    let elapsed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|err| format!("the system clock is before 1970: {}", err))?;
+    i64::try_from(elapsed.as_millis()).map_err(|_| "the system clock is out of range".to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn now_millis() -> Result<i64, String> {
+    Err("the current time is not available in this build".to_string())
+}
+
+/// Parses an ISO-8601 date (`2024-05-01`) or date and time
+/// (`2024-05-01T12:30:00.250+02:00`) into milliseconds since the epoch.
+/// Seconds, fractions, and the offset are optional; a missing offset means
+/// UTC. Returns `None` for anything else.
+pub fn parse_iso(text: &str) -> Option<i64> {
+    let mut cursor = Cursor { rest: text };
+    let year = cursor.digits(4)?;
+    cursor.eat('-')?;
+    let month = cursor.digits(2)?;
+    cursor.eat('-')?;
+    let day = cursor.digits(2)?;
+    let mut time = DateTime {
+        year,
+        month,
+        day,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        millisecond: 0,
+    };
+    let mut offset_minutes = 0;
+    if cursor.eat('T').or_else(|| cursor.eat(' ')).is_some() {
+        time.hour = cursor.digits(2)?;
+        cursor.eat(':')?;
+        time.minute = cursor.digits(2)?;
+        if cursor.eat(':').is_some() {
+            time.second = cursor.digits(2)?;
+            if cursor.eat('.').is_some() {
+                time.millisecond = cursor.fraction_millis()?;
+            }
+        }
+        offset_minutes = cursor.offset()?;
+    }
+    if !cursor.rest.is_empty() {
+        return None;
+    }
+    time.validate().ok()?;
+    Some(time.to_millis() - offset_minutes * 60_000)
+}
+
+/// Formats a timestamp as `YYYY-MM-DDTHH:MM:SSZ`, adding `.mmm` when the
+/// milliseconds are not zero.
+pub fn format_iso(millis: i64) -> String {
+    let time = DateTime::from_millis(millis);
+    let year = if (0..=9999).contains(&time.year) {
+        format!("{:04}", time.year)
+    } else {
+        format!("{:+}", time.year)
+    };
+    let fraction = if time.millisecond == 0 {
+        String::new()
+    } else {
+        format!(".{:03}", time.millisecond)
+    };
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year, time.month, time.day, time.hour, time.minute, time.second, fraction
+    )
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl Cursor<'_> {
+    fn eat(&mut self, expected: char) -> Option<()> {
+        self.rest = self.rest.strip_prefix(expected)?;
+        Some(())
+    }
+
+    fn digits(&mut self, count: usize) -> Option<i64> {
+        let digits = self.rest.get(..count)?;
+        if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        self.rest = &self.rest[count..];
+        digits.parse().ok()
+    }
+
+    /// Reads a decimal fraction of a second, keeping millisecond precision.
+    fn fraction_millis(&mut self) -> Option<i64> {
+        let length = self
+            .rest
+            .bytes()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        if length == 0 {
+            return None;
+        }
+        let digits = &self.rest[..length];
+        self.rest = &self.rest[length..];
+        let padded = format!("{:0<3}", &digits[..length.min(3)]);
+        padded.parse().ok()
+    }
+
+    /// Reads `Z`, `±HH:MM`, `±HHMM`, or `±HH`, returning the offset from UTC
+    /// in minutes. No offset at all means UTC.
+    fn offset(&mut self) -> Option<i64> {
+        if self.eat('Z').or_else(|| self.eat('z')).is_some() || self.rest.is_empty() {
+            return Some(0);
+        }
+        let sign = if self.eat('+').is_some() {
+            1
+        } else {
+            self.eat('-')?;
+            -1
+        };
+        let hours = self.digits(2)?;
+        let minutes = if self.rest.is_empty() {
+            0
+        } else {
+            self.eat(':');
+            self.digits(2)?
+        };
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        Some(sign * (hours * 60 + minutes))
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Count from March so the leap day falls at the end of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = (month + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
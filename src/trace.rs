@@ -0,0 +1,54 @@
+//! Call tracer behind `fip run --trace` and `set-trace!`. It hears every
+//! function and builtin call through [`Hooks`] and writes the ones made
+//! while tracing is on, indented by how deeply they are nested.
+
+use crate::{error::LangError, hooks::Hooks, interpreter::Value};
+use std::io::Write;
+
+pub struct CallTracer {
+    output: Box<dyn Write>,
+    enabled: bool,
+    /// Whether each call still running was traced when it started, so its
+    /// return is traced the same way even if tracing was switched since.
+    open: Vec<bool>,
+}
+
+impl CallTracer {
+    /// A tracer writing to `output`, tracing from the start when `enabled`.
+    pub fn new(output: Box<dyn Write>, enabled: bool) -> Self {
+        Self {
+            output,
+            enabled,
+            open: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, depth: usize, line: std::fmt::Arguments) {
+        // Tracing is a debugging aid, so a broken output must not stop the program.
+        let _ = writeln!(self.output, "{}{}", "  ".repeat(depth), line);
+    }
+}
+
+impl Hooks for CallTracer {
+    fn on_call(&mut self, name: &str, args: &[Value], depth: usize) {
+        self.open.push(self.enabled);
+        if self.enabled {
+            let rendered: Vec<String> = args.iter().map(|arg| format!("{:?}", arg)).collect();
+            self.write(depth, format_args!("{}({})", name, rendered.join(", ")));
+        }
+    }
+
+    fn on_return(&mut self, name: &str, result: Result<&Value, &LangError>, depth: usize) {
+        if self.open.pop() != Some(true) {
+            return;
+        }
+        match result {
+            Ok(value) => self.write(depth, format_args!("{} -> {:?}", name, value)),
+            Err(_) => self.write(depth, format_args!("{} failed", name)),
+        }
+    }
+
+    fn on_set_trace(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
@@ -0,0 +1,1039 @@
+//! Optional static type checking, run by `fip check`.
+//!
+//! Inference is Hindley-Milner style: every expression gets a type, unknowns
+//! are type variables solved by unification, and top-level bindings are
+//! generalized so a function like `identity` works on numbers and strings
+//! alike. Annotations (`(x: Number): String { ... }`) add constraints but are
+//! never required.
+//!
+//! The system is gradual. Anything the checker cannot see into, such as
+//! imported names, builtins without a signature, or lists that mix element
+//! types, has the type `Any`, which fits everywhere, and `null` fits any
+//! type. `fip run` stays fully dynamic and never consults this module.
+
+use crate::{
+    ast::{
+        BinaryOperator, Expression, ObjectField, ObjectPatternField, Pattern, Program, Signature,
+        Slot, Statement, StringSegment, TypeExpr, UseStatement,
+    },
+    parser::Parser,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Number,
+    String,
+    Bytes,
+    /// Sets and maps are not generic over their elements.
+    Set,
+    Map,
+    Boolean,
+    Null,
+    Any,
+    List(Box<Type>),
+    /// An object whose fields are all known.
+    Object(BTreeMap<String, Type>),
+    Function(Vec<Type>, Box<Type>),
+    Var(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    /// Index of the top-level statement the error was found in.
+    pub statement: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct TypeReport {
+    pub errors: Vec<TypeError>,
+    /// The inferred type of each top-level binding, in definition order.
+    pub bindings: Vec<(String, String)>,
+}
+
+/// Signatures for the builtins whose types can be stated. The rest are `Any`.
+const BUILTIN_SIGNATURES: &[(&str, &str)] = &[
+    ("identity", "(a) -> a"),
+    ("increment", "(Number) -> Number"),
+    ("decrement", "(Number) -> Number"),
+    ("add", "(Number, Number) -> Number"),
+    ("subtract", "(Number, Number) -> Number"),
+    ("multiply", "(Number, Number) -> Number"),
+    ("divide", "(Number, Number) -> Number"),
+    ("map", "((a) -> b, [a]) -> [b]"),
+    ("filter", "((a) -> Boolean, [a]) -> [a]"),
+    ("partition", "((a) -> Boolean, [a]) -> [[a]]"),
+    ("find", "((a) -> Boolean, [a]) -> a"),
+    ("index-of", "(a, [a]) -> Number"),
+    ("unique", "([a]) -> [a]"),
+    ("flatten", "([[a]]) -> [a]"),
+    ("reduce", "((b, a) -> b, b, [a]) -> b"),
+    ("every?", "((a) -> Boolean, [a]) -> Boolean"),
+    ("some?", "((a) -> Boolean, [a]) -> Boolean"),
+    ("none?", "((a) -> Boolean, [a]) -> Boolean"),
+    ("and?", "(Boolean, Boolean) -> Boolean"),
+    ("or?", "(Boolean, Boolean) -> Boolean"),
+    ("not?", "(Boolean) -> Boolean"),
+    ("defined?", "(a) -> Boolean"),
+    ("type-of", "(a) -> String"),
+    ("number?", "(a) -> Boolean"),
+    ("string?", "(a) -> Boolean"),
+    ("list?", "(a) -> Boolean"),
+    ("object?", "(a) -> Boolean"),
+    ("function?", "(a) -> Boolean"),
+    ("equal?", "(a, b) -> Boolean"),
+    ("diff", "(a, a) -> [Any]"),
+    ("compare", "(a, a) -> Number"),
+    ("tag", "(String, a) -> { tag: String, value: a }"),
+    ("tag?", "(a) -> Boolean"),
+    ("tag-name", "(Any) -> String"),
+    ("match-tag", "(Any, Any) -> Any"),
+    ("unwrap-or", "(Any, Any) -> Any"),
+    ("map-ok", "((Any) -> Any, Any) -> Any"),
+    ("and-then", "((Any) -> Any, Any) -> Any"),
+    ("set-trace!", "(Boolean) -> Null"),
+    ("print!", "(Any) -> Null"),
+    ("elog!", "(Any) -> Null"),
+    ("log-debug!", "(Any) -> Null"),
+    ("log-info!", "(Any) -> Null"),
+    ("log-warn!", "(Any) -> Null"),
+    ("log-error!", "(Any) -> Null"),
+    ("set-log-level!", "(String) -> Null"),
+    ("assert!", "(Boolean, String) -> Null"),
+    ("assert-equal!", "(a, a) -> Null"),
+    ("assert-throws!", "(() -> a) -> String"),
+    ("expect-snapshot!", "(String, a) -> Null"),
+    ("for-all", "(() -> a, (a) -> Boolean) -> Null"),
+    ("gen-number", "(Number, Number) -> () -> Number"),
+    ("gen-string", "(String) -> () -> String"),
+    ("gen-list", "(() -> a) -> () -> [a]"),
+    ("gen-object", "(Any) -> () -> Any"),
+    ("gen-one-of", "([a]) -> () -> a"),
+    ("gen-map", "((a) -> b, () -> a) -> () -> b"),
+    ("iterate", "((a) -> a, a, Number) -> a"),
+    ("until", "((a) -> Boolean, (a) -> a, a) -> a"),
+    ("for-each!", "((a) -> b, [a]) -> Null"),
+    ("map!", "((a) -> b, [a]) -> [b]"),
+    ("parallel-map!", "((a) -> b, [a]) -> [b]"),
+    ("spawn!", "(() -> a) -> Any"),
+    ("await-all!", "([Any]) -> [Any]"),
+    ("channel!", "() -> Any"),
+    ("send!", "(Any, Any) -> Null"),
+    ("receive!", "(Any) -> Any"),
+    ("select!", "([Any], Any) -> Any"),
+    ("filter!", "((a) -> Boolean, [a]) -> [a]"),
+    ("for-each-line!", "((String) -> a, String) -> Null"),
+    ("read-bytes!", "(String) -> Bytes"),
+    ("read-file!", "(String) -> String"),
+    ("write-file!", "(String, Any) -> Null"),
+    ("casefold", "(String) -> String"),
+    ("chars", "(String) -> [String]"),
+    ("char-code", "(String) -> Number"),
+    ("from-char-code", "(Number) -> String"),
+    ("string-to-number", "(String) -> Number"),
+    ("number-to-string", "(Number) -> String"),
+    ("format-number", "(Number, Any) -> String"),
+    ("format", "(String, Any) -> String"),
+    ("format-table", "([Any]) -> String"),
+    ("format-markdown-table", "([Any]) -> String"),
+    ("string-to-bytes", "(String, String) -> Bytes"),
+    ("bytes-to-string", "(String, Bytes) -> String"),
+    ("bytes-length", "(Bytes) -> Number"),
+    ("byte-at", "(Bytes, Number) -> Number"),
+    ("bytes-slice", "(Bytes, Number, Number) -> Bytes"),
+    ("locale-compare", "(String, String, String) -> Number"),
+    ("locale-sort", "(String, [String]) -> [String]"),
+    ("to-set", "([a]) -> Set"),
+    ("set-to-list", "(Set) -> [Any]"),
+    ("set-contains?", "(Set, a) -> Boolean"),
+    ("set-union", "(Set, Set) -> Set"),
+    ("set-intersection", "(Set, Set) -> Set"),
+    ("set-difference", "(Set, Set) -> Set"),
+    ("to-map", "([{ key: a, value: b }]) -> Map"),
+    ("map-entries", "(Map) -> [{ key: Any, value: Any }]"),
+    ("map-get", "(Map, a) -> Any"),
+    ("map-set", "(Map, a, b) -> Map"),
+    ("map-remove", "(Map, a) -> Map"),
+    ("map-has?", "(Map, a) -> Boolean"),
+    ("json-parse", "(String) -> Any"),
+    ("json-encode", "(a) -> String"),
+    ("sha256", "(String) -> String"),
+    ("md5", "(String) -> String"),
+    ("base64-encode", "(String) -> String"),
+    ("base64-decode", "(String) -> String"),
+    ("hex-encode", "(String) -> String"),
+    ("hex-decode", "(String) -> String"),
+    ("uuid!", "() -> String"),
+    ("uuid-v5", "(String, String) -> String"),
+    ("nth", "([a], Number) -> a"),
+    ("sort", "([a]) -> [a]"),
+    ("sort-with", "((a, a) -> Number, [a]) -> [a]"),
+];
+
+/// Infers types for the whole program and collects every type error.
+pub fn check_program(program: &Program) -> TypeReport {
+    let mut checker = Checker::new();
+    for (index, statement) in program.statements.iter().enumerate() {
+        checker.statement = index;
+        checker.check_statement(statement);
+    }
+    TypeReport {
+        errors: checker.errors,
+        bindings: checker.bindings,
+    }
+}
+
+#[derive(Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Type,
+}
+
+struct Checker {
+    /// Solutions for type variables, indexed by variable id.
+    substitution: Vec<Option<Type>>,
+    globals: HashMap<String, Scheme>,
+    /// Globals bound by the program or its imports rather than builtins.
+    user_names: HashSet<String>,
+    /// Parameters of the enclosing functions, innermost last.
+    scopes: Vec<HashMap<String, Type>>,
+    /// Names of the enclosing named functions, for messages.
+    context: Vec<String>,
+    statement: usize,
+    errors: Vec<TypeError>,
+    bindings: Vec<(String, String)>,
+}
+
+impl Checker {
+    fn new() -> Self {
+        let mut checker = Self {
+            substitution: Vec::new(),
+            globals: HashMap::new(),
+            user_names: HashSet::new(),
+            scopes: Vec::new(),
+            context: Vec::new(),
+            statement: 0,
+            errors: Vec::new(),
+            bindings: Vec::new(),
+        };
+        for (name, signature) in BUILTIN_SIGNATURES {
+            let annotation = Parser::parse_type_source(signature)
+                .unwrap_or_else(|err| panic!("bad signature for '{}': {}", name, err));
+            let ty = checker.annotation_type(&annotation, &mut HashMap::new());
+            let scheme = checker.generalize(&ty);
+            checker.globals.insert(name.to_string(), scheme);
+        }
+        checker
+    }
+
+    fn check_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Function(func) => {
+                // Recursive calls see the function monomorphically
+                let placeholder = self.fresh();
+                self.globals.insert(
+                    func.name.clone(),
+                    Scheme {
+                        vars: Vec::new(),
+                        ty: placeholder.clone(),
+                    },
+                );
+                self.context.push(func.name.clone());
+                let ty = self.infer_function(&func.params, &func.signature, &func.body);
+                self.context.pop();
+                self.unify(&placeholder, &ty).ok();
+                self.bind_global(&func.name, &ty);
+            }
+            Statement::Assignment { pattern, expr, .. } => {
+                let ty = self.infer(expr);
+                self.bind_pattern(pattern, &ty);
+            }
+            Statement::Expression(expr) => {
+                self.infer(expr);
+            }
+            Statement::Use(use_stmt) => self.bind_imports(use_stmt),
+            Statement::ConditionalUse {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.infer(condition);
+                self.bind_imports(then);
+                if let Some(otherwise) = otherwise {
+                    self.bind_imports(otherwise);
+                }
+            }
+            Statement::Export(_) => {}
+        }
+    }
+
+    fn bind_imports(&mut self, use_stmt: &UseStatement) {
+        let names: Vec<&String> = match use_stmt {
+            UseStatement::Single { name, .. } => vec![name],
+            UseStatement::Namespace { alias, .. } => vec![alias],
+            UseStatement::Selective { names, .. } => names.iter().collect(),
+            UseStatement::Glob { .. } => Vec::new(),
+        };
+        for name in names {
+            self.user_names.insert(name.clone());
+            self.globals.insert(
+                name.clone(),
+                Scheme {
+                    vars: Vec::new(),
+                    ty: Type::Any,
+                },
+            );
+        }
+    }
+
+    fn bind_global(&mut self, name: &str, ty: &Type) {
+        let scheme = self.generalize(ty);
+        self.user_names.insert(name.to_string());
+        self.bindings
+            .push((name.to_string(), self.display(&scheme.ty)));
+        self.globals.insert(name.to_string(), scheme);
+    }
+
+    fn bind_pattern(&mut self, pattern: &Pattern, ty: &Type) {
+        match pattern {
+            Pattern::Identifier(name) => self.bind_global(name, ty),
+            Pattern::List(patterns) => {
+                let element = self.fresh();
+                let list = Type::List(Box::new(element.clone()));
+                if self.unify(ty, &list).is_err() {
+                    self.error(format!("Cannot destructure {} as a list", self.display(ty)));
+                }
+                for pattern in patterns {
+                    self.bind_pattern(pattern, &element);
+                }
+            }
+            Pattern::Object(fields) => {
+                for field in fields {
+                    let (name, pattern) = match field {
+                        ObjectPatternField::Shorthand(name) => {
+                            (name, Pattern::Identifier(name.clone()))
+                        }
+                        ObjectPatternField::Field { name, pattern } => (name, pattern.clone()),
+                    };
+                    let field_ty = self.property_type(ty, name);
+                    self.bind_pattern(&pattern, &field_ty);
+                }
+            }
+        }
+    }
+
+    fn infer(&mut self, expr: &Expression) -> Type {
+        match expr {
+            Expression::Number { .. } => Type::Number,
+            Expression::Boolean(_) => Type::Boolean,
+            Expression::Null => Type::Null,
+            Expression::String(template) => {
+                for segment in &template.segments {
+                    if let StringSegment::Expr(expr) = segment {
+                        self.infer(expr);
+                    }
+                }
+                Type::String
+            }
+            Expression::Identifier(name) | Expression::Local(Slot { name, .. }) => {
+                self.lookup(name)
+            }
+            Expression::Closure { lambda, .. } => self.infer(lambda),
+            Expression::Block(expressions) => self.infer_block(expressions),
+            Expression::Lambda {
+                params,
+                signature,
+                body,
+                ..
+            } => self.infer_function(params, signature, body),
+            Expression::Object(fields) => {
+                let mut known = BTreeMap::new();
+                let mut complete = true;
+                for field in fields {
+                    match field {
+                        ObjectField::Field { name, value } => {
+                            let ty = self.infer(value);
+                            known.insert(name.clone(), ty);
+                        }
+                        ObjectField::Spread(expr) | ObjectField::Base(expr) => {
+                            let ty = self.infer(expr);
+                            match self.resolve(&ty) {
+                                Type::Object(fields) => known.extend(fields),
+                                _ => complete = false,
+                            }
+                        }
+                    }
+                }
+                if complete {
+                    Type::Object(known)
+                } else {
+                    Type::Any
+                }
+            }
+            Expression::List(elements) => self.infer_list(elements),
+            Expression::Spread(expr) => {
+                self.infer(expr);
+                Type::Any
+            }
+            Expression::Not(expr) => {
+                let ty = self.infer(expr);
+                if self.unify(&ty, &Type::Boolean).is_err() {
+                    self.error(format!(
+                        "Operand of '!' should be Boolean, found {}",
+                        self.display(&ty)
+                    ));
+                }
+                Type::Boolean
+            }
+            Expression::Call { callee, args }
+                if args.len() == 3 && self.is_builtin(callee, "if") =>
+            {
+                self.infer_if(args)
+            }
+            Expression::Call { callee, args } => {
+                let callee_ty = self.infer(callee);
+                let arg_types: Vec<Type> = args.iter().map(|arg| self.infer(arg)).collect();
+                let name = match callee.as_ref() {
+                    Expression::Identifier(name) => format!("'{}'", name),
+                    _ => "the called function".to_string(),
+                };
+                self.apply(&callee_ty, arg_types, &name)
+            }
+            Expression::PropertyAccess {
+                object, property, ..
+            } => {
+                let ty = self.infer(object);
+                self.property_type(&ty, property)
+            }
+            Expression::Binary { left, op, right } => {
+                let left_ty = self.infer(left);
+                let right_ty = self.infer(right);
+                let (operand, result) = match op {
+                    BinaryOperator::Add
+                    | BinaryOperator::Sub
+                    | BinaryOperator::Mul
+                    | BinaryOperator::Div => (Some(Type::Number), Type::Number),
+                    BinaryOperator::LessThan
+                    | BinaryOperator::LessThanEq
+                    | BinaryOperator::GreaterThan
+                    | BinaryOperator::GreaterThanEq => {
+                        self.check_ordered(*op, &left_ty, &right_ty);
+                        return Type::Boolean;
+                    }
+                    BinaryOperator::And | BinaryOperator::Or => {
+                        (Some(Type::Boolean), Type::Boolean)
+                    }
+                    BinaryOperator::Eq | BinaryOperator::NotEq => (None, Type::Boolean),
+                    BinaryOperator::PipeForward => {
+                        let name = match right.as_ref() {
+                            Expression::Identifier(name) => format!("'{}'", name),
+                            _ => "the step after '|>'".to_string(),
+                        };
+                        return self.apply(&right_ty, vec![left_ty], &name);
+                    }
+                    // The default only matters when the left side is null
+                    BinaryOperator::Coalesce => {
+                        return match self.resolve(&left_ty) {
+                            Type::Null => right_ty,
+                            _ => self.join(&left_ty, &right_ty),
+                        };
+                    }
+                };
+                if let Some(operand) = operand {
+                    for (side, ty) in [("Left", &left_ty), ("Right", &right_ty)] {
+                        if self.unify(ty, &operand).is_err() {
+                            self.error(format!(
+                                "{} operand of '{}' should be {}, found {}",
+                                side,
+                                operator_symbol(*op),
+                                self.display(&operand),
+                                self.display(ty)
+                            ));
+                        }
+                    }
+                }
+                result
+            }
+        }
+    }
+
+    /// Comparison operands must share a type that has an ordering.
+    fn check_ordered(&mut self, op: BinaryOperator, left: &Type, right: &Type) {
+        if self.unify(left, right).is_err() {
+            self.error(format!(
+                "Operands of '{}' should have the same type, found {} and {}",
+                operator_symbol(op),
+                self.display(left),
+                self.display(right)
+            ));
+            return;
+        }
+        match self.resolve(left) {
+            Type::Number | Type::String | Type::List(_) | Type::Null | Type::Any | Type::Var(_) => {
+            }
+            other => self.error(format!(
+                "Operands of '{}' should be numbers, strings, or lists, found {}",
+                operator_symbol(op),
+                self.display(&other)
+            )),
+        }
+    }
+
+    fn infer_function(
+        &mut self,
+        params: &[String],
+        signature: &Signature,
+        body: &Expression,
+    ) -> Type {
+        let mut variables = HashMap::new();
+        let mut scope = HashMap::new();
+        let mut param_types = Vec::new();
+        for (index, name) in params.iter().enumerate() {
+            let ty = match signature.params.get(index) {
+                Some(Some(annotation)) => self.annotation_type(annotation, &mut variables),
+                _ => self.fresh(),
+            };
+            scope.insert(name.clone(), ty.clone());
+            param_types.push(ty);
+        }
+        self.scopes.push(scope);
+        let mut result = self.infer(body);
+        self.scopes.pop();
+        if let Some(annotation) = &signature.result {
+            let expected = self.annotation_type(annotation, &mut variables);
+            if self.unify(&result, &expected).is_err() {
+                self.error(format!(
+                    "Function returns {} but is annotated {}",
+                    self.display(&result),
+                    self.display(&expected)
+                ));
+            }
+            result = expected;
+        }
+        Type::Function(param_types, Box::new(result))
+    }
+
+    /// A block pipes its first value through the steps that follow. A step
+    /// that is a function is called with the value so far; any other step
+    /// replaces it.
+    fn infer_block(&mut self, expressions: &[Expression]) -> Type {
+        let mut current = match expressions.first() {
+            Some(first) => self.infer(first),
+            None => return Type::Null,
+        };
+        for step in &expressions[1..] {
+            let ty = self.infer(step);
+            current = match self.resolve(&ty) {
+                Type::Function(..) => self.apply(&ty, vec![current], "a pipeline step"),
+                // Unknown steps might be functions; don't guess
+                Type::Var(_) | Type::Any => Type::Any,
+                _ => ty,
+            };
+        }
+        current
+    }
+
+    /// `if` gets its own rule so the branches may return different types,
+    /// as in `if(defined?(x), () { x }, () { "none" })`.
+    fn infer_if(&mut self, args: &[Expression]) -> Type {
+        let condition = self.infer(&args[0]);
+        if self.unify(&condition, &Type::Boolean).is_err() {
+            self.error(format!(
+                "Condition of 'if' should be Boolean, found {}",
+                self.display(&condition)
+            ));
+        }
+        let mut results = Vec::new();
+        for branch in &args[1..] {
+            let ty = self.infer(branch);
+            let result = match self.resolve(&ty) {
+                Type::Function(params, result) if params.is_empty() => *result,
+                Type::Var(_) => {
+                    let result = self.fresh();
+                    let thunk = Type::Function(Vec::new(), Box::new(result.clone()));
+                    self.unify(&ty, &thunk).ok();
+                    result
+                }
+                Type::Any | Type::Null => Type::Any,
+                other => {
+                    self.error(format!(
+                        "Branches of 'if' should be functions without parameters, found {}",
+                        self.display(&other)
+                    ));
+                    Type::Any
+                }
+            };
+            results.push(result);
+        }
+        self.join(&results[0], &results[1])
+    }
+
+    /// The common type of two values, or `Any` when they have none.
+    fn join(&mut self, left: &Type, right: &Type) -> Type {
+        let saved = self.substitution.clone();
+        if self.unify(left, right).is_ok() {
+            left.clone()
+        } else {
+            self.substitution = saved;
+            Type::Any
+        }
+    }
+
+    fn is_builtin(&self, callee: &Expression, name: &str) -> bool {
+        matches!(callee, Expression::Identifier(callee) if callee == name)
+            && !self.user_names.contains(name)
+            && !self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
+    fn infer_list(&mut self, elements: &[Expression]) -> Type {
+        let types: Vec<(bool, Type)> = elements
+            .iter()
+            .map(|element| match element {
+                Expression::Spread(inner) => (true, self.infer(inner)),
+                other => (false, self.infer(other)),
+            })
+            .collect();
+        // Lists may mix element types; those just aren't tracked
+        let mut element = self.fresh();
+        for (spread, ty) in &types {
+            let ty = if *spread {
+                let inner = self.fresh();
+                if self
+                    .unify(ty, &Type::List(Box::new(inner.clone())))
+                    .is_err()
+                {
+                    self.error(format!("Cannot spread {} into a list", self.display(ty)));
+                }
+                inner
+            } else {
+                ty.clone()
+            };
+            element = self.join(&element, &ty);
+        }
+        Type::List(Box::new(element))
+    }
+
+    fn apply(&mut self, callee: &Type, args: Vec<Type>, name: &str) -> Type {
+        match self.resolve(callee) {
+            Type::Function(params, result) => {
+                if args.len() > params.len() {
+                    // Calling the returned function with the extra arguments
+                    let rest = args[params.len()..].to_vec();
+                    let first = self.apply(callee, args[..params.len()].to_vec(), name);
+                    return match self.resolve(&first) {
+                        Type::Function(..) | Type::Var(_) | Type::Any => {
+                            self.apply(&first, rest, name)
+                        }
+                        _ => {
+                            self.error(format!(
+                                "{} expects {} argument(s) but is called with {}",
+                                capitalize(name),
+                                params.len(),
+                                args.len()
+                            ));
+                            Type::Any
+                        }
+                    };
+                }
+                for (index, (param, arg)) in params.iter().zip(&args).enumerate() {
+                    if self.unify(param, arg).is_err() {
+                        self.error(format!(
+                            "Argument {} of {} should be {}, found {}",
+                            index + 1,
+                            name,
+                            self.display(param),
+                            self.display(arg)
+                        ));
+                    }
+                }
+                if args.len() < params.len() {
+                    // Too few arguments curry
+                    Type::Function(params[args.len()..].to_vec(), result)
+                } else {
+                    *result
+                }
+            }
+            Type::Var(_) => {
+                let result = self.fresh();
+                let function = Type::Function(args, Box::new(result.clone()));
+                self.unify(callee, &function).ok();
+                result
+            }
+            Type::Any | Type::Null => Type::Any,
+            other => {
+                self.error(format!(
+                    "{} is not a function; it has type {}",
+                    capitalize(name),
+                    self.display(&other)
+                ));
+                Type::Any
+            }
+        }
+    }
+
+    fn property_type(&mut self, ty: &Type, property: &str) -> Type {
+        match self.resolve(ty) {
+            // Reading a field the object doesn't have gives null
+            Type::Object(fields) => fields.get(property).cloned().unwrap_or(Type::Null),
+            Type::List(element) if property.parse::<usize>().is_ok() => *element,
+            Type::Var(_) | Type::Any | Type::Null => Type::Any,
+            other => {
+                self.error(format!(
+                    "Cannot read property '{}' of {}",
+                    property,
+                    self.display(&other)
+                ));
+                Type::Any
+            }
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Type {
+        for scope in self.scopes.iter().rev() {
+            if let Some(ty) = scope.get(name) {
+                return ty.clone();
+            }
+        }
+        match self.globals.get(name).cloned() {
+            Some(scheme) => self.instantiate(&scheme),
+            // Undefined names are the interpreter's business
+            None => Type::Any,
+        }
+    }
+
+    fn annotation_type(
+        &mut self,
+        annotation: &TypeExpr,
+        variables: &mut HashMap<String, Type>,
+    ) -> Type {
+        match annotation {
+            TypeExpr::Named(name) => match name.as_str() {
+                "Number" => Type::Number,
+                "String" => Type::String,
+                "Bytes" => Type::Bytes,
+                "Set" => Type::Set,
+                "Map" => Type::Map,
+                "Boolean" => Type::Boolean,
+                "Null" => Type::Null,
+                _ => Type::Any,
+            },
+            TypeExpr::Variable(name) => {
+                if let Some(ty) = variables.get(name) {
+                    return ty.clone();
+                }
+                let ty = self.fresh();
+                variables.insert(name.clone(), ty.clone());
+                ty
+            }
+            TypeExpr::List(element) => {
+                Type::List(Box::new(self.annotation_type(element, variables)))
+            }
+            TypeExpr::Object(fields) => Type::Object(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), self.annotation_type(ty, variables)))
+                    .collect(),
+            ),
+            TypeExpr::Function(params, result) => Type::Function(
+                params
+                    .iter()
+                    .map(|param| self.annotation_type(param, variables))
+                    .collect(),
+                Box::new(self.annotation_type(result, variables)),
+            ),
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.substitution.push(None);
+        Type::Var(self.substitution.len() - 1)
+    }
+
+    /// Follows solved variables until reaching a concrete type or an
+    /// unsolved variable.
+    fn resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(id) = ty {
+            match &self.substitution[id] {
+                Some(solved) => ty = solved.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    /// Applies the substitution everywhere inside a type.
+    fn zonk(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::List(element) => Type::List(Box::new(self.zonk(&element))),
+            Type::Object(fields) => Type::Object(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), self.zonk(ty)))
+                    .collect(),
+            ),
+            Type::Function(params, result) => Type::Function(
+                params.iter().map(|param| self.zonk(param)).collect(),
+                Box::new(self.zonk(&result)),
+            ),
+            other => other,
+        }
+    }
+
+    fn unify(&mut self, left: &Type, right: &Type) -> Result<(), ()> {
+        let left = self.resolve(left);
+        let right = self.resolve(right);
+        match (&left, &right) {
+            (Type::Any, _) | (_, Type::Any) | (Type::Null, _) | (_, Type::Null) => Ok(()),
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(id), other) | (other, Type::Var(id)) => {
+                if self.occurs(*id, other) {
+                    return Err(());
+                }
+                self.substitution[*id] = Some(other.clone());
+                Ok(())
+            }
+            (Type::Number, Type::Number)
+            | (Type::String, Type::String)
+            | (Type::Bytes, Type::Bytes)
+            | (Type::Set, Type::Set)
+            | (Type::Map, Type::Map)
+            | (Type::Boolean, Type::Boolean) => Ok(()),
+            (Type::List(a), Type::List(b)) => self.unify(a, b),
+            (Type::Object(a), Type::Object(b)) => {
+                // Objects are compared on the fields both sides know about
+                for (name, ty) in a {
+                    if let Some(other) = b.get(name) {
+                        self.unify(ty, other)?;
+                    }
+                }
+                Ok(())
+            }
+            (Type::Function(a_params, a_result), Type::Function(b_params, b_result)) => {
+                // `(a, b) -> c` and `(a) -> (b) -> c` are the same curried function
+                let shared = a_params.len().min(b_params.len());
+                for (a, b) in a_params.iter().zip(b_params) {
+                    self.unify(a, b)?;
+                }
+                let rest = |params: &[Type], result: &Type| {
+                    if params.len() > shared {
+                        Type::Function(params[shared..].to_vec(), Box::new(result.clone()))
+                    } else {
+                        result.clone()
+                    }
+                };
+                let a_rest = rest(a_params, a_result);
+                let b_rest = rest(b_params, b_result);
+                self.unify(&a_rest, &b_rest)
+            }
+            _ => Err(()),
+        }
+    }
+
+    fn occurs(&self, id: usize, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(other) => other == id,
+            Type::List(element) => self.occurs(id, &element),
+            Type::Object(fields) => fields.values().any(|field| self.occurs(id, field)),
+            Type::Function(params, result) => {
+                params.iter().any(|param| self.occurs(id, param)) || self.occurs(id, &result)
+            }
+            _ => false,
+        }
+    }
+
+    /// Top-level bindings close over nothing but other generalized
+    /// bindings, so every variable left unsolved can be generalized.
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.zonk(ty);
+        let mut vars = Vec::new();
+        collect_vars(&ty, &mut vars);
+        Scheme { vars, ty }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: HashMap<usize, Type> =
+            scheme.vars.iter().map(|var| (*var, self.fresh())).collect();
+        substitute(&scheme.ty, &fresh)
+    }
+
+    /// Renders a type with its variables named `a`, `b`, ... in order of
+    /// appearance.
+    fn display(&self, ty: &Type) -> String {
+        let ty = self.zonk(ty);
+        let mut vars = Vec::new();
+        collect_vars(&ty, &mut vars);
+        render(&ty, &vars)
+    }
+
+    fn error(&mut self, message: String) {
+        let message = match self.context.last() {
+            Some(function) => format!("In '{}': {}", function, message),
+            None => message,
+        };
+        self.errors.push(TypeError {
+            statement: self.statement,
+            message,
+        });
+    }
+}
+
+fn collect_vars(ty: &Type, vars: &mut Vec<usize>) {
+    match ty {
+        Type::Var(id) if !vars.contains(id) => vars.push(*id),
+        Type::List(element) => collect_vars(element, vars),
+        Type::Object(fields) => fields.values().for_each(|field| collect_vars(field, vars)),
+        Type::Function(params, result) => {
+            params.iter().for_each(|param| collect_vars(param, vars));
+            collect_vars(result, vars);
+        }
+        _ => {}
+    }
+}
+
+fn substitute(ty: &Type, mapping: &HashMap<usize, Type>) -> Type {
+    match ty {
+        Type::Var(id) => mapping.get(id).cloned().unwrap_or(Type::Var(*id)),
+        Type::List(element) => Type::List(Box::new(substitute(element, mapping))),
+        Type::Object(fields) => Type::Object(
+            fields
+                .iter()
+                .map(|(name, field)| (name.clone(), substitute(field, mapping)))
+                .collect(),
+        ),
+        Type::Function(params, result) => Type::Function(
+            params
+                .iter()
+                .map(|param| substitute(param, mapping))
+                .collect(),
+            Box::new(substitute(result, mapping)),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn render(ty: &Type, vars: &[usize]) -> String {
+    match ty {
+        Type::Number => "Number".to_string(),
+        Type::String => "String".to_string(),
+        Type::Bytes => "Bytes".to_string(),
+        Type::Set => "Set".to_string(),
+        Type::Map => "Map".to_string(),
+        Type::Boolean => "Boolean".to_string(),
+        Type::Null => "Null".to_string(),
+        Type::Any => "Any".to_string(),
+        Type::Var(id) => {
+            let index = vars.iter().position(|var| var == id).unwrap_or(0);
+            let letter = (b'a' + (index % 26) as u8) as char;
+            if index < 26 {
+                letter.to_string()
+            } else {
+                format!("{}{}", letter, index / 26)
+            }
+        }
+        Type::List(element) => format!("[{}]", render(element, vars)),
+        Type::Object(fields) if fields.is_empty() => "{}".to_string(),
+        Type::Object(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, field)| format!("{}: {}", name, render(field, vars)))
+                .collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        Type::Function(params, result) => {
+            let params: Vec<String> = params.iter().map(|param| render(param, vars)).collect();
+            format!("({}) -> {}", params.join(", "), render(result, vars))
+        }
+    }
+}
+
+fn operator_symbol(op: BinaryOperator) -> &'static str {
+    match op {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Sub => "-",
+        BinaryOperator::Mul => "*",
+        BinaryOperator::Div => "/",
+        BinaryOperator::Eq => "=",
+        BinaryOperator::NotEq => "≠",
+        BinaryOperator::LessThan => "<",
+        BinaryOperator::LessThanEq => "<=",
+        BinaryOperator::GreaterThan => ">",
+        BinaryOperator::GreaterThanEq => ">=",
+        BinaryOperator::And => "&",
+        BinaryOperator::Or => "|",
+        BinaryOperator::Coalesce => "??",
+        BinaryOperator::PipeForward => "|>",
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn check(source: &str) -> TypeReport {
+        let tokens = Lexer::new(source).lex().expect("lexes");
+        let program = Parser::new(tokens).parse_program().expect("parses");
+        check_program(&program)
+    }
+
+    fn binding(report: &TypeReport, name: &str) -> String {
+        report
+            .bindings
+            .iter()
+            .find(|(binding, _)| binding == name)
+            .map(|(_, ty)| ty.clone())
+            .unwrap_or_else(|| panic!("no binding '{}'", name))
+    }
+
+    #[test]
+    fn infers_generic_and_curried_functions() {
+        let report = check(
+            "twice: (f, x) { f(f(x)) }\nlengths: map((xs: [a]) { 1 })\nn: twice(increment, 1)\nlabel: twice((s) { \"<s>!\" }, \"hi\")\nadd-one: add(1)\npiped: [1] |> map(add-one)\n",
+        );
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(binding(&report, "twice"), "((a) -> a, a) -> a");
+        assert_eq!(binding(&report, "lengths"), "([[a]]) -> [Number]");
+        assert_eq!(binding(&report, "n"), "Number");
+        assert_eq!(binding(&report, "label"), "String");
+        assert_eq!(binding(&report, "add-one"), "(Number) -> Number");
+        assert_eq!(binding(&report, "piped"), "[Number]");
+    }
+
+    #[test]
+    fn reports_mismatches_against_annotations_and_operators() {
+        let report = check(
+            "greet: (name: String): String { \"hi <name>\" }\nx: greet(42)\nsize: (n): String { n + 1 }\nuser: { name: \"Ada\" }\nshout: user.name * 2\nmixed: [1, \"two\"]\nordered: \"a\" < \"b\"\nodd: 1 < \"b\"\n",
+        );
+        let messages: Vec<(usize, &str)> = report
+            .errors
+            .iter()
+            .map(|error| (error.statement, error.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, "Argument 1 of 'greet' should be String, found Number"),
+                (
+                    2,
+                    "In 'size': Function returns Number but is annotated String"
+                ),
+                (4, "Left operand of '*' should be Number, found String"),
+                (
+                    7,
+                    "Operands of '<' should have the same type, found Number and String"
+                ),
+            ]
+        );
+        assert_eq!(binding(&report, "mixed"), "[Any]");
+    }
+}
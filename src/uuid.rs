@@ -0,0 +1,110 @@
+//! UUID text for the `uuid!` and `uuid-v5` builtins (RFC 9562). UUIDs are
+//! written in the usual lowercase `8-4-4-4-12` hex form.
+
+use crate::encoding;
+
+/// A random (version 4) UUID. The bits come from the standard library's
+/// per-process hash seed, which is unpredictable enough for identifiers and
+/// fixtures but is not a source of secrets.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn v4() -> Result<String, String> {
+    use std::cell::Cell;
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    thread_local! {
+        static COUNTER: Cell<u64> = const { Cell::new(0) };
+    }
+    let mut bytes = [0u8; 16];
+    for half in bytes.chunks_exact_mut(8) {
+        let count = COUNTER.with(|counter| {
+            counter.set(counter.get().wrapping_add(1));
+            counter.get()
+        });
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(count);
+        if let Ok(elapsed) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            hasher.write_u128(elapsed.as_nanos());
+        }
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    Ok(v4_from(bytes))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn v4() -> Result<String, String> {
+    Err("random numbers are not available in this build".to_string())
+}
+
+/// The version 4 UUID carrying the given random bits, for callers with
+/// their own source of randomness.
+pub fn v4_from(bytes: [u8; 16]) -> String {
+    format(stamp(bytes, 4))
+}
+
+/// The name-based (version 5) UUID for `name` within `namespace`. The same
+/// inputs always give the same UUID.
+pub fn v5(namespace: &str, name: &str) -> Result<String, String> {
+    let namespace = parse(namespace).ok_or_else(|| format!("'{}' is not a UUID", namespace))?;
+    let mut input = namespace.to_vec();
+    input.extend_from_slice(name.as_bytes());
+    let digest = encoding::sha1(&input);
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    Ok(format(stamp(bytes, 5)))
+}
+
+/// Sets the version nibble and the RFC variant bits.
+fn stamp(mut bytes: [u8; 16], version: u8) -> [u8; 16] {
+    bytes[6] = (bytes[6] & 0x0f) | (version << 4);
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    bytes
+}
+
+fn format(bytes: [u8; 16]) -> String {
+    let hex = encoding::hex_encode(&bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Reads the hyphenated form in either case.
+fn parse(text: &str) -> Option<[u8; 16]> {
+    let groups: Vec<&str> = text.split('-').collect();
+    let lengths: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+    if lengths != [8, 4, 4, 4, 12] {
+        return None;
+    }
+    encoding::hex_decode(&groups.concat()).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_random_and_name_based_uuids() {
+        assert_eq!(
+            v5("6ba7b810-9dad-11d1-80b4-00c04fd430c8", "python.org"),
+            Ok("886313e1-3b8a-5372-9b90-0c9aee199e5d".to_string())
+        );
+        assert_eq!(
+            v5("6BA7B810-9DAD-11D1-80B4-00C04FD430C8", "python.org"),
+            v5("6ba7b810-9dad-11d1-80b4-00c04fd430c8", "python.org")
+        );
+        assert!(v5("6ba7b8109dad11d180b400c04fd430c8", "x").is_err());
+        assert!(v5("6ba7b810-9dad-11d1-80b4-00c04fd430cg", "x").is_err());
+
+        let first = v4().expect("v4 should be available");
+        let second = v4().expect("v4 should be available");
+        assert_ne!(first, second);
+        let bytes = parse(&first).expect("v4 output should parse");
+        assert_eq!(bytes[6] >> 4, 4);
+        assert_eq!(bytes[8] >> 6, 0b10);
+    }
+}
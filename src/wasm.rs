@@ -0,0 +1,211 @@
+//! C ABI exports for `wasm32-unknown-unknown` builds, used by the docs
+//! playground. Every entry point takes a UTF-8 buffer written into memory
+//! obtained from `fip_alloc`, stores a JSON result that the host reads via
+//! `fip_result_ptr`/`fip_result_len`, and returns 0 on success or 1 on error.
+//! Results look like `{"ok":true,...}` or `{"ok":false,"error":"..."}`.
+
+use crate::{
+    ast::Statement,
+    error::LangResult,
+    formatter::Formatter,
+    interpreter::{Interpreter, Limits, Value},
+    json,
+    lexer::Lexer,
+    loader::NoModuleLoader,
+    parser::Parser,
+};
+use std::{cell::RefCell, collections::BTreeMap};
+
+thread_local! {
+    static RESULT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+#[no_mangle]
+pub extern "C" fn fip_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// # Safety
+/// `ptr` and `len` must come from a single `fip_alloc` call.
+#[no_mangle]
+pub unsafe extern "C" fn fip_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+#[no_mangle]
+pub extern "C" fn fip_result_ptr() -> *const u8 {
+    RESULT.with(|result| result.borrow().as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn fip_result_len() -> usize {
+    RESULT.with(|result| result.borrow().len())
+}
+
+/// Lexes the source and returns `{"ok":true,"tokens":[{kind,start,end}]}`.
+///
+/// # Safety
+/// `ptr` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fip_lex(ptr: *const u8, len: usize) -> u32 {
+    respond(ptr, len, |source| {
+        let tokens = Lexer::new(source).lex()?;
+        let tokens = tokens
+            .into_iter()
+            .map(|token| {
+                let mut fields = BTreeMap::new();
+                fields.insert(
+                    "kind".to_string(),
+                    Value::String(format!("{:?}", token.kind)),
+                );
+                fields.insert("start".to_string(), Value::Number(token.span.start as i64));
+                fields.insert("end".to_string(), Value::Number(token.span.end as i64));
+                Value::Object(fields)
+            })
+            .collect();
+        Ok(("tokens", Value::List(tokens)))
+    })
+}
+
+/// Parses the source and returns `{"ok":true,"ast":"<debug tree>"}`.
+///
+/// # Safety
+/// `ptr` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fip_parse(ptr: *const u8, len: usize) -> u32 {
+    respond(ptr, len, |source| {
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        Ok(("ast", Value::String(format!("{:#?}", program))))
+    })
+}
+
+/// Formats the source with the default settings and returns
+/// `{"ok":true,"formatted":"..."}`.
+///
+/// # Safety
+/// `ptr` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fip_format(ptr: *const u8, len: usize) -> u32 {
+    respond(ptr, len, |source| {
+        let mut parser = Parser::new(Lexer::new(source).lex()?);
+        let program = parser.parse_program()?;
+        let formatted = Formatter::new()
+            .with_source(source, parser.statement_starts())
+            .format_program(&program);
+        Ok(("formatted", Value::String(formatted)))
+    })
+}
+
+/// Evaluates the source in a fresh interpreter without module imports and
+/// returns `{"ok":true,"bindings":{name: rendered value}}` for every binding
+/// the program's statements define. Runaway programs are stopped by step and call depth
+/// limits instead of hanging the page.
+///
+/// # Safety
+/// `ptr` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fip_eval(ptr: *const u8, len: usize) -> u32 {
+    respond(ptr, len, |source| {
+        let program = Parser::new(Lexer::new(source).lex()?).parse_program()?;
+        let mut interpreter = Interpreter::new();
+        interpreter.set_module_loader(Box::new(NoModuleLoader));
+        interpreter.set_limits(Limits {
+            max_call_depth: Some(200),
+            max_steps: Some(1_000_000),
+            timeout: None,
+        });
+        interpreter.eval_program(&program)?;
+        let globals = interpreter.snapshot().globals;
+
+        let mut bindings = BTreeMap::new();
+        let defined = program
+            .statements
+            .iter()
+            .flat_map(|statement| match statement {
+                Statement::Assignment { pattern, .. } => pattern.names(),
+                Statement::Function(function) => vec![function.name.as_str()],
+                _ => Vec::new(),
+            });
+        for name in defined {
+            if let Some(value) = globals.get(name) {
+                bindings.insert(
+                    name.to_string(),
+                    Value::String(interpreter.value_to_string(value)?),
+                );
+            }
+        }
+        Ok(("bindings", Value::Object(bindings)))
+    })
+}
+
+unsafe fn respond(
+    ptr: *const u8,
+    len: usize,
+    run: impl FnOnce(&str) -> LangResult<(&'static str, Value)>,
+) -> u32 {
+    let bytes = std::slice::from_raw_parts(ptr, len);
+    let outcome = match std::str::from_utf8(bytes) {
+        Ok(source) => run(source).map_err(|err| err.to_string()),
+        Err(err) => Err(format!("Source is not valid UTF-8: {}", err)),
+    };
+
+    let mut fields = BTreeMap::new();
+    let status = match outcome {
+        Ok((key, value)) => {
+            fields.insert("ok".to_string(), Value::Boolean(true));
+            fields.insert(key.to_string(), value);
+            0
+        }
+        Err(message) => {
+            fields.insert("ok".to_string(), Value::Boolean(false));
+            fields.insert("error".to_string(), Value::String(message));
+            1
+        }
+    };
+    let encoded = json::encode(&Value::Object(fields))
+        .unwrap_or_else(|err| format!("{{\"ok\":false,\"error\":{:?}}}", err));
+    RESULT.with(|result| *result.borrow_mut() = encoded);
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(export: unsafe extern "C" fn(*const u8, usize) -> u32, source: &str) -> (u32, String) {
+        let status = unsafe { export(source.as_ptr(), source.len()) };
+        let result = unsafe {
+            std::str::from_utf8(std::slice::from_raw_parts(
+                fip_result_ptr(),
+                fip_result_len(),
+            ))
+            .expect("result is UTF-8")
+            .to_string()
+        };
+        (status, result)
+    }
+
+    #[test]
+    fn eval_reports_new_bindings_and_rejects_imports() {
+        let (status, result) = call(fip_eval, "x: 20\n[y, z]: [add(x, 1), 2]");
+        assert_eq!(status, 0);
+        assert_eq!(
+            result,
+            r#"{"bindings":{"x":"20","y":"21","z":"2"},"ok":true}"#
+        );
+
+        let (status, result) = call(fip_eval, "use { a } from \"./lib\"");
+        assert_eq!(status, 1);
+        assert!(result.contains("module imports are not available"));
+    }
+
+    #[test]
+    fn format_returns_formatted_source() {
+        let (status, result) = call(fip_format, "x:   add( 1,2 )");
+        assert_eq!(status, 0);
+        assert_eq!(result, r#"{"formatted":"x: add(1, 2)","ok":true}"#);
+    }
+}
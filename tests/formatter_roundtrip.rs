@@ -0,0 +1,288 @@
+//! Formats a corpus of programs with `fip format` and checks that the output
+//! parses to the same AST as the input and that formatting it again changes
+//! nothing. The corpus is the example programs, every runnable ```fip block
+//! in the docs, and a batch of randomly generated programs. Each check runs
+//! once with the default settings and once with a narrow `.fipfmt.toml` that
+//! forces wrapping.
+
+use fippli_lang::{ast::Program, lexer::Lexer, parser::Parser};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const CONFIGS: &[(&str, &str)] = &[
+    ("default", ""),
+    (
+        "narrow",
+        "indent-size = 4\nmax-width = 24\ntrailing-comma = \"always\"\nblank-lines = \"grouped\"\n",
+    ),
+];
+
+const RANDOM_PROGRAMS: usize = 150;
+
+#[test]
+fn example_programs_round_trip() {
+    let mut corpus = Vec::new();
+    for entry in fs::read_dir(repo_path("examples")).expect("examples directory") {
+        let path = entry.expect("directory entry").path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some("fip") {
+            let source = fs::read_to_string(&path).expect("readable example");
+            corpus.push((path.display().to_string(), source));
+        }
+    }
+    assert!(!corpus.is_empty(), "no example programs found");
+    check_corpus("examples", &corpus);
+}
+
+#[test]
+fn doc_examples_round_trip() {
+    let mut files = Vec::new();
+    collect_markdown(&repo_path("syntax"), &mut files);
+    let mut corpus = Vec::new();
+    for file in files {
+        let markdown = fs::read_to_string(&file).expect("readable markdown");
+        for (line, source) in fip_blocks(&markdown) {
+            corpus.push((format!("{}:{}", file.display(), line), source));
+        }
+    }
+    assert!(!corpus.is_empty(), "no doc examples found");
+    check_corpus("docs", &corpus);
+}
+
+#[test]
+fn random_programs_round_trip() {
+    let mut rng = Rng(0x5eed_f1b0_0c0f_fee5);
+    let corpus: Vec<(String, String)> = (0..RANDOM_PROGRAMS)
+        .map(|index| {
+            (
+                format!("random program #{}", index),
+                random_program(&mut rng),
+            )
+        })
+        .collect();
+    check_corpus("random", &corpus);
+}
+
+fn check_corpus(name: &str, corpus: &[(String, String)]) {
+    for (config_name, config) in CONFIGS {
+        let dir = std::env::temp_dir().join(format!(
+            "fip-roundtrip-{}-{}-{}",
+            std::process::id(),
+            name,
+            config_name
+        ));
+        fs::create_dir_all(&dir).expect("temp dir");
+        // An explicit config keeps settings from leaking in from parent dirs
+        fs::write(dir.join(".fipfmt.toml"), config).expect("write config");
+
+        for (label, source) in corpus {
+            let label = format!("{} ({})", label, config_name);
+            let original = parse(source).unwrap_or_else(|err| {
+                panic!("{}: input does not parse: {}\n{}", label, err, source)
+            });
+
+            let once = format(&dir, source)
+                .unwrap_or_else(|err| panic!("{}: formatting failed: {}\n{}", label, err, source));
+            let reparsed = parse(&once).unwrap_or_else(|err| {
+                panic!(
+                    "{}: formatted output does not parse: {}\n{}",
+                    label, err, once
+                )
+            });
+            assert_eq!(
+                original, reparsed,
+                "{}: formatting changed the program\n--- input\n{}\n--- output\n{}",
+                label, source, once
+            );
+
+            let twice = format(&dir, &once)
+                .unwrap_or_else(|err| panic!("{}: reformatting failed: {}\n{}", label, err, once));
+            assert_eq!(once, twice, "{}: formatting is not idempotent", label);
+        }
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
+
+fn parse(source: &str) -> Result<Program, String> {
+    let tokens = Lexer::new(source).lex().map_err(|err| err.to_string())?;
+    Parser::new(tokens)
+        .parse_program()
+        .map_err(|err| err.to_string())
+}
+
+fn format(dir: &Path, source: &str) -> Result<String, String> {
+    let file = dir.join("input.fip");
+    fs::write(&file, source).map_err(|err| err.to_string())?;
+    let output = Command::new(env!("CARGO_BIN_EXE_fip"))
+        .arg("format")
+        .arg(&file)
+        .output()
+        .map_err(|err| err.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    String::from_utf8(output.stdout).map_err(|err| err.to_string())
+}
+
+fn repo_path(relative: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+fn collect_markdown(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).expect("readable directory") {
+        let path = entry.expect("directory entry").path();
+        if path.is_dir() {
+            collect_markdown(&path, files);
+        } else if path.extension().and_then(|ext| ext.to_str()) == Some("md") {
+            files.push(path);
+        }
+    }
+    files.sort();
+}
+
+/// Returns the runnable ```fip blocks of a markdown file with the line each
+/// one starts on. Blocks tagged ```fip,ignore are skipped, as in doctest.
+fn fip_blocks(markdown: &str) -> Vec<(usize, String)> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (index, line) in markdown.lines().enumerate() {
+        match current.take() {
+            Some((start, source)) if line.trim() == "```" => blocks.push((start, source)),
+            Some((start, mut source)) => {
+                source.push_str(line);
+                source.push('\n');
+                current = Some((start, source));
+            }
+            None if line.trim() == "```fip" => current = Some((index + 2, String::new())),
+            None => {}
+        }
+    }
+    blocks
+}
+
+/// Deterministic xorshift generator so failures reproduce.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &'a [&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+fn random_program(rng: &mut Rng) -> String {
+    let mut scope: Vec<String> = Vec::new();
+    let mut lines = Vec::new();
+    for index in 0..1 + rng.below(8) {
+        match rng.below(4) {
+            0 => {
+                let name = format!("fun-{}", index);
+                let body = random_expression(rng, 3, &["left", "right"]);
+                lines.push(format!("{}: (left, right) {{ {} }}", name, body));
+                scope.push(name);
+            }
+            1 => {
+                let names: Vec<&str> = scope.iter().map(String::as_str).collect();
+                lines.push(format!("log!({})", random_expression(rng, 3, &names)));
+            }
+            _ => {
+                let name = format!("value-{}", index);
+                let names: Vec<&str> = scope.iter().map(String::as_str).collect();
+                lines.push(format!("{}: {}", name, random_expression(rng, 3, &names)));
+                scope.push(name);
+            }
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn random_expression(rng: &mut Rng, depth: usize, scope: &[&str]) -> String {
+    if depth == 0 {
+        return random_atom(rng, scope);
+    }
+    let inner = |rng: &mut Rng| random_expression(rng, depth - 1, scope);
+    match rng.below(9) {
+        0 => {
+            let mut items: Vec<String> = (0..rng.below(5)).map(|_| inner(rng)).collect();
+            if !scope.is_empty() && rng.below(3) == 0 {
+                items.push(format!("...{}", rng.pick(scope)));
+            }
+            format!("[{}]", items.join(", "))
+        }
+        1 => {
+            let fields: Vec<String> = (0..1 + rng.below(3))
+                .map(|index| format!("key-{}: {}", index, inner(rng)))
+                .collect();
+            let record = format!("{{ {} }}", fields.join(", "));
+            if rng.below(3) == 0 {
+                format!("({}) with {}", inner(rng), record)
+            } else {
+                record
+            }
+        }
+        2 => {
+            let callee = rng.pick(&["add", "multiply", "identity", "get", "nth"]);
+            let args: Vec<String> = (0..1 + rng.below(3)).map(|_| inner(rng)).collect();
+            format!("{}({})", callee, args.join(", "))
+        }
+        3 | 4 => {
+            let op = rng.pick(&["+", "-", "*", "/", "=", "<", ">=", "&", "|", "??", "|>"]);
+            format!("({} {} {})", inner(rng), op, inner(rng))
+        }
+        5 => format!("({}){}field", inner(rng), rng.pick(&[".", "?."])),
+        6 => {
+            let mut lambda_scope = scope.to_vec();
+            lambda_scope.push("item");
+            let body = random_expression(rng, depth - 1, &lambda_scope);
+            if rng.below(2) == 0 {
+                format!("(item) {{ {} }}", body)
+            } else {
+                let second = random_expression(rng, depth - 1, &lambda_scope);
+                format!("(item) {{\n  {}\n  {}\n}}", body, second)
+            }
+        }
+        7 => format!("{}{}", rng.pick(&["-", "!"]), inner(rng)),
+        _ => random_atom(rng, scope),
+    }
+}
+
+fn random_atom(rng: &mut Rng, scope: &[&str]) -> String {
+    match rng.below(6) {
+        0 => rng.below(1000).to_string(),
+        1 => rng
+            .pick(&[
+                "\"\"",
+                "\"plain\"",
+                "\"quote \\\" here\"",
+                "\"tab\\tline\\n\"",
+            ])
+            .to_string(),
+        2 if !scope.is_empty() => format!("\"x is <{}>\"", rng.pick(scope)),
+        3 => rng.pick(&["true", "false", "null"]).to_string(),
+        4 | 5 if !scope.is_empty() => rng.pick(scope).to_string(),
+        _ if rng.below(4) == 0 => rng
+            .pick(&[
+                "1_000",
+                "10_000_000",
+                "0xff",
+                "0XFF_FF",
+                "0b1010",
+                "0b1111_0000",
+            ])
+            .to_string(),
+        _ => rng.below(100).to_string(),
+    }
+}
@@ -0,0 +1,46 @@
+//! Runs `fip rename` on a module and the file that imports it, and checks
+//! that an exported binding is only renamed together with its importers.
+
+use std::{fs, process::Command};
+
+#[test]
+fn exported_bindings_need_across_modules() {
+    let dir = std::env::temp_dir().join(format!("fip-rename-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("temp dir");
+    let lib = "count: (items) { length(items) }\nexport count\n";
+    let main = "use { count } from \"lib\"\nlog!(count([1, 2]))\n";
+    fs::write(dir.join("lib.fip"), lib).expect("lib file");
+    fs::write(dir.join("main.fip"), main).expect("main file");
+    let rename = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_fip"))
+            .current_dir(&dir)
+            .args(["rename", "lib.fip", "count", "size"])
+            .args(args)
+            .output()
+            .expect("fip runs")
+    };
+
+    let output = rename(&[]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("'count' is exported; pass --across-modules"),
+        "{}",
+        stderr
+    );
+    assert_eq!(fs::read_to_string(dir.join("lib.fip")).unwrap(), lib);
+    assert_eq!(fs::read_to_string(dir.join("main.fip")).unwrap(), main);
+
+    let output = rename(&["--across-modules"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(dir.join("lib.fip")).unwrap(),
+        "size: (items) { length(items) }\nexport size\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("main.fip")).unwrap(),
+        "use { size } from \"lib\"\nlog!(size([1, 2]))\n"
+    );
+
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,32 @@
+//! Runs `fip run` with its flags on either side of the file.
+
+use std::{fs, process::Command};
+
+#[test]
+fn flags_may_come_before_the_file() {
+    let dir = std::env::temp_dir().join(format!("fip-run-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("temp dir");
+    fs::write(
+        dir.join("main.fip"),
+        "double: (x) { x * 2 }\nlog!(double(4))\n",
+    )
+    .expect("file");
+
+    for args in [
+        &["--trace", "--seed", "1", "main.fip"][..],
+        &["main.fip", "--seed", "1", "--trace"][..],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_fip"))
+            .current_dir(&dir)
+            .arg("run")
+            .args(args)
+            .output()
+            .expect("fip runs");
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "8\n");
+        let trace = String::from_utf8_lossy(&output.stderr);
+        assert!(trace.contains("double(4)\n"), "{}", trace);
+    }
+
+    fs::remove_dir_all(&dir).ok();
+}
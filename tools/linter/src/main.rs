@@ -1,12 +1,26 @@
-use std::{collections::HashSet, env, fs, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use fippli_lang::ast::{
     BinaryOperator, Expression, Function, ObjectField, ObjectPatternField, Pattern, Program,
-    Statement, StringSegment,
+    Statement, StringSegment, UseStatement,
 };
 use fippli_lang::error::{byte_offset_to_line, LangError};
+use fippli_lang::formatter::Formatter;
+use fippli_lang::highlight::{self, Role};
+use fippli_lang::interpreter::Interpreter;
 use fippli_lang::lexer::Lexer;
+use fippli_lang::loader::{FileSystemLoader, ModuleLoader};
+use fippli_lang::naming::{self, NamingRules};
 use fippli_lang::parser::Parser;
+use fippli_lang::report::{Reporter, Severity};
+use fippli_lang::semantic;
+use fippli_lang::source_file::SourceFile;
+use fippli_lang::suggest;
 
 #[derive(Debug, Clone)]
 pub struct LintError {
@@ -16,11 +30,106 @@ pub struct LintError {
     pub severity: Severity,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Severity {
-    Error,
-    Warning,
-    Info,
+/// Where the names and block steps of the linted file are. The syntax tree
+/// keeps no positions, so the rules count the names and blocks they have
+/// passed in the current statement and take the one with the same number.
+struct Positions {
+    statement_starts: Vec<usize>,
+    /// Offsets of the names of each statement, by role and spelling.
+    names: HashMap<(usize, Role, String), Vec<usize>>,
+    /// Where each name is first bound.
+    definitions: HashMap<String, usize>,
+    /// Where the steps of each block start, blocks in source order.
+    blocks: Vec<(usize, Vec<usize>)>,
+    statement: usize,
+    /// How many names of each role and spelling the statement has had.
+    passed: HashMap<(Role, String), usize>,
+    /// How many of the statement's blocks have been entered.
+    entered: usize,
+}
+
+impl Positions {
+    fn new(source: &str) -> Self {
+        let file = SourceFile::new("<lint>", source);
+        let mut names: HashMap<(usize, Role, String), Vec<usize>> = HashMap::new();
+        let mut definitions = HashMap::new();
+        for name in highlight::name_spans(&file) {
+            if name.role == Role::Binding {
+                definitions
+                    .entry(name.name.clone())
+                    .or_insert(name.span.start);
+            }
+            names
+                .entry((name.statement, name.role, name.name))
+                .or_default()
+                .push(name.span.start);
+        }
+        Self {
+            statement_starts: file.statement_starts().to_vec(),
+            names,
+            definitions,
+            blocks: file.block_steps().clone().into_iter().collect(),
+            statement: 0,
+            passed: HashMap::new(),
+            entered: 0,
+        }
+    }
+
+    /// Starts counting in the top-level statement `index`.
+    fn enter_statement(&mut self, index: usize) {
+        self.statement = index;
+        self.passed.clear();
+        self.entered = 0;
+    }
+
+    fn statement_start(&self) -> usize {
+        self.statement_starts
+            .get(self.statement)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Where the next name of `role` spelled `name` is, without passing it.
+    fn peek(&self, role: Role, name: &str) -> usize {
+        let nth = self
+            .passed
+            .get(&(role, name.to_string()))
+            .copied()
+            .unwrap_or(0);
+        self.names
+            .get(&(self.statement, role, name.to_string()))
+            .and_then(|offsets| offsets.get(nth))
+            .copied()
+            .unwrap_or_else(|| self.statement_start())
+    }
+
+    /// Where the next name of `role` spelled `name` is, passing it.
+    fn pass(&mut self, role: Role, name: &str) -> usize {
+        let offset = self.peek(role, name);
+        *self.passed.entry((role, name.to_string())).or_insert(0) += 1;
+        offset
+    }
+
+    /// Where `name` is first bound, or the start of the file.
+    fn definition(&self, name: &str) -> usize {
+        self.definitions.get(name).copied().unwrap_or(0)
+    }
+
+    /// Enters the statement's next block and returns where its steps
+    /// start.
+    fn enter_block(&mut self) -> Vec<usize> {
+        let start = self.statement_start();
+        let end = self.statement_starts.get(self.statement + 1).copied();
+        let steps = self
+            .blocks
+            .iter()
+            .filter(|(open, _)| *open >= start && end.is_none_or(|end| *open < end))
+            .nth(self.entered)
+            .map(|(_, steps)| steps.clone())
+            .unwrap_or_default();
+        self.entered += 1;
+        steps
+    }
 }
 
 pub struct Linter {
@@ -28,7 +137,37 @@ pub struct Linter {
     defined_names: HashSet<String>,
     used_names: HashSet<String>,
     exported_names: HashSet<String>,
+    builtin_names: HashSet<String>,
+    /// Names brought in by `use * from` imports.
+    glob_names: HashSet<String>,
+    /// Whether every name the program can see is known; a glob import that
+    /// cannot be read hides what it provides.
+    names_complete: bool,
+    /// Undefined names already reported, so each is reported once.
+    undefined_names: HashSet<String>,
+    /// The statement that first binds each top-level binding or import.
+    /// Functions are defined before anything runs and are not listed.
+    binding_order: HashMap<String, usize>,
+    /// The top-level statement being linted.
+    statement_index: usize,
+    /// How names that break the naming convention are reported; `None`
+    /// turns the rule off.
+    naming: Option<Severity>,
+    naming_rules: NamingRules,
+    /// Names already reported for their spelling.
+    misnamed: HashSet<String>,
+    /// The program's own functions that always return a boolean, whatever
+    /// their name.
+    boolean_functions: HashSet<String>,
+    /// Parameters of the enclosing functions, innermost last.
+    scopes: Vec<Vec<String>>,
+    /// Blocks inside string interpolations have no recorded steps.
+    in_template: bool,
+    /// Parameter counts of the program's own top-level functions.
+    arities: HashMap<String, usize>,
     source: String,
+    positions: Positions,
+    base_dir: Option<PathBuf>,
 }
 
 impl Linter {
@@ -38,10 +177,44 @@ impl Linter {
             defined_names: HashSet::new(),
             used_names: HashSet::new(),
             exported_names: HashSet::new(),
+            builtin_names: Interpreter::new()
+                .builtins()
+                .iter()
+                .map(|builtin| builtin.name.clone())
+                .collect(),
+            glob_names: HashSet::new(),
+            names_complete: true,
+            undefined_names: HashSet::new(),
+            binding_order: HashMap::new(),
+            statement_index: 0,
+            naming: Some(Severity::Error),
+            naming_rules: NamingRules::default(),
+            misnamed: HashSet::new(),
+            boolean_functions: HashSet::new(),
+            scopes: Vec::new(),
+            in_template: false,
+            arities: HashMap::new(),
+            positions: Positions::new(&source),
             source,
+            base_dir: None,
         }
     }
 
+    /// Sets the directory imports are resolved from, enabling rules that
+    /// need to read imported modules.
+    pub fn with_base_dir(mut self, base_dir: PathBuf) -> Self {
+        self.base_dir = Some(base_dir);
+        self
+    }
+
+    /// Sets how the naming rule reports names that are not kebab-case and
+    /// which departures from it are accepted. `None` turns the rule off.
+    pub fn with_naming(mut self, severity: Option<Severity>, rules: NamingRules) -> Self {
+        self.naming = severity;
+        self.naming_rules = rules;
+        self
+    }
+
     fn error_at(&mut self, offset: usize, message: String, severity: Severity) {
         let line = byte_offset_to_line(&self.source, offset);
         let column = self.source[..offset.min(self.source.len())]
@@ -63,40 +236,98 @@ impl Linter {
         self.defined_names.clear();
         self.used_names.clear();
         self.exported_names.clear();
+        self.glob_names.clear();
+        self.names_complete = true;
+        self.undefined_names.clear();
+        self.binding_order.clear();
+        self.misnamed.clear();
+        self.scopes.clear();
+        self.in_template = false;
+        self.arities.clear();
 
         // First pass: collect all definitions and exports
-        for stmt in &program.statements {
+        for (index, stmt) in program.statements.iter().enumerate() {
+            self.statement_index = index;
             self.collect_definitions(stmt);
         }
 
+        self.infer_boolean_functions(program);
+
         // Second pass: check rules and collect usage
-        for stmt in &program.statements {
+        for (index, stmt) in program.statements.iter().enumerate() {
+            self.statement_index = index;
+            self.positions.enter_statement(index);
             self.check_statement(stmt);
         }
 
+        self.check_glob_imports(program);
+
         self.errors.clone()
     }
 
     fn collect_definitions(&mut self, stmt: &Statement) {
         match stmt {
-            Statement::Assignment { pattern, .. } => {
+            Statement::Assignment { pattern, expr, .. } => {
                 self.collect_pattern_identifiers(pattern);
+                if let (Pattern::Identifier(name), Expression::Lambda { params, .. }) =
+                    (pattern, expr)
+                {
+                    self.arities.insert(name.clone(), params.len());
+                }
             }
             Statement::Function(func) => {
+                self.check_name(&func.name, None);
                 self.defined_names.insert(func.name.clone());
+                self.arities.insert(func.name.clone(), func.params.len());
             }
             Statement::Export(export) => {
                 self.exported_names.insert(export.name.clone());
             }
+            Statement::Use(use_stmt) => self.collect_import(use_stmt),
+            // Either import may run, so both count as defined
+            Statement::ConditionalUse {
+                then, otherwise, ..
+            } => {
+                self.collect_import(then);
+                if let Some(otherwise) = otherwise {
+                    self.collect_import(otherwise);
+                }
+            }
             _ => {}
         }
     }
 
+    fn collect_import(&mut self, use_stmt: &UseStatement) {
+        match use_stmt {
+            UseStatement::Single { name, .. } => self.define_binding(name),
+            UseStatement::Namespace { alias, .. } => self.define_binding(alias),
+            UseStatement::Selective { names, .. } => {
+                names.iter().for_each(|name| self.define_binding(name))
+            }
+            UseStatement::Glob { module_path } => {
+                let exports = self
+                    .base_dir
+                    .as_deref()
+                    .and_then(|base_dir| Self::module_exports(base_dir, module_path));
+                match exports {
+                    Some(exports) => self.glob_names.extend(exports),
+                    None => self.names_complete = false,
+                }
+            }
+        }
+    }
+
+    fn define_binding(&mut self, name: &str) {
+        self.check_name(name, None);
+        self.defined_names.insert(name.to_string());
+        self.binding_order
+            .entry(name.to_string())
+            .or_insert(self.statement_index);
+    }
+
     fn collect_pattern_identifiers(&mut self, pattern: &Pattern) {
         match pattern {
-            Pattern::Identifier(name) => {
-                self.defined_names.insert(name.clone());
-            }
+            Pattern::Identifier(name) => self.define_binding(name),
             Pattern::List(patterns) => {
                 for p in patterns {
                     self.collect_pattern_identifiers(p);
@@ -105,9 +336,7 @@ impl Linter {
             Pattern::Object(fields) => {
                 for field in fields {
                     match field {
-                        ObjectPatternField::Shorthand(name) => {
-                            self.defined_names.insert(name.clone());
-                        }
+                        ObjectPatternField::Shorthand(name) => self.define_binding(name),
                         ObjectPatternField::Field { pattern, .. } => {
                             self.collect_pattern_identifiers(pattern);
                         }
@@ -131,90 +360,404 @@ impl Linter {
                 self.collect_usage(expr);
             }
             Statement::Use(_) => {}
+            Statement::ConditionalUse { condition, .. } => {
+                self.check_expression(condition);
+                self.collect_usage(condition);
+            }
             Statement::Export(_) => {}
         }
     }
 
+    /// Flags names that more than one `use * from` import would provide.
+    fn check_glob_imports(&mut self, program: &Program) {
+        let base_dir = match &self.base_dir {
+            Some(dir) => dir.clone(),
+            None => return,
+        };
+        let mut providers: HashMap<String, String> = HashMap::new();
+        for stmt in &program.statements {
+            let module_path = match stmt {
+                Statement::Use(UseStatement::Glob { module_path }) => module_path,
+                _ => continue,
+            };
+            let offset = self
+                .source
+                .find(&format!("\"{}\"", module_path))
+                .unwrap_or(0);
+            let mut exports = match Self::module_exports(&base_dir, module_path) {
+                Some(exports) => exports,
+                None => {
+                    self.error_at(
+                        offset,
+                        format!("Cannot read module '{}' to check glob import", module_path),
+                        Severity::Warning,
+                    );
+                    continue;
+                }
+            };
+            exports.sort();
+            for name in exports {
+                if let Some(previous) = providers.get(&name) {
+                    self.error_at(
+                        offset,
+                        format!(
+                            "Ambiguous glob import: '{}' is exported by both '{}' and '{}'; import it explicitly",
+                            name, previous, module_path
+                        ),
+                        Severity::Warning,
+                    );
+                } else {
+                    providers.insert(name, module_path.clone());
+                }
+            }
+        }
+    }
+
+    fn module_exports(base_dir: &std::path::Path, module_path: &str) -> Option<Vec<String>> {
+        let (file, source) = FileSystemLoader.load(Some(base_dir), module_path).ok()?;
+        let tokens = Lexer::new(&source).lex().ok()?;
+        let program = Parser::with_source_and_file(tokens, source.clone(), file)
+            .parse_program()
+            .ok()?;
+        Some(
+            program
+                .statements
+                .iter()
+                .filter_map(|stmt| match stmt {
+                    Statement::Export(export) => Some(export.name.clone()),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Finds which of the program's functions return a boolean. Each
+    /// function is first assumed to, and those whose bodies can return
+    /// something else are dropped until none are left to drop, so helpers
+    /// may call each other in any order and recursion does not count against
+    /// a function.
+    fn infer_boolean_functions(&mut self, program: &Program) {
+        let functions: Vec<(&str, &Expression)> = program
+            .statements
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Statement::Function(func) => Some((func.name.as_str(), func.body.as_ref())),
+                Statement::Assignment {
+                    pattern: Pattern::Identifier(name),
+                    expr: Expression::Lambda { body, .. },
+                    ..
+                } => Some((name.as_str(), body.as_ref())),
+                _ => None,
+            })
+            .collect();
+        self.boolean_functions = functions.iter().map(|(name, _)| name.to_string()).collect();
+        loop {
+            let dropped: Vec<&str> = functions
+                .iter()
+                .filter(|(name, body)| {
+                    self.boolean_functions.contains(*name) && !self.returns_boolean(body)
+                })
+                .map(|(name, _)| *name)
+                .collect();
+            if dropped.is_empty() {
+                break;
+            }
+            for name in dropped {
+                self.boolean_functions.remove(name);
+            }
+        }
+    }
+
     fn check_function(&mut self, func: &Function) {
-        let has_impure_suffix = func.name.ends_with('!');
-        let has_boolean_suffix = func.name.ends_with('?');
+        if func.name.ends_with('?') && !self.returns_boolean(&func.body) {
+            let offset = self.positions.definition(&func.name);
+            self.error_at(
+                offset,
+                format!("Function '{}' must return a boolean value", func.name),
+                Severity::Error,
+            );
+        }
 
-        // Check if function marked as impure actually calls impure functions
-        if func.impure || has_impure_suffix {
-            if !Self::find_impure_call(&func.body) {
-                // Use offset 0 as fallback since we don't have location info
+        // Check expression for other issues
+        self.check_shadowing(&func.params);
+        self.scopes.push(func.params.clone());
+        self.check_expression(&func.body);
+        self.scopes.pop();
+        self.collect_usage(&func.body);
+    }
+
+    /// Warns about parameters that hide an outer binding or a builtin.
+    /// Inside a pipeline block a parameter named `map`, say, silently
+    /// replaces the builtin for every step that follows.
+    fn check_shadowing(&mut self, params: &[String]) {
+        for param in params {
+            let offset = self.positions.pass(Role::Parameter, param);
+            self.check_name(param, Some(offset));
+            let shadowed = if self.scopes.iter().any(|scope| scope.contains(param)) {
+                Some("an enclosing parameter")
+            } else if self.defined_names.contains(param) {
+                Some("a top-level binding")
+            } else if self.builtin_names.contains(param) {
+                Some("a builtin")
+            } else {
+                None
+            };
+            if let Some(shadowed) = shadowed {
                 self.error_at(
-                    0,
+                    offset,
                     format!(
-                        "Function '{}' is marked impure but performs no impure operations",
-                        func.name
+                        "Parameter '{}' shadows {} of the same name",
+                        param, shadowed
                     ),
-                    Severity::Error,
+                    Severity::Warning,
                 );
             }
-        } else {
-            // Check if function calls impure functions but isn't marked impure
-            if let Some(impure_call) = Self::find_impure_call_name(&func.body) {
+        }
+    }
+
+    /// Flags a name that breaks the naming convention, once per name.
+    /// Without an `offset`, the name is found where it is defined.
+    fn check_name(&mut self, name: &str, offset: Option<usize>) {
+        let Some(severity) = self.naming else {
+            return;
+        };
+        let Err(message) = naming::check_kebab_case(name, self.naming_rules) else {
+            return;
+        };
+        if self.misnamed.insert(name.to_string()) {
+            let offset = offset.unwrap_or_else(|| self.positions.definition(name));
+            self.error_at(offset, message, severity);
+        }
+    }
+
+    /// Flags calls that pass one of the program's own functions more
+    /// arguments than it has parameters. Passing fewer is fine: the call
+    /// returns a curried function waiting for the rest.
+    fn check_arity(&mut self, call: &Expression) {
+        let Expression::Call { callee, args } = call else {
+            return;
+        };
+        let Some((name, remaining)) = self.remaining_arity(callee) else {
+            return;
+        };
+        if args.len() > remaining {
+            // The call is checked before the walk passes the function's name
+            let offset = self.positions.peek(Role::Read, &name);
+            self.error_at(
+                offset,
+                format!(
+                    "Function '{}' takes {} argument(s) but this call passes {}",
+                    name,
+                    remaining,
+                    args.len()
+                ),
+                Severity::Error,
+            );
+        }
+    }
+
+    /// A pipeline step that is a function receives the piped value, so it
+    /// must accept at least one more argument.
+    fn check_pipeline_arity(&mut self, step: &Expression, start: Option<usize>) {
+        if let Some((name, 0)) = self.remaining_arity(step) {
+            let offset = start.unwrap_or_else(|| self.positions.statement_start());
+            self.error_at(
+                offset,
+                format!(
+                    "Function '{}' takes no arguments but is used as a pipeline step, which passes it one",
+                    name
+                ),
+                Severity::Error,
+            );
+        }
+    }
+
+    /// For a known function, or a call that curries one, returns its name
+    /// and how many arguments it still accepts.
+    fn remaining_arity(&self, expr: &Expression) -> Option<(String, usize)> {
+        match expr {
+            Expression::Identifier(name) => {
+                if self.scopes.iter().any(|scope| scope.contains(name)) {
+                    return None;
+                }
+                self.arities.get(name).map(|arity| (name.clone(), *arity))
+            }
+            Expression::Call { callee, args } => {
+                let (name, remaining) = self.remaining_arity(callee)?;
+                // A call that supplies every argument returns an unknown value
+                (args.len() < remaining).then(|| (name, remaining - args.len()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Flags pure pipeline steps whose result is thrown away. A step that
+    /// evaluates to a plain value (a literal, list, object, or operator
+    /// expression) replaces the value piped so far, so everything before it
+    /// since the last such step only matters for its effects.
+    fn check_discarded_steps(&mut self, exprs: &[Expression], starts: &[usize]) {
+        let mut run_start = 0;
+        for (index, expr) in exprs.iter().enumerate().skip(1) {
+            if !Self::is_plain_value(expr) {
+                continue;
+            }
+            let run = &exprs[run_start..index];
+            if run
+                .iter()
+                .all(|step| semantic::find_impure_call(step).is_none())
+            {
+                let steps = run
+                    .iter()
+                    .map(Self::describe)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let offset = starts
+                    .get(run_start)
+                    .copied()
+                    .unwrap_or_else(|| self.positions.statement_start());
                 self.error_at(
-                    0,
+                    offset,
                     format!(
-                        "Function '{}' must be declared impure (end the name with '!') to call '{}'",
-                        func.name, impure_call
+                        "Dead code: the result of {} is discarded by the step `{}` and has no effect",
+                        steps,
+                        Self::describe(expr).trim_matches('`')
                     ),
-                    Severity::Error,
+                    Severity::Warning,
                 );
             }
+            run_start = index;
         }
+    }
 
-        // Check boolean suffix
-        if has_boolean_suffix {
-            if !Self::returns_boolean(&func.body) {
-                self.error_at(
-                    0,
-                    format!("Function '{}' must return a boolean value", func.name),
-                    Severity::Error,
-                );
-            }
+    fn is_plain_value(expr: &Expression) -> bool {
+        matches!(
+            expr,
+            Expression::Number { .. }
+                | Expression::String(_)
+                | Expression::Boolean(_)
+                | Expression::Null
+                | Expression::List(_)
+                | Expression::Object(_)
+                | Expression::Binary { .. }
+                | Expression::Not(_)
+        )
+    }
+
+    /// Renders an expression for a message, keeping only its first line.
+    fn describe(expr: &Expression) -> String {
+        let program = Program {
+            statements: vec![Statement::Expression(expr.clone())],
+            doc: None,
+        };
+        let text = Formatter::new().format_program(&program);
+        let mut lines = text.trim().lines();
+        let first = lines.next().unwrap_or_default();
+        if lines.next().is_some() {
+            format!("`{} …`", first)
+        } else {
+            format!("`{}`", first)
         }
+    }
 
-        // Check expression for other issues
-        self.check_expression(&func.body);
-        self.collect_usage(&func.body);
+    /// Reported at the `if`, before the walk passes it.
+    fn check_literal_if(&mut self, condition: bool) {
+        let offset = self.positions.peek(Role::Read, "if");
+        let skipped = if condition { "else" } else { "then" };
+        self.error_at(
+            offset,
+            format!(
+                "Dead code: 'if' condition is always {}, so the {} branch never runs",
+                condition, skipped
+            ),
+            Severity::Warning,
+        );
+    }
+
+    /// Flags a name that is not a parameter, top-level binding, import, or
+    /// builtin, suggesting the closest one that is. Top-level code outside
+    /// any function runs in order, so there a binding is only defined once
+    /// the statement that binds it has run; function bodies run later and
+    /// see every binding.
+    fn check_defined(&mut self, name: &str, offset: usize) {
+        let bound_later = self
+            .binding_order
+            .get(name)
+            .is_some_and(|&index| index >= self.statement_index);
+        if bound_later
+            && self.scopes.is_empty()
+            && !self.builtin_names.contains(name)
+            && self.undefined_names.insert(name.to_string())
+        {
+            self.error_at(
+                offset,
+                format!("Identifier '{}' is used before it is defined", name),
+                Severity::Error,
+            );
+        }
+        let defined = self
+            .scopes
+            .iter()
+            .any(|scope| scope.iter().any(|param| param == name))
+            || self.defined_names.contains(name)
+            || self.glob_names.contains(name)
+            || self.builtin_names.contains(name);
+        if defined || !self.names_complete || !self.undefined_names.insert(name.to_string()) {
+            return;
+        }
+        let hint = {
+            let candidates = self
+                .scopes
+                .iter()
+                .flatten()
+                .chain(&self.defined_names)
+                .chain(&self.glob_names)
+                .chain(&self.builtin_names)
+                .map(String::as_str);
+            suggest::hint(name, candidates)
+        };
+        self.error_at(
+            offset,
+            format!("Undefined identifier '{}'{}", name, hint),
+            Severity::Error,
+        );
     }
 
     fn check_expression(&mut self, expr: &Expression) {
         match expr {
-            Expression::Lambda { body, impure, .. } => {
-                if *impure {
-                    if !Self::find_impure_call(body.as_ref()) {
-                        self.error_at(
-                            0,
-                            "Anonymous function is marked impure but performs no impure operations"
-                                .to_string(),
-                            Severity::Error,
-                        );
-                    }
-                } else {
-                    if let Some(impure_call) = Self::find_impure_call_name(body.as_ref()) {
-                        self.error_at(
-                            0,
-                            format!(
-                                "Anonymous function must be marked impure (use '!') to call '{}'",
-                                impure_call
-                            ),
-                            Severity::Error,
-                        );
-                    }
-                }
+            Expression::Identifier(name) => {
+                let offset = self.positions.pass(Role::Read, name);
+                self.check_defined(name, offset);
+            }
+            Expression::Lambda { params, body, .. } => {
+                self.check_shadowing(params);
+                self.scopes.push(params.clone());
                 self.check_expression(body.as_ref());
+                self.scopes.pop();
             }
             Expression::Call { callee, args } => {
+                self.check_arity(expr);
+                if let (Expression::Identifier(name), Some(Expression::Boolean(condition))) =
+                    (callee.as_ref(), args.first())
+                {
+                    if name == "if" {
+                        self.check_literal_if(*condition);
+                    }
+                }
                 self.check_expression(callee.as_ref());
                 for arg in args {
                     self.check_expression(arg);
                 }
             }
             Expression::Block(exprs) => {
+                let starts = if self.in_template {
+                    Vec::new()
+                } else {
+                    self.positions.enter_block()
+                };
+                self.check_discarded_steps(exprs, &starts);
+                for (index, step) in exprs.iter().enumerate().skip(1) {
+                    self.check_pipeline_arity(step, starts.get(index).copied());
+                }
                 for expr in exprs {
                     self.check_expression(expr);
                 }
@@ -225,13 +768,13 @@ impl Linter {
                         ObjectField::Field { value, .. } => {
                             self.check_expression(value);
                         }
-                        ObjectField::Spread(expr) => {
+                        ObjectField::Spread(expr) | ObjectField::Base(expr) => {
                             self.check_expression(expr);
                         }
                     }
                 }
             }
-            Expression::Spread(expr) => {
+            Expression::Spread(expr) | Expression::Not(expr) => {
                 self.check_expression(expr.as_ref());
             }
             Expression::List(elements) => {
@@ -247,11 +790,13 @@ impl Linter {
                 self.check_expression(object.as_ref());
             }
             Expression::String(template) => {
+                let in_template = std::mem::replace(&mut self.in_template, true);
                 for segment in &template.segments {
                     if let StringSegment::Expr(expr) = segment {
                         self.check_expression(expr);
                     }
                 }
+                self.in_template = in_template;
             }
             _ => {}
         }
@@ -282,13 +827,13 @@ impl Linter {
                         ObjectField::Field { value, .. } => {
                             self.collect_usage(value);
                         }
-                        ObjectField::Spread(expr) => {
+                        ObjectField::Spread(expr) | ObjectField::Base(expr) => {
                             self.collect_usage(expr);
                         }
                     }
                 }
             }
-            Expression::Spread(expr) => {
+            Expression::Spread(expr) | Expression::Not(expr) => {
                 self.collect_usage(expr.as_ref());
             }
             Expression::List(elements) => {
@@ -314,82 +859,6 @@ impl Linter {
         }
     }
 
-    fn find_impure_call(expr: &Expression) -> bool {
-        match expr {
-            Expression::Call { callee, args } => {
-                if let Some(name) = Self::identifier_name(callee.as_ref()) {
-                    if name.ends_with('!') {
-                        return true;
-                    }
-                }
-                Self::find_impure_call(callee.as_ref())
-                    || args.iter().any(|arg| Self::find_impure_call(arg))
-            }
-            Expression::Identifier(name) => name.ends_with('!'),
-            Expression::Block(exprs) => exprs.iter().any(|e| Self::find_impure_call(e)),
-            Expression::Lambda { body, .. } => Self::find_impure_call(body.as_ref()),
-            Expression::Object(fields) => fields.iter().any(|f| match f {
-                ObjectField::Field { value, .. } => Self::find_impure_call(value),
-                ObjectField::Spread(expr) => Self::find_impure_call(expr),
-            }),
-            Expression::Spread(expr) => Self::find_impure_call(expr.as_ref()),
-            Expression::List(elements) => elements.iter().any(|e| Self::find_impure_call(e)),
-            Expression::Binary { left, right, .. } => {
-                Self::find_impure_call(left.as_ref()) || Self::find_impure_call(right.as_ref())
-            }
-            Expression::PropertyAccess { object, .. } => Self::find_impure_call(object.as_ref()),
-            Expression::String(template) => template
-                .segments
-                .iter()
-                .any(|s| matches!(s, StringSegment::Expr(e) if Self::find_impure_call(e))),
-            _ => false,
-        }
-    }
-
-    fn find_impure_call_name(expr: &Expression) -> Option<String> {
-        match expr {
-            Expression::Call { callee, args } => {
-                if let Some(name) = Self::identifier_name(callee.as_ref()) {
-                    if name.ends_with('!') {
-                        return Some(name);
-                    }
-                }
-                Self::find_impure_call_name(callee.as_ref())
-                    .or_else(|| args.iter().find_map(|arg| Self::find_impure_call_name(arg)))
-            }
-            Expression::Identifier(name) => {
-                if name.ends_with('!') {
-                    Some(name.clone())
-                } else {
-                    None
-                }
-            }
-            Expression::Block(exprs) => exprs.iter().find_map(|e| Self::find_impure_call_name(e)),
-            Expression::Lambda { body, .. } => Self::find_impure_call_name(body.as_ref()),
-            Expression::Object(fields) => fields.iter().find_map(|f| match f {
-                ObjectField::Field { value, .. } => Self::find_impure_call_name(value),
-                ObjectField::Spread(expr) => Self::find_impure_call_name(expr),
-            }),
-            Expression::Spread(expr) => Self::find_impure_call_name(expr.as_ref()),
-            Expression::List(elements) => {
-                elements.iter().find_map(|e| Self::find_impure_call_name(e))
-            }
-            Expression::Binary { left, right, .. } => Self::find_impure_call_name(left.as_ref())
-                .or_else(|| Self::find_impure_call_name(right.as_ref())),
-            Expression::PropertyAccess { object, .. } => {
-                Self::find_impure_call_name(object.as_ref())
-            }
-            Expression::String(template) => template.segments.iter().find_map(|s| {
-                if let StringSegment::Expr(e) = s {
-                    Self::find_impure_call_name(e)
-                } else {
-                    None
-                }
-            }),
-            _ => None,
-        }
-    }
-
     fn identifier_name(expr: &Expression) -> Option<String> {
         match expr {
             Expression::Identifier(name) => Some(name.clone()),
@@ -397,9 +866,22 @@ impl Linter {
         }
     }
 
-    fn returns_boolean(expr: &Expression) -> bool {
+    /// Whether `expr` always evaluates to a boolean. Calls count when the
+    /// function ends in `?` or is one of the program's own functions that
+    /// returns a boolean, and a block counts when its last step does.
+    fn returns_boolean(&self, expr: &Expression) -> bool {
         match expr {
-            Expression::Boolean(_) => true,
+            Expression::Boolean(_) | Expression::Not(_) => true,
+            Expression::Binary {
+                op: BinaryOperator::Coalesce,
+                right,
+                ..
+            } => self.returns_boolean(right),
+            Expression::Binary {
+                op: BinaryOperator::PipeForward,
+                right,
+                ..
+            } => self.is_boolean_function(right),
             Expression::Binary { op, .. } => {
                 matches!(
                     op,
@@ -413,101 +895,378 @@ impl Linter {
                         | BinaryOperator::Or
                 )
             }
-            Expression::Call { callee, .. } => {
-                if let Some(name) = Self::identifier_name(callee.as_ref()) {
-                    name.ends_with('?')
-                } else {
-                    false
-                }
+            // Both branches of an `if` are functions called without arguments
+            Expression::Call { callee, args }
+                if Self::identifier_name(callee).as_deref() == Some("if") && args.len() == 3 =>
+            {
+                args[1..]
+                    .iter()
+                    .all(|branch| self.is_boolean_function(branch))
+            }
+            Expression::Call { callee, .. } => self.is_boolean_function(callee),
+            // A later step is called with the value so far when it is a
+            // function, and replaces it otherwise
+            Expression::Block(exprs) => match exprs.as_slice() {
+                [] => false,
+                [only] => self.returns_boolean(only),
+                [.., last] => self.is_boolean_function(last) || self.returns_boolean(last),
+            },
+            Expression::PropertyAccess { property, .. } => property.ends_with('?'),
+            _ => false,
+        }
+    }
+
+    /// Whether calling `callee` returns a boolean: a name or namespace
+    /// member ending in `?`, one of the program's boolean functions, or a
+    /// lambda whose body returns one.
+    fn is_boolean_function(&self, callee: &Expression) -> bool {
+        match callee {
+            Expression::Identifier(name) => {
+                name.ends_with('?') || self.boolean_functions.contains(name)
             }
-            Expression::Block(exprs) => exprs
-                .last()
-                .map(|e| Self::returns_boolean(e))
-                .unwrap_or(false),
+            Expression::PropertyAccess { property, .. } => property.ends_with('?'),
+            Expression::Lambda { body, .. } => self.returns_boolean(body),
             _ => false,
         }
     }
 }
 
+/// What a run found, for the summary line and the exit code.
+#[derive(Default)]
+struct Tally {
+    files: usize,
+    errors: usize,
+    warnings: usize,
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        eprintln!("Usage: fip-lint <file.fip>");
+        eprintln!(
+            "Usage: fip-lint <file.fip | directory> [--max-warnings <n>] [--naming <error|warning|off>] [--allow-acronyms] [--allow-underscores]"
+        );
         std::process::exit(1);
     }
+    let max_warnings = match args.iter().position(|arg| arg == "--max-warnings") {
+        None => None,
+        Some(index) => match args.get(index + 1).and_then(|value| value.parse().ok()) {
+            Some(max) => Some(max),
+            None => {
+                report_error("--max-warnings requires a non-negative number");
+                std::process::exit(1);
+            }
+        },
+    };
 
-    let file_path = &args[1];
-    let source = match fs::read_to_string(file_path) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error reading file: {}", e);
+    let naming = match args.iter().position(|arg| arg == "--naming") {
+        None => Some(Severity::Error),
+        Some(index) => match args.get(index + 1).map(String::as_str) {
+            Some("error") => Some(Severity::Error),
+            Some("warning") => Some(Severity::Warning),
+            Some("off") => None,
+            _ => {
+                report_error("--naming requires 'error', 'warning', or 'off'");
+                std::process::exit(1);
+            }
+        },
+    };
+    let naming_rules = NamingRules {
+        allow_acronyms: args.iter().any(|arg| arg == "--allow-acronyms"),
+        allow_underscores: args.iter().any(|arg| arg == "--allow-underscores"),
+    };
+
+    let target = Path::new(&args[1]);
+    let reporter = Reporter::stdout();
+    let started = Instant::now();
+    let mut tally = Tally::default();
+    if target.is_dir() {
+        // Imports resolve from the entry point directory, which for a
+        // library is the directory being linted
+        let mut files = Vec::new();
+        if let Err(e) = collect_sources(target, &mut files) {
+            report_error(&format!("Error reading directory: {}", e));
             std::process::exit(1);
         }
-    };
+        files.sort();
+        for file in &files {
+            lint_file(file, target, (naming, naming_rules), &reporter, &mut tally);
+        }
+        println!(
+            "Linted {} file(s) in {}: {} error(s), {} warning(s)",
+            tally.files,
+            format_duration(started.elapsed()),
+            tally.errors,
+            tally.warnings
+        );
+    } else {
+        let base_dir = target.parent().unwrap_or(Path::new(""));
+        lint_file(
+            target,
+            base_dir,
+            (naming, naming_rules),
+            &reporter,
+            &mut tally,
+        );
+        if tally.errors == 0 && tally.warnings == 0 {
+            println!("No linting errors found.");
+        }
+    }
 
-    let file_path_buf = PathBuf::from(file_path);
-    let tokens = match Lexer::new(&source).lex() {
-        Ok(t) => t,
+    let too_many_warnings = max_warnings.is_some_and(|max| tally.warnings > max);
+    if too_many_warnings {
+        report_error(&format!(
+            "{} warning(s) exceed --max-warnings {}",
+            tally.warnings,
+            max_warnings.unwrap_or_default()
+        ));
+    }
+    if tally.errors > 0 || too_many_warnings {
+        std::process::exit(1);
+    }
+}
+
+/// Lints one file, printing its problems and counting them in `tally`.
+/// Files that cannot be read or parsed count as one error.
+fn lint_file(
+    path: &Path,
+    base_dir: &Path,
+    (naming, naming_rules): (Option<Severity>, NamingRules),
+    reporter: &Reporter,
+    tally: &mut Tally,
+) {
+    tally.files += 1;
+    let file_path = path.display().to_string();
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
         Err(e) => {
-            eprintln!("Lexer error: {}", e);
-            std::process::exit(1);
+            tally.errors += 1;
+            println!(
+                "{}",
+                reporter.diagnostic(
+                    Severity::Error,
+                    Some(&file_path),
+                    &format!("Error reading file: {}", e)
+                )
+            );
+            return;
         }
     };
 
-    let mut parser = Parser::with_source_and_file(tokens, source.clone(), file_path_buf.clone());
-    let program = match parser.parse_program() {
+    let program = Lexer::new(&source).lex().and_then(|tokens| {
+        Parser::with_source_and_file(tokens, source.clone(), path.to_path_buf()).parse_program()
+    });
+    let program = match program {
         Ok(p) => p,
         Err(e) => {
-            // Extract location from parser error and format it properly
-            match &e {
-                LangError::Parser(msg, location) => {
-                    if let Some(loc) = location {
-                        println!("{}:{}:1: error: {}", file_path, loc.line, msg);
-                    } else {
-                        println!("{}:1:1: error: {}", file_path, msg);
-                    }
-                }
-                LangError::Lexer(msg, location) => {
-                    if let Some(loc) = location {
-                        println!("{}:{}:1: error: {}", file_path, loc.line, msg);
-                    } else {
-                        println!("{}:1:1: error: {}", file_path, msg);
-                    }
-                }
-                _ => {
-                    eprintln!("Error: {}", e);
+            tally.errors += 1;
+            let (message, line) = match &e {
+                LangError::Parser(msg, location) | LangError::Lexer(msg, location) => {
+                    (msg.clone(), location.as_ref().map_or(1, |loc| loc.line))
                 }
-            }
-            std::process::exit(1);
+                _ => (e.to_string(), 1),
+            };
+            let position = format!("{}:{}:1", file_path, line);
+            println!(
+                "{}",
+                reporter.diagnostic(Severity::Error, Some(&position), &message)
+            );
+            return;
         }
     };
 
-    let mut linter = Linter::new(source);
-    let errors = linter.lint(&program);
+    let mut linter = Linter::new(source)
+        .with_base_dir(base_dir.to_path_buf())
+        .with_naming(naming, naming_rules);
+    for error in &linter.lint(&program) {
+        match error.severity {
+            Severity::Error => tally.errors += 1,
+            Severity::Warning => tally.warnings += 1,
+            Severity::Info => {}
+        }
+        let position = format!("{}:{}:{}", file_path, error.line, error.column);
+        println!(
+            "{}",
+            reporter.diagnostic(error.severity, Some(&position), &error.message)
+        );
+    }
+}
+
+fn collect_sources(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_sources(&entry?.path(), files)?;
+        }
+    } else if path.extension().and_then(|ext| ext.to_str()) == Some("fip") {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
 
-    if errors.is_empty() {
-        println!("No linting errors found.");
-        std::process::exit(0);
+fn format_duration(duration: Duration) -> String {
+    if duration.as_millis() < 1000 {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
     }
+}
 
-    let mut has_errors = false;
-    for error in &errors {
-        let severity_str = match error.severity {
-            Severity::Error => "error",
-            Severity::Warning => "warning",
-            Severity::Info => "info",
-        };
-        println!(
-            "{}:{}:{}: {}: {}",
-            file_path, error.line, error.column, severity_str, error.message
+fn report_error(message: &str) {
+    eprintln!(
+        "{}",
+        Reporter::stderr().diagnostic(Severity::Error, None, message)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The line, column, and message of each problem in `source`.
+    fn lint(source: &str) -> Vec<(usize, usize, String)> {
+        let tokens = Lexer::new(source).lex().expect("lexes");
+        let program =
+            Parser::with_source_and_file(tokens, source.to_string(), PathBuf::from("test.fip"))
+                .parse_program()
+                .expect("parses");
+        Linter::new(source.to_string())
+            .lint(&program)
+            .into_iter()
+            .map(|error| (error.line, error.column, error.message))
+            .collect()
+    }
+
+    /// Where each problem whose message starts with `prefix` is.
+    fn positions(source: &str, prefix: &str) -> Vec<(usize, usize)> {
+        lint(source)
+            .into_iter()
+            .filter(|(_, _, message)| message.starts_with(prefix))
+            .map(|(line, column, _)| (line, column))
+            .collect()
+    }
+
+    #[test]
+    fn dead_code_is_reported_where_it_is() {
+        let source = "note: \"if(true, a) { [1, 2] }\"\n\
+                      total: {\n  [1, 2]\n  map((n) {\n    n\n    n * 2\n  })\n  0\n}\n\
+                      pick: (x) { if(true, () { x }, () { 0 }) }\n";
+        assert_eq!(positions(source, "Dead code"), [(3, 3), (5, 5), (10, 13)]);
+    }
+
+    #[test]
+    fn shadowing_parameters_are_reported_where_they_are_declared() {
+        let source = "// (map) { map }\n\
+                      tag: \"(count, map) { count }\"\n\
+                      count: 3\n\
+                      wrap: (xs) {\n  xs\n  map((count) { count + 1 })\n}\n\
+                      apply: (f, map) { f(map) }\n\
+                      nest: (n) { map((n) { n }, [n]) }\n";
+        let shadowing: Vec<(usize, usize, String)> = lint(source)
+            .into_iter()
+            .filter(|(_, _, message)| message.starts_with("Parameter"))
+            .collect();
+        assert_eq!(
+            shadowing,
+            [
+                (
+                    6,
+                    8,
+                    "Parameter 'count' shadows a top-level binding of the same name".to_string()
+                ),
+                (
+                    8,
+                    12,
+                    "Parameter 'map' shadows a builtin of the same name".to_string()
+                ),
+                (
+                    9,
+                    18,
+                    "Parameter 'n' shadows an enclosing parameter of the same name".to_string()
+                ),
+            ]
         );
-        if error.severity == Severity::Error {
-            has_errors = true;
-        }
     }
 
-    if has_errors {
-        std::process::exit(1);
+    #[test]
+    fn arity_problems_are_reported_at_the_call() {
+        let source = "pair: (a, b) { [a, b] }\n\
+                      now: () { 1 }\n\
+                      first: pair(1, 2)\n\
+                      // pair(1, 2, 3)\n\
+                      second: [pair(1)(2), pair(1, 2, 3), pair(1)(2, 3)]\n\
+                      steps: {\n  0\n  now\n  now\n}\n\
+                      again: {\n  now()\n  now\n}\n";
+        assert_eq!(
+            positions(source, "Function"),
+            [(5, 22), (5, 37), (8, 3), (9, 3), (13, 3)]
+        );
+    }
+
+    #[test]
+    fn names_are_reported_where_they_are_read() {
+        let source = "label: \"total and totl\"\n\
+                      early: total + 1\n\
+                      later: () { total }\n\
+                      total: 3\n\
+                      sum: [total, totl]\n";
+        let found: Vec<(usize, usize, String)> = lint(source)
+            .into_iter()
+            .filter(|(_, _, message)| {
+                message.contains("identifier") || message.contains("Identifier")
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    2,
+                    8,
+                    "Identifier 'total' is used before it is defined".to_string()
+                ),
+                (
+                    5,
+                    14,
+                    "Undefined identifier 'totl'. Did you mean 'total'?".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_are_checked_where_they_are_bound() {
+        let source = "note: \"badName: and someFactor\"\n\
+                      badName: 1\n\
+                      scale: (someFactor) { someFactor * 2 }\n";
+        assert_eq!(positions(source, "Identifier"), [(2, 1), (3, 9)]);
+    }
+
+    #[test]
+    fn predicates_may_return_a_boolean_through_other_functions() {
+        let source = "note: \"broken?: no\"\n\
+                      big?: (n) { n > 10 }\n\
+                      is-big: (n) { big?(n) }\n\
+                      positive?: (n) { is-big(n) }\n\
+                      through-pipe?: (n) {\n  n\n  is-big\n}\n\
+                      broken?: (n) { n + 1 }\n\
+                      halves?: (n) { is-big(n) / 2 }\n";
+        let found: Vec<(usize, usize, String)> = lint(source)
+            .into_iter()
+            .filter(|(_, _, message)| message.starts_with("Function"))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    9,
+                    1,
+                    "Function 'broken?' must return a boolean value".to_string()
+                ),
+                (
+                    10,
+                    1,
+                    "Function 'halves?' must return a boolean value".to_string()
+                ),
+            ]
+        );
     }
 }
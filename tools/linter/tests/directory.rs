@@ -0,0 +1,56 @@
+//! Runs `fip-lint` over a directory and checks that the exit code follows
+//! the errors and warnings found across every file in it.
+
+use std::{fs, path::Path, process::Command};
+
+fn lint(dir: &Path, args: &[&str]) -> (Option<i32>, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_fip-lint"))
+        .arg(dir)
+        .args(args)
+        .output()
+        .expect("fip-lint runs");
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn max_warnings_sets_the_exit_code_for_a_directory() {
+    let dir = std::env::temp_dir().join(format!("fip-lint-dir-{}", std::process::id()));
+    fs::create_dir_all(dir.join("nested")).expect("temp dir");
+    // One shadowing warning in each file
+    fs::write(dir.join("first.fip"), "pick: (map) { map }\n").expect("first file");
+    fs::write(dir.join("nested/second.fip"), "keep: (filter) { filter }\n").expect("second file");
+    fs::write(dir.join("notes.txt"), "not: (a) { program }\n").expect("other file");
+
+    let (code, stdout, _) = lint(&dir, &[]);
+    assert_eq!(code, Some(0), "{}", stdout);
+    assert!(
+        stdout.contains("Linted 2 file(s)") && stdout.contains("0 error(s), 2 warning(s)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("first.fip:1:8"), "{}", stdout);
+    assert!(stdout.contains("second.fip:1:8"), "{}", stdout);
+
+    let (code, _, _) = lint(&dir, &["--max-warnings", "2"]);
+    assert_eq!(code, Some(0));
+
+    let (code, _, stderr) = lint(&dir, &["--max-warnings", "1"]);
+    assert_eq!(code, Some(1));
+    assert!(
+        stderr.contains("2 warning(s) exceed --max-warnings 1"),
+        "{}",
+        stderr
+    );
+
+    // An error fails the run whatever the warning limit
+    fs::write(dir.join("third.fip"), "broken: missing\n").expect("third file");
+    let (code, stdout, _) = lint(&dir, &["--max-warnings", "10"]);
+    assert_eq!(code, Some(1), "{}", stdout);
+    assert!(stdout.contains("1 error(s), 2 warning(s)"), "{}", stdout);
+
+    fs::remove_dir_all(&dir).ok();
+}